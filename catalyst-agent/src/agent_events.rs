@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+
+use regex::Regex;
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Recent warnings/errors kept for `get_agent_events`.
+const MAX_AGENT_EVENTS: usize = 500;
+const MAX_EVENT_MESSAGE_LEN: usize = 2048;
+/// `NAME=value` / `name: value` pairs whose name looks secret; the value is masked.
+const SECRET_ASSIGNMENT_PATTERN: &str =
+    r"(?i)\b([A-Z0-9_]*(?:password|passwd|secret|token|api[_-]?key)[A-Z0-9_]*)(\s*[=:]\s*)\S+";
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentEvent {
+    pub timestamp: i64,
+    pub severity: &'static str,
+    pub target: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_id: Option<String>,
+}
+
+/// Bounded ring buffer of significant agent log events, so the panel can see agent-internal
+/// failures (CNI, firewall, snapshots, gRPC) without host access.
+pub struct AgentEventLog {
+    entries: Mutex<VecDeque<AgentEvent>>,
+    secrets: Mutex<Vec<String>>,
+    dropped: Mutex<u64>,
+}
+
+static AGENT_EVENTS: OnceLock<AgentEventLog> = OnceLock::new();
+
+/// The process-wide event log fed by [`AgentEventLayer`].
+pub fn agent_events() -> &'static AgentEventLog {
+    AGENT_EVENTS.get_or_init(|| AgentEventLog {
+        entries: Mutex::new(VecDeque::with_capacity(MAX_AGENT_EVENTS)),
+        secrets: Mutex::new(Vec::new()),
+        dropped: Mutex::new(0),
+    })
+}
+
+impl AgentEventLog {
+    /// Register a literal secret (the node API or HMAC key) that must never appear in stored
+    /// events.
+    pub fn add_secret(&self, secret: &str) {
+        let secret = secret.trim();
+        if !secret.is_empty() {
+            self.secrets
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(secret.to_string());
+        }
+    }
+
+    fn push(&self, mut event: AgentEvent) {
+        event.message = self.redact(&event.message);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= MAX_AGENT_EVENTS {
+            entries.pop_front();
+            *self.dropped.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        }
+        entries.push_back(event);
+    }
+
+    fn redact(&self, message: &str) -> String {
+        static SECRET_RE: OnceLock<Regex> = OnceLock::new();
+        let re = SECRET_RE.get_or_init(|| {
+            Regex::new(SECRET_ASSIGNMENT_PATTERN).expect("valid secret redaction regex")
+        });
+        let mut redacted = re.replace_all(message, "$1$2[REDACTED]").into_owned();
+        for secret in self
+            .secrets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            redacted = redacted.replace(secret.as_str(), "[REDACTED]");
+        }
+        if redacted.len() > MAX_EVENT_MESSAGE_LEN {
+            let mut end = MAX_EVENT_MESSAGE_LEN;
+            while !redacted.is_char_boundary(end) {
+                end -= 1;
+            }
+            redacted.truncate(end);
+            redacted.push('…');
+        }
+        redacted
+    }
+
+    /// Newest-last events matching the filters, at most `limit` of them, plus how many older
+    /// events have been evicted from the buffer since startup.
+    pub fn recent(
+        &self,
+        limit: usize,
+        errors_only: bool,
+        server_id: Option<&str>,
+        since_ms: Option<i64>,
+    ) -> (Vec<AgentEvent>, u64) {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut matched: Vec<AgentEvent> = entries
+            .iter()
+            .rev()
+            .filter(|e| !errors_only || e.severity == "error")
+            .filter(|e| since_ms.is_none_or(|since| e.timestamp >= since))
+            .filter(|e| {
+                server_id
+                    .is_none_or(|id| e.server_id.as_deref() == Some(id) || e.message.contains(id))
+            })
+            .take(limit)
+            .cloned()
+            .collect();
+        matched.reverse();
+        let dropped = *self.dropped.lock().unwrap_or_else(|e| e.into_inner());
+        (matched, dropped)
+    }
+}
+
+/// Tracing layer that copies WARN and ERROR events into [`agent_events`].
+pub struct AgentEventLayer;
+
+impl<S: Subscriber> Layer<S> for AgentEventLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let severity = match *event.metadata().level() {
+            Level::ERROR => "error",
+            Level::WARN => "warn",
+            _ => return,
+        };
+        let mut visitor = EventVisitor::default();
+        event.record(&mut visitor);
+        agent_events().push(AgentEvent {
+            timestamp: chrono::Utc::now().timestamp_millis(),
+            severity,
+            target: event.metadata().target().to_string(),
+            message: visitor.message,
+            server_id: visitor.server_id,
+        });
+    }
+}
+
+#[derive(Default)]
+struct EventVisitor {
+    message: String,
+    server_id: Option<String>,
+}
+
+impl Visit for EventVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "server_id" | "serverId" => self.server_id = Some(value.to_string()),
+            _ => self.record_debug(field, &value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use sha2::Sha256;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::{AgentError, AgentResult};
+
+pub const ALGORITHM: &str = "AES-256-GCM";
+pub const KDF: &str = "PBKDF2-HMAC-SHA256";
+const MAGIC: &[u8; 8] = b"CATBAK\x00\x01";
+/// Iterations are stored per archive, so raising this later keeps old backups readable.
+const PBKDF2_ITERATIONS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+/// Iteration counts accepted from an archive header, so a crafted file can't pin a core.
+const MIN_PBKDF2_ITERATIONS: u32 = 1_000;
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+const IV_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + SALT_LEN + IV_LEN;
+/// Plaintext bytes per sealed record; archives are streamed a record at a time.
+const RECORD_SIZE: usize = 1024 * 1024;
+/// Set on the length prefix of the last record so truncated files fail to decrypt.
+const FINAL_RECORD: u32 = 1 << 31;
+
+/// Where the archive key comes from. Passphrases arrive with each request and the node secret
+/// comes from `server.backup_secret`; neither is written to disk. Each archive's key is derived
+/// from the secret with PBKDF2 over its own salt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Node,
+    Passphrase,
+    /// Node key derived from the API key, used by archives written before `backup_secret`.
+    /// Only read, never written.
+    LegacyNode,
+}
+
+impl KeySource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            KeySource::Node => "node",
+            KeySource::Passphrase => "passphrase",
+            KeySource::LegacyNode => "node-legacy",
+        }
+    }
+}
+
+/// Parameters stored at the start of an encrypted archive.
+#[derive(Debug, Clone)]
+pub struct BackupHeader {
+    pub key_source: KeySource,
+    pub iterations: u32,
+    pub salt: [u8; SALT_LEN],
+    pub iv: [u8; IV_LEN],
+}
+
+impl BackupHeader {
+    fn generate(key_source: KeySource) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        let mut iv = [0u8; IV_LEN];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+        Self {
+            key_source,
+            iterations: PBKDF2_ITERATIONS,
+            salt,
+            iv,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(match self.key_source {
+            KeySource::LegacyNode => 0,
+            KeySource::Passphrase => 1,
+            KeySource::Node => 2,
+        });
+        bytes.extend_from_slice(&self.iterations.to_be_bytes());
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.iv);
+        bytes
+    }
+
+    fn parse(bytes: &[u8; HEADER_LEN]) -> Option<Self> {
+        if &bytes[..MAGIC.len()] != MAGIC {
+            return None;
+        }
+        let key_source = match bytes[MAGIC.len()] {
+            0 => KeySource::LegacyNode,
+            1 => KeySource::Passphrase,
+            2 => KeySource::Node,
+            _ => return None,
+        };
+        let rest = &bytes[MAGIC.len() + 1..];
+        let iterations = u32::from_be_bytes(rest[..4].try_into().ok()?);
+        if !(MIN_PBKDF2_ITERATIONS..=MAX_PBKDF2_ITERATIONS).contains(&iterations) {
+            return None;
+        }
+        let salt = rest[4..4 + SALT_LEN].try_into().ok()?;
+        let iv = rest[4 + SALT_LEN..].try_into().ok()?;
+        Some(Self {
+            key_source,
+            iterations,
+            salt,
+            iv,
+        })
+    }
+
+    /// Derive the archive key. PBKDF2 is deliberately slow, so this runs off the async workers.
+    async fn cipher(&self, secret: &str) -> AgentResult<Aes256Gcm> {
+        let secret = secret.to_string();
+        let salt = self.salt;
+        let iterations = self.iterations;
+        let key = tokio::task::spawn_blocking(move || {
+            let mut key = [0u8; 32];
+            pbkdf2::pbkdf2_hmac::<Sha256>(secret.as_bytes(), &salt, iterations, &mut key);
+            key
+        })
+        .await
+        .map_err(|e| AgentError::InternalError(format!("Key derivation failed: {}", e)))?;
+        Aes256Gcm::new_from_slice(&key)
+            .map_err(|e| AgentError::InternalError(format!("Invalid backup key: {}", e)))
+    }
+}
+
+/// Nonce and associated data for record `counter`: the base IV with the counter folded into
+/// its last eight bytes, and the counter plus final flag authenticated alongside the data.
+fn record_params(iv: &[u8; IV_LEN], counter: u64, last: bool) -> ([u8; IV_LEN], [u8; 9]) {
+    let mut nonce = *iv;
+    for (byte, count) in nonce[IV_LEN - 8..].iter_mut().zip(counter.to_be_bytes()) {
+        *byte ^= count;
+    }
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&counter.to_be_bytes());
+    aad[8] = last as u8;
+    (nonce, aad)
+}
+
+/// Encrypt everything `reader` yields into `writer`, returning the header that was written.
+pub async fn encrypt_stream<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    key_source: KeySource,
+    secret: &str,
+) -> AgentResult<BackupHeader>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let header = BackupHeader::generate(key_source);
+    let cipher = header.cipher(secret).await?;
+    writer.write_all(&header.to_bytes()).await?;
+
+    let mut buffer = vec![0u8; RECORD_SIZE];
+    let mut counter = 0u64;
+    loop {
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            let read = reader.read(&mut buffer[filled..]).await?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        // A short record ends the stream; an exact multiple ends with an empty one.
+        let last = filled < RECORD_SIZE;
+        let (nonce, aad) = record_params(&header.iv, counter, last);
+        let sealed = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &buffer[..filled],
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| AgentError::InternalError("Backup encryption failed".to_string()))?;
+        let mut prefix = sealed.len() as u32;
+        if last {
+            prefix |= FINAL_RECORD;
+        }
+        writer.write_all(&prefix.to_be_bytes()).await?;
+        writer.write_all(&sealed).await?;
+        if last {
+            break;
+        }
+        counter += 1;
+    }
+    writer.flush().await?;
+    Ok(header)
+}
+
+/// Read the encryption header of a backup file. Plain archives return `None` with the file
+/// rewound to the start.
+pub async fn read_header(file: &mut tokio::fs::File) -> AgentResult<Option<BackupHeader>> {
+    let mut bytes = [0u8; HEADER_LEN];
+    let mut filled = 0;
+    while filled < HEADER_LEN {
+        let read = file.read(&mut bytes[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    if filled >= MAGIC.len() && &bytes[..MAGIC.len()] == MAGIC {
+        return BackupHeader::parse(&bytes)
+            .map(Some)
+            .ok_or_else(|| AgentError::InvalidRequest("Corrupt backup encryption header".into()));
+    }
+    file.seek(io::SeekFrom::Start(0)).await?;
+    Ok(None)
+}
+
+/// Wraps the remainder of an encrypted backup (after its header) and yields the plaintext
+/// archive. Tampered, truncated or wrongly keyed data surfaces as an `InvalidData` read error.
+pub struct DecryptingReader<R> {
+    inner: R,
+    cipher: Aes256Gcm,
+    iv: [u8; IV_LEN],
+    counter: u64,
+    prefix: [u8; 4],
+    prefix_filled: usize,
+    record: Vec<u8>,
+    record_filled: usize,
+    plaintext: Vec<u8>,
+    position: usize,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> DecryptingReader<R> {
+    pub async fn new(inner: R, header: &BackupHeader, secret: &str) -> AgentResult<Self> {
+        Ok(Self {
+            inner,
+            cipher: header.cipher(secret).await?,
+            iv: header.iv,
+            counter: 0,
+            prefix: [0u8; 4],
+            prefix_filled: 0,
+            record: Vec::new(),
+            record_filled: 0,
+            plaintext: Vec::new(),
+            position: 0,
+            finished: false,
+        })
+    }
+
+    /// Fill `target` from the inner reader; `Ready(Ok(true))` once it is full.
+    fn poll_fill(
+        inner: &mut R,
+        cx: &mut Context<'_>,
+        target: &mut [u8],
+        filled: &mut usize,
+    ) -> Poll<io::Result<bool>> {
+        while *filled < target.len() {
+            let mut buf = ReadBuf::new(&mut target[*filled..]);
+            match Pin::new(&mut *inner).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => return Poll::Ready(Ok(false)),
+                Poll::Ready(Ok(())) => *filled += buf.filled().len(),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(true))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptingReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.plaintext.len() {
+                let count = buf.remaining().min(this.plaintext.len() - this.position);
+                buf.put_slice(&this.plaintext[this.position..this.position + count]);
+                this.position += count;
+                return Poll::Ready(Ok(()));
+            }
+            if this.finished {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.prefix_filled < this.prefix.len() {
+                let complete = match Self::poll_fill(
+                    &mut this.inner,
+                    cx,
+                    &mut this.prefix,
+                    &mut this.prefix_filled,
+                ) {
+                    Poll::Ready(Ok(complete)) => complete,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                };
+                if !complete {
+                    return Poll::Ready(Err(invalid("Encrypted backup is truncated")));
+                }
+                let length = (u32::from_be_bytes(this.prefix) & !FINAL_RECORD) as usize;
+                if !(TAG_LEN..=RECORD_SIZE + TAG_LEN).contains(&length) {
+                    return Poll::Ready(Err(invalid("Encrypted backup record is malformed")));
+                }
+                this.record = vec![0u8; length];
+                this.record_filled = 0;
+            }
+
+            let complete = match Self::poll_fill(
+                &mut this.inner,
+                cx,
+                &mut this.record,
+                &mut this.record_filled,
+            ) {
+                Poll::Ready(Ok(complete)) => complete,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            if !complete {
+                return Poll::Ready(Err(invalid("Encrypted backup is truncated")));
+            }
+
+            let last = u32::from_be_bytes(this.prefix) & FINAL_RECORD != 0;
+            let (nonce, aad) = record_params(&this.iv, this.counter, last);
+            this.plaintext = this
+                .cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &this.record,
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| {
+                    invalid("Backup decryption failed (wrong key or corrupted archive)")
+                })?;
+            this.position = 0;
+            this.prefix_filled = 0;
+            this.counter += 1;
+            this.finished = last;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn round_trips_and_rejects_wrong_key() {
+        let plaintext: Vec<u8> = (0..RECORD_SIZE * 2 + 17).map(|i| i as u8).collect();
+        let mut sealed = Vec::new();
+        let header = encrypt_stream(
+            &mut plaintext.as_slice(),
+            &mut sealed,
+            KeySource::Passphrase,
+            "hunter2",
+        )
+        .await
+        .unwrap();
+
+        let body = &sealed[HEADER_LEN..];
+        let mut reader = DecryptingReader::new(body, &header, "hunter2")
+            .await
+            .unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).await.unwrap();
+        assert_eq!(decrypted, plaintext);
+
+        let mut reader = DecryptingReader::new(body, &header, "wrong").await.unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+
+        let truncated = &body[..body.len() - 40];
+        let mut reader = DecryptingReader::new(truncated, &header, "hunter2")
+            .await
+            .unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+    }
+}
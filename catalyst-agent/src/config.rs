@@ -1,5 +1,7 @@
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AgentConfig {
@@ -7,6 +9,16 @@ pub struct AgentConfig {
     pub containerd: ContainerdConfig,
     #[serde(default)]
     pub networking: NetworkingConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub console: ConsoleConfig,
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    #[serde(default)]
+    pub installer: InstallerConfig,
     pub logging: LoggingConfig,
 }
 
@@ -17,7 +29,63 @@ pub struct ServerConfig {
     pub api_key: String,
     pub hostname: String,
     pub data_dir: PathBuf,
+    /// How per-server directories are arranged under `data_dir`.
+    #[serde(default)]
+    pub data_layout: DataLayout,
     pub max_connections: usize,
+    /// Shared secret for signing outgoing control messages with HMAC-SHA256, offered to the
+    /// backend in the handshake. Unset keeps messages unsigned.
+    #[serde(default)]
+    pub hmac_key: Option<String>,
+    /// Secret the node key for encrypted backups is derived from. Keep a copy apart from the
+    /// backups: losing it makes node-keyed backups unreadable. Unset refuses node-keyed
+    /// encryption.
+    #[serde(default)]
+    pub backup_secret: Option<String>,
+}
+
+impl ServerConfig {
+    /// The configured HMAC key, ignoring an empty value.
+    pub fn hmac_key(&self) -> Option<&str> {
+        self.hmac_key
+            .as_deref()
+            .filter(|key| !key.trim().is_empty())
+    }
+
+    /// The configured backup secret, ignoring an empty value.
+    pub fn backup_secret(&self) -> Option<&str> {
+        self.backup_secret
+            .as_deref()
+            .filter(|secret| !secret.trim().is_empty())
+    }
+}
+
+/// Arrangement of per-server directories under the data directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataLayout {
+    /// `{data_dir}/{uuid}`
+    #[default]
+    Flat,
+    /// `{data_dir}/{first two chars of uuid}/{uuid}`, keeping directories small on busy nodes.
+    Sharded,
+}
+
+impl DataLayout {
+    /// Directory for `server_uuid` under `base`. The uuid must already be a safe path segment.
+    pub fn server_dir(self, base: &Path, server_uuid: &str) -> PathBuf {
+        match self {
+            DataLayout::Flat => base.join(server_uuid),
+            DataLayout::Sharded => {
+                let shard = server_uuid
+                    .chars()
+                    .take(2)
+                    .collect::<String>()
+                    .to_ascii_lowercase();
+                base.join(shard).join(server_uuid)
+            }
+        }
+    }
 }
 
 impl std::fmt::Debug for ServerConfig {
@@ -28,7 +96,13 @@ impl std::fmt::Debug for ServerConfig {
             .field("api_key", &"[REDACTED]")
             .field("hostname", &self.hostname)
             .field("data_dir", &self.data_dir)
+            .field("data_layout", &self.data_layout)
             .field("max_connections", &self.max_connections)
+            .field("hmac_key", &self.hmac_key.as_ref().map(|_| "[REDACTED]"))
+            .field(
+                "backup_secret",
+                &self.backup_secret.as_ref().map(|_| "[REDACTED]"),
+            )
             .finish()
     }
 }
@@ -37,6 +111,59 @@ impl std::fmt::Debug for ServerConfig {
 pub struct ContainerdConfig {
     pub socket_path: PathBuf,
     pub namespace: String,
+    /// Pull images that are not already present. Disable on air-gapped nodes to fail fast.
+    #[serde(default = "default_true")]
+    pub allow_image_pull: bool,
+    /// What to do when containers named after both a server's id and its uuid exist.
+    #[serde(default)]
+    pub duplicate_containers: DuplicateContainerAction,
+    /// Runtime classes servers may select with `runtimeClass`, mapped to containerd runtime
+    /// names (e.g. `gvisor = "io.containerd.runsc.v1"`). Servers without one use runc.
+    #[serde(default)]
+    pub runtime_classes: HashMap<String, String>,
+    /// Seconds after a stop or kill during which exits of that server aren't reported as
+    /// crashes, covering exit events that race the stop.
+    #[serde(default = "default_stop_crash_grace_secs")]
+    pub stop_crash_grace_secs: u64,
+    /// What happens to running servers when the agent receives SIGTERM or SIGINT.
+    #[serde(default)]
+    pub on_shutdown: ShutdownAction,
+    /// After a node reboot, start the servers flagged with `set_autostart` that were running
+    /// before it, without waiting for the backend.
+    #[serde(default)]
+    pub autostart_after_reboot: bool,
+    /// Platform (`os/arch[/variant]`) to pull images for instead of the host's, for nodes
+    /// that run emulated workloads. Templates can override it with `platform`.
+    #[serde(default)]
+    pub image_platform: Option<String>,
+}
+
+fn default_stop_crash_grace_secs() -> u64 {
+    5
+}
+
+/// Servers' fate when the agent process is asked to exit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownAction {
+    /// Keep servers running; the agent re-adopts them when it comes back.
+    #[default]
+    Leave,
+    /// Gracefully stop every running server before exiting.
+    Stop,
+}
+
+/// Handling of a server that has both a serverId- and a serverUuid-named container.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateContainerAction {
+    /// Send `duplicate_container_detected` to the backend and keep both.
+    #[default]
+    Report,
+    /// Report, then remove the stopped container when the other one is running.
+    RemoveStopped,
+    /// Keep both without reporting; the serverId container is preferred.
+    Ignore,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -52,6 +179,24 @@ pub struct NetworkingConfig {
     /// DNS servers for containers. Defaults to Cloudflare (1.1.1.1) and Google (8.8.8.8) if not set.
     #[serde(default = "default_dns_servers")]
     pub dns_servers: Vec<String>,
+    /// How containers get their `/etc/resolv.conf`.
+    #[serde(default)]
+    pub dns_mode: DnsMode,
+    /// CNI data base directory: plugin results and port-forward state live in `results/`,
+    /// host-local IP allocations in `networks/`.
+    #[serde(default = "default_cni_data_dir")]
+    pub cni_data_dir: PathBuf,
+    /// Refuse to start when the iptables self-check fails. When false the agent starts anyway
+    /// and reports the missing capability to the backend in its handshake.
+    #[serde(default = "default_require_firewall")]
+    pub require_firewall: bool,
+    /// Total egress bandwidth for the node in Mbps, shared fairly between servers. Unset
+    /// leaves egress unshaped.
+    #[serde(default)]
+    pub egress_limit_mbps: Option<u64>,
+    /// Extra attempts at the CNI DEL when tearing down a container's network.
+    #[serde(default = "default_cni_teardown_retries")]
+    pub cni_teardown_retries: u32,
 }
 
 impl Default for NetworkingConfig {
@@ -59,14 +204,262 @@ impl Default for NetworkingConfig {
         Self {
             networks: Vec::new(),
             dns_servers: default_dns_servers(),
+            dns_mode: DnsMode::default(),
+            cni_data_dir: default_cni_data_dir(),
+            require_firewall: default_require_firewall(),
+            egress_limit_mbps: None,
+            cni_teardown_retries: default_cni_teardown_retries(),
         }
     }
 }
 
+/// Source of container DNS configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsMode {
+    /// Generate a resolv.conf from `dns_servers`.
+    #[default]
+    Override,
+    /// Bind-mount the host's resolv.conf read-only (host-local or split-horizon resolvers).
+    Inherit,
+    /// Leave whatever the image or CNI plugins provide.
+    Off,
+}
+
 fn default_dns_servers() -> Vec<String> {
     vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()]
 }
 
+fn default_cni_data_dir() -> PathBuf {
+    PathBuf::from("/var/lib/cni")
+}
+
+fn default_require_firewall() -> bool {
+    true
+}
+
+fn default_cni_teardown_retries() -> u32 {
+    3
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LimitsConfig {
+    /// Maximum number of servers hosted on this node. Unset means unlimited.
+    #[serde(default)]
+    pub max_servers: Option<usize>,
+    /// Maximum number of servers running at the same time. Unset means unlimited.
+    #[serde(default)]
+    pub max_running_servers: Option<usize>,
+    /// Backup create/restore/download and upload sessions allowed at once; extra requests queue.
+    #[serde(default = "default_max_concurrent_backups")]
+    pub max_concurrent_backups: usize,
+    /// `test_install` runs allowed at once. Each holds a tmpfs scratch mount in RAM, so
+    /// further requests are refused.
+    #[serde(default = "default_max_concurrent_test_installs")]
+    pub max_concurrent_test_installs: usize,
+    /// Size cap for metrics buffered on disk while the backend is unreachable; the oldest
+    /// samples are dropped once it is exceeded.
+    #[serde(default = "default_metrics_buffer_max_mb")]
+    pub metrics_buffer_max_mb: u64,
+    /// Free space (MB) on the data or backup filesystem below which installs, backups and
+    /// uploads are refused. 0 disables the guard.
+    #[serde(default = "default_low_disk_floor_mb")]
+    pub low_disk_floor_mb: u64,
+    /// Free space above the floor required before the guard lifts, so it doesn't flap.
+    #[serde(default = "default_low_disk_recovery_mb")]
+    pub low_disk_recovery_mb: u64,
+    /// Hours between automatic `fstrim` sweeps of mounted server storage images, returning
+    /// space freed inside them to the host. 0 disables the sweep.
+    #[serde(default = "default_fstrim_interval_hours")]
+    pub fstrim_interval_hours: u64,
+    /// Containers queried at once during state reconciliation and resource stats passes.
+    #[serde(default = "default_container_query_concurrency")]
+    pub container_query_concurrency: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_servers: None,
+            max_running_servers: None,
+            max_concurrent_backups: default_max_concurrent_backups(),
+            max_concurrent_test_installs: default_max_concurrent_test_installs(),
+            metrics_buffer_max_mb: default_metrics_buffer_max_mb(),
+            low_disk_floor_mb: default_low_disk_floor_mb(),
+            low_disk_recovery_mb: default_low_disk_recovery_mb(),
+            fstrim_interval_hours: default_fstrim_interval_hours(),
+            container_query_concurrency: default_container_query_concurrency(),
+        }
+    }
+}
+
+fn default_max_concurrent_backups() -> usize {
+    2
+}
+
+fn default_max_concurrent_test_installs() -> usize {
+    2
+}
+
+fn default_container_query_concurrency() -> usize {
+    8
+}
+
+fn default_metrics_buffer_max_mb() -> u64 {
+    64
+}
+
+fn default_low_disk_floor_mb() -> u64 {
+    2048
+}
+
+fn default_low_disk_recovery_mb() -> u64 {
+    1024
+}
+
+fn default_fstrim_interval_hours() -> u64 {
+    24
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// Minimum TLS version for wss:// backend connections ("1.2" or "1.3"). Unset uses the library default.
+    #[serde(default)]
+    pub min_version: Option<String>,
+    /// Allowed cipher suites by IANA name, e.g. "TLS13_AES_256_GCM_SHA384". Empty allows all.
+    #[serde(default)]
+    pub cipher_suites: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConsoleConfig {
+    /// Recent console lines kept in memory per server to answer history requests without disk reads.
+    #[serde(default = "default_history_lines")]
+    pub history_lines: usize,
+    /// Restart tailing from the top when a console output file is truncated or replaced,
+    /// instead of waiting for it to grow back past the old read position.
+    #[serde(default = "default_true")]
+    pub follow_rotation: bool,
+    /// Largest `console_input` payload written to a server's stdin; bigger ones are rejected
+    /// so a huge paste cannot block on the stdin FIFO. Console output that runs this long
+    /// without a newline is streamed as it is instead of being held back.
+    #[serde(default = "default_max_input_bytes")]
+    pub max_input_bytes: usize,
+    /// Most console log streams tailed at once across all servers; further streams are
+    /// refused with a warning instead of piling up file readers.
+    #[serde(default = "default_max_log_streams")]
+    pub max_log_streams: usize,
+    /// Size at which a container's stdout or stderr file is cut back to its most recent half
+    /// so chatty servers cannot fill `/tmp`. 0 leaves them unbounded.
+    #[serde(default = "default_max_log_bytes")]
+    pub max_log_bytes: u64,
+    /// How stdout and stderr output is ordered relative to each other on the live console.
+    #[serde(default)]
+    pub stream_order: ConsoleStreamOrder,
+}
+
+/// Ordering of a server's stdout and stderr output on the live console.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleStreamOrder {
+    /// Each stream is forwarded as it is read, stdout before stderr within a poll.
+    #[default]
+    Separate,
+    /// Both streams are polled more often. When both grew between polls, all new output of
+    /// the one whose last write is older goes first; lines within a poll are not interleaved.
+    Merged,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            history_lines: default_history_lines(),
+            follow_rotation: true,
+            max_input_bytes: default_max_input_bytes(),
+            max_log_streams: default_max_log_streams(),
+            max_log_bytes: default_max_log_bytes(),
+            stream_order: ConsoleStreamOrder::default(),
+        }
+    }
+}
+
+fn default_history_lines() -> usize {
+    1000
+}
+
+fn default_max_input_bytes() -> usize {
+    16 * 1024
+}
+
+fn default_max_log_streams() -> usize {
+    512
+}
+
+fn default_max_log_bytes() -> u64 {
+    64 * 1024 * 1024
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProtocolConfig {
+    /// Reply with `unknown_message` when the backend sends a type this agent does not handle.
+    #[serde(default = "default_true")]
+    pub reply_unknown_messages: bool,
+    /// Log unknown message types at warn level; when false they are logged at debug.
+    #[serde(default = "default_true")]
+    pub warn_unknown_messages: bool,
+    /// Longest a single message handler may run before it is abandoned and reported as a
+    /// `command_error`; 0 disables the limit. A template's `installTimeoutSecs` replaces it
+    /// for that template's installs.
+    #[serde(default = "default_handler_timeout_secs")]
+    pub handler_timeout_secs: u64,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            reply_unknown_messages: true,
+            warn_unknown_messages: true,
+            handler_timeout_secs: default_handler_timeout_secs(),
+        }
+    }
+}
+
+fn default_handler_timeout_secs() -> u64 {
+    3600
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstallerConfig {
+    /// Installer limits are the server's allocation times this factor; installs often
+    /// compile or unpack and legitimately need more than the runtime.
+    #[serde(default = "default_installer_multiplier")]
+    pub resource_multiplier: f64,
+    /// Upper bound on installer memory regardless of allocation. Unset means no cap.
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+    /// Upper bound on installer CPU cores regardless of allocation. Unset means no cap.
+    #[serde(default)]
+    pub max_cpu_cores: Option<u64>,
+}
+
+impl Default for InstallerConfig {
+    fn default() -> Self {
+        Self {
+            resource_multiplier: default_installer_multiplier(),
+            max_memory_mb: None,
+            max_cpu_cores: None,
+        }
+    }
+}
+
+fn default_installer_multiplier() -> f64 {
+    2.0
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CniNetworkConfig {
     pub name: String,
@@ -75,6 +468,9 @@ pub struct CniNetworkConfig {
     pub gateway: Option<String>,
     pub range_start: Option<String>,
     pub range_end: Option<String>,
+    /// Interface MTU for containers on this network. Unset inherits the master interface MTU.
+    #[serde(default)]
+    pub mtu: Option<u32>,
 }
 
 impl AgentConfig {
@@ -86,6 +482,8 @@ impl AgentConfig {
         if config.server.api_key.trim().is_empty() {
             return Err("server.api_key must be set".to_string());
         }
+        let mut config = config;
+        config.server.backend_url = parse_backend_url(&config.server.backend_url)?.to_string();
         Ok(config)
     }
 
@@ -101,7 +499,13 @@ impl AgentConfig {
                 data_dir: PathBuf::from(
                     std::env::var("DATA_DIR").unwrap_or_else(|_| "/var/lib/catalyst".to_string()),
                 ),
+                data_layout: match std::env::var("DATA_LAYOUT").as_deref() {
+                    Ok("sharded") => DataLayout::Sharded,
+                    _ => DataLayout::Flat,
+                },
                 max_connections: 100,
+                hmac_key: std::env::var("HMAC_KEY").ok(),
+                backup_secret: std::env::var("BACKUP_SECRET").ok(),
             },
             containerd: ContainerdConfig {
                 socket_path: PathBuf::from(
@@ -110,8 +514,27 @@ impl AgentConfig {
                 ),
                 namespace: std::env::var("CONTAINERD_NAMESPACE")
                     .unwrap_or_else(|_| "catalyst".to_string()),
+                allow_image_pull: std::env::var("ALLOW_IMAGE_PULL")
+                    .map(|v| v != "false" && v != "0")
+                    .unwrap_or(true),
+                duplicate_containers: DuplicateContainerAction::default(),
+                runtime_classes: HashMap::new(),
+                stop_crash_grace_secs: default_stop_crash_grace_secs(),
+                on_shutdown: match std::env::var("ON_SHUTDOWN").as_deref() {
+                    Ok("stop") => ShutdownAction::Stop,
+                    _ => ShutdownAction::Leave,
+                },
+                autostart_after_reboot: std::env::var("AUTOSTART_AFTER_REBOOT")
+                    .map(|v| v == "true" || v == "1")
+                    .unwrap_or(false),
+                image_platform: std::env::var("IMAGE_PLATFORM").ok(),
             },
             networking: NetworkingConfig::default(),
+            limits: LimitsConfig::default(),
+            tls: TlsConfig::default(),
+            console: ConsoleConfig::default(),
+            protocol: ProtocolConfig::default(),
+            installer: InstallerConfig::default(),
             logging: LoggingConfig {
                 level: std::env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string()),
                 format: "json".to_string(),
@@ -120,10 +543,76 @@ impl AgentConfig {
         if config.server.api_key.trim().is_empty() {
             return Err("NODE_API_KEY must not be empty".to_string());
         }
+        let mut config = config;
+        config.server.backend_url = parse_backend_url(&config.server.backend_url)?.to_string();
         Ok(config)
     }
 }
 
+/// Parse `server.backend_url`, explaining the usual mistakes (HTTP schemes, a missing agent
+/// path, stray whitespace) instead of failing later with an opaque connection error.
+pub fn parse_backend_url(raw: &str) -> Result<Url, String> {
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err("server.backend_url must be set, e.g. wss://panel.example.com/ws".to_string());
+    }
+    if trimmed.contains(char::is_whitespace) {
+        return Err(format!(
+            "server.backend_url '{}' must not contain spaces",
+            trimmed
+        ));
+    }
+    if !trimmed.contains("://") {
+        return Err(format!(
+            "server.backend_url '{}' has no scheme; use ws://host:port/ws or wss://host/ws",
+            trimmed
+        ));
+    }
+    let url = Url::parse(trimmed)
+        .map_err(|e| format!("Invalid server.backend_url '{}': {}", trimmed, e))?;
+    match url.scheme() {
+        "ws" | "wss" => {}
+        "https" => {
+            return Err(format!(
+                "server.backend_url '{}' uses https://; use wss:// for the agent connection",
+                trimmed
+            ));
+        }
+        "http" => {
+            return Err(format!(
+                "server.backend_url '{}' uses http://; use ws:// (or wss:// behind TLS) for the agent connection",
+                trimmed
+            ));
+        }
+        other => {
+            return Err(format!(
+                "Invalid server.backend_url scheme '{}': expected ws:// or wss://",
+                other
+            ));
+        }
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!(
+            "server.backend_url '{}' must include the backend host",
+            trimmed
+        ));
+    }
+    if url.path().trim_matches('/').is_empty() {
+        return Err(format!(
+            "server.backend_url '{}' must include the agent path, e.g. {}/ws",
+            trimmed,
+            trimmed.trim_end_matches('/')
+        ));
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        return Err(format!(
+            "server.backend_url '{}' must not include a query string or fragment",
+            trimmed
+        ));
+    }
+    Ok(url)
+}
+
 fn hostname() -> Result<String, std::io::Error> {
     std::process::Command::new("hostname")
         .output()
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::{AgentError, AgentResult};
+
+/// mangle chain holding the per-container CLASSIFY rules for routed (bridge) traffic.
+const EGRESS_CHAIN: &str = "CATALYST-EGRESS";
+/// Class for traffic not attributed to a server container (host, host-network servers).
+/// tc parses class minors as hex.
+const DEFAULT_CLASS_MINOR: u16 = 0xfffe;
+const FIRST_CONTAINER_MINOR: u16 = 2;
+/// Guaranteed rate for each class; equal guarantees make HTB share spare bandwidth evenly.
+const CLASS_RATE: &str = "1mbit";
+
+/// Node-wide egress cap: an HTB root qdisc on the uplink whose root class is capped, with one
+/// class per server container nested under it so servers share the cap fairly.
+///
+/// Macvlan traffic is classified by source address with a u32 filter. Bridged traffic is
+/// masqueraded before it reaches the qdisc, so it is classified in mangle POSTROUTING
+/// (which runs before NAT) with the CLASSIFY target instead.
+pub struct EgressLimiter {
+    interface: String,
+    limit_mbps: u64,
+    /// container id -> (class minor, container IP)
+    classes: Mutex<HashMap<String, (u16, String)>>,
+    last_sample: Mutex<Option<(Instant, u64)>>,
+}
+
+impl EgressLimiter {
+    /// Replace whatever root qdisc `interface` has with the capped HTB hierarchy.
+    pub async fn install(interface: &str, limit_mbps: u64) -> AgentResult<Self> {
+        if limit_mbps == 0 {
+            return Err(AgentError::ConfigError(
+                "networking.egress_limit_mbps must be greater than 0".to_string(),
+            ));
+        }
+        let ceil = format!("{}mbit", limit_mbps);
+        let default_class = format!("1:{:x}", DEFAULT_CLASS_MINOR);
+        // Start from a clean slate; classes from a previous run are restored separately.
+        let _ = tc(&["qdisc", "del", "dev", interface, "root"]).await;
+        tc(&[
+            "qdisc",
+            "add",
+            "dev",
+            interface,
+            "root",
+            "handle",
+            "1:",
+            "htb",
+            "default",
+            &format!("{:x}", DEFAULT_CLASS_MINOR),
+        ])
+        .await?;
+        tc(&[
+            "class", "add", "dev", interface, "parent", "1:", "classid", "1:1", "htb", "rate",
+            &ceil, "ceil", &ceil,
+        ])
+        .await?;
+        tc(&[
+            "class",
+            "add",
+            "dev",
+            interface,
+            "parent",
+            "1:1",
+            "classid",
+            &default_class,
+            "htb",
+            "rate",
+            CLASS_RATE,
+            "ceil",
+            &ceil,
+        ])
+        .await?;
+
+        if iptables(&["-t", "mangle", "-N", EGRESS_CHAIN])
+            .await
+            .is_err()
+        {
+            iptables(&["-t", "mangle", "-F", EGRESS_CHAIN]).await?;
+        }
+        let jump = ["-o", interface, "-j", EGRESS_CHAIN];
+        let check: Vec<&str> = ["-t", "mangle", "-C", "POSTROUTING"]
+            .into_iter()
+            .chain(jump)
+            .collect();
+        if iptables(&check).await.is_err() {
+            let append: Vec<&str> = ["-t", "mangle", "-A", "POSTROUTING"]
+                .into_iter()
+                .chain(jump)
+                .collect();
+            iptables(&append).await?;
+        }
+
+        info!("Node egress capped at {} Mbps on {}", limit_mbps, interface);
+        Ok(Self {
+            interface: interface.to_string(),
+            limit_mbps,
+            classes: Mutex::new(HashMap::new()),
+            last_sample: Mutex::new(None),
+        })
+    }
+
+    /// Remove a cap left by an earlier run on `interface`, now that no limit is configured:
+    /// the HTB root qdisc this limiter installs and its mangle chain with the jumps into it.
+    pub async fn remove_leftover(interface: &str) {
+        let qdisc = output("tc", &["qdisc", "show", "dev", interface, "root"]).await;
+        let signature = "htb 1: root";
+        // Printed as `default fffe` or `default 0xfffe` depending on the iproute2 version.
+        let default = format!("{:x}", DEFAULT_CLASS_MINOR);
+        if qdisc
+            .as_deref()
+            .is_some_and(|q| q.contains(signature) && q.contains(&default))
+        {
+            match tc(&["qdisc", "del", "dev", interface, "root"]).await {
+                Ok(()) => info!("Removed the egress cap left on {}", interface),
+                Err(e) => warn!(
+                    "Failed to remove the egress cap left on {}: {}",
+                    interface, e
+                ),
+            }
+        }
+
+        let Some(rules) = output("iptables", &["-w", "-t", "mangle", "-S", "POSTROUTING"]).await
+        else {
+            return;
+        };
+        let target = format!("-j {}", EGRESS_CHAIN);
+        for rule in rules.lines().filter(|rule| rule.ends_with(&target)) {
+            let Some(spec) = rule.strip_prefix("-A POSTROUTING ") else {
+                continue;
+            };
+            let delete: Vec<&str> = ["-t", "mangle", "-D", "POSTROUTING"]
+                .into_iter()
+                .chain(spec.split_whitespace())
+                .collect();
+            if let Err(e) = iptables(&delete).await {
+                warn!("Failed to remove egress rule '{}': {}", rule, e);
+            }
+        }
+        if iptables(&["-t", "mangle", "-F", EGRESS_CHAIN])
+            .await
+            .is_ok()
+        {
+            if let Err(e) = iptables(&["-t", "mangle", "-X", EGRESS_CHAIN]).await {
+                warn!("Failed to remove the {} chain: {}", EGRESS_CHAIN, e);
+            }
+        }
+    }
+
+    /// Give a container its own class under the cap. Re-adding a known container is a no-op.
+    pub async fn add_container(&self, container_id: &str, ip: &str) -> AgentResult<()> {
+        let minor = {
+            let mut classes = self.classes.lock().unwrap_or_else(|e| e.into_inner());
+            if classes.contains_key(container_id) {
+                return Ok(());
+            }
+            let used: Vec<u16> = classes.values().map(|(minor, _)| *minor).collect();
+            let minor = (FIRST_CONTAINER_MINOR..DEFAULT_CLASS_MINOR)
+                .find(|m| !used.contains(m))
+                .ok_or_else(|| AgentError::InternalError("No free egress classes".to_string()))?;
+            classes.insert(container_id.to_string(), (minor, ip.to_string()));
+            minor
+        };
+        let result = self.install_class(minor, ip).await;
+        if result.is_err() {
+            self.remove_container(container_id).await;
+        }
+        result
+    }
+
+    async fn install_class(&self, minor: u16, ip: &str) -> AgentResult<()> {
+        let classid = format!("1:{:x}", minor);
+        let ceil = format!("{}mbit", self.limit_mbps);
+        let source = format!("{}/32", ip);
+        let prio = minor.to_string();
+        let dev = self.interface.as_str();
+        tc(&[
+            "class", "replace", "dev", dev, "parent", "1:1", "classid", &classid, "htb", "rate",
+            CLASS_RATE, "ceil", &ceil,
+        ])
+        .await?;
+        tc(&[
+            "filter", "replace", "dev", dev, "parent", "1:", "protocol", "ip", "prio", &prio,
+            "u32", "match", "ip", "src", &source, "flowid", &classid,
+        ])
+        .await?;
+        iptables(&[
+            "-t",
+            "mangle",
+            "-A",
+            EGRESS_CHAIN,
+            "-s",
+            &source,
+            "-j",
+            "CLASSIFY",
+            "--set-class",
+            &classid,
+        ])
+        .await
+    }
+
+    /// Drop a container's class and classifiers. Unknown containers are ignored.
+    pub async fn remove_container(&self, container_id: &str) {
+        let Some((minor, ip)) = self
+            .classes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(container_id)
+        else {
+            return;
+        };
+        let classid = format!("1:{:x}", minor);
+        let source = format!("{}/32", ip);
+        let dev = self.interface.as_str();
+        let _ = iptables(&[
+            "-t",
+            "mangle",
+            "-D",
+            EGRESS_CHAIN,
+            "-s",
+            &source,
+            "-j",
+            "CLASSIFY",
+            "--set-class",
+            &classid,
+        ])
+        .await;
+        let _ = tc(&[
+            "filter",
+            "del",
+            "dev",
+            dev,
+            "parent",
+            "1:",
+            "prio",
+            &minor.to_string(),
+        ])
+        .await;
+        if let Err(e) = tc(&["class", "del", "dev", dev, "classid", &classid]).await {
+            warn!("Failed to remove egress class for {}: {}", container_id, e);
+        }
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub fn limit_mbps(&self) -> u64 {
+        self.limit_mbps
+    }
+
+    /// Uplink transmit rate since the previous call, in Mbps. `None` on the first call.
+    pub fn sample_throughput_mbps(&self) -> Option<f64> {
+        let tx_bytes: u64 = std::fs::read_to_string(format!(
+            "/sys/class/net/{}/statistics/tx_bytes",
+            self.interface
+        ))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+        let now = Instant::now();
+        let previous = self
+            .last_sample
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace((now, tx_bytes));
+        let (then, previous_bytes) = previous?;
+        let secs = now.duration_since(then).as_secs_f64();
+        if secs <= 0.0 {
+            return None;
+        }
+        let bits = tx_bytes.saturating_sub(previous_bytes) as f64 * 8.0;
+        Some((bits / secs / 1_000_000.0 * 100.0).round() / 100.0)
+    }
+}
+
+async fn tc(args: &[&str]) -> AgentResult<()> {
+    run("tc", args).await
+}
+
+async fn iptables(args: &[&str]) -> AgentResult<()> {
+    let mut with_wait = vec!["-w"];
+    with_wait.extend_from_slice(args);
+    run("iptables", &with_wait).await
+}
+
+/// Stdout of a successful run, `None` if it couldn't be run or failed.
+async fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().await.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+async fn run(program: &str, args: &[&str]) -> AgentResult<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| AgentError::FirewallError(format!("Failed to run {}: {}", program, e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(AgentError::FirewallError(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
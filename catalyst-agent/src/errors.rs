@@ -10,6 +10,9 @@ pub enum AgentError {
     #[error("Network error: {0}")]
     NetworkError(String),
 
+    #[error("Handshake rejected: {0}")]
+    HandshakeRejected(String),
+
     #[error("Container error: {0}")]
     ContainerError(String),
 
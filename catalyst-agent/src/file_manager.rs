@@ -1,19 +1,38 @@
+use std::collections::HashMap;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use tokio::fs;
 use tracing::{debug, info, warn};
 
+use crate::config::DataLayout;
 use crate::{AgentError, AgentResult};
 
 const MAX_FILE_SIZE: u64 = 100 * 1024 * 1024; // 100MB
 
+// Server containers run as 1000:1000; entries created on their behalf must match.
+const RUNTIME_UID: u32 = 1000;
+const RUNTIME_GID: u32 = 1000;
+
 pub struct FileManager {
     data_dir: PathBuf,
+    layout: DataLayout,
+    /// Per-server locks serializing appends, so the size check and the write of one append
+    /// can't interleave with another's.
+    append_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 impl FileManager {
-    pub fn new(data_dir: PathBuf) -> Self {
-        Self { data_dir }
+    pub fn new(data_dir: PathBuf, layout: DataLayout) -> Self {
+        Self {
+            data_dir,
+            layout,
+            append_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn server_dir(&self, server_id: &str) -> PathBuf {
+        self.layout.server_dir(&self.data_dir, server_id)
     }
 
     /// Validate and resolve a path within the container's data directory
@@ -21,7 +40,7 @@ impl FileManager {
         if server_id.contains('/') || server_id.contains('\\') {
             return Err(AgentError::InvalidRequest("Invalid server id".to_string()));
         }
-        let server_base = self.data_dir.join(server_id);
+        let server_base = self.server_dir(server_id);
         let requested = PathBuf::from(requested_path);
 
         // Prevent directory traversal before resolving.
@@ -84,6 +103,16 @@ impl FileManager {
         Ok(canonical_base.join(relative))
     }
 
+    /// Resolve a path that must name an existing regular file. Used by file tails, which
+    /// re-resolve on every read so a swapped symlink can't escape the data directory.
+    pub fn resolve_existing_file(&self, server_id: &str, path: &str) -> AgentResult<PathBuf> {
+        let full_path = self.resolve_path(server_id, path)?;
+        if !full_path.is_file() {
+            return Err(AgentError::NotFound(format!("Not a file: {}", path)));
+        }
+        Ok(full_path)
+    }
+
     /// Resolve a path and ensure its parent directory exists. Used by install-url.
     pub async fn resolve_and_ensure_parent(
         &self,
@@ -160,6 +189,84 @@ impl FileManager {
         Ok(())
     }
 
+    /// Append `data` to a file, creating it if missing. The file is opened with `O_APPEND`
+    /// and written in one call, so concurrent appends (ours or the server's) never interleave
+    /// or overwrite each other. The size limit is checked on that same handle, and our own
+    /// appends to a server are serialized so together they can't exceed it.
+    pub async fn append_file(&self, server_id: &str, path: &str, data: &[u8]) -> AgentResult<()> {
+        let full_path = self.resolve_path(server_id, path)?;
+        debug!("Appending {} bytes to {:?}", data.len(), full_path);
+        // Rejected before opening so an oversized first append doesn't leave an empty file.
+        if data.len() as u64 > MAX_FILE_SIZE {
+            return Err(AgentError::FileSystemError(format!(
+                "File too large: {} > {}MB",
+                data.len(),
+                MAX_FILE_SIZE / 1024 / 1024
+            )));
+        }
+
+        let lock = self
+            .append_locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(server_id.to_string())
+            .or_default()
+            .clone();
+        let _append_lock = lock.lock().await;
+
+        let target = full_path.clone();
+        let display_path = path.to_string();
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || -> AgentResult<()> {
+            use std::io::Write;
+            let open = |create_new: bool| {
+                std::fs::OpenOptions::new()
+                    .append(true)
+                    .create_new(create_new)
+                    .open(&target)
+            };
+            let (file, created) = match open(true) {
+                Ok(file) => (Ok(file), true),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (open(false), false),
+                Err(e) => (Err(e), false),
+            };
+            let mut file = file.map_err(|e| {
+                if e.raw_os_error() == Some(libc::EISDIR) {
+                    AgentError::InvalidRequest(format!(
+                        "Cannot append to a directory: {}",
+                        display_path
+                    ))
+                } else {
+                    AgentError::FileSystemError(format!("Failed to open file: {}", e))
+                }
+            })?;
+            if created {
+                std::os::unix::fs::chown(&target, Some(RUNTIME_UID), Some(RUNTIME_GID)).map_err(
+                    |e| AgentError::FileSystemError(format!("Failed to set owner: {}", e)),
+                )?;
+            }
+            let existing = file
+                .metadata()
+                .map_err(|e| AgentError::FileSystemError(format!("Failed to stat file: {}", e)))?
+                .len();
+            let total = existing + data.len() as u64;
+            if total > MAX_FILE_SIZE {
+                return Err(AgentError::FileSystemError(format!(
+                    "File too large: {} > {}MB",
+                    total,
+                    MAX_FILE_SIZE / 1024 / 1024
+                )));
+            }
+            file.write_all(&data)
+                .map_err(|e| AgentError::FileSystemError(format!("Failed to append: {}", e)))
+        })
+        .await
+        .map_err(|e| AgentError::FileSystemError(format!("Append task failed: {}", e)))??;
+
+        info!("Appended to {:?}", full_path);
+        Ok(())
+    }
+
     pub async fn delete_file(&self, server_id: &str, path: &str) -> AgentResult<()> {
         let full_path = self.resolve_path(server_id, path)?;
 
@@ -180,6 +287,37 @@ impl FileManager {
         Ok(())
     }
 
+    /// Empty a server's data directory, keeping the directory itself since it may be a mount
+    /// point. Symlinks are unlinked, never followed. Returns how many entries were removed.
+    pub async fn clear_server_dir(&self, server_id: &str) -> AgentResult<usize> {
+        if server_id.contains('/') || server_id.contains('\\') {
+            return Err(AgentError::InvalidRequest("Invalid server id".to_string()));
+        }
+        let server_base = self.server_dir(server_id);
+        let mut entries = fs::read_dir(&server_base)
+            .await
+            .map_err(|e| AgentError::FileSystemError(format!("Cannot read server dir: {}", e)))?;
+        let mut removed = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            // lost+found belongs to the filesystem of a mounted storage image.
+            if entry.file_name() == "lost+found" {
+                continue;
+            }
+            let path = entry.path();
+            let result = if entry.file_type().await?.is_dir() {
+                fs::remove_dir_all(&path).await
+            } else {
+                fs::remove_file(&path).await
+            };
+            result.map_err(|e| {
+                AgentError::FileSystemError(format!("Failed to delete {:?}: {}", path, e))
+            })?;
+            removed += 1;
+        }
+        info!("Cleared {} entries from {:?}", removed, server_base);
+        Ok(removed)
+    }
+
     pub async fn rename_file(&self, server_id: &str, from: &str, to: &str) -> AgentResult<()> {
         let from_path = self.resolve_path(server_id, from)?;
         let to_path = self.resolve_path(server_id, to)?;
@@ -201,6 +339,71 @@ impl FileManager {
         Ok(())
     }
 
+    /// Create a directory, optionally including any missing parents.
+    /// Newly created directories are owned by the runtime user.
+    pub async fn create_dir(
+        &self,
+        server_id: &str,
+        path: &str,
+        recursive: bool,
+    ) -> AgentResult<()> {
+        let full_path = self.resolve_path(server_id, path)?;
+        let canonical_base = self
+            .server_dir(server_id)
+            .canonicalize()
+            .map_err(|_| AgentError::PermissionDenied("Server directory missing".to_string()))?;
+
+        debug!(
+            "Creating directory: {:?} (recursive={})",
+            full_path, recursive
+        );
+
+        if full_path.exists() {
+            return Err(AgentError::FileSystemError(format!(
+                "Path already exists: {}",
+                path
+            )));
+        }
+
+        // Collect the components that will be created and make sure the deepest existing
+        // ancestor is still inside the server directory (guards against symlinked parents).
+        let mut created = Vec::new();
+        let mut ancestor = full_path.as_path();
+        while !ancestor.exists() {
+            created.push(ancestor.to_path_buf());
+            ancestor = ancestor
+                .parent()
+                .ok_or_else(|| AgentError::InvalidRequest("Invalid path".to_string()))?;
+        }
+        let ancestor_canon = ancestor.canonicalize().map_err(|_| {
+            AgentError::PermissionDenied("Path traversal attempt detected".to_string())
+        })?;
+        if !ancestor_canon.starts_with(&canonical_base) {
+            return Err(AgentError::PermissionDenied(
+                "Access denied: path outside data directory".to_string(),
+            ));
+        }
+        if !recursive && created.len() > 1 {
+            return Err(AgentError::FileSystemError(format!(
+                "Parent directory does not exist: {}",
+                path
+            )));
+        }
+
+        fs::create_dir_all(&full_path)
+            .await
+            .map_err(|e| AgentError::FileSystemError(format!("Failed to create dir: {}", e)))?;
+
+        for dir in created.iter().rev() {
+            std::os::unix::fs::chown(dir, Some(RUNTIME_UID), Some(RUNTIME_GID)).map_err(|e| {
+                AgentError::FileSystemError(format!("Failed to set directory owner: {}", e))
+            })?;
+        }
+
+        info!("Directory created: {:?}", full_path);
+        Ok(())
+    }
+
     pub async fn list_dir(&self, server_id: &str, path: &str) -> AgentResult<Vec<FileEntry>> {
         let full_path = self.resolve_path(server_id, path)?;
 
@@ -358,7 +561,7 @@ impl FileManager {
         source_paths: &[String],
     ) -> AgentResult<()> {
         let archive_full = self.resolve_path(server_id, archive_path)?;
-        let server_base = self.data_dir.join(server_id);
+        let server_base = self.server_dir(server_id);
         let canonical_base = server_base
             .canonicalize()
             .map_err(|_| AgentError::PermissionDenied("Server directory missing".to_string()))?;
@@ -501,7 +704,7 @@ impl FileManager {
         extract_dir: &std::path::Path,
         server_id: &str,
     ) -> AgentResult<()> {
-        let server_base = self.data_dir.join(server_id);
+        let server_base = self.server_dir(server_id);
         let canonical_base = server_base.canonicalize().map_err(|e| {
             AgentError::FileSystemError(format!("Cannot resolve server dir: {}", e))
         })?;
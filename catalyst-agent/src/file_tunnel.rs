@@ -1,4 +1,6 @@
+use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWriteExt;
@@ -21,6 +23,10 @@ const RETRY_DELAY: Duration = Duration::from_secs(2);
 const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 const MAX_INSTALL_URL_BYTES: u64 = 100 * 1024 * 1024; // 100MB cap to prevent memory/disk exhaustion
 const MAX_INSTALL_URL_REDIRECTS: usize = 10;
+/// Operations still allowed while a server's files are read-only.
+const READ_ONLY_OPERATIONS: &[&str] = &["list", "download", "archive-contents"];
+/// Operations refused while the node is below its free-space floor.
+const DISK_GROWING_OPERATIONS: &[&str] = &["upload", "install-url", "decompress"];
 
 #[derive(Debug, Deserialize)]
 struct TunnelRequest {
@@ -56,6 +62,8 @@ pub struct FileTunnelClient {
     config: Arc<AgentConfig>,
     file_manager: Arc<FileManager>,
     backend_connected: Arc<RwLock<bool>>,
+    read_only_servers: Arc<RwLock<HashSet<String>>>,
+    low_disk: Arc<AtomicBool>,
     client: Client,
     base_url: String,
     request_semaphore: Arc<Semaphore>,
@@ -66,6 +74,8 @@ impl FileTunnelClient {
         config: Arc<AgentConfig>,
         file_manager: Arc<FileManager>,
         backend_connected: Arc<RwLock<bool>>,
+        read_only_servers: Arc<RwLock<HashSet<String>>>,
+        low_disk: Arc<AtomicBool>,
     ) -> Self {
         let client = Client::builder()
             .pool_max_idle_per_host(POLL_CONCURRENCY + 2)
@@ -90,6 +100,8 @@ impl FileTunnelClient {
             config,
             file_manager,
             backend_connected,
+            read_only_servers,
+            low_disk,
             client,
             base_url,
             request_semaphore,
@@ -116,6 +128,8 @@ impl FileTunnelClient {
             let api_key = self.config.server.api_key.clone();
             let file_manager = self.file_manager.clone();
             let backend_connected = self.backend_connected.clone();
+            let read_only_servers = self.read_only_servers.clone();
+            let low_disk = self.low_disk.clone();
             let request_semaphore = self.request_semaphore.clone();
 
             handles.push(tokio::spawn(async move {
@@ -127,6 +141,8 @@ impl FileTunnelClient {
                     api_key,
                     file_manager,
                     backend_connected,
+                    read_only_servers,
+                    low_disk,
                     request_semaphore,
                 )
                 .await;
@@ -151,6 +167,8 @@ async fn poll_worker(
     api_key: String,
     file_manager: Arc<FileManager>,
     backend_connected: Arc<RwLock<bool>>,
+    read_only_servers: Arc<RwLock<HashSet<String>>>,
+    low_disk: Arc<AtomicBool>,
     request_semaphore: Arc<Semaphore>,
 ) {
     let poll_url = format!("{}/api/internal/file-tunnel/poll", base_url);
@@ -189,14 +207,19 @@ async fn poll_worker(
                             let node_id = node_id.clone();
                             let api_key = api_key.clone();
                             let fm = file_manager.clone();
+                            let read_only = read_only_servers.clone();
+                            let low_disk = low_disk.clone();
                             let semaphore = request_semaphore.clone();
 
                             // Process each request concurrently, limited by semaphore
                             tokio::spawn(async move {
                                 // Acquire permit before processing to limit concurrency
                                 let _permit = semaphore.acquire().await.unwrap();
-                                process_request(client, base_url, node_id, api_key, fm, request)
-                                    .await;
+                                process_request(
+                                    client, base_url, node_id, api_key, fm, read_only, low_disk,
+                                    request,
+                                )
+                                .await;
                             });
                         }
                     }
@@ -218,12 +241,15 @@ async fn poll_worker(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_request(
     client: Client,
     base_url: String,
     node_id: String,
     api_key: String,
     file_manager: Arc<FileManager>,
+    read_only_servers: Arc<RwLock<HashSet<String>>>,
+    low_disk: Arc<AtomicBool>,
     request: TunnelRequest,
 ) {
     // Reduced logging - don't log full path in debug
@@ -241,6 +267,41 @@ async fn process_request(
         request_id: &request.request_id,
     };
 
+    if !READ_ONLY_OPERATIONS.contains(&request.operation.as_str())
+        && read_only_servers
+            .read()
+            .await
+            .contains(&request.server_uuid)
+    {
+        send_json_response(
+            &ctx,
+            false,
+            None,
+            Some(format!(
+                "Server files are read-only; {} is not allowed",
+                request.operation
+            )),
+        )
+        .await;
+        return;
+    }
+
+    if DISK_GROWING_OPERATIONS.contains(&request.operation.as_str())
+        && low_disk.load(Ordering::Relaxed)
+    {
+        send_json_response(
+            &ctx,
+            false,
+            None,
+            Some(format!(
+                "low_disk: node is low on disk space; {} is not allowed",
+                request.operation
+            )),
+        )
+        .await;
+        return;
+    }
+
     match request.operation.as_str() {
         "list" => handle_list(&ctx, &file_manager, &request).await,
         "download" => handle_download(&ctx, &file_manager, &request).await,
@@ -1,13 +1,25 @@
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{error, info, warn};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
+use config::ShutdownAction;
+use runtime_manager::ImagePlatform;
+
+mod agent_events;
+mod backup_crypto;
 mod config;
+mod egress_limiter;
 mod errors;
 mod file_manager;
 mod file_tunnel;
 mod firewall_manager;
+mod message_signing;
 mod network_manager;
+mod rcon;
 mod runtime_manager;
 mod storage_manager;
 mod system_setup;
@@ -40,23 +52,59 @@ impl CatalystAgent {
         info!("Initializing Catalyst Agent");
 
         let config = Arc::new(config);
+        let image_platform = config
+            .containerd
+            .image_platform
+            .as_deref()
+            .map(ImagePlatform::parse)
+            .transpose()
+            .map_err(|e| AgentError::ConfigError(format!("containerd.image_platform: {}", e)))?;
         let runtime = Arc::new(
             ContainerdRuntime::new(
                 config.containerd.socket_path.clone(),
                 config.containerd.namespace.clone(),
                 config.networking.dns_servers.clone(),
+                config.networking.dns_mode,
+                config.containerd.allow_image_pull,
+                config.networking.cni_data_dir.clone(),
+                config.networking.egress_limit_mbps,
+                config.networking.cni_teardown_retries,
+                image_platform,
+                config.console.max_log_bytes,
+                config.console.max_input_bytes,
             )
             .await?,
         );
 
-        // FileManager uses the same base data_dir as storage - servers are stored at {data_dir}/{server_uuid}
-        let file_manager = Arc::new(FileManager::new(config.server.data_dir.clone()));
-        let storage_manager = Arc::new(StorageManager::new(config.server.data_dir.clone()));
+        // FileManager uses the same base data_dir and layout as storage so both resolve the same server directories
+        let file_manager = Arc::new(FileManager::new(
+            config.server.data_dir.clone(),
+            config.server.data_layout,
+        ));
+        let storage_manager = Arc::new(StorageManager::new(
+            config.server.data_dir.clone(),
+            config.server.data_layout,
+        ));
         let backend_connected = Arc::new(RwLock::new(false));
+        // Servers whose files may only be read; enforced by WebSocket file operations and the
+        // file tunnel alike.
+        let read_only = storage_manager
+            .read_read_only_servers()
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to read persisted read-only servers: {}", e);
+                Default::default()
+            });
+        let read_only_servers =
+            Arc::new(RwLock::new(read_only.into_iter().collect::<HashSet<_>>()));
+        // Set while the node is below its free-space floor; refuses work that grows disk usage.
+        let low_disk = Arc::new(AtomicBool::new(false));
         let file_tunnel = Arc::new(FileTunnelClient::new(
             config.clone(),
             file_manager.clone(),
             backend_connected.clone(),
+            read_only_servers.clone(),
+            low_disk.clone(),
         ));
 
         let ws_handler = Arc::new(WebSocketHandler::new(
@@ -65,6 +113,8 @@ impl CatalystAgent {
             file_manager.clone(),
             storage_manager.clone(),
             backend_connected.clone(),
+            read_only_servers,
+            low_disk,
         ));
 
         Ok(Self {
@@ -81,6 +131,18 @@ impl CatalystAgent {
     pub async fn run(&self) -> AgentResult<()> {
         info!("Starting Catalyst Agent");
 
+        // Installing the egress qdisc dropped the classes of servers that kept running
+        self.runtime.restore_egress_classes().await;
+
+        // Remember how servers were started before this agent restarted
+        self.ws_handler.restore_start_state().await;
+
+        // Watch servers that kept running across the restart before the backend is reachable
+        self.ws_handler.adopt_running_containers().await;
+
+        // Remember what ran before this start; after a reboot, optionally resume it
+        self.ws_handler.restore_autostart().await;
+
         // Run an initial resource snapshot immediately (captures current usage at startup)
         if let Err(e) = self.ws_handler.send_resource_stats().await {
             warn!("Initial resource snapshot failed: {}", e);
@@ -100,6 +162,18 @@ impl CatalystAgent {
             agent.start_health_monitoring().await;
         });
 
+        // Periodically return space freed inside storage images to the host
+        let agent = self.clone_refs();
+        tokio::spawn(async move {
+            agent.ws_handler.run_fstrim_schedule().await;
+        });
+
+        // Keep console files from filling /tmp
+        let runtime = self.runtime.clone();
+        tokio::spawn(async move {
+            runtime.run_console_log_cap().await;
+        });
+
         // Start file tunnel (HTTP-based file operations)
         let file_tunnel = self.file_tunnel.clone();
         let tunnel_task = tokio::spawn(async move {
@@ -174,19 +248,35 @@ async fn main() -> AgentResult<()> {
         }
     };
 
-    let filter = format!("catalyst_agent={},tokio=info", config.logging.level);
+    let filter = tracing_subscriber::EnvFilter::new(format!(
+        "catalyst_agent={},tokio=info",
+        config.logging.level
+    ));
+    // Warnings and errors are also kept in memory so the backend can fetch them.
+    agent_events::agent_events().add_secret(&config.server.api_key);
+    if let Some(hmac_key) = config.server.hmac_key() {
+        agent_events::agent_events().add_secret(hmac_key);
+    }
+    if let Some(backup_secret) = config.server.backup_secret() {
+        agent_events::agent_events().add_secret(backup_secret);
+    }
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(agent_events::AgentEventLayer);
     if config.logging.format == "json" {
-        tracing_subscriber::fmt()
-            .json()
-            .with_env_filter(filter)
+        registry
+            .with(tracing_subscriber::fmt::layer().json())
             .init();
     } else {
-        tracing_subscriber::fmt().with_env_filter(filter).init();
+        registry.with(tracing_subscriber::fmt::layer()).init();
     }
 
     info!("Catalyst Agent starting");
     info!("Configuration loaded: {:?}", config);
 
+    // Resource stats and limits depend on the unified cgroup hierarchy; fail loudly without it.
+    SystemSetup::ensure_cgroup_v2()?;
+
     // Run system initialization
     info!("Running system setup and dependency check...");
     if let Err(e) = SystemSetup::initialize(&config).await {
@@ -194,9 +284,67 @@ async fn main() -> AgentResult<()> {
         warn!("Continuing with existing configuration...");
     }
 
+    // Port forwarding silently breaks without iptables access, so check it up front.
+    let firewall_warning = match SystemSetup::verify_firewall_access() {
+        Ok(()) => None,
+        Err(e) if config.networking.require_firewall => {
+            error!("Firewall self-check failed: {}", e);
+            error!("Grant CAP_NET_ADMIN or set networking.require_firewall = false");
+            return Err(e);
+        }
+        Err(e) => {
+            warn!(
+                "Firewall self-check failed: {}; port forwarding will not work on this node",
+                e
+            );
+            Some(e.to_string())
+        }
+    };
+
+    if let Err(e) = SystemSetup::verify_runtime_class_shims(&config) {
+        error!("{}", e);
+        return Err(e);
+    }
+
     // Create and run agent
     let agent = CatalystAgent::new(config).await?;
-    agent.run().await?;
+    if let Some(message) = firewall_warning {
+        agent
+            .ws_handler
+            .add_capability_warning("firewall_unavailable", message)
+            .await;
+    }
+    tokio::select! {
+        result = agent.run() => result?,
+        signal = shutdown_signal() => {
+            info!("Received {}, shutting down", signal);
+            match agent.config.containerd.on_shutdown {
+                ShutdownAction::Leave => {
+                    info!("Leaving servers running for the next agent to re-adopt")
+                }
+                ShutdownAction::Stop => {
+                    info!("Stopping running servers before exit");
+                    agent.ws_handler.stop_all_servers().await;
+                }
+            }
+        }
+    }
 
     Ok(())
 }
+
+/// Resolves with the name of the first termination signal the agent receives.
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        warn!("Failed to install shutdown signal handlers");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
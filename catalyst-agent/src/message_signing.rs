@@ -0,0 +1,141 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Sink;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio_tungstenite::tungstenite::Message;
+
+pub const ALGORITHM: &str = "hmac-sha256";
+
+/// Signs outgoing JSON text frames once the backend has agreed to it in the handshake.
+///
+/// A signed frame is the original object with `"signingNonce"` (the nonce the backend sent
+/// for this connection), `"signatureSeq"` (per-connection counter, starting at 1) and
+/// `"signedAt"` (unix millis) appended, followed by `"signature"` as the last member: the hex
+/// HMAC-SHA256 of every byte before `,"signature"` with a closing `}`. The backend verifies by
+/// stripping that final member, and rejects frames whose nonce is not the connection's or
+/// whose sequence does not increase, so frames replayed within or across connections are
+/// caught.
+pub struct SigningSink<S> {
+    inner: S,
+    signer: Option<Signer>,
+}
+
+struct Signer {
+    key: Vec<u8>,
+    /// `signingNonce` as a JSON string literal.
+    nonce: String,
+    seq: u64,
+}
+
+impl<S> SigningSink<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            signer: None,
+        }
+    }
+
+    /// Sign every text frame sent from now on, bound to this connection's `nonce`.
+    pub fn enable_signing(&mut self, key: &str, nonce: &str) {
+        self.signer = Some(Signer {
+            key: key.as_bytes().to_vec(),
+            nonce: serde_json::Value::from(nonce).to_string(),
+            seq: 0,
+        });
+    }
+}
+
+impl Signer {
+    fn sign(&mut self, text: &str) -> Option<String> {
+        let body = text.trim_end().strip_suffix('}')?.trim_end();
+        if !body.trim_start().starts_with('{') {
+            return None;
+        }
+        self.seq += 1;
+        let separator = if body.ends_with('{') { "" } else { "," };
+        let signed = format!(
+            "{}{}\"signingNonce\":{},\"signatureSeq\":{},\"signedAt\":{}",
+            body,
+            separator,
+            self.nonce,
+            self.seq,
+            chrono::Utc::now().timestamp_millis()
+        );
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).ok()?;
+        mac.update(signed.as_bytes());
+        mac.update(b"}");
+        let signature = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        Some(format!("{},\"signature\":\"{}\"}}", signed, signature))
+    }
+}
+
+impl<S: Sink<Message> + Unpin> Sink<Message> for SigningSink<S> {
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        Pin::new(&mut self.inner).poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Message) -> Result<(), S::Error> {
+        let item = match (&mut self.signer, item) {
+            (Some(signer), Message::Text(text)) => match signer.sign(text.as_str()) {
+                Some(signed) => Message::Text(signed.into()),
+                None => Message::Text(text),
+            },
+            (_, item) => item,
+        };
+        Pin::new(&mut self.inner).start_send(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_covers_frame_without_signature_member() {
+        let mut signer = Signer {
+            key: b"shared-secret".to_vec(),
+            nonce: "\"connection-1\"".to_string(),
+            seq: 0,
+        };
+        let first = signer.sign(r#"{"type":"health_report","cpu":1}"#).unwrap();
+        let second = signer.sign(r#"{"type":"health_report","cpu":1}"#).unwrap();
+
+        let parsed: serde_json::Value = serde_json::from_str(&first).unwrap();
+        assert_eq!(parsed["type"], "health_report");
+        assert_eq!(parsed["signatureSeq"], 1);
+        assert_eq!(parsed["signingNonce"], "connection-1");
+        let next: serde_json::Value = serde_json::from_str(&second).unwrap();
+        assert_eq!(next["signatureSeq"], 2);
+
+        let (signed, signature) = first.rsplit_once(",\"signature\":\"").unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"shared-secret").unwrap();
+        mac.update(signed.as_bytes());
+        mac.update(b"}");
+        let expected = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        assert_eq!(signature.trim_end_matches("\"}"), expected);
+
+        assert!(signer.sign("not json").is_none());
+    }
+}
@@ -4,38 +4,112 @@ use std::process::Command;
 use tracing::{info, warn};
 
 use crate::config::CniNetworkConfig;
+use crate::runtime_manager::CNI_BIN_DIRS;
 use crate::AgentError;
 use serde_json::json;
+use thiserror::Error;
 use toml::Value as TomlValue;
 
 const CNI_DIR: &str = "/etc/cni/net.d";
 const CONFIG_PATH: &str = "/opt/catalyst-agent/config.toml";
+/// Plugins every network written by this manager depends on.
+const REQUIRED_CNI_PLUGINS: [&str; 2] = ["macvlan", "host-local"];
+
+/// Categorized network configuration failures, so the backend can show targeted guidance.
+#[derive(Error, Debug)]
+pub enum NetworkConfigError {
+    #[error("Invalid network configuration: {0}")]
+    Invalid(String),
+
+    #[error("Invalid CIDR: {0}")]
+    InvalidCidr(String),
+
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Interface not found: {0}")]
+    InterfaceNotFound(String),
+
+    #[error("Interface down: {0}")]
+    InterfaceDown(String),
+
+    #[error("CNI plugin missing: {0}")]
+    PluginMissing(String),
+
+    #[error("Network conflict: {0}")]
+    Conflict(String),
+
+    #[error("Network not found: {0}")]
+    NotFound(String),
+
+    #[error("Network detection failed: {0}")]
+    DetectionFailed(String),
+
+    #[error("IO error: {0}")]
+    Io(String),
+}
+
+impl NetworkConfigError {
+    /// Stable identifier reported to the backend as `errorCode`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NetworkConfigError::Invalid(_) => "invalid_config",
+            NetworkConfigError::InvalidCidr(_) => "invalid_cidr",
+            NetworkConfigError::InvalidAddress(_) => "invalid_address",
+            NetworkConfigError::InterfaceNotFound(_) => "interface_not_found",
+            NetworkConfigError::InterfaceDown(_) => "interface_down",
+            NetworkConfigError::PluginMissing(_) => "plugin_missing",
+            NetworkConfigError::Conflict(_) => "conflict",
+            NetworkConfigError::NotFound(_) => "not_found",
+            NetworkConfigError::DetectionFailed(_) => "detection_failed",
+            NetworkConfigError::Io(_) => "io_error",
+        }
+    }
+}
+
+impl From<NetworkConfigError> for AgentError {
+    fn from(err: NetworkConfigError) -> Self {
+        match err {
+            NetworkConfigError::Invalid(_)
+            | NetworkConfigError::InvalidCidr(_)
+            | NetworkConfigError::InvalidAddress(_)
+            | NetworkConfigError::InterfaceDown(_)
+            | NetworkConfigError::Conflict(_) => AgentError::InvalidRequest(err.to_string()),
+            NetworkConfigError::InterfaceNotFound(_) | NetworkConfigError::NotFound(_) => {
+                AgentError::NotFound(err.to_string())
+            }
+            NetworkConfigError::PluginMissing(_) => AgentError::ConfigError(err.to_string()),
+            NetworkConfigError::DetectionFailed(_) => AgentError::InternalError(err.to_string()),
+            NetworkConfigError::Io(_) => AgentError::IoError(err.to_string()),
+        }
+    }
+}
 
 /// Network Manager - Handles dynamic network configuration
 pub struct NetworkManager;
 
 impl NetworkManager {
-    fn validate_network_name(name: &str) -> Result<(), AgentError> {
+    fn validate_network_name(name: &str) -> Result<(), NetworkConfigError> {
         let name = name.trim();
         if name.is_empty() || name.len() > 63 {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid network name: must be 1-63 characters".to_string(),
             ));
         }
         if name.contains('/') || name.contains('\\') {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid network name: must not contain path separators".to_string(),
             ));
         }
 
         let mut chars = name.chars();
         let Some(first) = chars.next() else {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid network name: must not be empty".to_string(),
             ));
         };
         if !first.is_ascii_alphanumeric() {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid network name: must start with an alphanumeric character".to_string(),
             ));
         }
@@ -43,7 +117,7 @@ impl NetworkManager {
             .chars()
             .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
         {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid network name: allowed characters are a-z, A-Z, 0-9, '-', '_', '.'"
                     .to_string(),
             ));
@@ -58,26 +132,26 @@ impl NetworkManager {
         interface.trim().split('@').next().unwrap_or("").to_string()
     }
 
-    fn validate_interface_name(interface: &str) -> Result<(), AgentError> {
+    fn validate_interface_name(interface: &str) -> Result<(), NetworkConfigError> {
         let interface = interface.trim();
         if interface.is_empty() || interface.len() > 15 {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid interface name: must be 1-15 characters".to_string(),
             ));
         }
         if interface.contains('/') || interface.contains('\\') {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid interface name: must not contain path separators".to_string(),
             ));
         }
         let mut chars = interface.chars();
         let Some(first) = chars.next() else {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid interface name: must not be empty".to_string(),
             ));
         };
         if !first.is_ascii_alphanumeric() {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid interface name: must start with an alphanumeric character".to_string(),
             ));
         }
@@ -85,7 +159,7 @@ impl NetworkManager {
             .chars()
             .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
         {
-            return Err(AgentError::InvalidRequest(
+            return Err(NetworkConfigError::Invalid(
                 "Invalid interface name: allowed characters are a-z, A-Z, 0-9, '-', '_', '.'"
                     .to_string(),
             ));
@@ -93,14 +167,25 @@ impl NetworkManager {
         Ok(())
     }
 
+    /// Reject MTUs outside what Ethernet links (up to jumbo frames) can carry.
+    pub fn validate_mtu(mtu: u32) -> Result<(), NetworkConfigError> {
+        if !(576..=9000).contains(&mtu) {
+            return Err(NetworkConfigError::Invalid(format!(
+                "Invalid MTU {}: must be between 576 and 9000",
+                mtu
+            )));
+        }
+        Ok(())
+    }
+
     /// Create a new CNI network configuration
-    pub fn create_network(network: &CniNetworkConfig) -> Result<(), AgentError> {
+    pub fn create_network(network: &CniNetworkConfig) -> Result<(), NetworkConfigError> {
         Self::validate_network_name(&network.name)?;
         let cni_config_path = format!("{}/{}.conflist", CNI_DIR, network.name);
 
         // Check if network already exists
         if Path::new(&cni_config_path).exists() {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::Conflict(format!(
                 "Network '{}' already exists",
                 network.name
             )));
@@ -113,6 +198,7 @@ impl NetworkManager {
             Self::detect_network_interface()?
         };
         Self::validate_interface_name(&interface)?;
+        Self::ensure_interface_usable(&interface)?;
 
         // Detect CIDR if not specified
         let cidr = if let Some(ref cidr) = network.cidr {
@@ -135,6 +221,11 @@ impl NetworkManager {
 
         // Validate network configuration
         Self::validate_network_config(&cidr, &gateway, &range_start, &range_end)?;
+        if let Some(mtu) = network.mtu {
+            Self::validate_mtu(mtu)?;
+        }
+        Self::ensure_no_subnet_overlap(&[&network.name], &cidr)?;
+        Self::ensure_cni_plugins()?;
 
         // Generate CNI configuration
         let cni_config = Self::generate_cni_config(
@@ -144,11 +235,12 @@ impl NetworkManager {
             &range_start,
             &range_end,
             &gateway,
+            network.mtu,
         );
 
         // Write CNI config file
         fs::write(&cni_config_path, cni_config)
-            .map_err(|e| AgentError::IoError(format!("Failed to write CNI config: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to write CNI config: {}", e)))?;
 
         info!(
             "✓ Created CNI network '{}' at {}",
@@ -169,29 +261,31 @@ impl NetworkManager {
     }
 
     /// Update an existing CNI network configuration
-    pub fn update_network(old_name: &str, network: &CniNetworkConfig) -> Result<(), AgentError> {
+    pub fn update_network(
+        old_name: &str,
+        network: &CniNetworkConfig,
+    ) -> Result<(), NetworkConfigError> {
         Self::validate_network_name(old_name)?;
         Self::validate_network_name(&network.name)?;
         let old_cni_path = format!("{}/{}.conflist", CNI_DIR, old_name);
 
         // Check if old network exists
         if !Path::new(&old_cni_path).exists() {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::NotFound(format!(
                 "Network '{}' does not exist",
                 old_name
             )));
         }
 
-        // If name changed, delete old config
-        if old_name != network.name {
-            fs::remove_file(&old_cni_path).map_err(|e| {
-                AgentError::IoError(format!("Failed to remove old CNI config: {}", e))
-            })?;
-            info!("✓ Removed old CNI network '{}'", old_name);
-        }
-
         // Create new config (will handle rename)
+        let renamed = old_name != network.name;
         let cni_config_path = format!("{}/{}.conflist", CNI_DIR, network.name);
+        if renamed && Path::new(&cni_config_path).exists() {
+            return Err(NetworkConfigError::Conflict(format!(
+                "Network '{}' already exists",
+                network.name
+            )));
+        }
 
         // Detect interface if not specified
         let interface = if let Some(ref iface) = network.interface {
@@ -200,6 +294,7 @@ impl NetworkManager {
             Self::detect_network_interface()?
         };
         Self::validate_interface_name(&interface)?;
+        Self::ensure_interface_usable(&interface)?;
 
         // Detect CIDR if not specified
         let cidr = if let Some(ref cidr) = network.cidr {
@@ -222,6 +317,11 @@ impl NetworkManager {
 
         // Validate network configuration
         Self::validate_network_config(&cidr, &gateway, &range_start, &range_end)?;
+        if let Some(mtu) = network.mtu {
+            Self::validate_mtu(mtu)?;
+        }
+        Self::ensure_no_subnet_overlap(&[old_name, &network.name], &cidr)?;
+        Self::ensure_cni_plugins()?;
 
         // Generate CNI configuration
         let cni_config = Self::generate_cni_config(
@@ -231,11 +331,20 @@ impl NetworkManager {
             &range_start,
             &range_end,
             &gateway,
+            network.mtu,
         );
 
         // Write CNI config file
         fs::write(&cni_config_path, cni_config)
-            .map_err(|e| AgentError::IoError(format!("Failed to write CNI config: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to write CNI config: {}", e)))?;
+
+        // Only drop the old config once its replacement is in place
+        if renamed {
+            fs::remove_file(&old_cni_path).map_err(|e| {
+                NetworkConfigError::Io(format!("Failed to remove old CNI config: {}", e))
+            })?;
+            info!("✓ Removed old CNI network '{}'", old_name);
+        }
 
         info!(
             "✓ Updated CNI network '{}' at {}",
@@ -257,13 +366,13 @@ impl NetworkManager {
     }
 
     /// Delete a CNI network configuration
-    pub fn delete_network(network_name: &str) -> Result<(), AgentError> {
+    pub fn delete_network(network_name: &str) -> Result<(), NetworkConfigError> {
         Self::validate_network_name(network_name)?;
         let cni_config_path = format!("{}/{}.conflist", CNI_DIR, network_name);
 
         // Check if network exists
         if !Path::new(&cni_config_path).exists() {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::NotFound(format!(
                 "Network '{}' does not exist",
                 network_name
             )));
@@ -271,7 +380,7 @@ impl NetworkManager {
 
         // Remove CNI config file
         fs::remove_file(&cni_config_path)
-            .map_err(|e| AgentError::IoError(format!("Failed to remove CNI config: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to remove CNI config: {}", e)))?;
 
         info!("✓ Deleted CNI network '{}'", network_name);
 
@@ -281,6 +390,118 @@ impl NetworkManager {
         Ok(())
     }
 
+    /// The macvlan master must exist and be administratively up, otherwise the network is
+    /// accepted here but every container start on it fails inside the CNI plugin. Applies to
+    /// auto-detected interfaces too.
+    fn ensure_interface_usable(interface: &str) -> Result<(), NetworkConfigError> {
+        let sys_path = Path::new("/sys/class/net").join(interface);
+        if !sys_path.exists() {
+            return Err(NetworkConfigError::InterfaceNotFound(format!(
+                "'{}' does not exist on this host",
+                interface
+            )));
+        }
+        // IFF_UP is bit 0 of the interface flags.
+        let flags = fs::read_to_string(sys_path.join("flags"))
+            .ok()
+            .and_then(|raw| u32::from_str_radix(raw.trim().trim_start_matches("0x"), 16).ok());
+        if flags.is_some_and(|flags| flags & 0x1 == 0) {
+            return Err(NetworkConfigError::InterfaceDown(format!(
+                "'{}' exists but is down; bring it up with `ip link set {} up`",
+                interface, interface
+            )));
+        }
+        Ok(())
+    }
+
+    fn ensure_cni_plugins() -> Result<(), NetworkConfigError> {
+        let missing: Vec<&str> = REQUIRED_CNI_PLUGINS
+            .iter()
+            .copied()
+            .filter(|plugin| {
+                !CNI_BIN_DIRS
+                    .iter()
+                    .any(|dir| Path::new(dir).join(plugin).is_file())
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(NetworkConfigError::PluginMissing(format!(
+            "{} not found in {}",
+            missing.join(", "),
+            CNI_BIN_DIRS.join(" or ")
+        )))
+    }
+
+    /// Reject a subnet that overlaps one already used by another CNI network on this host.
+    /// Networks named in `skip` (the one being written, and its old name on a rename) are
+    /// not compared.
+    fn ensure_no_subnet_overlap(skip: &[&str], cidr: &str) -> Result<(), NetworkConfigError> {
+        let (base, prefix) = Self::parse_cidr(cidr)?;
+        let Ok(entries) = fs::read_dir(CNI_DIR) else {
+            return Ok(());
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(other) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".conflist"))
+            else {
+                continue;
+            };
+            if skip.contains(&other) {
+                continue;
+            }
+            let Some(conf) = fs::read_to_string(&path)
+                .ok()
+                .and_then(|raw| serde_json::from_str::<serde_json::Value>(&raw).ok())
+            else {
+                continue;
+            };
+            let subnets = conf["plugins"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|plugin| plugin["ipam"]["ranges"].as_array())
+                .flatten()
+                .filter_map(|set| set.as_array())
+                .flatten()
+                .filter_map(|range| range["subnet"].as_str());
+            for subnet in subnets {
+                let Ok((other_base, other_prefix)) = Self::parse_cidr(subnet) else {
+                    continue;
+                };
+                let shared = prefix.min(other_prefix);
+                let mask = if shared == 0 {
+                    0
+                } else {
+                    u32::MAX << (32 - u32::from(shared))
+                };
+                if base & mask == other_base & mask {
+                    return Err(NetworkConfigError::Conflict(format!(
+                        "{} overlaps {} used by network '{}'",
+                        cidr, subnet, other
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_cidr(cidr: &str) -> Result<(u32, u8), NetworkConfigError> {
+        let (ip, prefix) = cidr
+            .split_once('/')
+            .ok_or_else(|| NetworkConfigError::InvalidCidr(cidr.to_string()))?;
+        let prefix: u8 = prefix
+            .parse()
+            .ok()
+            .filter(|p| *p <= 32)
+            .ok_or_else(|| NetworkConfigError::InvalidCidr(cidr.to_string()))?;
+        Ok((Self::parse_ipv4(ip)?, prefix))
+    }
+
     /// Generate CNI configuration JSON
     fn generate_cni_config(
         name: &str,
@@ -289,9 +510,10 @@ impl NetworkManager {
         range_start: &str,
         range_end: &str,
         gateway: &str,
+        mtu: Option<u32>,
     ) -> String {
         // Build JSON via a serializer to avoid config injection via user-controlled fields.
-        let config = json!({
+        let mut config = json!({
             "cniVersion": "1.0.0",
             "name": name,
             "plugins": [
@@ -316,6 +538,9 @@ impl NetworkManager {
                 }
             ]
         });
+        if let Some(mtu) = mtu {
+            config["plugins"][0]["mtu"] = json!(mtu);
+        }
 
         serde_json::to_string_pretty(&config).unwrap_or_else(|_| "{}".to_string())
     }
@@ -328,7 +553,7 @@ impl NetworkManager {
         gateway: &str,
         range_start: &str,
         range_end: &str,
-    ) -> Result<(), AgentError> {
+    ) -> Result<(), NetworkConfigError> {
         let mut config = Self::load_agent_config_toml()?;
         let networks = Self::networks_array_mut(&mut config)?;
 
@@ -354,6 +579,7 @@ impl NetworkManager {
             gateway,
             range_start,
             range_end,
+            network.mtu,
         ));
 
         Self::store_agent_config_toml(&config)?;
@@ -370,7 +596,7 @@ impl NetworkManager {
         gateway: &str,
         range_start: &str,
         range_end: &str,
-    ) -> Result<(), AgentError> {
+    ) -> Result<(), NetworkConfigError> {
         let mut config = Self::load_agent_config_toml()?;
         let networks = Self::networks_array_mut(&mut config)?;
 
@@ -394,6 +620,7 @@ impl NetworkManager {
                     gateway,
                     range_start,
                     range_end,
+                    network.mtu,
                 );
                 updated = true;
                 break;
@@ -408,6 +635,7 @@ impl NetworkManager {
                 gateway,
                 range_start,
                 range_end,
+                network.mtu,
             ));
         }
 
@@ -417,7 +645,7 @@ impl NetworkManager {
     }
 
     /// Remove network configuration from config.toml
-    fn remove_from_config(network_name: &str) -> Result<(), AgentError> {
+    fn remove_from_config(network_name: &str) -> Result<(), NetworkConfigError> {
         if !Path::new(CONFIG_PATH).exists() {
             return Ok(());
         }
@@ -440,29 +668,32 @@ impl NetworkManager {
         Ok(())
     }
 
-    fn load_agent_config_toml() -> Result<TomlValue, AgentError> {
+    fn load_agent_config_toml() -> Result<TomlValue, NetworkConfigError> {
         if !Path::new(CONFIG_PATH).exists() {
             return Ok(TomlValue::Table(toml::value::Table::new()));
         }
         let raw = fs::read_to_string(CONFIG_PATH)
-            .map_err(|e| AgentError::IoError(format!("Failed to read config: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to read config: {}", e)))?;
         toml::from_str::<TomlValue>(&raw)
-            .map_err(|e| AgentError::IoError(format!("Failed to parse config TOML: {}", e)))
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to parse config TOML: {}", e)))
     }
 
-    fn store_agent_config_toml(value: &TomlValue) -> Result<(), AgentError> {
-        let raw = toml::to_string_pretty(value)
-            .map_err(|e| AgentError::IoError(format!("Failed to serialize config TOML: {}", e)))?;
+    fn store_agent_config_toml(value: &TomlValue) -> Result<(), NetworkConfigError> {
+        let raw = toml::to_string_pretty(value).map_err(|e| {
+            NetworkConfigError::Io(format!("Failed to serialize config TOML: {}", e))
+        })?;
         fs::write(CONFIG_PATH, raw)
-            .map_err(|e| AgentError::IoError(format!("Failed to write config: {}", e)))
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to write config: {}", e)))
     }
 
-    fn networks_array_mut(value: &mut TomlValue) -> Result<&mut Vec<TomlValue>, AgentError> {
+    fn networks_array_mut(
+        value: &mut TomlValue,
+    ) -> Result<&mut Vec<TomlValue>, NetworkConfigError> {
         if !value.is_table() {
             *value = TomlValue::Table(toml::value::Table::new());
         }
         let root = value.as_table_mut().ok_or_else(|| {
-            AgentError::IoError("Invalid config TOML: expected table".to_string())
+            NetworkConfigError::Io("Invalid config TOML: expected table".to_string())
         })?;
 
         let networking = root
@@ -472,7 +703,7 @@ impl NetworkManager {
             *networking = TomlValue::Table(toml::value::Table::new());
         }
         let networking_table = networking.as_table_mut().ok_or_else(|| {
-            AgentError::IoError("Invalid config TOML: networking must be a table".to_string())
+            NetworkConfigError::Io("Invalid config TOML: networking must be a table".to_string())
         })?;
 
         let networks = networking_table
@@ -482,7 +713,7 @@ impl NetworkManager {
             *networks = TomlValue::Array(Vec::new());
         }
         networks.as_array_mut().ok_or_else(|| {
-            AgentError::IoError(
+            NetworkConfigError::Io(
                 "Invalid config TOML: networking.networks must be an array".to_string(),
             )
         })
@@ -495,6 +726,7 @@ impl NetworkManager {
         gateway: &str,
         range_start: &str,
         range_end: &str,
+        mtu: Option<u32>,
     ) -> TomlValue {
         let mut table = toml::value::Table::new();
         table.insert("name".to_string(), TomlValue::String(name.to_string()));
@@ -515,16 +747,21 @@ impl NetworkManager {
             "range_end".to_string(),
             TomlValue::String(range_end.to_string()),
         );
+        if let Some(mtu) = mtu {
+            table.insert("mtu".to_string(), TomlValue::Integer(i64::from(mtu)));
+        }
         TomlValue::Table(table)
     }
 
     /// Detect the primary network interface
-    fn detect_network_interface() -> Result<String, AgentError> {
+    fn detect_network_interface() -> Result<String, NetworkConfigError> {
         // Try to get default route interface
         let output = Command::new("ip")
             .args(["route", "show", "default"])
             .output()
-            .map_err(|e| AgentError::IoError(format!("Failed to detect default route: {}", e)))?;
+            .map_err(|e| {
+                NetworkConfigError::Io(format!("Failed to detect default route: {}", e))
+            })?;
 
         if output.status.success() {
             let interface = String::from_utf8_lossy(&output.stdout)
@@ -552,7 +789,7 @@ impl NetworkManager {
         let output = Command::new("ip")
             .args(["-o", "link", "show"])
             .output()
-            .map_err(|e| AgentError::IoError(format!("Failed to detect interfaces: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to detect interfaces: {}", e)))?;
 
         if output.status.success() {
             let interface = String::from_utf8_lossy(&output.stdout)
@@ -577,22 +814,25 @@ impl NetworkManager {
             }
         }
 
-        Err(AgentError::InternalError(
+        Err(NetworkConfigError::DetectionFailed(
             "Could not detect network interface".to_string(),
         ))
     }
 
     /// Detect interface CIDR
-    fn detect_interface_cidr(interface: &str) -> Result<String, AgentError> {
+    fn detect_interface_cidr(interface: &str) -> Result<String, NetworkConfigError> {
         let output = Command::new("ip")
             .args(["addr", "show", interface])
             .output()
-            .map_err(|e| AgentError::IoError(format!("Failed to detect interface CIDR: {}", e)))?;
+            .map_err(|e| {
+                NetworkConfigError::Io(format!("Failed to detect interface CIDR: {}", e))
+            })?;
 
         if !output.status.success() {
-            return Err(AgentError::InternalError(
-                "Failed to get interface address".to_string(),
-            ));
+            return Err(NetworkConfigError::InterfaceNotFound(format!(
+                "'{}' has no address information",
+                interface
+            )));
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
@@ -605,13 +845,13 @@ impl NetworkManager {
             }
         }
 
-        Err(AgentError::InternalError(
+        Err(NetworkConfigError::DetectionFailed(
             "Could not detect interface CIDR".to_string(),
         ))
     }
 
     /// Normalize CIDR to ensure it has a subnet mask
-    fn normalize_cidr(cidr: &str) -> Result<String, AgentError> {
+    fn normalize_cidr(cidr: &str) -> Result<String, NetworkConfigError> {
         if cidr.contains('/') {
             Ok(cidr.to_string())
         } else {
@@ -620,17 +860,21 @@ impl NetworkManager {
     }
 
     /// Calculate usable IP range from CIDR
-    fn cidr_usable_range(cidr: &str) -> Result<(String, String), AgentError> {
+    fn cidr_usable_range(cidr: &str) -> Result<(String, String), NetworkConfigError> {
         let parts: Vec<&str> = cidr.split('/').collect();
         if parts.len() != 2 {
-            return Err(AgentError::InternalError("Invalid CIDR format".to_string()));
+            return Err(NetworkConfigError::InvalidCidr(
+                "Invalid CIDR format".to_string(),
+            ));
         }
 
         let base_ip = parts[0];
         let ip_parts: Vec<&str> = base_ip.split('.').collect();
 
         if ip_parts.len() != 4 {
-            return Err(AgentError::InternalError("Invalid IP address".to_string()));
+            return Err(NetworkConfigError::InvalidAddress(
+                "Invalid IP address".to_string(),
+            ));
         }
 
         let _third_octet = ip_parts[2];
@@ -641,14 +885,14 @@ impl NetworkManager {
     }
 
     /// Detect default gateway
-    fn detect_default_gateway() -> Result<String, AgentError> {
+    fn detect_default_gateway() -> Result<String, NetworkConfigError> {
         let output = Command::new("ip")
             .args(["route", "show", "default"])
             .output()
-            .map_err(|e| AgentError::IoError(format!("Failed to detect gateway: {}", e)))?;
+            .map_err(|e| NetworkConfigError::Io(format!("Failed to detect gateway: {}", e)))?;
 
         if !output.status.success() {
-            return Err(AgentError::InternalError(
+            return Err(NetworkConfigError::DetectionFailed(
                 "Failed to detect gateway".to_string(),
             ));
         }
@@ -665,7 +909,7 @@ impl NetworkManager {
             }
         }
 
-        Err(AgentError::InternalError(
+        Err(NetworkConfigError::DetectionFailed(
             "Could not detect default gateway".to_string(),
         ))
     }
@@ -676,11 +920,11 @@ impl NetworkManager {
         gateway: &str,
         range_start: &str,
         range_end: &str,
-    ) -> Result<(), AgentError> {
+    ) -> Result<(), NetworkConfigError> {
         // Parse and validate CIDR
         let cidr_parts: Vec<&str> = cidr.split('/').collect();
         if cidr_parts.len() != 2 {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidCidr(format!(
                 "Invalid CIDR format: '{}'. Expected format: x.x.x.x/yy",
                 cidr
             )));
@@ -688,11 +932,14 @@ impl NetworkManager {
 
         let base_ip = cidr_parts[0];
         let prefix_len: u8 = cidr_parts[1].parse().map_err(|_| {
-            AgentError::InternalError(format!("Invalid CIDR prefix length: '{}'", cidr_parts[1]))
+            NetworkConfigError::InvalidCidr(format!(
+                "Invalid CIDR prefix length: '{}'",
+                cidr_parts[1]
+            ))
         })?;
 
         if !(8..=30).contains(&prefix_len) {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidCidr(format!(
                 "Invalid CIDR prefix length: '{}'. Must be between 8 and 30",
                 prefix_len
             )));
@@ -705,7 +952,7 @@ impl NetworkManager {
 
         // Validate gateway is within the subnet
         if !Self::ip_in_subnet(gateway, base_ip, prefix_len) {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidAddress(format!(
                 "Gateway '{}' is not within the subnet '{}/{}'",
                 gateway, base_ip, prefix_len
             )));
@@ -713,7 +960,7 @@ impl NetworkManager {
 
         // Validate range start is within the subnet
         if !Self::ip_in_subnet(range_start, base_ip, prefix_len) {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidAddress(format!(
                 "Range start '{}' is not within the subnet '{}/{}'",
                 range_start, base_ip, prefix_len
             )));
@@ -721,7 +968,7 @@ impl NetworkManager {
 
         // Validate range end is within the subnet
         if !Self::ip_in_subnet(range_end, base_ip, prefix_len) {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidAddress(format!(
                 "Range end '{}' is not within the subnet '{}/{}'",
                 range_end, base_ip, prefix_len
             )));
@@ -729,7 +976,7 @@ impl NetworkManager {
 
         // Validate range start < range end
         if range_start_ip >= range_end_ip {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidAddress(format!(
                 "Range start '{}' must be less than range end '{}'",
                 range_start, range_end
             )));
@@ -758,10 +1005,10 @@ impl NetworkManager {
     }
 
     /// Parse IPv4 address to u32 for comparison
-    fn parse_ipv4(ip: &str) -> Result<u32, AgentError> {
+    fn parse_ipv4(ip: &str) -> Result<u32, NetworkConfigError> {
         let parts: Vec<&str> = ip.split('.').collect();
         if parts.len() != 4 {
-            return Err(AgentError::InternalError(format!(
+            return Err(NetworkConfigError::InvalidAddress(format!(
                 "Invalid IP address: '{}'",
                 ip
             )));
@@ -770,7 +1017,7 @@ impl NetworkManager {
         let mut result: u32 = 0;
         for (i, part) in parts.iter().enumerate() {
             let octet: u8 = part.parse().map_err(|_| {
-                AgentError::InternalError(format!("Invalid IP address octet: '{}'", part))
+                NetworkConfigError::InvalidAddress(format!("Invalid IP address octet: '{}'", part))
             })?;
             result |= (octet as u32) << (24 - i * 8);
         }
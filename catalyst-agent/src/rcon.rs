@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::{AgentError, AgentResult};
+
+const PACKET_AUTH: i32 = 3;
+const PACKET_EXEC: i32 = 2;
+const AUTH_REQUEST_ID: i32 = 1;
+const EXEC_REQUEST_ID: i32 = 2;
+/// Protocol limit for a single packet (id + type + body + two NULs).
+const MAX_PACKET_SIZE: usize = 4096;
+pub const MAX_RCON_PASSWORD_LEN: usize = 512;
+
+/// Minimal Source RCON client, used to deliver stop commands to servers whose console
+/// is not attached to stdin.
+pub struct RconClient {
+    stream: TcpStream,
+    io_timeout: Duration,
+}
+
+impl RconClient {
+    /// Connect to `host:port` and authenticate with `password`.
+    pub async fn connect(
+        host: &str,
+        port: u16,
+        password: &str,
+        io_timeout: Duration,
+    ) -> AgentResult<Self> {
+        let stream = tokio::time::timeout(io_timeout, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| {
+                AgentError::NetworkError(format!("RCON connect to {}:{} timed out", host, port))
+            })?
+            .map_err(|e| {
+                AgentError::NetworkError(format!("RCON connect to {}:{} failed: {}", host, port, e))
+            })?;
+        let mut client = Self { stream, io_timeout };
+
+        client
+            .write_packet(AUTH_REQUEST_ID, PACKET_AUTH, password)
+            .await?;
+        // Some servers send an empty RESPONSE_VALUE before the auth response; skip it.
+        loop {
+            let (id, kind, _) = client.read_packet().await?;
+            if id == -1 {
+                return Err(AgentError::PermissionDenied(
+                    "RCON authentication rejected".to_string(),
+                ));
+            }
+            if id == AUTH_REQUEST_ID && kind == PACKET_EXEC {
+                return Ok(client);
+            }
+        }
+    }
+
+    /// Run `command` and return the server's (first) response body. A stop command may
+    /// close the connection before the server replies, so a missing response is not an error.
+    pub async fn execute(&mut self, command: &str) -> AgentResult<String> {
+        self.write_packet(EXEC_REQUEST_ID, PACKET_EXEC, command)
+            .await?;
+        Ok(self
+            .read_packet()
+            .await
+            .map(|(_, _, body)| body)
+            .unwrap_or_default())
+    }
+
+    async fn write_packet(&mut self, id: i32, kind: i32, body: &str) -> AgentResult<()> {
+        let length = 4 + 4 + body.len() + 2;
+        if length > MAX_PACKET_SIZE {
+            return Err(AgentError::InvalidRequest(
+                "RCON payload exceeds packet size limit".to_string(),
+            ));
+        }
+        let mut packet = Vec::with_capacity(4 + length);
+        packet.extend_from_slice(&(length as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&kind.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+        tokio::time::timeout(self.io_timeout, self.stream.write_all(&packet))
+            .await
+            .map_err(|_| AgentError::NetworkError("RCON write timed out".to_string()))?
+            .map_err(|e| AgentError::NetworkError(format!("RCON write failed: {}", e)))
+    }
+
+    async fn read_packet(&mut self) -> AgentResult<(i32, i32, String)> {
+        let io_timeout = self.io_timeout;
+        let stream = &mut self.stream;
+        let read = async move {
+            let mut len_buf = [0u8; 4];
+            stream.read_exact(&mut len_buf).await?;
+            let length = i32::from_le_bytes(len_buf);
+            if !(10..=MAX_PACKET_SIZE as i32).contains(&length) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("invalid RCON packet length {}", length),
+                ));
+            }
+            let mut buf = vec![0u8; length as usize];
+            stream.read_exact(&mut buf).await?;
+            Ok(buf)
+        };
+        let buf = tokio::time::timeout(io_timeout, read)
+            .await
+            .map_err(|_| AgentError::NetworkError("RCON read timed out".to_string()))?
+            .map_err(|e| AgentError::NetworkError(format!("RCON read failed: {}", e)))?;
+
+        let id = i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let kind = i32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        let body = &buf[8..buf.len() - 2];
+        Ok((id, kind, String::from_utf8_lossy(body).into_owned()))
+    }
+}
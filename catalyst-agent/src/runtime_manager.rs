@@ -2,9 +2,11 @@ use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::Write;
 use std::net::Ipv4Addr;
+use std::os::fd::AsRawFd;
 use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
@@ -15,7 +17,7 @@ use containerd_client::services::v1::events_client::EventsClient;
 use containerd_client::services::v1::images_client::ImagesClient;
 use containerd_client::services::v1::snapshots::snapshots_client::SnapshotsClient;
 use containerd_client::services::v1::snapshots::{
-    MountsRequest, PrepareSnapshotRequest, RemoveSnapshotRequest,
+    ListSnapshotsRequest, MountsRequest, PrepareSnapshotRequest, RemoveSnapshotRequest,
 };
 use containerd_client::services::v1::tasks_client::TasksClient;
 use containerd_client::services::v1::GetImageRequest;
@@ -41,17 +43,25 @@ use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::stat::Mode;
 use nix::unistd::mkfifo;
 
+use crate::config::DnsMode;
+use crate::egress_limiter::EgressLimiter;
 use crate::errors::{AgentError, AgentResult};
 use crate::firewall_manager::FirewallManager;
 
 const RUNTIME_NAME: &str = "io.containerd.runc.v2";
 const SPEC_TYPE_URL: &str = "types.containerd.io/opencontainers/runtime-spec/1/Spec";
 const CONSOLE_BASE_DIR: &str = "/tmp/catalyst-console";
-const PORT_FWD_STATE_DIR: &str = "/var/lib/cni/results";
+const INSTALLER_PREFIX: &str = "catalyst-installer-";
+const TTY_LABEL: &str = "catalyst.tty";
+/// Mount point of the server's data directory inside the container, used as the cwd of execs.
+const DATA_DIR_LABEL: &str = "catalyst.data-dir";
+/// Digest the image reference resolved to when the container was created.
+const IMAGE_DIGEST_LABEL: &str = "catalyst.image-digest";
+const DEFAULT_CONTAINER_DATA_DIR: &str = "/data";
 
 // CNI plugin directories to search, in order of preference
 // Fedora/RHEL install to /usr/libexec/cni, others typically use /opt/cni/bin
-const CNI_BIN_DIRS: &[&str] = &["/opt/cni/bin", "/usr/libexec/cni"];
+pub(crate) const CNI_BIN_DIRS: &[&str] = &["/opt/cni/bin", "/usr/libexec/cni"];
 
 /// Discover the CNI plugin directory by checking which one has required plugins
 fn discover_cni_bin_dir() -> &'static str {
@@ -71,6 +81,17 @@ fn discover_cni_bin_dir() -> &'static str {
     CNI_BIN_DIRS[0]
 }
 const PORT_FWD_STATE_PREFIX: &str = "catalyst-";
+/// How often console files are checked against `console.max_log_bytes`.
+const CONSOLE_CAP_INTERVAL: Duration = Duration::from_secs(10);
+/// First line of a console file that was cut back, followed by the kept byte count.
+const CONSOLE_TRUNCATED_MARKER: &str = "[Catalyst] Console log truncated, bytes kept: ";
+/// Window over which per-process CPU usage is sampled for `list_processes`.
+const PROCESS_CPU_SAMPLE: Duration = Duration::from_millis(500);
+const MAX_PROCESS_COMMAND_LEN: usize = 512;
+/// Conntrack entries read per stats pass; busier tables are counted partially.
+const MAX_CONNTRACK_ENTRIES: usize = 262_144;
+/// Delay before the first CNI DEL retry; doubled for each further attempt.
+const CNI_TEARDOWN_BACKOFF: Duration = Duration::from_millis(250);
 
 #[derive(serde::Serialize, serde::Deserialize)]
 struct PortForwardState {
@@ -93,10 +114,518 @@ pub struct ContainerConfig<'a> {
     pub memory_mb: u64,
     pub cpu_cores: u64,
     pub data_dir: &'a str,
+    /// Where `data_dir` is mounted inside the container; also its cwd and `HOME`.
+    pub container_data_dir: &'a str,
     pub port: u16,
     pub port_bindings: &'a HashMap<u16, u16>,
     pub network_mode: Option<&'a str>,
     pub network_ip: Option<&'a str>,
+    /// Interface MTU override for this container; falls back to the network's setting.
+    pub mtu: Option<u32>,
+    /// Allocate a pseudo-terminal for the container process.
+    pub tty: bool,
+    /// Log deny-listed syscalls (`SCMP_ACT_LOG`) instead of failing them.
+    pub seccomp_audit: bool,
+    /// Kernel OOM-killer bias for the container process (-1000..=1000).
+    pub oom_score_adj: Option<i32>,
+    /// tmpfs scratch directory (container path, size in MiB). Its pages are charged to the
+    /// container's memory limit and vanish with the container.
+    pub scratch: Option<(&'a str, u64)>,
+    /// Preset of masked and read-only kernel paths.
+    pub path_profile: PathProfile,
+    /// Cgroup to nest the container under instead of `/{namespace}`, for per-tenant accounting.
+    pub cgroup_parent: Option<&'a str>,
+    /// containerd runtime name (e.g. `io.containerd.runsc.v1`); runc when unset.
+    pub runtime: Option<&'a str>,
+    /// Extra OCI annotations for tooling that keys off them.
+    pub annotations: &'a HashMap<String, String>,
+    /// Platform to pull and run the image for; the node default when unset.
+    pub platform: Option<&'a ImagePlatform>,
+}
+
+/// An OCI platform (`os/architecture[/variant]`) to pull and run images for instead of the
+/// host's, e.g. `linux/amd64` on an arm64 host with binfmt emulation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImagePlatform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl ImagePlatform {
+    pub fn parse(value: &str) -> AgentResult<Self> {
+        let invalid = || {
+            AgentError::InvalidRequest(format!(
+                "Invalid platform '{}': expected os/architecture[/variant]",
+                value
+            ))
+        };
+        let parts: Vec<&str> = value.trim().split('/').collect();
+        if !(2..=3).contains(&parts.len())
+            || parts.iter().any(|part| {
+                part.is_empty()
+                    || part.len() > 32
+                    || !part
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            })
+        {
+            return Err(invalid());
+        }
+        Ok(Self {
+            os: parts[0].to_string(),
+            architecture: parts[1].to_string(),
+            variant: parts.get(2).map(|v| v.to_string()),
+        })
+    }
+
+    /// The host's own platform, which images are pulled for by default.
+    pub fn host() -> Self {
+        Self {
+            os: "linux".to_string(),
+            architecture: host_oci_arch().to_string(),
+            variant: None,
+        }
+    }
+
+    /// Whether an index entry's `platform` object is this platform. An unset variant matches
+    /// any variant.
+    fn matches(&self, platform: Option<&serde_json::Value>) -> bool {
+        let field = |name: &str| platform.and_then(|p| p.get(name)).and_then(|v| v.as_str());
+        field("os") == Some(self.os.as_str())
+            && field("architecture") == Some(self.architecture.as_str())
+            && self
+                .variant
+                .as_deref()
+                .is_none_or(|variant| field("variant") == Some(variant))
+    }
+}
+
+impl std::fmt::Display for ImagePlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+/// Named presets for the OCI `maskedPaths`/`readonlyPaths` lists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathProfile {
+    /// The standard lists.
+    #[default]
+    Default,
+    /// Like `Default`, but `/proc/sys/net` is left out of the read-only paths, so it is
+    /// mounted writable and servers can tune the sysctls of their own network namespace
+    /// (given a user allowed to write them). Ignored for host-network containers, where the
+    /// tree is the host's.
+    SysctlFriendly,
+    /// Additionally hides hardware, keyring and power-usage details.
+    Strict,
+}
+
+impl PathProfile {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "default" => Some(Self::Default),
+            "sysctl-friendly" => Some(Self::SysctlFriendly),
+            "strict" => Some(Self::Strict),
+            _ => None,
+        }
+    }
+
+    fn masked_paths(self) -> Vec<&'static str> {
+        let mut paths = masked_paths();
+        if self == Self::Strict {
+            paths.extend([
+                "/proc/acpi",
+                "/proc/keys",
+                "/proc/scsi",
+                "/proc/interrupts",
+                "/sys/devices/virtual/powercap", // RAPL energy counters (side channel)
+            ]);
+        }
+        paths
+    }
+
+    fn readonly_paths(self, host_network: bool) -> Vec<&'static str> {
+        let mut paths = readonly_paths();
+        if self == Self::SysctlFriendly && !host_network {
+            // Everything under /proc/sys except the namespaced net tree stays read-only.
+            paths.retain(|p| *p != "/proc/sys");
+            paths.extend([
+                "/proc/sys/abi",
+                "/proc/sys/crypto",
+                "/proc/sys/debug",
+                "/proc/sys/dev",
+                "/proc/sys/fs",
+                "/proc/sys/kernel",
+                "/proc/sys/user",
+                "/proc/sys/vm",
+            ]);
+        }
+        paths
+    }
+}
+
+/// Read position in a console output file, kept open between reads so each poll only reads
+/// what was appended. Notices truncation and replacement (a new inode) so tailing restarts
+/// from the top instead of waiting for the old offset to be reached. A file cut back by
+/// `console.max_log_bytes` resumes after the kept history instead.
+#[derive(Default)]
+pub struct ConsoleTail {
+    pos: u64,
+    inode: Option<u64>,
+    /// Modification time of the file at the last read, i.e. roughly when it was last written.
+    pub modified: Option<SystemTime>,
+    file: Option<tokio::io::BufReader<tokio::fs::File>>,
+    /// Bytes after the last newline, held back until their line is complete.
+    partial: Vec<u8>,
+    /// Longest held-back line before it is passed on anyway; 0 for no limit.
+    max_line_bytes: usize,
+}
+
+impl ConsoleTail {
+    /// Start reading `path` at `pos`, e.g. its current end to follow only new output.
+    pub fn at(pos: u64, metadata: &std::fs::Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            pos,
+            inode: Some(metadata.ino()),
+            modified: metadata.modified().ok(),
+            ..Default::default()
+        }
+    }
+
+    /// Pass on output that goes this long without a newline, e.g. `\r`-only progress bars,
+    /// instead of holding it back for good.
+    pub fn with_line_limit(mut self, max_bytes: usize) -> Self {
+        self.max_line_bytes = max_bytes;
+        self
+    }
+
+    /// Complete lines appended since the last call, or `None` if there are none yet. With
+    /// `follow_rotation`, a shrunk or replaced file is read again from the start.
+    pub async fn read_new(&mut self, path: &Path, follow_rotation: bool) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let metadata = tokio::fs::metadata(path).await.ok()?;
+        let inode = metadata.ino();
+        self.modified = metadata.modified().ok();
+        let replaced = self.inode.is_some_and(|known| known != inode);
+        if !replaced && metadata.len() < self.pos {
+            if let Some(resume) = console_truncation_end(path).await {
+                debug!(
+                    "Console file {} was cut back; resuming after kept history",
+                    path.display()
+                );
+                self.pos = resume;
+                self.partial.clear();
+                self.file = None;
+            }
+        }
+        if follow_rotation && (replaced || metadata.len() < self.pos) {
+            debug!(
+                "Console file {} was rotated; reading from start",
+                path.display()
+            );
+            self.pos = 0;
+            self.partial.clear();
+            self.file = None;
+        }
+        if replaced {
+            self.file = None;
+        }
+        self.inode = Some(inode);
+        if metadata.len() <= self.pos {
+            return None;
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let mut file = tokio::fs::File::open(path).await.ok()?;
+                file.seek(std::io::SeekFrom::Start(self.pos)).await.ok()?;
+                self.file.insert(tokio::io::BufReader::new(file))
+            }
+        };
+        let read = file.read_to_end(&mut self.partial).await;
+        let Ok(read) = read else {
+            self.file = None;
+            return None;
+        };
+        self.pos += read as u64;
+
+        let mut end = self
+            .partial
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        if self.max_line_bytes > 0 && self.partial.len() - end > self.max_line_bytes {
+            end = self.partial.len();
+        }
+        if end == 0 {
+            return None;
+        }
+        let complete: Vec<u8> = self.partial.drain(..end).collect();
+        Some(String::from_utf8_lossy(&complete).into_owned())
+    }
+
+    /// A trailing line that never got its newline, e.g. a prompt left when the process exited.
+    pub fn take_partial(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            return None;
+        }
+        let rest = std::mem::take(&mut self.partial);
+        Some(String::from_utf8_lossy(&rest).into_owned())
+    }
+}
+
+/// Offset just past the history a cap kept in `path`, if it starts with the truncation marker.
+async fn console_truncation_end(path: &Path) -> Option<u64> {
+    use tokio::io::AsyncReadExt;
+
+    let mut head = vec![0u8; CONSOLE_TRUNCATED_MARKER.len() + 32];
+    let mut file = tokio::fs::File::open(path).await.ok()?;
+    let mut filled = 0;
+    while filled < head.len() {
+        match file.read(&mut head[filled..]).await.ok()? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    let line_end = head[..filled].iter().position(|&b| b == b'\n')?;
+    let line = std::str::from_utf8(&head[..line_end]).ok()?;
+    let kept: u64 = line.strip_prefix(CONSOLE_TRUNCATED_MARKER)?.parse().ok()?;
+    Some(line_end as u64 + 1 + kept)
+}
+
+/// Cut `path` back to the most recent half of `max_bytes` once it is larger, starting at a
+/// line boundary and behind a truncation marker.
+///
+/// This relies on the runc shim appending to console files: it opens a plain (non-FIFO)
+/// stdout/stderr path with `O_APPEND` when copying pipes, and a `file://` URI with `O_APPEND`
+/// when relaying a terminal (see `console_output_uri`). The kept part is appended too, so:
+/// - output written between reading the tail and truncating is lost (one `set_len` call);
+/// - output written between truncating and appending the kept part is kept, but lands ahead
+///   of the marker, so tails restart from the top instead of resuming.
+///
+/// A writer that doesn't append would leave the file sparse after a cut. Such files are
+/// refused with `ErrorKind::Unsupported` instead of being cut again.
+pub fn cap_console_file(path: &Path, max_bytes: u64) -> std::io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::unix::fs::MetadataExt;
+
+    let mut file = fs::OpenOptions::new().read(true).append(true).open(path)?;
+    let metadata = file.metadata()?;
+    let len = metadata.len();
+    if len <= max_bytes {
+        return Ok(false);
+    }
+    // Well under the length allocated means holes: the writer kept its offset past a cut.
+    if metadata.blocks() * 512 * 4 < len * 3 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "file is sparse, so its writer does not append; not cutting it back",
+        ));
+    }
+    file.seek(SeekFrom::Start(len - max_bytes / 2))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    if let Some(newline) = tail.iter().position(|&b| b == b'\n') {
+        tail.drain(..=newline);
+    }
+    let mut kept = format!("{}{}\n", CONSOLE_TRUNCATED_MARKER, tail.len()).into_bytes();
+    kept.extend_from_slice(&tail);
+    file.set_len(0)?;
+    file.write_all(&kept)?;
+    Ok(true)
+}
+
+/// Task stdout/stderr as handed to the shim. Pipes are copied into a plain path opened with
+/// `O_APPEND`, but a terminal is relayed into one opened without it unless it is given as a
+/// `file://` URI. `cap_console_file` needs an appending writer either way.
+fn console_output_uri(path: &Path, tty: bool) -> String {
+    if tty {
+        format!("file://{}", path.display())
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
+/// Wakes a console tail as soon as a console file is written, via inotify on the container's
+/// console directory, so output doesn't wait for the next poll.
+pub struct ConsoleWatch {
+    shared: Arc<ConsoleInotify>,
+    wd: nix::sys::inotify::WatchDescriptor,
+    id: u64,
+    notify: Arc<tokio::sync::Notify>,
+}
+
+/// The inotify instance shared by every console tail. One per tail would run root out of
+/// `fs.inotify.max_user_instances` (128 by default) on busy nodes, breaking other daemons.
+struct ConsoleInotify {
+    inotify: tokio::io::unix::AsyncFd<InotifyFd>,
+    /// Tails waiting on each watched console directory.
+    waiters: std::sync::Mutex<ConsoleWaiters>,
+    next_id: std::sync::atomic::AtomicU64,
+    /// Set once reading events failed; tails then poll instead.
+    failed: AtomicBool,
+}
+
+type ConsoleWaiters =
+    HashMap<nix::sys::inotify::WatchDescriptor, Vec<(u64, Arc<tokio::sync::Notify>)>>;
+
+struct InotifyFd(nix::sys::inotify::Inotify);
+
+impl std::os::fd::AsRawFd for InotifyFd {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        use std::os::fd::AsFd;
+        self.0.as_fd().as_raw_fd()
+    }
+}
+
+impl ConsoleInotify {
+    /// The shared instance, created with its event reader on first use. `None` when inotify
+    /// is unavailable.
+    fn get() -> Option<Arc<Self>> {
+        use nix::sys::inotify::{InitFlags, Inotify};
+        static SHARED: std::sync::OnceLock<Option<Arc<ConsoleInotify>>> =
+            std::sync::OnceLock::new();
+        SHARED
+            .get_or_init(|| {
+                let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+                    .map_err(|e| warn!("inotify unavailable, console tails will poll: {}", e))
+                    .ok()?;
+                let inotify = tokio::io::unix::AsyncFd::new(InotifyFd(inotify)).ok()?;
+                let shared = Arc::new(Self {
+                    inotify,
+                    waiters: std::sync::Mutex::new(HashMap::new()),
+                    next_id: std::sync::atomic::AtomicU64::new(0),
+                    failed: AtomicBool::new(false),
+                });
+                tokio::spawn(shared.clone().dispatch());
+                Some(shared)
+            })
+            .clone()
+    }
+
+    /// Wake the tails of every directory whose console files changed.
+    async fn dispatch(self: Arc<Self>) {
+        loop {
+            let mut guard = match self.inotify.readable().await {
+                Ok(guard) => guard,
+                Err(e) => return self.fail(e),
+            };
+            let events = guard.try_io(|inotify| {
+                inotify
+                    .get_ref()
+                    .0
+                    .read_events()
+                    .map_err(std::io::Error::from)
+            });
+            match events {
+                Ok(Ok(events)) => {
+                    let waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+                    // Exec output is captured in the same directory; only console files count.
+                    for event in events.iter().filter(|event| {
+                        event
+                            .name
+                            .as_deref()
+                            .is_some_and(|name| name == "stdout" || name == "stderr")
+                    }) {
+                        for (_, notify) in waiters.get(&event.wd).into_iter().flatten() {
+                            notify.notify_one();
+                        }
+                    }
+                }
+                Ok(Err(e)) => return self.fail(e),
+                Err(_would_block) => {}
+            }
+        }
+    }
+
+    fn fail(&self, error: std::io::Error) {
+        warn!(
+            "Reading console inotify events failed, console tails fall back to polling: {}",
+            error
+        );
+        self.failed.store(true, Ordering::Relaxed);
+        let waiters = self.waiters.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, notify) in waiters.values().flatten() {
+            notify.notify_one();
+        }
+    }
+}
+
+impl ConsoleWatch {
+    /// Watch `dir` for writes to, truncation of and replacement of its `stdout`/`stderr`.
+    /// `None` when inotify is unavailable (e.g. the per-user watch limit is reached).
+    pub fn new(dir: &Path) -> Option<Self> {
+        use nix::sys::inotify::AddWatchFlags;
+        let shared = ConsoleInotify::get()?;
+        if shared.failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+        let wd = {
+            // Held across add_watch so a concurrent drop can't remove the watch in between.
+            let mut waiters = shared.waiters.lock().unwrap_or_else(|e| e.into_inner());
+            let wd = shared
+                .inotify
+                .get_ref()
+                .0
+                .add_watch(
+                    dir,
+                    AddWatchFlags::IN_MODIFY
+                        | AddWatchFlags::IN_CREATE
+                        | AddWatchFlags::IN_MOVED_TO,
+                )
+                .map_err(|e| debug!("Cannot watch {}: {}", dir.display(), e))
+                .ok()?;
+            waiters.entry(wd).or_default().push((id, notify.clone()));
+            wd
+        };
+        Some(Self {
+            shared,
+            wd,
+            id,
+            notify,
+        })
+    }
+
+    /// Return once a console file changed, or after `timeout` at the latest. `false` once
+    /// inotify stopped working, so the caller polls instead.
+    pub async fn wait(&self, timeout: Duration) -> bool {
+        if self.shared.failed.load(Ordering::Relaxed) {
+            return false;
+        }
+        let _ = tokio::time::timeout(timeout, self.notify.notified()).await;
+        !self.shared.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ConsoleWatch {
+    fn drop(&mut self) {
+        let mut waiters = self
+            .shared
+            .waiters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let Some(tails) = waiters.get_mut(&self.wd) else {
+            return;
+        };
+        tails.retain(|(id, _)| *id != self.id);
+        if tails.is_empty() {
+            waiters.remove(&self.wd);
+            // Fails harmlessly if the directory, and with it the watch, is already gone.
+            let _ = self.shared.inotify.get_ref().0.rm_watch(self.wd);
+        }
+    }
 }
 
 struct ContainerIo {
@@ -114,6 +643,12 @@ pub struct ContainerInfo {
     pub status: String,
     pub command: String,
     pub image: String,
+    /// Whether containerd has a task (running or exited) for the container. A container
+    /// without one is left over from an interrupted start or cleanup.
+    pub has_task: bool,
+    pub created_at: Option<SystemTime>,
+    /// Digest `image` resolved to when the container was created, if recorded.
+    pub image_digest: Option<String>,
 }
 
 #[derive(Debug)]
@@ -124,6 +659,19 @@ pub struct ContainerStats {
     pub memory_usage: String,
     pub net_io: String,
     pub block_io: String,
+    /// Share of the last 10s in which some task in the cgroup was stalled on I/O (PSI).
+    /// `None` when the kernel does not expose `io.pressure`.
+    pub io_pressure_percent: Option<f64>,
+}
+
+/// Conntrack entries involving one container address, by protocol.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConnectionCounts {
+    pub tcp: u64,
+    pub udp: u64,
+    pub other: u64,
+    /// TCP entries still in SYN_SENT/SYN_RECV; a large share suggests a SYN flood.
+    pub half_open: u64,
 }
 
 /// Log stream providing async file handles for stdout/stderr
@@ -144,6 +692,115 @@ pub struct EventStream {
     pub receiver: tonic::Streaming<containerd_client::types::Envelope>,
 }
 
+/// How an installer hands the server directory to the runtime user (1000:1000) afterwards.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChownMode {
+    /// `chown -R` the whole directory.
+    #[default]
+    Recursive,
+    /// Only the directory and its immediate entries, for templates with huge file trees whose
+    /// install script already sets ownership below that.
+    TopLevel,
+    /// Leave ownership alone, for images that run as root.
+    Skip,
+}
+
+impl ChownMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "recursive" => Some(ChownMode::Recursive),
+            "top-level" => Some(ChownMode::TopLevel),
+            "skip" => Some(ChownMode::Skip),
+            _ => None,
+        }
+    }
+
+    /// Shell appended to the install script to fix ownership of `dir` (already escaped).
+    fn script(self, dir: &str) -> String {
+        match self {
+            ChownMode::Recursive => format!(
+                "\n\necho '[Catalyst] Fixing file ownership for runtime user...'\nchown -R 1000:1000 {}",
+                dir
+            ),
+            ChownMode::TopLevel => format!(
+                "\n\necho '[Catalyst] Fixing top-level file ownership for runtime user...'\nfind {} -maxdepth 1 -exec chown 1000:1000 {{}} +",
+                dir
+            ),
+            ChownMode::Skip => String::new(),
+        }
+    }
+}
+
+/// Memory/CPU limits applied to an installer container.
+#[derive(Clone, Copy, Debug)]
+pub struct InstallerLimits {
+    pub memory_mb: u64,
+    pub cpu_cores: u64,
+}
+
+/// One entry of a container's filesystem diff against its image, like `docker diff`.
+#[derive(Debug, serde::Serialize)]
+pub struct FsChange {
+    /// `added`, `modified` or `deleted`.
+    pub kind: &'static str,
+    pub path: String,
+}
+
+/// One process in a server container, as reported by `list_processes`.
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessInfo {
+    /// Host PID (the container's PID namespace is not visible from the agent).
+    pub pid: u32,
+    pub ppid: u32,
+    pub command: String,
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+}
+
+/// Block I/O scheduling class for a server's processes (see ioprio_set(2)).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoClass {
+    /// Kernel default: derived from the CPU nice value.
+    None,
+    /// Normal scheduling at a level from 0 (highest) to 7 (lowest).
+    BestEffort,
+    /// Only gets disk time when no other process needs it.
+    Idle,
+}
+
+/// CPU and I/O scheduling priority applied to every thread of a server container.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessPriority {
+    pub nice: i32,
+    pub io_class: IoClass,
+    pub io_level: u8,
+}
+
+impl Default for ProcessPriority {
+    fn default() -> Self {
+        Self {
+            nice: 0,
+            io_class: IoClass::None,
+            io_level: 0,
+        }
+    }
+}
+
+impl ProcessPriority {
+    fn ioprio(&self) -> libc::c_int {
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        let class = match self.io_class {
+            IoClass::None => 0,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        };
+        (class << IOPRIO_CLASS_SHIFT) | self.io_level as libc::c_int
+    }
+}
+
 /// Installer container handle for interactive install scripts
 pub struct InstallerHandle {
     container_id: String,
@@ -151,6 +808,10 @@ pub struct InstallerHandle {
     channel: tonic::transport::Channel,
     pub stdout_path: PathBuf,
     pub stderr_path: PathBuf,
+    cleaned_up: AtomicBool,
+    /// Installers owned by a live handle in this process; shared with the runtime so the
+    /// orphan sweep leaves them alone.
+    active: Arc<std::sync::Mutex<HashSet<String>>>,
 }
 
 impl InstallerHandle {
@@ -165,7 +826,45 @@ impl InstallerHandle {
         Ok(resp.into_inner().exit_status as i32)
     }
 
+    /// Whether the kernel OOM-killed anything in the installer's cgroup. Must be called
+    /// before `cleanup`, which removes the cgroup.
+    pub async fn was_oom_killed(&self) -> bool {
+        let events = format!(
+            "/sys/fs/cgroup/{}/{}/memory.events",
+            self.namespace, self.container_id
+        );
+        tokio::fs::read_to_string(events)
+            .await
+            .map(|content| {
+                content.lines().any(|line| {
+                    line.strip_prefix("oom_kill ")
+                        .and_then(|n| n.trim().parse::<u64>().ok())
+                        .is_some_and(|n| n > 0)
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Kill the installer if it is still running, then remove it.
+    pub async fn force_remove(&self) {
+        let mut tasks = TasksClient::new(self.channel.clone());
+        let req = TaskKillRequest {
+            container_id: self.container_id.clone(),
+            signal: 9,
+            all: true,
+            ..Default::default()
+        };
+        let req = with_namespace!(req, &self.namespace);
+        let _ = tasks.kill(req).await;
+        let _ = tokio::time::timeout(Duration::from_secs(10), self.wait()).await;
+        let _ = self.cleanup().await;
+    }
+
     pub async fn cleanup(&self) -> AgentResult<()> {
+        self.cleaned_up.store(true, Ordering::Relaxed);
+        if let Ok(mut active) = self.active.lock() {
+            active.remove(&self.container_id);
+        }
         let mut tasks = TasksClient::new(self.channel.clone());
         let req = DeleteTaskRequest {
             container_id: self.container_id.clone(),
@@ -194,6 +893,33 @@ impl InstallerHandle {
     }
 }
 
+/// An install abandoned mid-run (e.g. its handler timed out and was dropped, or spawning it
+/// failed halfway) must not leak a running installer container, so kill and remove it in
+/// the background.
+impl Drop for InstallerHandle {
+    fn drop(&mut self) {
+        if self.cleaned_up.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let orphan = InstallerHandle {
+            container_id: self.container_id.clone(),
+            namespace: self.namespace.clone(),
+            channel: self.channel.clone(),
+            stdout_path: self.stdout_path.clone(),
+            stderr_path: self.stderr_path.clone(),
+            cleaned_up: AtomicBool::new(false),
+            active: self.active.clone(),
+        };
+        runtime.spawn(async move {
+            warn!("Removing abandoned installer {}", orphan.container_id);
+            orphan.force_remove().await;
+        });
+    }
+}
+
 #[derive(Clone)]
 pub struct ContainerdRuntime {
     _socket_path: String,
@@ -201,14 +927,69 @@ pub struct ContainerdRuntime {
     channel: tonic::transport::Channel,
     container_io: Arc<Mutex<HashMap<String, ContainerIo>>>,
     dns_servers: Vec<String>,
+    dns_mode: DnsMode,
+    allow_image_pull: bool,
+    active_installers: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// CNI data base: `results/` holds plugin results and port-forward state, `networks/`
+    /// holds host-local IPAM allocations.
+    cni_data_dir: PathBuf,
+    egress: Option<Arc<EgressLimiter>>,
+    cni_teardown_retries: u32,
+    /// Containers whose CNI DEL kept failing; their stored config is kept for a later retry.
+    failed_cni_teardowns: Arc<std::sync::Mutex<HashSet<String>>>,
+    /// `containerd.image_platform`: images are pulled for this platform instead of the host's.
+    image_platform: Option<ImagePlatform>,
+    /// `console.max_log_bytes`: console files are cut back past this size (0 = unbounded).
+    console_max_log_bytes: u64,
+    /// `console.max_input_bytes`: console output without a newline is passed on past this.
+    console_max_input_bytes: usize,
+    /// Network and block I/O counters from each container's previous stats sample.
+    io_samples: Arc<std::sync::Mutex<HashMap<String, IoCounters>>>,
+    /// Time and cgroup `usage_usec` of each container's previous stats sample.
+    cpu_samples: Arc<std::sync::Mutex<HashMap<String, (std::time::Instant, u64)>>>,
+}
+
+/// Cumulative byte counters of a container's network interfaces and cgroup block I/O.
+#[derive(Debug, Default, Clone, Copy)]
+struct IoCounters {
+    net_rx: u64,
+    net_tx: u64,
+    block_read: u64,
+    block_write: u64,
+}
+
+impl IoCounters {
+    /// Bytes moved since `previous`. A counter that went backwards was reset (new task or
+    /// network namespace), so its whole current value is new traffic.
+    fn since(self, previous: Option<IoCounters>) -> IoCounters {
+        let Some(previous) = previous else {
+            return IoCounters::default();
+        };
+        let delta = |now: u64, before: u64| if now >= before { now - before } else { now };
+        IoCounters {
+            net_rx: delta(self.net_rx, previous.net_rx),
+            net_tx: delta(self.net_tx, previous.net_tx),
+            block_read: delta(self.block_read, previous.block_read),
+            block_write: delta(self.block_write, previous.block_write),
+        }
+    }
 }
 
 impl ContainerdRuntime {
     /// Connect to containerd socket and create runtime
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         socket_path: PathBuf,
         namespace: String,
         dns_servers: Vec<String>,
+        dns_mode: DnsMode,
+        allow_image_pull: bool,
+        cni_data_dir: PathBuf,
+        egress_limit_mbps: Option<u64>,
+        cni_teardown_retries: u32,
+        image_platform: Option<ImagePlatform>,
+        console_max_log_bytes: u64,
+        console_max_input_bytes: usize,
     ) -> AgentResult<Self> {
         let channel = containerd_client::connect(&socket_path)
             .await
@@ -220,16 +1001,126 @@ impl ContainerdRuntime {
                 ))
             })?;
         info!("Connected to containerd at {}", socket_path.display());
-        info!("DNS servers configured for containers: {:?}", dns_servers);
+        match dns_mode {
+            DnsMode::Override => {
+                info!("DNS servers configured for containers: {:?}", dns_servers)
+            }
+            DnsMode::Inherit => info!("Containers inherit the host resolv.conf"),
+            DnsMode::Off => info!("Container DNS left to the image/CNI"),
+        }
+        let egress = match egress_limit_mbps {
+            Some(limit) => match detect_host_network() {
+                Some((iface, _, _)) => match EgressLimiter::install(&iface, limit).await {
+                    Ok(limiter) => Some(Arc::new(limiter)),
+                    Err(e) => {
+                        error!("Egress limit not applied: {}", e);
+                        None
+                    }
+                },
+                None => {
+                    error!("Egress limit not applied: no default route to find the uplink");
+                    None
+                }
+            },
+            None => {
+                if let Some((iface, _, _)) = detect_host_network() {
+                    EgressLimiter::remove_leftover(&iface).await;
+                }
+                None
+            }
+        };
         Ok(Self {
             _socket_path: socket_path.to_string_lossy().to_string(),
             namespace,
             channel,
             container_io: Arc::new(Mutex::new(HashMap::new())),
             dns_servers,
+            dns_mode,
+            allow_image_pull,
+            active_installers: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            cni_data_dir,
+            egress,
+            cni_teardown_retries,
+            failed_cni_teardowns: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            image_platform,
+            console_max_log_bytes,
+            console_max_input_bytes,
+            io_samples: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            cpu_samples: Arc::new(std::sync::Mutex::new(HashMap::new())),
         })
     }
 
+    /// `requested`, else the configured default platform. `None` means the host's.
+    fn effective_platform<'a>(
+        &'a self,
+        requested: Option<&'a ImagePlatform>,
+    ) -> Option<&'a ImagePlatform> {
+        requested.or(self.image_platform.as_ref())
+    }
+
+    /// The active node egress cap, if one is installed.
+    pub fn egress_limiter(&self) -> Option<&EgressLimiter> {
+        self.egress.as_deref()
+    }
+
+    /// Put running managed containers back under the egress cap after the qdisc was
+    /// (re)installed at startup.
+    pub async fn restore_egress_classes(&self) {
+        if self.egress.is_none() {
+            return;
+        }
+        let containers = match self.list_containers().await {
+            Ok(containers) => containers,
+            Err(e) => {
+                warn!("Failed to list containers for egress classes: {}", e);
+                return;
+            }
+        };
+        for container in containers.iter().filter(|c| c.managed && c.status == "Up") {
+            self.add_egress_class(&container.id).await;
+        }
+    }
+
+    async fn add_egress_class(&self, container_id: &str) {
+        let Some(egress) = &self.egress else {
+            return;
+        };
+        // Host-network containers have no CNI address; their traffic lands in the default class.
+        let ip = match self.get_container_ip(container_id).await {
+            Ok(ip) if !ip.is_empty() => ip,
+            _ => return,
+        };
+        if let Err(e) = egress.add_container(container_id, &ip).await {
+            warn!("Failed to add egress class for {}: {}", container_id, e);
+        }
+    }
+
+    /// Directory holding CNI plugin results and the agent's port-forward state files.
+    pub fn cni_results_dir(&self) -> PathBuf {
+        self.cni_data_dir.join("results")
+    }
+
+    fn cni_networks_dir(&self) -> PathBuf {
+        self.cni_data_dir.join("networks")
+    }
+
+    fn cni_result_path(&self, container_id: &str) -> PathBuf {
+        self.cni_results_dir()
+            .join(format!("catalyst-{}", container_id))
+    }
+
+    fn cni_config_path(&self, container_id: &str) -> PathBuf {
+        self.cni_results_dir()
+            .join(format!("catalyst-{}-config", container_id))
+    }
+
+    fn port_forward_state_path(&self, container_id: &str) -> PathBuf {
+        self.cni_results_dir().join(format!(
+            "{}{}-ports.json",
+            PORT_FWD_STATE_PREFIX, container_id
+        ))
+    }
+
     /// Create and start a container via containerd gRPC
     pub async fn create_container(&self, config: ContainerConfig<'_>) -> AgentResult<String> {
         let qualified_image = Self::qualify_image_ref(config.image);
@@ -238,10 +1129,11 @@ impl ContainerdRuntime {
             config.container_id, qualified_image
         );
 
-        self.ensure_image(config.image).await?;
+        let platform = self.effective_platform(config.platform);
+        self.ensure_image(config.image, platform).await?;
 
         // Read image's default environment variables (PATH, JAVA_HOME, etc.)
-        let image_env = self.get_image_env(&qualified_image).await;
+        let image_env = self.get_image_env(&qualified_image, platform).await;
 
         // Prepare I/O paths
         let io_dir = PathBuf::from(CONSOLE_BASE_DIR).join(config.container_id);
@@ -288,15 +1180,33 @@ impl ContainerdRuntime {
 
         // Prepare rootfs snapshot
         let snap_key = format!("{}-snap", config.container_id);
-        self.prepare_snapshot(&qualified_image, &snap_key).await?;
+        self.prepare_snapshot(&qualified_image, &snap_key, platform)
+            .await?;
 
         // Create container
+        let mut labels = HashMap::from([("catalyst.managed".to_string(), "true".to_string())]);
+        if config.tty {
+            // Remembered so restarts recreate the task with a terminal as well.
+            labels.insert(TTY_LABEL.to_string(), "true".to_string());
+        }
+        labels.insert(
+            DATA_DIR_LABEL.to_string(),
+            config.container_data_dir.to_string(),
+        );
+        // Recorded once so later reports don't have to look the tag up again, and keep
+        // describing what actually runs even if the tag moves.
+        match self.image_digest(&qualified_image).await {
+            Ok(digest) => {
+                labels.insert(IMAGE_DIGEST_LABEL.to_string(), digest);
+            }
+            Err(e) => warn!("Could not resolve digest of {}: {}", qualified_image, e),
+        }
         let container = Container {
             id: config.container_id.to_string(),
             image: qualified_image,
-            labels: HashMap::from([("catalyst.managed".to_string(), "true".to_string())]),
+            labels,
             runtime: Some(Runtime {
-                name: RUNTIME_NAME.to_string(),
+                name: config.runtime.unwrap_or(RUNTIME_NAME).to_string(),
                 options: None,
             }),
             spec: Some(spec_any),
@@ -314,11 +1224,18 @@ impl ContainerdRuntime {
         // Get rootfs mounts and create task
         let mounts = self.get_snapshot_mounts(&snap_key).await?;
         let mut tasks = TasksClient::new(self.channel.clone());
+        // With a terminal the shim owns the PTY master and relays it through the same stdin
+        // FIFO and stdout file; stderr is merged into the terminal output.
         let req = CreateTaskRequest {
             container_id: config.container_id.to_string(),
             stdin: stdin_path.to_string_lossy().to_string(),
-            stdout: stdout_path.to_string_lossy().to_string(),
-            stderr: stderr_path.to_string_lossy().to_string(),
+            stdout: console_output_uri(&stdout_path, config.tty),
+            stderr: if config.tty {
+                String::new()
+            } else {
+                stderr_path.to_string_lossy().to_string()
+            },
+            terminal: config.tty,
             rootfs: mounts,
             ..Default::default()
         };
@@ -337,6 +1254,7 @@ impl ContainerdRuntime {
                     pid,
                     config.network_mode,
                     config.network_ip,
+                    config.mtu,
                     config.port,
                     config.port_bindings,
                 )
@@ -352,45 +1270,24 @@ impl ContainerdRuntime {
 
             // CNI plugins may overwrite /etc/resolv.conf in the container's namespace.
             // Write our configured DNS directly into the container's /etc/resolv.conf.
-            let mut resolv_content = String::new();
-            for dns in &self.dns_servers {
-                resolv_content.push_str(&format!("nameserver {}\n", dns));
-            }
-            resolv_content.push_str("options attempts:3 timeout:2\n");
-
-            // Use nsenter to write into the container's mount namespace
-            let resolv_dest = "/etc/resolv.conf";
-            let nsenter_output = Command::new("nsenter")
-                .args(["-t", &pid.to_string(), "-m", "--", "sh", "-c"])
-                .arg(format!(
-                    "echo '{}' > {}",
-                    resolv_content.trim(),
-                    resolv_dest
-                ))
-                .output()
-                .await;
-
-            match nsenter_output {
-                Ok(output) if output.status.success() => {
-                    info!(
-                        "Updated resolv.conf in container {} with DNS: {:?}",
-                        config.container_id, self.dns_servers
-                    );
-                }
-                Ok(output) => {
-                    warn!(
-                        "Failed to update resolv.conf in container {}: {}",
-                        config.container_id,
-                        String::from_utf8_lossy(&output.stderr)
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        "Failed to run nsenter for resolv.conf update in {}: {}",
-                        config.container_id, e
-                    );
+            if self.dns_mode == DnsMode::Override {
+                match self.write_resolv_conf(pid).await {
+                    Ok(()) => {
+                        info!(
+                            "Updated resolv.conf in container {} with DNS: {:?}",
+                            config.container_id, self.dns_servers
+                        );
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to update resolv.conf in container {}: {}",
+                            config.container_id, e
+                        );
+                    }
                 }
             }
+
+            self.add_egress_class(config.container_id).await;
         }
 
         // Start task
@@ -428,21 +1325,28 @@ impl ContainerdRuntime {
         Ok(config.container_id.to_string())
     }
 
-    /// Spawn an ephemeral installer container via containerd gRPC
+    /// Spawn an ephemeral installer container via containerd gRPC, pulling `image` for
+    /// `platform` (else the configured default) like the server's own image.
+    #[allow(clippy::too_many_arguments)]
     pub async fn spawn_installer_container(
         &self,
         image: &str,
+        platform: Option<&ImagePlatform>,
         script: &str,
         env: &HashMap<String, String>,
         data_dir: &str,
+        container_data_dir: &str,
+        limits: Option<InstallerLimits>,
+        chown: ChownMode,
     ) -> AgentResult<InstallerHandle> {
-        let container_id = format!("catalyst-installer-{}", uuid::Uuid::new_v4());
+        let container_id = format!("{}{}", INSTALLER_PREFIX, uuid::Uuid::new_v4());
         let qualified_image = Self::qualify_image_ref(image);
         info!(
             "Spawning installer {} with image: {}",
             container_id, qualified_image
         );
-        self.ensure_image(image).await?;
+        let platform = self.effective_platform(platform);
+        self.ensure_image(image, platform).await?;
 
         let io_dir = PathBuf::from(CONSOLE_BASE_DIR).join(&container_id);
         fs::create_dir_all(&io_dir)
@@ -459,19 +1363,14 @@ impl ContainerdRuntime {
         File::create(&stderr_path)
             .map_err(|e| AgentError::ContainerError(format!("stderr: {}", e)))?;
 
-        // Create /etc/resolv.conf for DNS resolution using configured DNS servers
-        let resolv_path = io_dir.join("resolv.conf");
-        let mut resolv_content = String::new();
-        for dns in &self.dns_servers {
-            resolv_content.push_str(&format!("nameserver {}\n", dns));
+        // Owning the handle from here on means any early return below removes whatever was
+        // already created.
+        let handle = self.installer_handle(&container_id, stdout_path.clone(), stderr_path.clone());
+        if let Ok(mut active) = self.active_installers.lock() {
+            active.insert(container_id.clone());
         }
-        resolv_content.push_str("options attempts:3 timeout:2\n");
-        info!(
-            "Installer {} resolv.conf:\n{}",
-            container_id, resolv_content
-        );
-        fs::write(&resolv_path, &resolv_content)
-            .map_err(|e| AgentError::ContainerError(format!("resolv.conf: {}", e)))?;
+
+        let resolv_mount = self.resolv_conf_mount(&io_dir, &container_id)?;
 
         let mut env_list = vec![
             "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
@@ -492,39 +1391,55 @@ impl ContainerdRuntime {
         ];
 
         // Build mounts including DNS resolv.conf
-        let mut mounts = base_mounts(data_dir);
-        mounts.push(serde_json::json!({
-            "destination": "/etc/resolv.conf",
-            "type": "bind",
-            "source": resolv_path.to_string_lossy().to_string(),
-            "options": ["rbind", "rw"]
-        }));
+        let mut mounts = base_mounts(data_dir, container_data_dir);
+        mounts.extend(resolv_mount);
 
-        // Wrap the install script so all files are chowned to the runtime user (1000:1000)
+        // Wrap the install script so files are chowned to the runtime user (1000:1000)
         // after the user-provided script completes. The installer runs as root but the
         // runtime container runs as 1000:1000, so files must be accessible.
         let wrapped_script = format!(
-            "{}\n\necho '[Catalyst] Fixing file ownership for runtime user...'\nchown -R 1000:1000 /data",
-            script
+            "{}{}",
+            script,
+            chown.script(&shell_escape_value(container_data_dir))
         );
 
+        let mut linux = serde_json::json!({
+            "cgroupsPath": format!("/{}/{}", self.namespace, container_id),
+            "namespaces": [{"type":"pid"},{"type":"ipc"},{"type":"uts"},{"type":"mount"}],
+            "maskedPaths": masked_paths(), "readonlyPaths": readonly_paths(),
+            "seccomp": default_seccomp_profile(false)
+        });
+        if let Some(limits) = limits {
+            let mut resources = serde_json::Map::new();
+            if limits.memory_mb > 0 {
+                let bytes = (limits.memory_mb as i64) * 1024 * 1024;
+                resources.insert(
+                    "memory".to_string(),
+                    serde_json::json!({"limit": bytes, "swap": bytes}),
+                );
+            }
+            if limits.cpu_cores > 0 {
+                resources.insert(
+                    "cpu".to_string(),
+                    serde_json::json!({"quota": (limits.cpu_cores as i64) * 100_000, "period": 100000u64}),
+                );
+            }
+            linux["resources"] = serde_json::Value::Object(resources);
+        }
+
         let spec = serde_json::json!({
             "ociVersion": "1.1.0",
             "process": {
                 "terminal": false, "user": {"uid":0,"gid":0},
                 "args": ["sh", "-c", &wrapped_script], "env": env_list,
-                "cwd": "/data",
+                "cwd": container_data_dir,
                 "capabilities":{"bounding":caps,"effective":caps,"permitted":caps,"ambient":caps},
                 "noNewPrivileges": true
             },
             "root": {"path":"rootfs","readonly":false},
             "hostname": &container_id,
             "mounts": mounts,
-            "linux": {
-                "namespaces": [{"type":"pid"},{"type":"ipc"},{"type":"uts"},{"type":"mount"}],
-                "maskedPaths": masked_paths(), "readonlyPaths": readonly_paths(),
-                "seccomp": default_seccomp_profile()
-            }
+            "linux": linux
         });
         let spec_any = Any {
             type_url: SPEC_TYPE_URL.to_string(),
@@ -532,7 +1447,8 @@ impl ContainerdRuntime {
         };
 
         let snap_key = format!("{}-snap", container_id);
-        self.prepare_snapshot(&qualified_image, &snap_key).await?;
+        self.prepare_snapshot(&qualified_image, &snap_key, platform)
+            .await?;
 
         let container = Container {
             id: container_id.clone(),
@@ -573,13 +1489,66 @@ impl ContainerdRuntime {
         let req = with_namespace!(req, &self.namespace);
         tasks.start(req).await.map_err(grpc_err)?;
 
-        Ok(InstallerHandle {
-            container_id,
+        Ok(handle)
+    }
+
+    fn installer_handle(
+        &self,
+        container_id: &str,
+        stdout_path: PathBuf,
+        stderr_path: PathBuf,
+    ) -> InstallerHandle {
+        InstallerHandle {
+            container_id: container_id.to_string(),
             namespace: self.namespace.clone(),
             channel: self.channel.clone(),
             stdout_path,
             stderr_path,
-        })
+            cleaned_up: AtomicBool::new(false),
+            active: self.active_installers.clone(),
+        }
+    }
+
+    /// Force-remove installer containers not owned by a running install in this process
+    /// (e.g. left behind by a crash or agent restart) once they are older than `grace`.
+    pub async fn cleanup_orphaned_installers(&self, grace: Duration) -> AgentResult<Vec<String>> {
+        let mut containers = ContainersClient::new(self.channel.clone());
+        let req = with_namespace!(ListContainersRequest::default(), &self.namespace);
+        let listed = containers
+            .list(req)
+            .await
+            .map_err(grpc_err)?
+            .into_inner()
+            .containers;
+        let active = self
+            .active_installers
+            .lock()
+            .map(|active| active.clone())
+            .unwrap_or_default();
+        let now = SystemTime::now();
+
+        let mut removed = Vec::new();
+        for container in listed {
+            if !container.id.starts_with(INSTALLER_PREFIX) || active.contains(&container.id) {
+                continue;
+            }
+            let Some(created) = container
+                .created_at
+                .and_then(|t| SystemTime::try_from(t).ok())
+            else {
+                continue;
+            };
+            if now.duration_since(created).unwrap_or_default() < grace {
+                continue;
+            }
+            let io_dir = PathBuf::from(CONSOLE_BASE_DIR).join(&container.id);
+            let handle =
+                self.installer_handle(&container.id, io_dir.join("stdout"), io_dir.join("stderr"));
+            handle.force_remove().await;
+            info!("Removed orphaned installer {}", container.id);
+            removed.push(container.id);
+        }
+        Ok(removed)
     }
 
     pub async fn start_container(&self, container_id: &str) -> AgentResult<()> {
@@ -631,12 +1600,18 @@ impl ContainerdRuntime {
             .await
             .unwrap_or_default();
         let io_dir = PathBuf::from(CONSOLE_BASE_DIR).join(container_id);
+        let tty = self.container_uses_tty(container_id).await;
 
         let req = CreateTaskRequest {
             container_id: container_id.to_string(),
             stdin: io_dir.join("stdin").to_string_lossy().to_string(),
-            stdout: io_dir.join("stdout").to_string_lossy().to_string(),
-            stderr: io_dir.join("stderr").to_string_lossy().to_string(),
+            stdout: console_output_uri(&io_dir.join("stdout"), tty),
+            stderr: if tty {
+                String::new()
+            } else {
+                io_dir.join("stderr").to_string_lossy().to_string()
+            },
+            terminal: tty,
             rootfs: mounts,
             ..Default::default()
         };
@@ -652,17 +1627,56 @@ impl ContainerdRuntime {
         Ok(())
     }
 
+    async fn container_label(&self, container_id: &str, label: &str) -> Option<String> {
+        let mut client = ContainersClient::new(self.channel.clone());
+        let req = GetContainerRequest {
+            id: container_id.to_string(),
+        };
+        let req = with_namespace!(req, &self.namespace);
+        client
+            .get(req)
+            .await
+            .ok()?
+            .into_inner()
+            .container?
+            .labels
+            .remove(label)
+    }
+
+    async fn container_uses_tty(&self, container_id: &str) -> bool {
+        self.container_label(container_id, TTY_LABEL)
+            .await
+            .as_deref()
+            == Some("true")
+    }
+
+    /// Digest of the image the container was created from, as recorded at creation.
+    pub async fn container_image_digest(&self, container_id: &str) -> Option<String> {
+        self.container_label(container_id, IMAGE_DIGEST_LABEL).await
+    }
+
+    /// Container-side path of the server's data directory; `/data` for containers created
+    /// before it was configurable.
+    async fn container_data_dir(&self, container_id: &str) -> String {
+        self.container_label(container_id, DATA_DIR_LABEL)
+            .await
+            .unwrap_or_else(|| DEFAULT_CONTAINER_DATA_DIR.to_string())
+    }
+
     pub async fn stop_container(&self, container_id: &str, timeout_secs: u64) -> AgentResult<()> {
         self.stop_container_with_signal(container_id, "SIGTERM", timeout_secs)
             .await
+            .map(|_| ())
     }
 
+    /// Send `signal` and wait up to `timeout_secs` for the task to exit, then SIGKILL it.
+    /// Returns whether the SIGKILL escalation was needed.
     pub async fn stop_container_with_signal(
         &self,
         container_id: &str,
         signal: &str,
         timeout_secs: u64,
-    ) -> AgentResult<()> {
+    ) -> AgentResult<bool> {
         info!(
             "Stopping container: {} with signal {}",
             container_id, signal
@@ -678,17 +1692,17 @@ impl ContainerdRuntime {
         let req = with_namespace!(req, &self.namespace);
         if let Err(e) = tasks.kill(req).await {
             if is_not_found(&e) {
-                return Ok(());
+                return Ok(false);
             }
             return Err(grpc_err(e));
         }
-        match tokio::time::timeout(
+        let killed = match tokio::time::timeout(
             Duration::from_secs(timeout_secs),
             self.wait_for_exit(container_id),
         )
         .await
         {
-            Ok(Ok(_)) | Ok(Err(_)) => {}
+            Ok(Ok(_)) | Ok(Err(_)) => false,
             Err(_) => {
                 warn!(
                     "Container {} did not stop in {}s after {}, sending SIGKILL",
@@ -703,14 +1717,15 @@ impl ContainerdRuntime {
                 let req = with_namespace!(req, &self.namespace);
                 let _ = tasks.kill(req).await;
                 let _ = self.wait_for_exit(container_id).await;
+                true
             }
-        }
+        };
         let req = DeleteTaskRequest {
             container_id: container_id.to_string(),
         };
         let req = with_namespace!(req, &self.namespace);
         let _ = tasks.delete(req).await;
-        Ok(())
+        Ok(killed)
     }
 
     pub async fn kill_container(&self, container_id: &str, signal: &str) -> AgentResult<()> {
@@ -807,6 +1822,17 @@ impl ContainerdRuntime {
 
     pub async fn remove_container(&self, container_id: &str) -> AgentResult<()> {
         info!("Removing container: {}", container_id);
+        self.io_samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(container_id);
+        self.cpu_samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(container_id);
+        if let Some(egress) = &self.egress {
+            egress.remove_container(container_id).await;
+        }
         let _ = self.teardown_cni_network(container_id).await;
         let mut tasks = TasksClient::new(self.channel.clone());
         let req = TaskKillRequest {
@@ -941,6 +1967,33 @@ impl ContainerdRuntime {
         Ok(())
     }
 
+    /// Drop and reopen the stdin FIFO writer of a running container, then check that the FIFO
+    /// takes input without blocking. Returns whether a FIFO exists and whether it is writable.
+    pub async fn repair_container_io(&self, container_id: &str) -> AgentResult<(bool, bool)> {
+        if !self
+            .is_container_running(container_id)
+            .await
+            .unwrap_or(false)
+        {
+            return Err(AgentError::ContainerError(format!(
+                "Cannot repair console: container {} is not running",
+                container_id
+            )));
+        }
+        self.container_io.lock().await.remove(container_id);
+        if !self.ensure_container_io(container_id).await? {
+            return Ok((false, false));
+        }
+        let writable = self
+            .container_io
+            .lock()
+            .await
+            .get(container_id)
+            .and_then(|io| io.stdin_writer.as_ref())
+            .is_some_and(fifo_has_room);
+        Ok((true, writable))
+    }
+
     pub async fn restore_console_writers(&self) -> AgentResult<()> {
         info!("Restoring console writers for running containers");
         let containers = self.list_containers().await?;
@@ -984,24 +2037,34 @@ impl ContainerdRuntime {
         F: FnMut(String) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()>>>,
     {
         let base = PathBuf::from(CONSOLE_BASE_DIR).join(container_id);
-        let mut positions = [0u64; 2];
-        let paths = [base.join("stdout"), base.join("stderr")];
+        let mut tails = [
+            (
+                base.join("stdout"),
+                ConsoleTail::default().with_line_limit(self.console_max_input_bytes),
+            ),
+            (
+                base.join("stderr"),
+                ConsoleTail::default().with_line_limit(self.console_max_input_bytes),
+            ),
+        ];
         loop {
             let running = self
                 .is_container_running(container_id)
                 .await
                 .unwrap_or(false);
-            for i in 0..2 {
-                if let Ok(content) = tokio::fs::read_to_string(&paths[i]).await {
-                    if (positions[i] as usize) < content.len() {
-                        for line in content[positions[i] as usize..].lines() {
-                            callback(line.to_string()).await;
-                        }
-                        positions[i] = content.len() as u64;
+            for (path, tail) in tails.iter_mut() {
+                if let Some(content) = tail.read_new(path, true).await {
+                    for line in content.lines() {
+                        callback(line.to_string()).await;
                     }
                 }
             }
             if !running {
+                for (_, tail) in tails.iter_mut() {
+                    if let Some(rest) = tail.take_partial() {
+                        callback(rest).await;
+                    }
+                }
                 break;
             }
             tokio::time::sleep(Duration::from_millis(100)).await;
@@ -1009,6 +2072,65 @@ impl ContainerdRuntime {
         Ok(())
     }
 
+    /// Keep every container's stdout/stderr file under `console.max_log_bytes`, checking
+    /// every `CONSOLE_CAP_INTERVAL`. Returns at once when the cap is disabled.
+    pub async fn run_console_log_cap(&self) {
+        let max_bytes = self.console_max_log_bytes;
+        if max_bytes == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(CONSOLE_CAP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // Files refused as sparse, warned about once.
+        let mut refused: HashSet<PathBuf> = HashSet::new();
+        loop {
+            interval.tick().await;
+            let sweep = spawn_blocking(move || {
+                let Ok(entries) = fs::read_dir(CONSOLE_BASE_DIR) else {
+                    refused.clear();
+                    return (0, refused);
+                };
+                let mut capped = 0;
+                let mut seen = HashSet::new();
+                for entry in entries.flatten() {
+                    for name in ["stdout", "stderr"] {
+                        let path = entry.path().join(name);
+                        match cap_console_file(&path, max_bytes) {
+                            Ok(true) => capped += 1,
+                            Ok(false) => {}
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                            Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+                                if !refused.contains(&path) {
+                                    warn!("Cannot cap console file {}: {}", path.display(), e);
+                                }
+                                seen.insert(path);
+                            }
+                            Err(e) => warn!("Failed to cap console file {}: {}", path.display(), e),
+                        }
+                    }
+                }
+                (capped, seen)
+            })
+            .await;
+            let capped = match sweep {
+                Ok((capped, seen)) => {
+                    refused = seen;
+                    capped
+                }
+                Err(_) => {
+                    refused = HashSet::new();
+                    0
+                }
+            };
+            if capped > 0 {
+                info!(
+                    "Cut back {} console files larger than {} bytes",
+                    capped, max_bytes
+                );
+            }
+        }
+    }
+
     pub async fn spawn_log_stream(&self, container_id: &str) -> AgentResult<LogStream> {
         info!("Starting log stream for container: {}", container_id);
         let base = PathBuf::from(CONSOLE_BASE_DIR).join(container_id);
@@ -1040,33 +2162,87 @@ impl ContainerdRuntime {
         let resp = client.list(req).await.map_err(grpc_err)?;
         let mut result = Vec::new();
         for c in resp.into_inner().containers {
-            let running = self.is_container_running(&c.id).await.unwrap_or(false);
+            // An unknown task state is treated as a present task so it is never mistaken
+            // for an orphan.
+            let task = self.task_running(&c.id).await.unwrap_or(Some(false));
             result.push(ContainerInfo {
                 id: c.id.clone(),
                 names: c.id.clone(),
                 managed: c.labels.contains_key("catalyst.managed"),
-                status: if running {
-                    "Up".to_string()
-                } else {
-                    "Exited".to_string()
+                status: match task {
+                    Some(true) => "Up".to_string(),
+                    Some(false) => "Exited".to_string(),
+                    None => "Created".to_string(),
                 },
                 image: c.image.clone(),
                 command: String::new(),
+                has_task: task.is_some(),
+                created_at: c.created_at.and_then(|t| SystemTime::try_from(t).ok()),
+                image_digest: c.labels.get(IMAGE_DIGEST_LABEL).cloned(),
             });
         }
         Ok(result)
     }
 
-    pub async fn container_exists(&self, container_id: &str) -> bool {
-        let mut client = ContainersClient::new(self.channel.clone());
-        let req = GetContainerRequest {
-            id: container_id.to_string(),
-        };
-        let req = with_namespace!(req, &self.namespace);
-        client.get(req).await.is_ok()
+    /// The container's OCI runtime spec as stored in containerd.
+    pub async fn get_container_spec(&self, container_id: &str) -> AgentResult<serde_json::Value> {
+        let mut client = ContainersClient::new(self.channel.clone());
+        let req = GetContainerRequest {
+            id: container_id.to_string(),
+        };
+        let req = with_namespace!(req, &self.namespace);
+        let container = client
+            .get(req)
+            .await
+            .map_err(grpc_err)?
+            .into_inner()
+            .container
+            .ok_or_else(|| AgentError::NotFound(format!("Container {}", container_id)))?;
+        match container.spec {
+            Some(spec) => Ok(serde_json::from_slice(&spec.value)?),
+            None => Ok(serde_json::Value::Null),
+        }
+    }
+
+    pub async fn container_exists(&self, container_id: &str) -> bool {
+        let mut client = ContainersClient::new(self.channel.clone());
+        let req = GetContainerRequest {
+            id: container_id.to_string(),
+        };
+        let req = with_namespace!(req, &self.namespace);
+        client.get(req).await.is_ok()
+    }
+
+    /// Whether the container carries the `catalyst.managed` label. `None` if it doesn't exist.
+    pub async fn is_managed_container(&self, container_id: &str) -> AgentResult<Option<bool>> {
+        let mut client = ContainersClient::new(self.channel.clone());
+        let req = GetContainerRequest {
+            id: container_id.to_string(),
+        };
+        let req = with_namespace!(req, &self.namespace);
+        match client.get(req).await {
+            Ok(resp) => {
+                Ok(Some(resp.into_inner().container.is_some_and(|c| {
+                    c.labels.contains_key("catalyst.managed")
+                })))
+            }
+            Err(e) if e.code() == tonic::Code::NotFound => Ok(None),
+            Err(e) => Err(grpc_err(e)),
+        }
+    }
+
+    /// The container's current I/O pressure (`some avg10`), without taking a stats sample.
+    pub async fn io_pressure_percent(&self, container_id: &str) -> Option<f64> {
+        let cg = find_container_cgroup(container_id)?;
+        read_cgroup_io_pressure(&cg).await
     }
 
     pub async fn is_container_running(&self, container_id: &str) -> AgentResult<bool> {
+        Ok(self.task_running(container_id).await? == Some(true))
+    }
+
+    /// Whether the container's task is running, or `None` if it has no task at all.
+    async fn task_running(&self, container_id: &str) -> AgentResult<Option<bool>> {
         let mut tasks = TasksClient::new(self.channel.clone());
         let req = containerd_client::services::v1::GetRequest {
             container_id: container_id.to_string(),
@@ -1074,16 +2250,58 @@ impl ContainerdRuntime {
         };
         let req = with_namespace!(req, &self.namespace);
         match tasks.get(req).await {
-            Ok(resp) => Ok(resp
-                .into_inner()
-                .process
-                .map(|p| p.status == 2)
-                .unwrap_or(false)),
-            Err(e) if e.code() == tonic::Code::NotFound => Ok(false),
+            Ok(resp) => Ok(Some(
+                resp.into_inner()
+                    .process
+                    .map(|p| p.status == 2)
+                    .unwrap_or(false),
+            )),
+            Err(e) if e.code() == tonic::Code::NotFound => Ok(None),
             Err(e) => Err(grpc_err(e)),
         }
     }
 
+    /// Whether a socket inside the container's network namespace is listening on `port`.
+    /// Reads the task's own `/proc/<pid>/net` tables, so it works for every network mode
+    /// without the container having to be reachable from the host.
+    pub async fn is_port_listening(
+        &self,
+        container_id: &str,
+        port: u16,
+        udp: bool,
+    ) -> AgentResult<bool> {
+        let pid = self.task_pid(container_id).await?;
+        if pid == 0 {
+            return Ok(false);
+        }
+
+        let tables: [&str; 2] = if udp {
+            ["udp", "udp6"]
+        } else {
+            ["tcp", "tcp6"]
+        };
+        let port_hex = format!("{:04X}", port);
+        for table in tables {
+            let Ok(content) =
+                tokio::fs::read_to_string(format!("/proc/{}/net/{}", pid, table)).await
+            else {
+                continue;
+            };
+            for line in content.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 4 {
+                    continue;
+                }
+                let local_port = fields[1].rsplit(':').next().unwrap_or("");
+                // TCP sockets must be in LISTEN (0A); any bound UDP socket is receiving.
+                if local_port == port_hex && (udp || fields[3] == "0A") {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
     pub async fn get_container_exit_code(&self, container_id: &str) -> AgentResult<Option<i32>> {
         let mut tasks = TasksClient::new(self.channel.clone());
         let req = containerd_client::services::v1::GetRequest {
@@ -1103,9 +2321,127 @@ impl ContainerdRuntime {
         }
     }
 
+    /// Host pid of a container's init process, or 0 when it has no task.
+    async fn task_pid(&self, container_id: &str) -> AgentResult<u32> {
+        let mut tasks = TasksClient::new(self.channel.clone());
+        let req = containerd_client::services::v1::GetRequest {
+            container_id: container_id.to_string(),
+            ..Default::default()
+        };
+        let req = with_namespace!(req, &self.namespace);
+        Ok(tasks
+            .get(req)
+            .await
+            .map_err(grpc_err)?
+            .into_inner()
+            .process
+            .map(|p| p.pid)
+            .unwrap_or(0))
+    }
+
+    fn resolv_conf_content(&self) -> String {
+        let mut content = String::new();
+        for dns in &self.dns_servers {
+            content.push_str(&format!("nameserver {}\n", dns));
+        }
+        content.push_str("options attempts:3 timeout:2\n");
+        content
+    }
+
+    /// The `/etc/resolv.conf` bind mount for a container according to `dns_mode`. In override
+    /// mode the file is generated into `io_dir`; `None` leaves the image's own file alone.
+    fn resolv_conf_mount(
+        &self,
+        io_dir: &Path,
+        container_id: &str,
+    ) -> AgentResult<Option<serde_json::Value>> {
+        let (source, mode) = match self.dns_mode {
+            DnsMode::Off => return Ok(None),
+            DnsMode::Inherit => (host_resolv_conf_path(), "ro"),
+            DnsMode::Override => {
+                let resolv_path = io_dir.join("resolv.conf");
+                let content = self.resolv_conf_content();
+                info!("Container {} resolv.conf:\n{}", container_id, content);
+                fs::write(&resolv_path, &content)
+                    .map_err(|e| AgentError::ContainerError(format!("resolv.conf: {}", e)))?;
+                (resolv_path, "rw")
+            }
+        };
+        Ok(Some(serde_json::json!({
+            "destination": "/etc/resolv.conf",
+            "type": "bind",
+            "source": source.to_string_lossy().to_string(),
+            "options": ["rbind", mode]
+        })))
+    }
+
+    /// Write the configured DNS into /etc/resolv.conf inside the mount namespace of `pid`.
+    async fn write_resolv_conf(&self, pid: u32) -> AgentResult<()> {
+        let output = Command::new("nsenter")
+            .args(["-t", &pid.to_string(), "-m", "--", "sh", "-c"])
+            .arg(format!(
+                "echo '{}' > /etc/resolv.conf",
+                self.resolv_conf_content().trim()
+            ))
+            .output()
+            .await
+            .map_err(|e| AgentError::ContainerError(format!("nsenter: {}", e)))?;
+        if !output.status.success() {
+            return Err(AgentError::ContainerError(
+                String::from_utf8_lossy(&output.stderr).to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rewrite a running container's /etc/resolv.conf if its nameservers no longer match
+    /// the configured DNS. Returns whether a repair was needed.
+    pub async fn repair_resolv_conf(&self, container_id: &str) -> AgentResult<bool> {
+        if self.dns_mode != DnsMode::Override {
+            return Err(AgentError::InvalidRequest(
+                "DNS repair only applies when networking.dns_mode is \"override\"".to_string(),
+            ));
+        }
+        let pid = self.task_pid(container_id).await?;
+        if pid == 0 {
+            return Err(AgentError::ContainerError(format!(
+                "Container {} is not running",
+                container_id
+            )));
+        }
+        let output = Command::new("nsenter")
+            .args([
+                "-t",
+                &pid.to_string(),
+                "-m",
+                "--",
+                "cat",
+                "/etc/resolv.conf",
+            ])
+            .output()
+            .await
+            .map_err(|e| AgentError::ContainerError(format!("nsenter: {}", e)))?;
+        // A missing file is drift too, so a failed read is not an error here.
+        let current = String::from_utf8_lossy(&output.stdout);
+        let nameservers: Vec<&str> = current
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("nameserver"))
+            .map(str::trim)
+            .collect();
+        if nameservers == self.dns_servers {
+            return Ok(false);
+        }
+        warn!(
+            "resolv.conf in {} drifted (nameservers {:?}, expected {:?}); rewriting",
+            container_id, nameservers, self.dns_servers
+        );
+        self.write_resolv_conf(pid).await?;
+        Ok(true)
+    }
+
     pub async fn get_container_ip(&self, container_id: &str) -> AgentResult<String> {
         // Check CNI result file
-        let cni_state = format!("/var/lib/cni/results/catalyst-{}", container_id);
+        let cni_state = self.cni_result_path(container_id);
         if let Ok(content) = fs::read_to_string(&cni_state) {
             if let Ok(v) = serde_json::from_str::<serde_json::Value>(&content) {
                 if let Some(ips) = v.get("ips").and_then(|v| v.as_array()) {
@@ -1121,7 +2457,7 @@ impl ContainerdRuntime {
             }
         }
         // Fallback: scan CNI networks dir
-        if let Ok(entries) = fs::read_dir("/var/lib/cni/networks") {
+        if let Ok(entries) = fs::read_dir(self.cni_networks_dir()) {
             for entry in entries.flatten() {
                 let d = entry.path();
                 if !d.is_dir() {
@@ -1149,7 +2485,10 @@ impl ContainerdRuntime {
     pub async fn get_stats(&self, container_id: &str) -> AgentResult<ContainerStats> {
         let cg = find_container_cgroup(container_id).unwrap_or_default();
         let cpu = if !cg.is_empty() {
-            read_cgroup_cpu_percent(&cg).await.unwrap_or(0.0)
+            read_cgroup_cpu_usage_usec(&cg)
+                .await
+                .map(|usage| self.cpu_percent_since_last_sample(container_id, usage))
+                .unwrap_or(0.0)
         } else {
             0.0
         };
@@ -1158,17 +2497,183 @@ impl ContainerdRuntime {
         } else {
             0
         };
+        let io_pressure_percent = if !cg.is_empty() {
+            read_cgroup_io_pressure(&cg).await
+        } else {
+            None
+        };
+        let (block_read, block_write) = if !cg.is_empty() {
+            read_cgroup_io_bytes(&cg).await.unwrap_or((0, 0))
+        } else {
+            (0, 0)
+        };
+        let (net_rx, net_tx) = match self.task_pid(container_id).await {
+            Ok(pid) if pid != 0 => read_netns_bytes(pid).await.unwrap_or((0, 0)),
+            _ => (0, 0),
+        };
+        // Reported as traffic since the previous sample so the backend can derive rates.
+        let io = {
+            let current = IoCounters {
+                net_rx,
+                net_tx,
+                block_read,
+                block_write,
+            };
+            let mut samples = self.io_samples.lock().unwrap_or_else(|e| e.into_inner());
+            current.since(samples.insert(container_id.to_string(), current))
+        };
         Ok(ContainerStats {
             container_id: container_id.to_string(),
             container_name: container_id.to_string(),
             cpu_percent: format!("{:.2}%", cpu),
             memory_usage: format!("{}MiB / 0MiB", mem / (1024 * 1024)),
-            net_io: "0B / 0B".to_string(),
-            block_io: "0B / 0B".to_string(),
+            net_io: format!("{}B / {}B", io.net_rx, io.net_tx),
+            block_io: format!("{}B / {}B", io.block_read, io.block_write),
+            io_pressure_percent,
         })
     }
 
+    /// CPU use since the container's previous sample, in percent of one core (so a container
+    /// busy on two cores reports 200). The first sample has no baseline and reports 0.
+    fn cpu_percent_since_last_sample(&self, container_id: &str, usage_usec: u64) -> f64 {
+        let now = std::time::Instant::now();
+        let previous = self
+            .cpu_samples
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(container_id.to_string(), (now, usage_usec));
+        let Some((then, previous_usage)) = previous else {
+            return 0.0;
+        };
+        let wall_usec = now.duration_since(then).as_micros() as f64;
+        // A counter that went backwards belongs to a new cgroup; wait for the next sample.
+        if wall_usec <= 0.0 || usage_usec < previous_usage {
+            return 0.0;
+        }
+        (usage_usec - previous_usage) as f64 / wall_usec * 100.0
+    }
+
+    /// Processes in the container's cgroup, busiest first, with CPU usage sampled over
+    /// `PROCESS_CPU_SAMPLE`. At most `limit` entries are returned; the flag reports whether
+    /// the list was cut short.
+    pub async fn list_processes(
+        &self,
+        container_id: &str,
+        limit: usize,
+    ) -> AgentResult<(Vec<ProcessInfo>, bool)> {
+        let cg = find_container_cgroup(container_id).ok_or_else(|| {
+            AgentError::NotFound(format!("No cgroup found for container {}", container_id))
+        })?;
+        let procs = tokio::fs::read_to_string(format!("{}/cgroup.procs", cg))
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to read cgroup.procs: {}", e)))?;
+        let pids: Vec<u32> = procs
+            .lines()
+            .filter_map(|l| l.trim().parse().ok())
+            .collect();
+
+        let before: HashMap<u32, u64> = pids
+            .iter()
+            .filter_map(|&pid| read_proc_stat(pid).map(|(_, ticks)| (pid, ticks)))
+            .collect();
+        let started = std::time::Instant::now();
+        tokio::time::sleep(PROCESS_CPU_SAMPLE).await;
+        let elapsed = started.elapsed().as_secs_f64();
+        let ticks_per_sec = clock_ticks_per_sec();
+
+        let mut processes = Vec::with_capacity(pids.len());
+        for pid in pids {
+            // Exited during the sample window.
+            let Some((ppid, ticks)) = read_proc_stat(pid) else {
+                continue;
+            };
+            let delta = ticks.saturating_sub(before.get(&pid).copied().unwrap_or(ticks));
+            let cpu_percent = delta as f64 / ticks_per_sec / elapsed * 100.0;
+            let rss_bytes = read_proc_rss(pid).await.unwrap_or(0);
+            let command = read_proc_command(pid).await;
+            processes.push(ProcessInfo {
+                pid,
+                ppid,
+                command,
+                cpu_percent: (cpu_percent * 100.0).round() / 100.0,
+                rss_bytes,
+            });
+        }
+        processes.sort_by(|a, b| {
+            b.cpu_percent
+                .total_cmp(&a.cpu_percent)
+                .then(b.rss_bytes.cmp(&a.rss_bytes))
+        });
+        let truncated = processes.len() > limit;
+        processes.truncate(limit);
+        Ok((processes, truncated))
+    }
+
+    /// Apply `priority` to every thread of every process in the container's cgroup. Nice and
+    /// I/O priority are per-thread on Linux; threads spawned later inherit from their creator.
+    /// Returns the number of threads adjusted.
+    pub async fn apply_process_priority(
+        &self,
+        container_id: &str,
+        priority: ProcessPriority,
+    ) -> AgentResult<usize> {
+        let cg = find_container_cgroup(container_id).ok_or_else(|| {
+            AgentError::NotFound(format!("No cgroup found for container {}", container_id))
+        })?;
+        let procs = tokio::fs::read_to_string(format!("{}/cgroup.procs", cg))
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to read cgroup.procs: {}", e)))?;
+
+        let mut adjusted = 0;
+        for pid in procs.lines().filter_map(|l| l.trim().parse::<u32>().ok()) {
+            let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) else {
+                continue;
+            };
+            for tid in tasks
+                .flatten()
+                .filter_map(|t| t.file_name().to_str()?.parse::<libc::id_t>().ok())
+            {
+                // SAFETY: plain syscalls on a thread id; a thread that has exited yields ESRCH.
+                let nice = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, priority.nice) };
+                let io = unsafe {
+                    libc::syscall(
+                        libc::SYS_ioprio_set,
+                        1, // IOPRIO_WHO_PROCESS
+                        tid as libc::c_long,
+                        priority.ioprio() as libc::c_long,
+                    )
+                };
+                if nice == 0 && io == 0 {
+                    adjusted += 1;
+                    continue;
+                }
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(AgentError::PermissionDenied(format!(
+                        "Failed to set priority of thread {} in {}: {}",
+                        tid, container_id, err
+                    )));
+                }
+            }
+        }
+        Ok(adjusted)
+    }
+
     pub async fn exec(&self, container_id: &str, command: Vec<&str>) -> AgentResult<String> {
+        let (_, out, err) = self.exec_with_status(container_id, command).await?;
+        if !err.is_empty() && out.is_empty() {
+            return Err(AgentError::ContainerError(format!("Exec failed: {}", err)));
+        }
+        Ok(out)
+    }
+
+    /// Run a command in the container and return its exit status with stdout and stderr.
+    /// The status is `None` if the process did not finish within 30s.
+    pub async fn exec_with_status(
+        &self,
+        container_id: &str,
+        command: Vec<&str>,
+    ) -> AgentResult<(Option<u32>, String, String)> {
         let exec_id = format!("exec-{}", &uuid::Uuid::new_v4().to_string()[..8]);
         let io_dir = PathBuf::from(CONSOLE_BASE_DIR).join(container_id);
         fs::create_dir_all(&io_dir).ok();
@@ -1177,7 +2682,8 @@ impl ContainerdRuntime {
         File::create(&op).ok();
         File::create(&ep).ok();
 
-        let spec = serde_json::json!({"args":command,"env":["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],"cwd":"/data"});
+        let cwd = self.container_data_dir(container_id).await;
+        let spec = serde_json::json!({"args":command,"env":["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],"cwd":cwd});
         let spec_any = Any {
             type_url: "types.containerd.io/opencontainers/runtime-spec/1/Process".to_string(),
             value: spec.to_string().into_bytes(),
@@ -1207,16 +2713,16 @@ impl ContainerdRuntime {
             exec_id,
         };
         let req = with_namespace!(req, &self.namespace);
-        let _ = tokio::time::timeout(Duration::from_secs(30), tasks.wait(req)).await;
+        let status = match tokio::time::timeout(Duration::from_secs(30), tasks.wait(req)).await {
+            Ok(Ok(resp)) => Some(resp.into_inner().exit_status),
+            _ => None,
+        };
 
         let out = tokio::fs::read_to_string(&op).await.unwrap_or_default();
         let err = tokio::fs::read_to_string(&ep).await.unwrap_or_default();
         let _ = fs::remove_file(&op);
         let _ = fs::remove_file(&ep);
-        if !err.is_empty() && out.is_empty() {
-            return Err(AgentError::ContainerError(format!("Exec failed: {}", err)));
-        }
-        Ok(out)
+        Ok((status, out, err))
     }
 
     // -- Events --
@@ -1258,7 +2764,7 @@ impl ContainerdRuntime {
     // -- IP allocation --
 
     pub async fn clean_stale_ip_allocations(&self, network: &str) -> AgentResult<usize> {
-        let dir = format!("/var/lib/cni/networks/{}", network);
+        let dir = self.cni_networks_dir().join(network);
         let entries = match fs::read_dir(&dir) {
             Ok(e) => e,
             Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
@@ -1313,8 +2819,76 @@ impl ContainerdRuntime {
         Ok(removed)
     }
 
-    pub fn release_static_ip(network: &str, ip: &str) -> std::io::Result<()> {
-        fs::remove_file(format!("/var/lib/cni/networks/{}/{}", network, ip))
+    pub fn release_static_ip(&self, network: &str, ip: &str) -> std::io::Result<()> {
+        fs::remove_file(self.cni_networks_dir().join(network).join(ip))
+    }
+
+    // -- Snapshot GC --
+
+    /// Remove `<id>-snap` rootfs snapshots whose container no longer exists. Snapshots newer
+    /// than `grace` are left alone so an in-flight `create_container` is never raced.
+    pub async fn cleanup_orphaned_snapshots(&self, grace: Duration) -> AgentResult<Vec<String>> {
+        let mut containers = ContainersClient::new(self.channel.clone());
+        let req = with_namespace!(ListContainersRequest::default(), &self.namespace);
+        let existing: HashSet<String> = containers
+            .list(req)
+            .await
+            .map_err(grpc_err)?
+            .into_inner()
+            .containers
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+
+        let mut snaps = SnapshotsClient::new(self.channel.clone());
+        let req = ListSnapshotsRequest {
+            snapshotter: "overlayfs".to_string(),
+            ..Default::default()
+        };
+        let req = with_namespace!(req, &self.namespace);
+        let mut stream = snaps.list(req).await.map_err(grpc_err)?.into_inner();
+        let now = SystemTime::now();
+        let mut orphaned = Vec::new();
+        while let Some(chunk) = stream.message().await.map_err(grpc_err)? {
+            for info in chunk.info {
+                let Some(container_id) = info.name.strip_suffix("-snap") else {
+                    continue;
+                };
+                if container_id.is_empty() || existing.contains(container_id) {
+                    continue;
+                }
+                let Some(created) = info.created_at.and_then(|t| SystemTime::try_from(t).ok())
+                else {
+                    continue;
+                };
+                if now.duration_since(created).unwrap_or_default() < grace {
+                    continue;
+                }
+                orphaned.push(info.name);
+            }
+        }
+
+        let mut removed = Vec::new();
+        for key in orphaned {
+            let req = RemoveSnapshotRequest {
+                snapshotter: "overlayfs".to_string(),
+                key: key.clone(),
+            };
+            let req = with_namespace!(req, &self.namespace);
+            match snaps.remove(req).await {
+                Ok(_) => {
+                    info!("Removed orphaned snapshot {}", key);
+                    removed.push(key);
+                }
+                Err(e) if is_not_found(&e) => {}
+                Err(e) => warn!(
+                    "Failed to remove orphaned snapshot {}: {}",
+                    key,
+                    e.message()
+                ),
+            }
+        }
+        Ok(removed)
     }
 
     // -- Internal helpers --
@@ -1352,25 +2926,53 @@ impl ContainerdRuntime {
         Ok(true)
     }
 
-    async fn ensure_image(&self, image: &str) -> AgentResult<()> {
+    /// Make sure `image` is present locally for `platform` (the host's when `None`), pulling
+    /// it if allowed. Returns whether a pull happened.
+    async fn ensure_image(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<bool> {
         let qualified = Self::qualify_image_ref(image);
         let mut client = ImagesClient::new(self.channel.clone());
         let req = GetImageRequest {
             name: qualified.clone(),
         };
         let req = with_namespace!(req, &self.namespace);
-        match client.get(req).await {
-            Ok(_) => return Ok(()),
-            Err(e) if e.code() == tonic::Code::NotFound => {
-                info!("Image {} not found, pulling...", qualified)
-            }
+        let present = match client.get(req).await {
+            Ok(_) => true,
+            Err(e) if e.code() == tonic::Code::NotFound => false,
             Err(e) => return Err(grpc_err(e)),
+        };
+        // An image pulled for another platform has no manifest content for this one.
+        if present
+            && (platform.is_none()
+                || self
+                    .resolve_platform_manifest(&qualified, platform)
+                    .await
+                    .is_ok())
+        {
+            return Ok(false);
         }
-        let output = Command::new("ctr")
-            .arg("-n")
+        let wanted = platform
+            .map(|platform| format!(" for {}", platform))
+            .unwrap_or_default();
+        if !self.allow_image_pull {
+            return Err(AgentError::ContainerError(format!(
+                "Image {}{} not present and pulling disabled",
+                qualified, wanted
+            )));
+        }
+        info!("Image {}{} not found, pulling...", qualified, wanted);
+        let mut pull = Command::new("ctr");
+        pull.arg("-n")
             .arg(&self.namespace)
             .arg("images")
-            .arg("pull")
+            .arg("pull");
+        if let Some(platform) = platform {
+            pull.arg("--platform").arg(platform.to_string());
+        }
+        let output = pull
             .arg(&qualified)
             .output()
             .await
@@ -1382,7 +2984,43 @@ impl ContainerdRuntime {
             )));
         }
         info!("Image {} pulled", qualified);
-        Ok(())
+        Ok(true)
+    }
+
+    /// Pull `image` ahead of time without creating a container. Returns the qualified
+    /// reference, whether it had to be pulled, and its compressed size in bytes.
+    pub async fn prepull_image(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<(String, bool, u64)> {
+        let qualified = Self::qualify_image_ref(image);
+        let platform = self.effective_platform(platform);
+        let pulled = self.ensure_image(image, platform).await?;
+        let size = match self.image_size_bytes(&qualified, platform).await {
+            Ok(size) => size,
+            Err(e) => {
+                warn!("Could not determine size of image {}: {}", qualified, e);
+                0
+            }
+        };
+        Ok((qualified, pulled, size))
+    }
+
+    /// Compressed size of the image's config and layers for this platform.
+    async fn image_size_bytes(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<u64> {
+        let manifest = self.resolve_platform_manifest(image, platform).await?;
+        let size_of = |v: &serde_json::Value| v.get("size").and_then(|s| s.as_u64()).unwrap_or(0);
+        let layers: u64 = manifest
+            .get("layers")
+            .and_then(|v| v.as_array())
+            .map(|layers| layers.iter().map(size_of).sum())
+            .unwrap_or(0);
+        Ok(manifest.get("config").map(size_of).unwrap_or(0) + layers)
     }
 
     /// Normalize a Docker-style short image reference to a fully-qualified containerd reference.
@@ -1401,8 +3039,8 @@ impl ContainerdRuntime {
 
     /// Read the OCI image config to extract default environment variables.
     /// Falls back to empty vec on any error (best-effort).
-    async fn get_image_env(&self, image: &str) -> Vec<String> {
-        match self.get_image_env_inner(image).await {
+    async fn get_image_env(&self, image: &str, platform: Option<&ImagePlatform>) -> Vec<String> {
+        match self.get_image_env_inner(image, platform).await {
             Ok(env) => env,
             Err(e) => {
                 warn!("Failed to read image env for {}: {}", image, e);
@@ -1411,8 +3049,12 @@ impl ContainerdRuntime {
         }
     }
 
-    async fn get_image_env_inner(&self, image: &str) -> AgentResult<Vec<String>> {
-        let config_digest = self.resolve_image_config_digest(image).await?;
+    async fn get_image_env_inner(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<Vec<String>> {
+        let config_digest = self.resolve_image_config_digest(image, platform).await?;
 
         let config_bytes = self.read_content_blob(&config_digest).await?;
         let config: serde_json::Value = serde_json::from_slice(&config_bytes)
@@ -1430,7 +3072,47 @@ impl ContainerdRuntime {
             .unwrap_or_default())
     }
 
-    async fn resolve_image_config_digest(&self, image: &str) -> AgentResult<String> {
+    /// Digest of the image's target descriptor (its index or manifest), i.e. what the tag
+    /// currently points at.
+    async fn image_digest(&self, image: &str) -> AgentResult<String> {
+        let mut images = ImagesClient::new(self.channel.clone());
+        let req = GetImageRequest {
+            name: image.to_string(),
+        };
+        let req = with_namespace!(req, &self.namespace);
+        images
+            .get(req)
+            .await
+            .map_err(grpc_err)?
+            .into_inner()
+            .image
+            .and_then(|img| img.target)
+            .map(|target| target.digest)
+            .ok_or_else(|| AgentError::ContainerError("Image has no target descriptor".into()))
+    }
+
+    async fn resolve_image_config_digest(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<String> {
+        self.resolve_platform_manifest(image, platform)
+            .await?
+            .get("config")
+            .and_then(|c| c.get("digest"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+            .ok_or_else(|| AgentError::ContainerError("No config in manifest".into()))
+    }
+
+    /// The image manifest for `platform`, following an index to its matching entry. Without
+    /// a platform the host's linux entry is used, falling back to the index's first entry; an
+    /// explicitly requested platform must be present.
+    async fn resolve_platform_manifest(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<serde_json::Value> {
         let mut images = ImagesClient::new(self.channel.clone());
         let req = GetImageRequest {
             name: image.to_string(),
@@ -1450,40 +3132,42 @@ impl ContainerdRuntime {
             .map_err(|e| AgentError::ContainerError(format!("Bad manifest JSON: {}", e)))?;
 
         if let Some(manifests) = manifest.get("manifests").and_then(|v| v.as_array()) {
-            let manifest_digest = manifests
-                .iter()
-                .find(|m| {
-                    let p = m.get("platform");
-                    p.and_then(|p| p.get("architecture"))
-                        .and_then(|v| v.as_str())
-                        == Some("amd64")
-                        && p.and_then(|p| p.get("os")).and_then(|v| v.as_str()) == Some("linux")
-                })
-                .or_else(|| manifests.first())
+            let wanted = platform.cloned().unwrap_or_else(ImagePlatform::host);
+            let entry = manifests.iter().find(|m| wanted.matches(m.get("platform")));
+            let entry = match entry {
+                Some(entry) => Some(entry),
+                None if platform.is_some() => {
+                    return Err(AgentError::ContainerError(format!(
+                        "Image {} has no {} manifest",
+                        image, wanted
+                    )));
+                }
+                None => {
+                    warn!(
+                        "Image {} has no {} manifest; using its first entry",
+                        image, wanted
+                    );
+                    manifests.first()
+                }
+            };
+            let manifest_digest = entry
                 .and_then(|m| m.get("digest"))
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| AgentError::ContainerError("No manifest in index".into()))?;
             let inner_bytes = self.read_content_blob(manifest_digest).await?;
-            let inner: serde_json::Value = serde_json::from_slice(&inner_bytes)
-                .map_err(|e| AgentError::ContainerError(format!("Bad inner manifest: {}", e)))?;
-            return inner
-                .get("config")
-                .and_then(|c| c.get("digest"))
-                .and_then(|v| v.as_str())
-                .map(|v| v.to_string())
-                .ok_or_else(|| AgentError::ContainerError("No config in manifest".into()));
+            return serde_json::from_slice(&inner_bytes)
+                .map_err(|e| AgentError::ContainerError(format!("Bad inner manifest: {}", e)));
         }
 
-        manifest
-            .get("config")
-            .and_then(|c| c.get("digest"))
-            .and_then(|v| v.as_str())
-            .map(|v| v.to_string())
-            .ok_or_else(|| AgentError::ContainerError("No config in manifest".into()))
+        Ok(manifest)
     }
 
-    async fn resolve_snapshot_parent_key(&self, image: &str) -> AgentResult<Option<String>> {
-        let config_digest = self.resolve_image_config_digest(image).await?;
+    async fn resolve_snapshot_parent_key(
+        &self,
+        image: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<Option<String>> {
+        let config_digest = self.resolve_image_config_digest(image, platform).await?;
         let mut content = ContentClient::new(self.channel.clone());
         let req = InfoRequest {
             digest: config_digest,
@@ -1515,17 +3199,24 @@ impl ContainerdRuntime {
         Ok(data)
     }
 
-    async fn prepare_snapshot(&self, image: &str, key: &str) -> AgentResult<()> {
-        let _ = Command::new("ctr")
+    async fn prepare_snapshot(
+        &self,
+        image: &str,
+        key: &str,
+        platform: Option<&ImagePlatform>,
+    ) -> AgentResult<()> {
+        let mut unpack = Command::new("ctr");
+        unpack
             .arg("-n")
             .arg(&self.namespace)
             .arg("images")
             .arg("unpack")
             .arg("--snapshotter")
-            .arg("overlayfs")
-            .arg(image)
-            .output()
-            .await;
+            .arg("overlayfs");
+        if let Some(platform) = platform {
+            unpack.arg("--platform").arg(platform.to_string());
+        }
+        let _ = unpack.arg(image).output().await;
 
         let mut snaps = SnapshotsClient::new(self.channel.clone());
         // Try using image ref as parent first (works on some containerd setups).
@@ -1541,7 +3232,7 @@ impl ContainerdRuntime {
         }
 
         // Resolve the exact unpacked snapshot parent for this image from content labels.
-        if let Some(parent) = self.resolve_snapshot_parent_key(image).await? {
+        if let Some(parent) = self.resolve_snapshot_parent_key(image, platform).await? {
             let req = PrepareSnapshotRequest {
                 snapshotter: "overlayfs".to_string(),
                 key: key.to_string(),
@@ -1563,10 +3254,47 @@ impl ContainerdRuntime {
             );
         }
 
-        Err(AgentError::ContainerError(format!(
-            "Failed to prepare snapshot for {}",
-            image
-        )))
+        Err(AgentError::ContainerError(format!(
+            "Failed to prepare snapshot for {}",
+            image
+        )))
+    }
+
+    /// Files the container created, modified or deleted relative to its image, read from the
+    /// overlay upper dir of its rootfs snapshot. At most `limit` entries are returned; the
+    /// flag reports whether the list was cut short.
+    pub async fn container_diff(
+        &self,
+        container_id: &str,
+        limit: usize,
+    ) -> AgentResult<(Vec<FsChange>, bool)> {
+        let mounts = self
+            .get_snapshot_mounts(&format!("{}-snap", container_id))
+            .await?;
+        let options: Vec<&str> = mounts
+            .iter()
+            .filter(|m| m.r#type == "overlay")
+            .flat_map(|m| m.options.iter().map(String::as_str))
+            .collect();
+        let upper = options
+            .iter()
+            .find_map(|o| o.strip_prefix("upperdir="))
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                AgentError::ContainerError(format!(
+                    "Snapshot for {} does not expose an overlay upper dir",
+                    container_id
+                ))
+            })?;
+        let lowers: Vec<PathBuf> = options
+            .iter()
+            .find_map(|o| o.strip_prefix("lowerdir="))
+            .map(|dirs| dirs.split(':').map(PathBuf::from).collect())
+            .unwrap_or_default();
+
+        spawn_blocking(move || overlay_changes(&upper, &lowers, limit))
+            .await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?
     }
 
     async fn get_snapshot_mounts(
@@ -1625,7 +3353,7 @@ impl ContainerdRuntime {
         }
         env_map.insert("TERM".to_string(), "xterm".to_string());
         // Runtime container runs as 1000:1000; set HOME to the data dir
-        env_map.insert("HOME".to_string(), "/data".to_string());
+        env_map.insert("HOME".to_string(), config.container_data_dir.to_string());
         let env_list: Vec<String> = env_map
             .into_iter()
             .map(|(k, v)| format!("{}={}", k, v))
@@ -1644,10 +3372,23 @@ impl ContainerdRuntime {
 
         let mem_limit = (config.memory_mb as i64) * 1024 * 1024;
         let cpu_quota = (config.cpu_cores as i64) * 100_000;
-        let cgroup_path = format!("/{}/{}", self.namespace, config.container_id);
+        let cgroup_path = format!(
+            "/{}/{}",
+            config.cgroup_parent.unwrap_or(&self.namespace),
+            config.container_id
+        );
         // Runtime containers run as non-root (1000:1000) and need minimal capabilities.
         let caps = ["CAP_NET_BIND_SERVICE"];
-        let mut mounts = base_mounts(config.data_dir);
+        let mut mounts = base_mounts(config.data_dir, config.container_data_dir);
+        // Before the other mounts so a scratch dir at e.g. /tmp doesn't shadow them.
+        if let Some((path, size_mb)) = config.scratch {
+            mounts.push(serde_json::json!({
+                "destination": path,
+                "type": "tmpfs",
+                "source": "tmpfs",
+                "options": ["nosuid", "nodev", "mode=1777", format!("size={}m", size_mb)]
+            }));
+        }
         mounts.push(serde_json::json!({"destination":io_dir.to_string_lossy().to_string(),"type":"bind","source":io_dir.to_string_lossy().to_string(),"options":["rbind","rw"]}));
 
         // Generate /etc/hosts so the container hostname resolves (Java getLocalHost() etc.)
@@ -1660,19 +3401,7 @@ impl ContainerdRuntime {
         mounts.push(serde_json::json!({"destination":"/etc/hosts","type":"bind","source":hosts_path.to_string_lossy().to_string(),"options":["rbind","rw"]}));
 
         // Provide /etc/resolv.conf for DNS resolution inside the container
-        // Use configured DNS servers (defaults to 1.1.1.1, 8.8.8.8)
-        let resolv_path = io_dir.join("resolv.conf");
-        {
-            let mut resolv = String::new();
-            for dns in &self.dns_servers {
-                resolv.push_str(&format!("nameserver {}\n", dns));
-            }
-            // Add options for better DNS behavior
-            resolv.push_str("options attempts:3 timeout:2\n");
-            info!("Container {} resolv.conf:\n{}", config.container_id, resolv);
-            fs::write(&resolv_path, &resolv).ok();
-        }
-        mounts.push(serde_json::json!({"destination":"/etc/resolv.conf","type":"bind","source":resolv_path.to_string_lossy().to_string(),"options":["rbind","rw"]}));
+        mounts.extend(self.resolv_conf_mount(io_dir, config.container_id)?);
 
         for (h, c) in [
             ("/etc/machine-id", "/etc/machine-id"),
@@ -1695,32 +3424,61 @@ impl ContainerdRuntime {
         if !use_host_network {
             ns.push(serde_json::json!({"type":"network"}));
         }
+        let mut devices = vec![
+            serde_json::json!({"allow":false,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":1,"minor":3,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":1,"minor":5,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":1,"minor":8,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":1,"minor":9,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":5,"minor":0,"access":"rwm"}),
+            serde_json::json!({"allow":true,"type":"c","major":5,"minor":1,"access":"rwm"}),
+        ];
+        if config.tty {
+            // /dev/ptmx and the /dev/pts/* slaves backing the allocated terminal.
+            devices.push(
+                serde_json::json!({"allow":true,"type":"c","major":5,"minor":2,"access":"rwm"}),
+            );
+            devices.push(serde_json::json!({"allow":true,"type":"c","major":136,"access":"rwm"}));
+        }
 
-        Ok(serde_json::json!({
+        let mut spec = serde_json::json!({
             "ociVersion":"1.1.0",
-            "process":{"terminal":false,"user":{"uid":1000,"gid":1000},"args":args,"env":env_list,"cwd":"/data",
+            "process":{"terminal":config.tty,"user":{"uid":1000,"gid":1000},"args":args,"env":env_list,"cwd":config.container_data_dir,
                 "capabilities":{"bounding":caps,"effective":caps,"permitted":caps,"ambient":caps},
                 "noNewPrivileges":true,"rlimits":[{"type":"RLIMIT_NOFILE","hard":65536u64,"soft":65536u64}]},
             "root":{"path":"rootfs","readonly":false},"hostname":config.container_id,"mounts":mounts,
             "linux":{"cgroupsPath":cgroup_path,"resources":{"memory":{"limit":mem_limit},"cpu":{"quota":cpu_quota,"period":100000u64},
-                "devices":[{"allow":false,"access":"rwm"},{"allow":true,"type":"c","major":1,"minor":3,"access":"rwm"},
-                    {"allow":true,"type":"c","major":1,"minor":5,"access":"rwm"},{"allow":true,"type":"c","major":1,"minor":8,"access":"rwm"},
-                    {"allow":true,"type":"c","major":1,"minor":9,"access":"rwm"},{"allow":true,"type":"c","major":5,"minor":0,"access":"rwm"},
-                    {"allow":true,"type":"c","major":5,"minor":1,"access":"rwm"}]},
-                "namespaces":ns,"maskedPaths":masked_paths(),"readonlyPaths":readonly_paths(),
-                "seccomp": default_seccomp_profile()}
-        }))
+                "devices":devices},
+                "namespaces":ns,"maskedPaths":config.path_profile.masked_paths(),
+                "readonlyPaths":config.path_profile.readonly_paths(use_host_network),
+                "seccomp": default_seccomp_profile(config.seccomp_audit)}
+        });
+        if let Some(adj) = config.oom_score_adj {
+            spec["process"]["oomScoreAdj"] = serde_json::json!(adj);
+        }
+        if !config.annotations.is_empty() {
+            spec["annotations"] = serde_json::json!(config.annotations);
+        }
+        Ok(spec)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn setup_cni_network(
         &self,
         container_id: &str,
         pid: u32,
         network_mode: Option<&str>,
         network_ip: Option<&str>,
+        mtu: Option<u32>,
         primary_port: u16,
         port_bindings: &HashMap<u16, u16>,
     ) -> AgentResult<()> {
+        // A pending teardown retry for an earlier run of this id would otherwise delete the
+        // network config written below and take the new container off the network.
+        self.failed_cni_teardowns
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(container_id);
         let network = network_mode.unwrap_or("bridge");
         if network == "host" {
             return Ok(());
@@ -1760,7 +3518,7 @@ impl ContainerdRuntime {
                         "gateway": "10.42.0.1"
                     }]],
                     "routes": [{"dst": "0.0.0.0/0"}],
-                    "dataDir": "/var/lib/cni/networks"
+                    "dataDir": self.cni_networks_dir()
                 }
             })
         } else {
@@ -1770,6 +3528,17 @@ impl ContainerdRuntime {
                 if cfg.get("dns").is_none() {
                     cfg["dns"] = dns_config.clone();
                 }
+                // Keep host-local allocations under the configured CNI data dir.
+                if let Some(ipam) = cfg.get_mut("ipam").and_then(|v| v.as_object_mut()) {
+                    if ipam.get("type").and_then(|v| v.as_str()) == Some("host-local")
+                        && !ipam.contains_key("dataDir")
+                    {
+                        ipam.insert(
+                            "dataDir".to_string(),
+                            serde_json::json!(self.cni_networks_dir()),
+                        );
+                    }
+                }
                 cfg
             } else {
                 // Fallback: synthesize a macvlan config from detected host network.
@@ -1804,7 +3573,7 @@ impl ContainerdRuntime {
                             "gateway": gateway
                         }]],
                         "routes": [{"dst": "0.0.0.0/0"}],
-                        "dataDir": "/var/lib/cni/networks"
+                        "dataDir": self.cni_networks_dir()
                     }
                 })
             }
@@ -1829,15 +3598,20 @@ impl ContainerdRuntime {
                 );
             }
         }
+        if let Some(mtu) = mtu {
+            // Both the bridge and macvlan plugins honour a top-level `mtu`.
+            cfg["mtu"] = serde_json::json!(mtu);
+        }
         // Store CNI config for proper teardown
-        let cfg_path = format!("/var/lib/cni/results/catalyst-{}-config", container_id);
+        let _ = fs::create_dir_all(self.cni_results_dir());
+        let cfg_path = self.cni_config_path(container_id);
         if let Ok(j) = serde_json::to_string(&cfg) {
             let _ = fs::write(&cfg_path, &j);
         }
         let result = self
             .exec_cni_plugin(&cfg, "ADD", container_id, &netns, "eth0")
             .await?;
-        let rp = format!("/var/lib/cni/results/catalyst-{}", container_id);
+        let rp = self.cni_result_path(container_id);
         if let Ok(j) = serde_json::to_string_pretty(&result) {
             let _ = fs::write(&rp, &j);
         }
@@ -1875,10 +3649,7 @@ impl ContainerdRuntime {
                     container_ip: cip.to_string(),
                     forwards,
                 };
-                let state_path = format!(
-                    "{}/{}{}-ports.json",
-                    PORT_FWD_STATE_DIR, PORT_FWD_STATE_PREFIX, container_id
-                );
+                let state_path = self.port_forward_state_path(container_id);
                 if let Ok(j) = serde_json::to_string_pretty(&state) {
                     let _ = fs::write(&state_path, &j);
                 }
@@ -2140,11 +3911,167 @@ impl ContainerdRuntime {
         Ok(())
     }
 
-    async fn teardown_port_forward(&self, container_id: &str) -> AgentResult<()> {
-        let state_path = format!(
-            "{}/{}{}-ports.json",
-            PORT_FWD_STATE_DIR, PORT_FWD_STATE_PREFIX, container_id
+    /// Cross-check reported `container -> host` port bindings against the DNAT rules actually
+    /// present in the nat table. Returns the bindings with a missing rule, or `None` when the
+    /// agent manages no port forwarding for this container (host or macvlan networking).
+    pub async fn missing_port_forwards(
+        &self,
+        container_id: &str,
+        port_bindings: &HashMap<u16, u16>,
+    ) -> AgentResult<Option<Vec<(u16, u16)>>> {
+        let state_path = self.port_forward_state_path(container_id);
+        let raw = match fs::read_to_string(&state_path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(AgentError::IoError(e.to_string())),
+        };
+        let state: PortForwardState = serde_json::from_str(&raw)?;
+        let expected: Vec<(u16, u16)> = if port_bindings.is_empty() {
+            state
+                .forwards
+                .iter()
+                .map(|f| (f.container_port, f.host_port))
+                .collect()
+        } else {
+            port_bindings.iter().map(|(cp, hp)| (*cp, *hp)).collect()
+        };
+
+        let mut missing = Vec::new();
+        for (cp, hp) in expected {
+            let dest = format!("{}:{}", state.container_ip, cp);
+            let hps = hp.to_string();
+            let mut present = true;
+            for proto in ["tcp", "udp"] {
+                let o = Command::new("iptables")
+                    .args([
+                        "-t",
+                        "nat",
+                        "-C",
+                        "PREROUTING",
+                        "-p",
+                        proto,
+                        "--dport",
+                        &hps,
+                        "-j",
+                        "DNAT",
+                        "--to-destination",
+                        &dest,
+                    ])
+                    .output()
+                    .await?;
+                present &= o.status.success();
+            }
+            if !present {
+                missing.push((cp, hp));
+            }
+        }
+        Ok(Some(missing))
+    }
+
+    /// Replace a running container's port forwards with `port_bindings`, keeping its IP.
+    /// New host ports must not be forwarded to another container or bound on the host.
+    /// On failure the previous forwards are restored.
+    pub async fn update_port_forwards(
+        &self,
+        container_id: &str,
+        port_bindings: &HashMap<u16, u16>,
+    ) -> AgentResult<()> {
+        let state_path = self.port_forward_state_path(container_id);
+        let raw = match fs::read_to_string(&state_path) {
+            Ok(v) => v,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Container {} has no agent-managed port forwards",
+                    container_id
+                )))
+            }
+            Err(e) => return Err(AgentError::IoError(e.to_string())),
+        };
+        let old_state: PortForwardState = serde_json::from_str(&raw)?;
+        let own_ports: HashSet<u16> = old_state.forwards.iter().map(|f| f.host_port).collect();
+        let claimed = self.forwarded_host_ports();
+        for hp in port_bindings.values() {
+            if own_ports.contains(hp) {
+                continue;
+            }
+            let in_use = std::net::TcpListener::bind(("0.0.0.0", *hp)).is_err()
+                || std::net::UdpSocket::bind(("0.0.0.0", *hp)).is_err();
+            if claimed.contains(hp) || in_use {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Host port {} is already in use",
+                    hp
+                )));
+            }
+        }
+
+        let cip = old_state.container_ip.clone();
+        for fwd in &old_state.forwards {
+            let _ = self
+                .teardown_port_forward_rules(fwd.host_port, fwd.container_port, &cip)
+                .await;
+        }
+        let mut applied: Vec<PortForward> = Vec::new();
+        for (cp, hp) in port_bindings {
+            if let Err(e) = self.setup_port_forward(*hp, *cp, &cip).await {
+                warn!(
+                    "Port forward update failed for {}, restoring previous forwards: {}",
+                    container_id, e
+                );
+                for fwd in &applied {
+                    let _ = self
+                        .teardown_port_forward_rules(fwd.host_port, fwd.container_port, &cip)
+                        .await;
+                }
+                let _ = self.teardown_port_forward_rules(*hp, *cp, &cip).await;
+                for fwd in &old_state.forwards {
+                    let _ = self
+                        .setup_port_forward(fwd.host_port, fwd.container_port, &cip)
+                        .await;
+                }
+                return Err(e);
+            }
+            applied.push(PortForward {
+                host_port: *hp,
+                container_port: *cp,
+            });
+        }
+
+        let state = PortForwardState {
+            container_ip: cip,
+            forwards: applied,
+        };
+        fs::write(&state_path, serde_json::to_string_pretty(&state)?)
+            .map_err(|e| AgentError::IoError(e.to_string()))?;
+        info!(
+            "Updated port forwards for {}: {:?}",
+            container_id, port_bindings
         );
+        Ok(())
+    }
+
+    /// Host ports claimed by any container's port-forward state, running or not.
+    pub fn forwarded_host_ports(&self) -> HashSet<u16> {
+        let mut ports = HashSet::new();
+        let Ok(entries) = fs::read_dir(self.cni_results_dir()) else {
+            return ports;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(PORT_FWD_STATE_PREFIX) || !name.ends_with("-ports.json") {
+                continue;
+            }
+            let Ok(raw) = fs::read_to_string(entry.path()) else {
+                continue;
+            };
+            if let Ok(state) = serde_json::from_str::<PortForwardState>(&raw) {
+                ports.extend(state.forwards.iter().map(|f| f.host_port));
+            }
+        }
+        ports
+    }
+
+    async fn teardown_port_forward(&self, container_id: &str) -> AgentResult<()> {
+        let state_path = self.port_forward_state_path(container_id);
         if !Path::new(&state_path).exists() {
             return Ok(());
         }
@@ -2152,7 +4079,11 @@ impl ContainerdRuntime {
         let raw = match fs::read_to_string(&state_path) {
             Ok(v) => v,
             Err(e) => {
-                warn!("Failed to read port-forward state {}: {}", state_path, e);
+                warn!(
+                    "Failed to read port-forward state {}: {}",
+                    state_path.display(),
+                    e
+                );
                 let _ = fs::remove_file(&state_path);
                 return Ok(());
             }
@@ -2160,7 +4091,11 @@ impl ContainerdRuntime {
         let state: PortForwardState = match serde_json::from_str(&raw) {
             Ok(v) => v,
             Err(e) => {
-                warn!("Failed to parse port-forward state {}: {}", state_path, e);
+                warn!(
+                    "Failed to parse port-forward state {}: {}",
+                    state_path.display(),
+                    e
+                );
                 let _ = fs::remove_file(&state_path);
                 return Ok(());
             }
@@ -2260,15 +4195,15 @@ impl ContainerdRuntime {
 
     async fn teardown_cni_network(&self, container_id: &str) -> AgentResult<()> {
         let _ = self.teardown_port_forward(container_id).await;
-        let rp = format!("/var/lib/cni/results/catalyst-{}", container_id);
-        if !Path::new(&rp).exists() {
+        let rp = self.cni_result_path(container_id);
+        if !rp.exists() {
             return Ok(());
         }
         // Load stored CNI config for proper teardown (bridge vs macvlan)
-        let cfg_path = format!("/var/lib/cni/results/catalyst-{}-config", container_id);
+        let cfg_path = self.cni_config_path(container_id);
         let cfg = fs::read_to_string(&cfg_path).ok()
             .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok())
-            .unwrap_or_else(|| serde_json::json!({"cniVersion":"1.0.0","name":"catalyst","type":"bridge","bridge":"catalyst0","ipam":{"type":"host-local","dataDir":"/var/lib/cni/networks"}}));
+            .unwrap_or_else(|| serde_json::json!({"cniVersion":"1.0.0","name":"catalyst","type":"bridge","bridge":"catalyst0","ipam":{"type":"host-local","dataDir":self.cni_networks_dir()}}));
         let mut tasks = TasksClient::new(self.channel.clone());
         let req = containerd_client::services::v1::GetRequest {
             container_id: container_id.to_string(),
@@ -2283,16 +4218,124 @@ impl ContainerdRuntime {
                 .unwrap_or_default(),
             Err(_) => String::new(),
         };
-        if !netns.is_empty() {
-            let _ = self
-                .exec_cni_plugin(&cfg, "DEL", container_id, &netns, "eth0")
-                .await;
+        // DEL still runs once the task is gone: plugins release the IPAM lease without a netns.
+        let mut result = self.exec_cni_del(&cfg, container_id, &netns).await;
+        for attempt in 0..self.cni_teardown_retries {
+            let Err(e) = &result else {
+                break;
+            };
+            debug!(
+                "CNI DEL for {} failed (attempt {}): {}",
+                container_id,
+                attempt + 1,
+                e
+            );
+            tokio::time::sleep(CNI_TEARDOWN_BACKOFF * 2u32.pow(attempt)).await;
+            result = self.exec_cni_del(&cfg, container_id, &netns).await;
         }
+        self.release_ip_leases(&cfg, &rp, container_id);
         let _ = fs::remove_file(&rp);
+        if let Err(e) = result {
+            warn!(
+                "CNI teardown for {} failed after {} attempt(s), will retry later: {}",
+                container_id,
+                self.cni_teardown_retries + 1,
+                e
+            );
+            self.failed_cni_teardowns
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(container_id.to_string());
+            return Err(e);
+        }
         let _ = fs::remove_file(&cfg_path);
         Ok(())
     }
 
+    async fn exec_cni_del(
+        &self,
+        cfg: &serde_json::Value,
+        container_id: &str,
+        netns: &str,
+    ) -> AgentResult<()> {
+        self.exec_cni_plugin(cfg, "DEL", container_id, netns, "eth0")
+            .await
+            .map(|_| ())
+    }
+
+    /// Remove host-local leases the plugin should have released for this container. Leases
+    /// are only removed while they still name the container, in case the IP was reassigned.
+    fn release_ip_leases(&self, cfg: &serde_json::Value, result_path: &Path, container_id: &str) {
+        let Some(result) = fs::read_to_string(result_path)
+            .ok()
+            .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok())
+        else {
+            return;
+        };
+        let leases_dir = cfg["ipam"]["dataDir"]
+            .as_str()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.cni_networks_dir())
+            .join(cfg["name"].as_str().unwrap_or("catalyst"));
+        let ips = result["ips"].as_array().into_iter().flatten();
+        for addr in ips.filter_map(|ip| ip["address"].as_str()) {
+            let lease = leases_dir.join(addr.split('/').next().unwrap_or(addr));
+            let owned = fs::read_to_string(&lease)
+                .map(|owner| owner.lines().next() == Some(container_id))
+                .unwrap_or(false);
+            if owned && fs::remove_file(&lease).is_ok() {
+                info!(
+                    "Released IP lease {} left behind by CNI teardown of {}",
+                    lease.display(),
+                    container_id
+                );
+            }
+        }
+    }
+
+    /// Retry the CNI DEL for containers whose teardown failed earlier. Returns how many were
+    /// cleaned up.
+    pub async fn retry_failed_cni_teardowns(&self) -> usize {
+        let pending: Vec<String> = self
+            .failed_cni_teardowns
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .cloned()
+            .collect();
+        let mut cleaned = 0;
+        for container_id in pending {
+            // Ids are reused across restarts; never tear down the network of a live container.
+            if self.container_exists(&container_id).await {
+                continue;
+            }
+            let cfg_path = self.cni_config_path(&container_id);
+            let cfg = fs::read_to_string(&cfg_path)
+                .ok()
+                .and_then(|c| serde_json::from_str::<serde_json::Value>(&c).ok());
+            let done = match cfg {
+                // Nothing left to retry with; the sweep can't do better than the lease check.
+                None => true,
+                Some(cfg) => match self.exec_cni_del(&cfg, &container_id, "").await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        debug!("CNI teardown retry for {} failed: {}", container_id, e);
+                        false
+                    }
+                },
+            };
+            if done {
+                let _ = fs::remove_file(&cfg_path);
+                self.failed_cni_teardowns
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&container_id);
+                cleaned += 1;
+            }
+        }
+        cleaned
+    }
+
     fn cleanup_io(&self, container_id: &str) {
         let _ = fs::remove_dir_all(PathBuf::from(CONSOLE_BASE_DIR).join(container_id));
     }
@@ -2457,6 +4500,18 @@ fn open_fifo_rdwr(path: &Path) -> AgentResult<File> {
     Ok(file)
 }
 
+/// A full stdin FIFO means nothing in the container is reading it (the soft-locked console).
+fn fifo_has_room(file: &File) -> bool {
+    let mut fd = libc::pollfd {
+        fd: file.as_raw_fd(),
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    // SAFETY: a single valid pollfd and a zero timeout.
+    let ready = unsafe { libc::poll(&mut fd, 1, 0) };
+    ready == 1 && fd.revents & libc::POLLOUT != 0 && fd.revents & libc::POLLERR == 0
+}
+
 fn set_dir_perms(path: &Path, mode: u32) {
     if let Ok(md) = fs::metadata(path) {
         let mut p = md.permissions();
@@ -2470,11 +4525,20 @@ fn shell_escape_value(value: &str) -> String {
     format!("'{}'", escaped)
 }
 
+/// Signals a template may choose as its graceful stop signal (`sendSignalTo`).
+pub const STOP_SIGNALS: &[&str] = &[
+    "SIGTERM", "SIGINT", "SIGHUP", "SIGQUIT", "SIGUSR1", "SIGUSR2",
+];
+
 fn parse_signal(signal: &str) -> u32 {
     match signal.to_ascii_uppercase().as_str() {
-        "SIGTERM" | "15" => 15,
+        "SIGHUP" | "1" => 1,
         "SIGINT" | "2" => 2,
+        "SIGQUIT" | "3" => 3,
         "SIGKILL" | "9" => 9,
+        "SIGUSR1" | "10" => 10,
+        "SIGUSR2" | "12" => 12,
+        "SIGTERM" | "15" => 15,
         _ => 9,
     }
 }
@@ -2493,9 +4557,58 @@ fn is_not_found(e: &tonic::Status) -> bool {
         || e.code() == tonic::Code::NotFound
 }
 
-fn base_mounts(data_dir: &str) -> Vec<serde_json::Value> {
+/// Walk an overlay upper dir. Whiteouts (0/0 char devices) are deletions; other entries are
+/// modifications when a lower layer has the same path and additions otherwise.
+fn overlay_changes(
+    upper: &Path,
+    lowers: &[PathBuf],
+    limit: usize,
+) -> AgentResult<(Vec<FsChange>, bool)> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let mut changes = Vec::new();
+    let mut truncated = false;
+    let mut pending = vec![upper.to_path_buf()];
+    'walk: while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir).map_err(|e| AgentError::FileSystemError(e.to_string()))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(meta) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            let Ok(relative) = path.strip_prefix(upper) else {
+                continue;
+            };
+            if changes.len() >= limit {
+                truncated = true;
+                break 'walk;
+            }
+            let kind = if meta.file_type().is_char_device() && meta.rdev() == 0 {
+                "deleted"
+            } else if lowers
+                .iter()
+                .any(|lower| fs::symlink_metadata(lower.join(relative)).is_ok())
+            {
+                "modified"
+            } else {
+                "added"
+            };
+            changes.push(FsChange {
+                kind,
+                path: format!("/{}", relative.display()),
+            });
+            if meta.is_dir() {
+                pending.push(path);
+            }
+        }
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok((changes, truncated))
+}
+
+fn base_mounts(data_dir: &str, destination: &str) -> Vec<serde_json::Value> {
     vec![
-        serde_json::json!({"destination":"/data","type":"bind","source":data_dir,"options":["rbind","rw"]}),
+        serde_json::json!({"destination":destination,"type":"bind","source":data_dir,"options":["rbind","rw"]}),
         serde_json::json!({"destination":"/proc","type":"proc","source":"proc"}),
         serde_json::json!({"destination":"/dev","type":"tmpfs","source":"tmpfs","options":["nosuid","strictatime","mode=755","size=65536k"]}),
         serde_json::json!({"destination":"/dev/pts","type":"devpts","source":"devpts","options":["nosuid","noexec","newinstance","ptmxmode=0666","mode=0620","gid=5"]}),
@@ -2544,50 +4657,77 @@ fn seccomp_arches() -> Vec<&'static str> {
     }
 }
 
-fn default_seccomp_profile() -> serde_json::Value {
+fn default_seccomp_profile(audit: bool) -> serde_json::Value {
     // Deny-list a small set of high-risk syscalls while keeping broad compatibility.
     // This is intentionally conservative; consumers can harden further via host policy.
+    // Audit mode lets the same calls through but logs them (visible via the kernel audit log)
+    // so operators can see what a workload would trip over before enforcing.
+    let mut deny_rule = serde_json::json!({
+        "names": [
+            "acct",
+            "add_key",
+            "bpf",
+            "delete_module",
+            "finit_module",
+            "init_module",
+            "iopl",
+            "ioperm",
+            "kexec_file_load",
+            "kexec_load",
+            "keyctl",
+            "mount",
+            "open_by_handle_at",
+            "perf_event_open",
+            "pivot_root",
+            "process_vm_readv",
+            "process_vm_writev",
+            "ptrace",
+            "quotactl",
+            "reboot",
+            "request_key",
+            "setns",
+            "swapoff",
+            "swapon",
+            "syslog",
+            "umount2",
+            "unshare"
+        ]
+    });
+    if audit {
+        deny_rule["action"] = serde_json::json!("SCMP_ACT_LOG");
+    } else {
+        deny_rule["action"] = serde_json::json!("SCMP_ACT_ERRNO");
+        deny_rule["errnoRet"] = serde_json::json!(1);
+    }
     serde_json::json!({
         "defaultAction": "SCMP_ACT_ALLOW",
         "architectures": seccomp_arches(),
-        "syscalls": [
-            {
-                "names": [
-                    "acct",
-                    "add_key",
-                    "bpf",
-                    "delete_module",
-                    "finit_module",
-                    "init_module",
-                    "iopl",
-                    "ioperm",
-                    "kexec_file_load",
-                    "kexec_load",
-                    "keyctl",
-                    "mount",
-                    "open_by_handle_at",
-                    "perf_event_open",
-                    "pivot_root",
-                    "process_vm_readv",
-                    "process_vm_writev",
-                    "ptrace",
-                    "quotactl",
-                    "reboot",
-                    "request_key",
-                    "setns",
-                    "swapoff",
-                    "swapon",
-                    "syslog",
-                    "umount2",
-                    "unshare"
-                ],
-                "action": "SCMP_ACT_ERRNO",
-                "errnoRet": 1
-            }
-        ]
+        "syscalls": [deny_rule]
     })
 }
 
+/// The host resolv.conf to hand to containers. A systemd-resolved stub (127.0.0.53) is not
+/// reachable from a container network namespace, so prefer the upstream list it manages.
+fn host_resolv_conf_path() -> PathBuf {
+    const RESOLVED_UPSTREAM: &str = "/run/systemd/resolve/resolv.conf";
+    let host = PathBuf::from("/etc/resolv.conf");
+    let stub_only = fs::read_to_string(&host)
+        .map(|content| {
+            let mut nameservers = content
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("nameserver"))
+                .map(str::trim)
+                .peekable();
+            nameservers.peek().is_some() && nameservers.all(|ns| ns.starts_with("127."))
+        })
+        .unwrap_or(false);
+    if stub_only && Path::new(RESOLVED_UPSTREAM).exists() {
+        PathBuf::from(RESOLVED_UPSTREAM)
+    } else {
+        host
+    }
+}
+
 fn find_container_cgroup(container_id: &str) -> Option<String> {
     find_cgroup_recursive("/sys/fs/cgroup", container_id)
 }
@@ -2607,21 +4747,17 @@ fn find_cgroup_recursive(dir: &str, cid: &str) -> Option<String> {
     None
 }
 
-async fn read_cgroup_cpu_percent(path: &str) -> Option<f64> {
+/// Total CPU time (µs) the cgroup has used, from `usage_usec` in `cpu.stat`.
+async fn read_cgroup_cpu_usage_usec(path: &str) -> Option<u64> {
     let content = tokio::fs::read_to_string(format!("{}/cpu.stat", path))
         .await
         .ok()?;
-    for line in content.lines() {
-        if line.starts_with("usage_usec") {
-            return line
-                .split_whitespace()
-                .nth(1)?
-                .parse::<u64>()
-                .ok()
-                .map(|u| u as f64 / 1_000_000.0);
-        }
-    }
-    Some(0.0)
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("usage_usec"))?
+        .trim()
+        .parse()
+        .ok()
 }
 
 async fn read_cgroup_memory(path: &str) -> Option<u64> {
@@ -2632,3 +4768,266 @@ async fn read_cgroup_memory(path: &str) -> Option<u64> {
         .parse()
         .ok()
 }
+
+/// `some avg10` from the cgroup's `io.pressure`. Missing without CONFIG_PSI or with `psi=0`.
+async fn read_cgroup_io_pressure(path: &str) -> Option<f64> {
+    let pressure = tokio::fs::read_to_string(format!("{}/io.pressure", path))
+        .await
+        .ok()?;
+    pressure
+        .lines()
+        .find_map(|line| line.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Bytes read and written by the cgroup, summed over devices in `io.stat`.
+async fn read_cgroup_io_bytes(path: &str) -> Option<(u64, u64)> {
+    let stat = tokio::fs::read_to_string(format!("{}/io.stat", path))
+        .await
+        .ok()?;
+    let mut read = 0u64;
+    let mut written = 0u64;
+    for field in stat
+        .lines()
+        .flat_map(|line| line.split_whitespace().skip(1))
+    {
+        if let Some(value) = field.strip_prefix("rbytes=") {
+            read += value.parse::<u64>().unwrap_or(0);
+        } else if let Some(value) = field.strip_prefix("wbytes=") {
+            written += value.parse::<u64>().unwrap_or(0);
+        }
+    }
+    Some((read, written))
+}
+
+/// Bytes received and sent on every interface but `lo` in the network namespace of `pid`.
+/// `None` for processes in the agent's own namespace (host networking), whose counters are
+/// the whole node's traffic.
+async fn read_netns_bytes(pid: u32) -> Option<(u64, u64)> {
+    let netns = tokio::fs::read_link(format!("/proc/{}/ns/net", pid))
+        .await
+        .ok()?;
+    if tokio::fs::read_link("/proc/self/ns/net").await.ok()? == netns {
+        return None;
+    }
+    let dev = tokio::fs::read_to_string(format!("/proc/{}/net/dev", pid))
+        .await
+        .ok()?;
+    let mut received = 0u64;
+    let mut sent = 0u64;
+    // Two header lines, then `iface: rx_bytes rx_packets ... (8 rx fields) tx_bytes ...`.
+    for line in dev.lines().skip(2) {
+        let Some((iface, counters)) = line.split_once(':') else {
+            continue;
+        };
+        if iface.trim() == "lo" {
+            continue;
+        }
+        let fields: Vec<u64> = counters
+            .split_whitespace()
+            .map(|v| v.parse().unwrap_or(0))
+            .collect();
+        received += fields.first().copied().unwrap_or(0);
+        sent += fields.get(8).copied().unwrap_or(0);
+    }
+    Some((received, sent))
+}
+
+/// The host architecture as OCI platforms name it (`amd64`, `arm64`, ...).
+pub fn host_oci_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+        "powerpc64" => "ppc64",
+        "mips64" if cfg!(target_endian = "little") => "mips64le",
+        "loongarch64" => "loong64",
+        other => other,
+    }
+}
+
+/// Count conntrack entries whose original or reply addresses include one of `ips`. Reads
+/// `/proc/net/nf_conntrack`, falling back to `conntrack -L`, and stops after
+/// `MAX_CONNTRACK_ENTRIES` lines (the flag reports whether it did). `None` when neither source
+/// is available.
+pub async fn conntrack_counts(
+    ips: &HashSet<String>,
+) -> Option<(HashMap<String, ConnectionCounts>, bool)> {
+    use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+
+    async fn count<R: AsyncBufRead + Unpin>(
+        reader: R,
+        ips: &HashSet<String>,
+    ) -> (HashMap<String, ConnectionCounts>, bool) {
+        let mut counts: HashMap<String, ConnectionCounts> = HashMap::new();
+        let mut lines = reader.lines();
+        let mut read = 0;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if read == MAX_CONNTRACK_ENTRIES {
+                return (counts, true);
+            }
+            read += 1;
+            // "ipv4 2 tcp 6 431999 ESTABLISHED src=.. dst=.. sport=.. dport=.. src=.. dst=.. ..."
+            let mut fields = line.split_whitespace();
+            let Some(protocol) = fields.nth(2) else {
+                continue;
+            };
+            let mut matched: Vec<&str> = Vec::new();
+            let mut half_open = false;
+            for field in fields {
+                match field {
+                    "SYN_SENT" | "SYN_RECV" => half_open = true,
+                    _ => {
+                        let addr = field
+                            .strip_prefix("src=")
+                            .or_else(|| field.strip_prefix("dst="));
+                        if let Some(addr) = addr.filter(|a| ips.contains(*a)) {
+                            if !matched.contains(&addr) {
+                                matched.push(addr);
+                            }
+                        }
+                    }
+                }
+            }
+            for addr in matched {
+                let entry = counts.entry(addr.to_string()).or_default();
+                match protocol {
+                    "tcp" => {
+                        entry.tcp += 1;
+                        if half_open {
+                            entry.half_open += 1;
+                        }
+                    }
+                    "udp" => entry.udp += 1,
+                    _ => entry.other += 1,
+                }
+            }
+        }
+        (counts, false)
+    }
+
+    if let Ok(file) = tokio::fs::File::open("/proc/net/nf_conntrack").await {
+        return Some(count(BufReader::new(file), ips).await);
+    }
+    // Kernels built without CONFIG_NF_CONNTRACK_PROCFS; the extended format matches the proc file.
+    let mut child = Command::new("conntrack")
+        .args(["-L", "-o", "extended"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .ok()?;
+    let stdout = child.stdout.take()?;
+    let result = count(BufReader::new(stdout), ips).await;
+    let _ = child.kill().await;
+    Some(result)
+}
+
+/// Parent PID and cumulative user+system CPU ticks from `/proc/<pid>/stat`.
+pub fn read_proc_stat(pid: u32) -> Option<(u32, u64)> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name may contain spaces and parentheses; fields resume after the last ')'.
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let ppid = fields.get(1)?.parse().ok()?;
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some((ppid, utime + stime))
+}
+
+/// Kernel clock ticks per second, the unit of the CPU times in `/proc/<pid>/stat`.
+pub fn clock_ticks_per_sec() -> f64 {
+    // SAFETY: sysconf has no preconditions.
+    match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+        t if t > 0 => t as f64,
+        _ => 100.0,
+    }
+}
+
+pub async fn read_proc_rss(pid: u32) -> Option<u64> {
+    let status = tokio::fs::read_to_string(format!("/proc/{}/status", pid))
+        .await
+        .ok()?;
+    let kb: u64 = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Full command line, or the bracketed thread name for kernel-style processes without one.
+async fn read_proc_command(pid: u32) -> String {
+    let cmdline = tokio::fs::read(format!("/proc/{}/cmdline", pid))
+        .await
+        .unwrap_or_default();
+    let mut command = String::from_utf8_lossy(&cmdline)
+        .split('\0')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if command.is_empty() {
+        let comm = tokio::fs::read_to_string(format!("/proc/{}/comm", pid))
+            .await
+            .unwrap_or_default();
+        command = format!("[{}]", comm.trim());
+    }
+    if command.len() > MAX_PROCESS_COMMAND_LEN {
+        let mut end = MAX_PROCESS_COMMAND_LEN;
+        while !command.is_char_boundary(end) {
+            end -= 1;
+        }
+        command.truncate(end);
+        command.push('…');
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `path` is covered by one of `paths` (the entry itself or an ancestor).
+    fn covered(paths: &[&str], path: &str) -> bool {
+        paths
+            .iter()
+            .any(|p| path == *p || path.starts_with(&format!("{}/", p)))
+    }
+
+    #[test]
+    fn path_profiles_build_expected_spec_lists() {
+        for profile in [
+            PathProfile::Default,
+            PathProfile::SysctlFriendly,
+            PathProfile::Strict,
+        ] {
+            let masked = profile.masked_paths();
+            assert!(!covered(&masked, "/proc/sys/net"), "{:?}", profile);
+            assert!(covered(&masked, "/proc/kcore"), "{:?}", profile);
+            for host_network in [false, true] {
+                let readonly = profile.readonly_paths(host_network);
+                assert!(covered(&readonly, "/proc/sys/kernel"), "{:?}", profile);
+                assert!(covered(&readonly, "/proc/sysrq-trigger"), "{:?}", profile);
+                let net_writable = profile == PathProfile::SysctlFriendly && !host_network;
+                assert_eq!(
+                    covered(&readonly, "/proc/sys/net"),
+                    !net_writable,
+                    "{:?} host_network={}",
+                    profile,
+                    host_network
+                );
+            }
+        }
+        assert!(covered(&PathProfile::Strict.masked_paths(), "/proc/keys"));
+        assert!(!covered(&PathProfile::Default.masked_paths(), "/proc/keys"));
+        assert_eq!(
+            PathProfile::parse("sysctl-friendly"),
+            Some(PathProfile::SysctlFriendly)
+        );
+    }
+}
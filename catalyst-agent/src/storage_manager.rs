@@ -1,19 +1,85 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use tokio::task::spawn_blocking;
 use tracing::info;
 
+use crate::config::DataLayout;
+use crate::runtime_manager::ProcessPriority;
 use crate::{AgentError, AgentResult};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 pub struct StorageManager {
     data_dir: PathBuf,
+    layout: DataLayout,
+    /// Serializes read-modify-write of the autostart file.
+    autostart_lock: tokio::sync::Mutex<()>,
+    /// Serializes read-modify-write of the start state file.
+    start_state_lock: tokio::sync::Mutex<()>,
+}
+
+/// Restart-on-boot bookkeeping, kept on disk so it outlives a node reboot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutostartState {
+    /// Kernel boot id when the file was last written, telling a reboot from an agent restart.
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    #[serde(default)]
+    pub servers: BTreeMap<String, ServerAutostart>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerAutostart {
+    /// The last state reported for the server was `running`.
+    #[serde(default)]
+    pub running: bool,
+    /// The backend wants the server started again after a reboot.
+    #[serde(default)]
+    pub autostart: bool,
+    /// Last start message of an autostart server, replayed to start it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Value>,
+}
+
+/// How a server was last started and the `update_environment` changes still pending for
+/// it, kept on disk so an agent restart can restart it the same way.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStartState {
+    /// Last successful start message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Value>,
+    /// Environment changes applied to every start (`None` unsets).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, Option<String>>,
+}
+
+/// Filesystems whose free blocks can be discarded while mounted and in use.
+const ONLINE_TRIM_FILESYSTEMS: &[&str] = &["ext4", "xfs", "btrfs"];
+
+/// A host filesystem holding server data, identified by its mount point.
+#[derive(Clone, Debug)]
+pub struct DataVolume {
+    pub mount_point: PathBuf,
+    pub free_mb: u64,
+    pub total_mb: u64,
+    pub inodes_used: u64,
+    pub inodes_total: u64,
 }
 
 impl StorageManager {
-    pub fn new(data_dir: PathBuf) -> Self {
-        Self { data_dir }
+    pub fn new(data_dir: PathBuf, layout: DataLayout) -> Self {
+        Self {
+            data_dir,
+            layout,
+            autostart_lock: tokio::sync::Mutex::new(()),
+            start_state_lock: tokio::sync::Mutex::new(()),
+        }
     }
 
     pub async fn ensure_mounted(
@@ -75,6 +141,273 @@ impl StorageManager {
         Ok(())
     }
 
+    /// Verify that `mount_dir` is backed by this server's storage image.
+    /// Guards against starting a server on an empty directory after a stale or failed mount.
+    pub async fn verify_mounted(&self, server_uuid: &str, mount_dir: &Path) -> AgentResult<()> {
+        let image_path = self.image_path(server_uuid);
+        let mounts = fs::read_to_string("/proc/mounts").await?;
+        let target = mount_dir.to_string_lossy();
+        let source = mounts
+            .lines()
+            // The last entry wins when mounts are stacked on the same target.
+            .rev()
+            .find_map(|line| {
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                (parts.len() > 1 && parts[1] == target).then(|| parts[0].to_string())
+            })
+            .ok_or_else(|| {
+                AgentError::FileSystemError(format!(
+                    "Server storage is not mounted at {}",
+                    mount_dir.display()
+                ))
+            })?;
+
+        let Some(loop_dev) = source.strip_prefix("/dev/") else {
+            return Err(AgentError::FileSystemError(format!(
+                "Unexpected storage source {} mounted at {}",
+                source,
+                mount_dir.display()
+            )));
+        };
+        let backing_file = fs::read_to_string(format!("/sys/block/{}/loop/backing_file", loop_dev))
+            .await
+            .map_err(|e| {
+                AgentError::FileSystemError(format!(
+                    "Cannot resolve backing file for {}: {}",
+                    source, e
+                ))
+            })?;
+        let backing_file = backing_file.trim();
+        let expected = image_path
+            .canonicalize()
+            .unwrap_or_else(|_| image_path.clone());
+        if Path::new(backing_file) != expected && Path::new(backing_file) != image_path {
+            return Err(AgentError::FileSystemError(format!(
+                "Storage mounted at {} is backed by {}, expected {}",
+                mount_dir.display(),
+                backing_file,
+                image_path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Discard the free blocks of a server's storage image so space freed inside the quota is
+    /// returned to the host. Returns `(reclaimed_bytes, allocated_bytes)` for the image file.
+    /// A stopped server's image is mounted just for the trim; a running server's is trimmed
+    /// in place, which is only allowed for filesystems that support online discard.
+    pub async fn compact(
+        &self,
+        server_uuid: &str,
+        mount_dir: &Path,
+        running: bool,
+    ) -> AgentResult<(u64, u64)> {
+        let image_path = self.image_path(server_uuid);
+        if !image_path.exists() {
+            return Err(AgentError::NotFound(
+                "Server has no storage image to compact".to_string(),
+            ));
+        }
+        let allocated_before = fs::metadata(&image_path).await?.blocks() * 512;
+
+        let mounted = self.is_mounted(mount_dir).await?;
+        if mounted {
+            let mounts = fs::read_to_string("/proc/mounts").await?;
+            let target = mount_dir.to_string_lossy();
+            let fstype = mounts
+                .lines()
+                .rev()
+                .map(|line| line.split_whitespace().collect::<Vec<_>>())
+                .find(|parts| parts.len() > 2 && parts[1] == target)
+                .map(|parts| parts[2].to_string())
+                .unwrap_or_default();
+            if running && !ONLINE_TRIM_FILESYSTEMS.contains(&fstype.as_str()) {
+                return Err(AgentError::InvalidRequest(format!(
+                    "{} storage can't be compacted while the server is running",
+                    fstype
+                )));
+            }
+        } else if running {
+            return Err(AgentError::FileSystemError(format!(
+                "Server storage is not mounted at {}",
+                mount_dir.display()
+            )));
+        } else {
+            fs::create_dir_all(mount_dir).await?;
+            self.mount_image(&image_path, mount_dir).await?;
+        }
+
+        let mount = mount_dir.to_string_lossy().into_owned();
+        let trimmed = spawn_blocking(move || run("fstrim", &[&mount]))
+            .await
+            .map_err(|e| AgentError::FileSystemError(format!("fstrim task failed: {}", e)));
+        if !mounted {
+            self.unmount(mount_dir).await?;
+        }
+        trimmed??;
+
+        let allocated_after = fs::metadata(&image_path).await?.blocks() * 512;
+        let reclaimed = allocated_before.saturating_sub(allocated_after);
+        info!(
+            "Compacted storage for {}: reclaimed {} bytes",
+            server_uuid, reclaimed
+        );
+        Ok((reclaimed, allocated_after))
+    }
+
+    /// Servers whose storage image is currently mounted at their data directory.
+    pub async fn mounted_image_servers(&self) -> AgentResult<Vec<String>> {
+        let mut servers = Vec::new();
+        let Ok(mut entries) = fs::read_dir(self.images_dir()).await else {
+            return Ok(servers);
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("img") {
+                continue;
+            }
+            let Some(server_uuid) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let server_dir = self.layout.server_dir(&self.data_dir, server_uuid);
+            if self.is_mounted(&server_dir).await? {
+                servers.push(server_uuid.to_string());
+            }
+        }
+        Ok(servers)
+    }
+
+    /// Whether a storage image has already been provisioned for this server.
+    pub fn has_image(&self, server_uuid: &str) -> bool {
+        self.image_path(server_uuid).exists()
+    }
+
+    /// Mount point of the host volume a server's data lives on: the volume holding its
+    /// storage image once provisioned, otherwise the one holding its data directory.
+    pub async fn volume_for_server(&self, server_uuid: &str) -> AgentResult<PathBuf> {
+        let image_path = self.image_path(server_uuid);
+        let path = if image_path.exists() {
+            image_path
+        } else {
+            self.layout.server_dir(&self.data_dir, server_uuid)
+        };
+        let mounts = fs::read_to_string("/proc/mounts").await?;
+        Ok(mount_point_for(&mounts, &path))
+    }
+
+    /// Host-side `(used_mb, total_mb)` for a server, whether or not its container is running.
+    /// A mounted storage image reports its filesystem usage; a plain directory is walked with
+    /// `du`, which is abandoned after `walk_timeout` (total is 0 in that case).
+    pub async fn server_disk_usage(
+        &self,
+        server_uuid: &str,
+        walk_timeout: std::time::Duration,
+    ) -> AgentResult<(u64, u64)> {
+        let server_dir = self.layout.server_dir(&self.data_dir, server_uuid);
+        if !server_dir.exists() {
+            return Err(AgentError::NotFound(format!(
+                "Server directory not found: {}",
+                server_dir.display()
+            )));
+        }
+        if self.is_mounted(&server_dir).await? {
+            let stat = nix::sys::statvfs::statvfs(&server_dir).map_err(|e| {
+                AgentError::FileSystemError(format!(
+                    "statvfs {} failed: {}",
+                    server_dir.display(),
+                    e
+                ))
+            })?;
+            let block = stat.fragment_size() as u64;
+            let used = (stat.blocks() as u64 - stat.blocks_free() as u64) * block;
+            return Ok((
+                used / (1024 * 1024),
+                stat.blocks() as u64 * block / (1024 * 1024),
+            ));
+        }
+
+        let output = tokio::time::timeout(
+            walk_timeout,
+            tokio::process::Command::new("du")
+                .args(["-s", "-m", "-x"])
+                .arg(&server_dir)
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| {
+            AgentError::FileSystemError(format!(
+                "du of {} timed out after {}s",
+                server_dir.display(),
+                walk_timeout.as_secs()
+            ))
+        })??;
+        // du exits non-zero on unreadable entries but still prints a total.
+        let used_mb = String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .and_then(|value| value.parse::<u64>().ok())
+            .ok_or_else(|| {
+                AgentError::FileSystemError(format!(
+                    "du of {} failed: {}",
+                    server_dir.display(),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ))
+            })?;
+        Ok((used_mb, 0))
+    }
+
+    /// `(used, total)` inodes of a server's storage image. `None` for servers without an image,
+    /// whose files share the data volume's inode pool.
+    pub async fn server_inode_usage(&self, server_uuid: &str) -> Option<(u64, u64)> {
+        let server_dir = self.layout.server_dir(&self.data_dir, server_uuid);
+        if !self.is_mounted(&server_dir).await.ok()? {
+            return None;
+        }
+        let stat = nix::sys::statvfs::statvfs(&server_dir).ok()?;
+        Some(inode_usage(&stat))
+    }
+
+    /// Every distinct volume backing the data directory or a server storage image,
+    /// with its current free/total space.
+    pub async fn data_volumes(&self) -> AgentResult<Vec<DataVolume>> {
+        let mounts = fs::read_to_string("/proc/mounts").await?;
+        let mut paths = vec![self.data_dir.clone(), self.images_dir()];
+        if let Ok(mut entries) = fs::read_dir(self.images_dir()).await {
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) == Some("img") {
+                    paths.push(path);
+                }
+            }
+        }
+
+        let mut volumes: Vec<DataVolume> = Vec::new();
+        for path in paths {
+            let mount_point = mount_point_for(&mounts, &path);
+            if volumes.iter().any(|v| v.mount_point == mount_point) {
+                continue;
+            }
+            let stat = nix::sys::statvfs::statvfs(&mount_point).map_err(|e| {
+                AgentError::FileSystemError(format!(
+                    "statvfs {} failed: {}",
+                    mount_point.display(),
+                    e
+                ))
+            })?;
+            let block = stat.fragment_size() as u64;
+            let (inodes_used, inodes_total) = inode_usage(&stat);
+            volumes.push(DataVolume {
+                free_mb: stat.blocks_available() as u64 * block / (1024 * 1024),
+                total_mb: stat.blocks() as u64 * block / (1024 * 1024),
+                inodes_used,
+                inodes_total,
+                mount_point,
+            });
+        }
+        Ok(volumes)
+    }
+
     fn images_dir(&self) -> PathBuf {
         self.data_dir.join("images")
     }
@@ -255,7 +588,13 @@ impl StorageManager {
         self.data_dir.join("metrics_buffer.jsonl")
     }
 
-    pub async fn append_buffered_metric(&self, value: &Value) -> AgentResult<()> {
+    fn metrics_sending_path(&self) -> PathBuf {
+        self.data_dir.join("metrics_buffer.sending.jsonl")
+    }
+
+    /// Append a metric sample. When the buffer grows past `max_bytes`, the oldest samples
+    /// are dropped until it is back under three quarters of the cap.
+    pub async fn append_buffered_metric(&self, value: &Value, max_bytes: u64) -> AgentResult<()> {
         fs::create_dir_all(&self.data_dir).await?;
         let path = self.metrics_buffer_path();
         let mut file = fs::OpenOptions::new()
@@ -266,15 +605,51 @@ impl StorageManager {
         let mut line = value.to_string();
         line.push('\n');
         file.write_all(line.as_bytes()).await?;
+        if file.metadata().await?.len() > max_bytes {
+            drop(file);
+            self.trim_buffered_metrics(max_bytes / 4 * 3).await?;
+        }
         Ok(())
     }
 
-    pub async fn read_buffered_metrics(&self) -> AgentResult<Vec<Value>> {
+    async fn trim_buffered_metrics(&self, target_bytes: u64) -> AgentResult<()> {
         let path = self.metrics_buffer_path();
-        if !path.exists() {
-            return Ok(Vec::new());
-        }
         let s = fs::read_to_string(&path).await?;
+        let mut excess = (s.len() as u64).saturating_sub(target_bytes);
+        let mut dropped = 0usize;
+        let mut kept = s.as_str();
+        while excess > 0 {
+            let Some(end) = kept.find('\n') else {
+                kept = "";
+                break;
+            };
+            excess = excess.saturating_sub(end as u64 + 1);
+            kept = &kept[end + 1..];
+            dropped += 1;
+        }
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, kept).await?;
+        fs::rename(&tmp, &path).await?;
+        tracing::warn!(
+            "Metrics buffer exceeded its size cap; dropped {} oldest samples",
+            dropped
+        );
+        Ok(())
+    }
+
+    /// Samples to flush. The buffer is moved aside first, so samples appended while they are
+    /// sent start a new buffer instead of being cleared with them. A batch left by a failed
+    /// flush is returned again before the buffer is moved.
+    pub async fn read_buffered_metrics(&self) -> AgentResult<Vec<Value>> {
+        let sending = self.metrics_sending_path();
+        if !sending.exists() {
+            let path = self.metrics_buffer_path();
+            if !path.exists() {
+                return Ok(Vec::new());
+            }
+            fs::rename(&path, &sending).await?;
+        }
+        let s = fs::read_to_string(&sending).await?;
         let mut out = Vec::new();
         for line in s.lines() {
             if line.trim().is_empty() {
@@ -288,14 +663,200 @@ impl StorageManager {
         Ok(out)
     }
 
+    /// Drop the samples returned by `read_buffered_metrics` once they are sent.
     pub async fn clear_buffered_metrics(&self) -> AgentResult<()> {
-        let path = self.metrics_buffer_path();
+        let path = self.metrics_sending_path();
         if path.exists() {
             fs::remove_file(path).await?;
         }
         Ok(())
     }
 
+    // --- Persisted per-server scheduling priority ---------------------------------
+    fn priorities_path(&self) -> PathBuf {
+        self.data_dir.join("server_priorities.json")
+    }
+
+    pub async fn read_server_priorities(&self) -> AgentResult<HashMap<String, ProcessPriority>> {
+        let path = self.priorities_path();
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+        let s = fs::read_to_string(&path).await?;
+        serde_json::from_str(&s)
+            .map_err(|e| AgentError::FileSystemError(format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    /// Remember `priority` for `server_id`, or forget it when `None`.
+    pub async fn set_server_priority(
+        &self,
+        server_id: &str,
+        priority: Option<ProcessPriority>,
+    ) -> AgentResult<()> {
+        let mut priorities = self.read_server_priorities().await?;
+        match priority {
+            Some(priority) => priorities.insert(server_id.to_string(), priority),
+            None => priorities.remove(server_id),
+        };
+        fs::create_dir_all(&self.data_dir).await?;
+        let path = self.priorities_path();
+        let tmp = path.with_extension("json.tmp");
+        let body = serde_json::to_vec_pretty(&priorities)
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        fs::write(&tmp, body).await?;
+        fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    // --- Persisted read-only file access ---------------------------------------------
+    fn read_only_path(&self) -> PathBuf {
+        self.data_dir.join("read_only_servers.json")
+    }
+
+    pub async fn read_read_only_servers(&self) -> AgentResult<BTreeSet<String>> {
+        let path = self.read_only_path();
+        if !path.exists() {
+            return Ok(BTreeSet::new());
+        }
+        let s = fs::read_to_string(&path).await?;
+        serde_json::from_str(&s)
+            .map_err(|e| AgentError::FileSystemError(format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    /// Remember whether `server_uuid`'s files are read-only.
+    pub async fn set_server_read_only(
+        &self,
+        server_uuid: &str,
+        read_only: bool,
+    ) -> AgentResult<()> {
+        let mut servers = self.read_read_only_servers().await?;
+        let changed = if read_only {
+            servers.insert(server_uuid.to_string())
+        } else {
+            servers.remove(server_uuid)
+        };
+        if !changed {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.data_dir).await?;
+        let path = self.read_only_path();
+        let tmp = path.with_extension("json.tmp");
+        let body = serde_json::to_vec_pretty(&servers)
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        fs::write(&tmp, body).await?;
+        fs::rename(&tmp, &path).await?;
+        Ok(())
+    }
+
+    // --- Persisted restart-on-boot state -------------------------------------------
+    fn autostart_path(&self) -> PathBuf {
+        self.data_dir.join("server_autostart.json")
+    }
+
+    pub async fn read_autostart_state(&self) -> AgentResult<AutostartState> {
+        let _guard = self.autostart_lock.lock().await;
+        self.load_autostart_state().await
+    }
+
+    /// The persisted autostart state. A file that doesn't parse is kept aside as
+    /// `.json.corrupt` and replaced by an empty state, so it is warned about once.
+    async fn load_autostart_state(&self) -> AgentResult<AutostartState> {
+        let path = self.autostart_path();
+        if !path.exists() {
+            return Ok(AutostartState::default());
+        }
+        let s = fs::read_to_string(&path).await?;
+        match serde_json::from_str(&s) {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                let aside = path.with_extension("json.corrupt");
+                tracing::warn!(
+                    "Invalid {} ({}); starting from an empty state, old file kept as {}",
+                    path.display(),
+                    e,
+                    aside.display()
+                );
+                fs::rename(&path, &aside).await?;
+                let state = AutostartState::default();
+                self.write_root_only(&path, &state).await?;
+                Ok(state)
+            }
+        }
+    }
+
+    /// Apply `update` to the persisted autostart state, writing it back if it changed. The
+    /// file holds start messages, environment included, so only root may read it.
+    pub async fn update_autostart_state(
+        &self,
+        update: impl FnOnce(&mut AutostartState),
+    ) -> AgentResult<()> {
+        let _guard = self.autostart_lock.lock().await;
+        let current = self.load_autostart_state().await?;
+        let mut state = current.clone();
+        update(&mut state);
+        if state == current {
+            return Ok(());
+        }
+        self.write_root_only(&self.autostart_path(), &state).await
+    }
+
+    // --- Persisted start messages and environment changes ---------------------------
+    fn start_state_path(&self) -> PathBuf {
+        self.data_dir.join("server_start_state.json")
+    }
+
+    pub async fn read_start_state(&self) -> AgentResult<BTreeMap<String, ServerStartState>> {
+        let path = self.start_state_path();
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let s = fs::read_to_string(&path).await?;
+        serde_json::from_str(&s)
+            .map_err(|e| AgentError::FileSystemError(format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    /// Apply `update` to the persisted start state of `server_id`, forgetting the server once
+    /// nothing is left. Start messages carry the environment, so only root may read the file.
+    pub async fn update_start_state(
+        &self,
+        server_id: &str,
+        update: impl FnOnce(&mut ServerStartState),
+    ) -> AgentResult<()> {
+        let _guard = self.start_state_lock.lock().await;
+        let current = self.read_start_state().await?;
+        let mut states = current.clone();
+        let state = states.entry(server_id.to_string()).or_default();
+        update(state);
+        if *state == ServerStartState::default() {
+            states.remove(server_id);
+        }
+        if states == current {
+            return Ok(());
+        }
+        self.write_root_only(&self.start_state_path(), &states)
+            .await
+    }
+
+    /// Replace `path` with `value` as JSON, readable by root only.
+    async fn write_root_only(&self, path: &Path, value: &impl Serialize) -> AgentResult<()> {
+        fs::create_dir_all(&self.data_dir).await?;
+        let tmp = path.with_extension("json.tmp");
+        let body = serde_json::to_vec_pretty(value)
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)
+            .await?;
+        file.write_all(&body).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+
     // -----------------------------------------------------------------------------
 
     async fn dir_has_data(&self, dir: &Path) -> AgentResult<bool> {
@@ -304,6 +865,28 @@ impl StorageManager {
     }
 }
 
+/// Longest mount point in `/proc/mounts` containing `path`. Symlinks are resolved through
+/// the nearest existing ancestor so images linked onto other disks land on the right volume.
+fn mount_point_for(mounts: &str, path: &Path) -> PathBuf {
+    let resolved = path
+        .ancestors()
+        .find_map(|p| p.canonicalize().ok())
+        .unwrap_or_else(|| path.to_path_buf());
+    mounts
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|target| PathBuf::from(target.replace("\\040", " ")))
+        .filter(|target| resolved.starts_with(target))
+        .max_by_key(|target| target.components().count())
+        .unwrap_or_else(|| PathBuf::from("/"))
+}
+
+/// `(used, total)` inodes. Filesystems that allocate inodes dynamically (btrfs) report 0 total.
+fn inode_usage(stat: &nix::sys::statvfs::Statvfs) -> (u64, u64) {
+    let total = stat.files();
+    (total.saturating_sub(stat.files_free()), total)
+}
+
 fn run(command: &str, args: &[&str]) -> AgentResult<()> {
     let status = std::process::Command::new(command)
         .args(args)
@@ -317,3 +900,65 @@ fn run(command: &str, args: &[&str]) -> AgentResult<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn autostart_state_round_trips_and_recovers_from_corruption() {
+        let dir = std::env::temp_dir().join(format!("catalyst-storage-{}", uuid::Uuid::new_v4()));
+        let storage = StorageManager::new(dir.clone(), DataLayout::default());
+        assert_eq!(
+            storage.read_autostart_state().await.unwrap(),
+            AutostartState::default()
+        );
+
+        storage
+            .update_autostart_state(|state| {
+                state.boot_id = Some("boot-a".to_string());
+                state.servers.insert(
+                    "srv".to_string(),
+                    ServerAutostart {
+                        running: true,
+                        autostart: true,
+                        start: Some(serde_json::json!({ "serverId": "srv" })),
+                    },
+                );
+            })
+            .await
+            .unwrap();
+        let state = storage.read_autostart_state().await.unwrap();
+        assert_eq!(state.boot_id.as_deref(), Some("boot-a"));
+        assert!(state.servers["srv"].running && state.servers["srv"].autostart);
+        assert_eq!(
+            state.servers["srv"].start.as_ref().unwrap()["serverId"],
+            "srv"
+        );
+
+        let mode = std::fs::metadata(storage.autostart_path()).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::fs::write(storage.autostart_path(), "{ not json").unwrap();
+        assert_eq!(
+            storage.read_autostart_state().await.unwrap(),
+            AutostartState::default()
+        );
+        assert!(dir.join("server_autostart.json.corrupt").exists());
+        storage
+            .update_autostart_state(|state| state.boot_id = Some("boot-b".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            storage
+                .read_autostart_state()
+                .await
+                .unwrap()
+                .boot_id
+                .as_deref(),
+            Some("boot-b")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
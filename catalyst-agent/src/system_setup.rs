@@ -7,6 +7,7 @@ use tracing::{error, info, warn};
 use sha2::{Digest, Sha256};
 
 use crate::config::CniNetworkConfig;
+use crate::runtime_manager::{host_oci_arch, CNI_BIN_DIRS};
 use crate::{AgentConfig, AgentError};
 
 pub struct SystemSetup;
@@ -42,6 +43,88 @@ impl SystemSetup {
         Ok(())
     }
 
+    /// Refuse to run on cgroup v1 (or hybrid) hosts. Stats, limits and OOM detection all read
+    /// the unified hierarchy; on v1 they would silently report zeros and limits may not apply.
+    pub fn ensure_cgroup_v2() -> Result<(), AgentError> {
+        if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+            info!("✓ cgroup v2 (unified hierarchy) detected");
+            return Ok(());
+        }
+        error!("cgroup v1 or hybrid hierarchy detected; Catalyst requires cgroup v2");
+        Err(AgentError::ConfigError(
+            "cgroup v2 required: boot the host with systemd.unified_cgroup_hierarchy=1".to_string(),
+        ))
+    }
+
+    /// Prove the agent can manipulate iptables by creating, populating and removing a scratch
+    /// chain. Port forwarding relies on this; without it every server would be unreachable.
+    pub fn verify_firewall_access() -> Result<(), AgentError> {
+        const CHAIN: &str = "CATALYST-SELFTEST";
+        let run = |args: &[&str]| -> Result<(), AgentError> {
+            let output = Command::new("iptables")
+                .args(["-w", "-t", "nat"])
+                .args(args)
+                .output()
+                .map_err(|e| AgentError::FirewallError(format!("Failed to run iptables: {}", e)))?;
+            if output.status.success() {
+                Ok(())
+            } else {
+                Err(AgentError::FirewallError(format!(
+                    "iptables {} failed: {}",
+                    args.join(" "),
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )))
+            }
+        };
+
+        // A previous run may have been killed mid-check; start from an empty chain either way.
+        if run(&["-N", CHAIN]).is_err() {
+            run(&["-F", CHAIN])?;
+        }
+        let result =
+            run(&["-A", CHAIN, "-j", "RETURN"]).and_then(|_| run(&["-D", CHAIN, "-j", "RETURN"]));
+        let cleanup = run(&["-X", CHAIN]);
+        result.and(cleanup)?;
+        info!("✓ iptables rule manipulation works");
+        Ok(())
+    }
+
+    /// Check that the shim binary behind every configured runtime class is on the agent's
+    /// PATH, so a typo fails at startup instead of on the first server that selects it.
+    ///
+    /// Tasks created through containerd's API name their runtime directly; containerd keeps no
+    /// registry of them and only looks the shim up when a task starts. This checks the binary
+    /// exists, not that containerd's own PATH finds it or that the shim's options work.
+    pub fn verify_runtime_class_shims(config: &AgentConfig) -> Result<(), AgentError> {
+        for (class, runtime) in &config.containerd.runtime_classes {
+            // containerd resolves `io.containerd.<name>.<version>` to `containerd-shim-<name>-<version>`.
+            let shim = match runtime.split('.').collect::<Vec<_>>().as_slice() {
+                ["io", "containerd", name, version] if !name.is_empty() && !version.is_empty() => {
+                    format!("containerd-shim-{}-{}", name, version)
+                }
+                _ => {
+                    return Err(AgentError::ConfigError(format!(
+                        "Runtime class '{}': '{}' is not a containerd runtime name like io.containerd.runsc.v1",
+                        class, runtime
+                    )));
+                }
+            };
+            let path = std::env::var("PATH").unwrap_or_default();
+            let found = path
+                .split(':')
+                .chain(["/usr/local/bin", "/usr/bin", "/usr/local/sbin", "/usr/sbin"])
+                .any(|dir| Path::new(dir).join(&shim).is_file());
+            if !found {
+                return Err(AgentError::ConfigError(format!(
+                    "Runtime class '{}' needs {} ({}), which is not installed",
+                    class, shim, runtime
+                )));
+            }
+            info!("✓ Runtime class {} -> {} ({} found)", class, runtime, shim);
+        }
+        Ok(())
+    }
+
     /// Detect the system's package manager
     fn detect_package_manager() -> Result<String, AgentError> {
         let managers = vec![
@@ -413,9 +496,11 @@ impl SystemSetup {
             return Ok(());
         }
 
-        let arch = match std::env::consts::ARCH {
-            "x86_64" => "amd64",
-            "aarch64" => "arm64",
+        // Architectures the CNI plugins are released for.
+        let arch = match host_oci_arch() {
+            arch @ ("amd64" | "arm64" | "arm" | "ppc64le" | "s390x" | "riscv64" | "mips64le") => {
+                arch
+            }
             other => {
                 return Err(AgentError::InternalError(format!(
                     "Unsupported architecture for CNI plugin install: {}",
@@ -488,9 +573,6 @@ impl SystemSetup {
 
     fn has_required_cni_plugins() -> bool {
         const REQUIRED: [&str; 4] = ["bridge", "host-local", "portmap", "macvlan"];
-        // Check multiple CNI plugin directories (Fedora uses /usr/libexec/cni)
-        const CNI_BIN_DIRS: [&str; 2] = ["/opt/cni/bin", "/usr/libexec/cni"];
-
         for dir in CNI_BIN_DIRS {
             let has_all = REQUIRED
                 .iter()
@@ -518,6 +600,7 @@ impl SystemSetup {
                 gateway: None,
                 range_start: None,
                 range_end: None,
+                mtu: None,
             }]
         } else {
             config.networking.networks.clone()
@@ -553,6 +636,10 @@ impl SystemSetup {
                 None => Self::detect_default_gateway()?,
             };
 
+            let mtu = network
+                .mtu
+                .map(|value| format!("\n      \"mtu\": {},", value))
+                .unwrap_or_default();
             let config = format!(
                 r#"{{
   "cniVersion": "1.0.0",
@@ -561,7 +648,7 @@ impl SystemSetup {
     {{
       "type": "macvlan",
       "master": "{}",
-      "mode": "bridge",
+      "mode": "bridge",{}
       "ipam": {{
         "type": "host-local",
         "ranges": [[
@@ -579,7 +666,7 @@ impl SystemSetup {
     }}
   ]
 }}"#,
-                network.name, interface, cidr, range_start, range_end, gateway
+                network.name, interface, mtu, cidr, range_start, range_end, gateway
             );
 
             fs::write(&cni_config, config)
@@ -2,22 +2,35 @@ use base64::Engine;
 use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use regex::Regex;
-use reqwest::Url;
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::future::Future;
 use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::OnceLock;
 use std::time::Duration;
 use sysinfo::{Disks, System};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::sync::RwLock;
-use tokio_tungstenite::connect_async;
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore};
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async_tls_with_config, Connector};
 use tracing::{debug, error, info, warn};
 
-use crate::config::CniNetworkConfig;
+use crate::backup_crypto::{self, BackupHeader, DecryptingReader, KeySource};
+use crate::config::{
+    parse_backend_url, CniNetworkConfig, ConsoleStreamOrder, DnsMode, DuplicateContainerAction,
+    TlsConfig,
+};
+use crate::message_signing::{self, SigningSink};
+use crate::runtime_manager::{
+    clock_ticks_per_sec, conntrack_counts, host_oci_arch, read_proc_rss, read_proc_stat, ChownMode,
+    ConnectionCounts, ConsoleTail, ConsoleWatch, ContainerInfo, ImagePlatform, InstallerHandle,
+    InstallerLimits, IoClass, PathProfile, ProcessPriority, STOP_SIGNALS,
+};
+use crate::storage_manager::{AutostartState, DataVolume};
 use crate::{
     AgentConfig, AgentError, AgentResult, ContainerdRuntime, FileManager, NetworkManager,
     StorageManager,
@@ -25,10 +38,96 @@ use crate::{
 
 type WsStream =
     tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
-type WsWrite = SplitSink<WsStream, Message>;
+type WsWrite = SigningSink<SplitSink<WsStream, Message>>;
+/// Per-address connection counts from one conntrack read, and whether it was truncated.
+type ConntrackSnapshot = Option<(HashMap<String, ConnectionCounts>, bool)>;
 const CONTAINER_SERVER_DIR: &str = "/data";
+const DEFAULT_SCRATCH_PATH: &str = "/scratch";
+const INSTALL_LOG_FILE: &str = "install.log";
 const MAX_BACKUP_UPLOAD_BYTES: u64 = 10 * 1024 * 1024 * 1024; // 10GB
 const BACKUP_UPLOAD_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(600); // 10 minutes
+const HANDSHAKE_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+// Retrying a rejected token immediately is pointless; back off from 1 up to 10 minutes.
+const HANDSHAKE_REJECTED_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_HANDSHAKE_REJECTED_BACKOFF: Duration = Duration::from_secs(600);
+const LATENCY_PROBE_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_FREE_PORTS_REQUEST: u64 = 100;
+const MAX_FREE_PORT_SCAN: usize = 10_000;
+const DISK_USAGE_WALK_TIMEOUT: Duration = Duration::from_secs(30);
+const DNS_REPAIR_INTERVAL: Duration = Duration::from_secs(300);
+const SNAPSHOT_GC_INTERVAL: Duration = Duration::from_secs(1800);
+// Rootfs snapshots are prepared before their container record exists; never reap young ones.
+const ORPHANED_SNAPSHOT_GRACE: Duration = Duration::from_secs(3600);
+const MAX_CONTAINER_DIFF_ENTRIES: u64 = 10_000;
+const MAX_PROCESS_LIST_ENTRIES: u64 = 500;
+// A container is created shortly before its task; don't call a start in progress orphaned.
+const ORPHANED_CONTAINER_GRACE: Duration = Duration::from_secs(120);
+const STATE_ACK_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+const STATE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_STATE_RESENDS: u32 = 5;
+const INSTALLER_GC_INTERVAL: Duration = Duration::from_secs(900);
+const ORPHANED_INSTALLER_GRACE: Duration = Duration::from_secs(600);
+const RCON_IO_TIMEOUT: Duration = Duration::from_secs(5);
+const START_DEPENDENCY_TIMEOUT: Duration = Duration::from_secs(300);
+const MAX_START_DEPENDENCY_TIMEOUT_SECS: u64 = 3600;
+const START_DEPENDENCY_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_BROADCAST_MESSAGE_LEN: usize = 256;
+// With inotify, console tails otherwise only wake on output.
+const CONSOLE_WATCH_TIMEOUT: Duration = Duration::from_secs(1);
+// Node-wide, so a stuck retry loop in the backend cannot flood every server's chat.
+const CONSOLE_BROADCAST_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_BROADCAST_COMMAND: &str = "say {message}";
+// Scheduled trims skip servers stalled on I/O for more than this share of the last 10s.
+const FSTRIM_MAX_IO_PRESSURE_PERCENT: f64 = 10.0;
+/// Test installs run in a size-capped tmpfs, never in a server directory.
+const TEST_INSTALL_SCRATCH_DIR: &str = "/tmp/catalyst-test-install";
+const DEFAULT_TEST_INSTALL_SCRATCH_MB: u64 = 1024;
+const MAX_TEST_INSTALL_SCRATCH_MB: u64 = 8192;
+const DEFAULT_TEST_INSTALL_TIMEOUT_SECS: u64 = 600;
+const MAX_TEST_INSTALL_TIMEOUT_SECS: u64 = 3600;
+const DISK_GUARD_INTERVAL: Duration = Duration::from_secs(15);
+const CNI_TEARDOWN_RETRY_INTERVAL: Duration = Duration::from_secs(300);
+/// Root of per-server backup directories.
+const BACKUP_ROOT: &str = "/var/lib/catalyst/backups";
+const MAX_ENVIRONMENT_UPDATE_KEYS: usize = 256;
+const MAX_ENVIRONMENT_VALUE_LEN: usize = 32 * 1024;
+const MAX_FILE_TAILS: usize = 32;
+const FILE_TAIL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Larger bursts of appended data are cut to their last bytes.
+const MAX_FILE_TAIL_CHUNK: usize = 64 * 1024;
+const DEFAULT_PRE_START_TIMEOUT_SECS: u64 = 60;
+const MAX_PRE_START_TIMEOUT_SECS: u64 = 600;
+/// Tail of the hook's output carried in the start failure reason.
+const MAX_PRE_START_REASON_BYTES: usize = 2048;
+/// State updates kept while disconnected; older ones are dropped first.
+const MAX_UNSENT_STATE_UPDATES: usize = 256;
+/// Inode usage on a data volume that is logged as a warning.
+const INODE_WARNING_PERCENT: u64 = 90;
+
+/// Regular files and their total size under `root`, without following symlinks.
+fn summarize_tree(root: &Path) -> (u64, u64) {
+    let mut files = 0u64;
+    let mut bytes = 0u64;
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                files += 1;
+                bytes += metadata.len();
+            }
+        }
+    }
+    (files, bytes)
+}
 
 /// Shell-escape a value for safe interpolation into a bash script.
 /// Wraps the value in single quotes and escapes any embedded single quotes.
@@ -39,6 +138,62 @@ fn shell_escape_value(value: &str) -> String {
     format!("'{}'", escaped)
 }
 
+/// The install script exactly as `install_server` runs it, plus any unresolved placeholders.
+fn render_install_script(
+    install_script: &str,
+    environment: &serde_json::Map<String, Value>,
+    container_dir: &str,
+) -> (String, Vec<String>) {
+    // Strip carriage returns to avoid $'\r': command not found errors
+    let script = install_script.replace("\r\n", "\n").replace('\r', "\n");
+    // Shell-escape values (defaults included) to prevent command injection via
+    // user-controlled env vars.
+    substitute_template_vars(
+        &script,
+        |key| {
+            if key == "SERVER_DIR" {
+                return Some(container_dir.to_string());
+            }
+            environment
+                .get(key)
+                .map(|value| value.as_str().unwrap_or("").to_string())
+        },
+        shell_escape_value,
+    )
+}
+
+/// Expand `{{VAR}}` and `{{VAR:-default}}` placeholders, passing each substituted value
+/// through `escape`. As in the shell, the default also applies when `VAR` is set but empty.
+/// Placeholders with neither a value nor a default are left as-is and their names returned.
+fn substitute_template_vars(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    escape: impl Fn(&str) -> String,
+) -> (String, Vec<String>) {
+    static PLACEHOLDER_RE: OnceLock<Regex> = OnceLock::new();
+    let re = PLACEHOLDER_RE.get_or_init(|| {
+        Regex::new(r"\{\{([A-Za-z0-9_.\-]+)(?::-([^}]*))?\}\}").expect("valid placeholder regex")
+    });
+    let mut unresolved: Vec<String> = Vec::new();
+    let output = re
+        .replace_all(input, |caps: &regex::Captures<'_>| {
+            let default = caps.get(2).map(|m| m.as_str());
+            match (lookup(&caps[1]), default) {
+                (Some(value), Some(default)) if value.is_empty() => escape(default),
+                (Some(value), _) => escape(&value),
+                (None, Some(default)) => escape(default),
+                (None, None) => {
+                    if !unresolved.iter().any(|name| name == &caps[1]) {
+                        unresolved.push(caps[1].to_string());
+                    }
+                    caps[0].to_string()
+                }
+            }
+        })
+        .into_owned();
+    (output, unresolved)
+}
+
 /// Normalize common bash arithmetic condition syntax so startup commands run under /bin/sh.
 /// Example: `((1))` -> `[ $((1)) -ne 0 ]`
 fn normalize_startup_for_sh(command: &str) -> String {
@@ -57,6 +212,62 @@ fn normalize_startup_for_sh(command: &str) -> String {
     .into_owned()
 }
 
+/// Where the server directory is mounted inside its containers: the template's
+/// `containerDataDir` (e.g. `/home/container` for images built for other panels) or `/data`.
+fn container_data_dir(template: &serde_json::Map<String, Value>) -> AgentResult<String> {
+    let Some(value) = template.get("containerDataDir").filter(|v| !v.is_null()) else {
+        return Ok(CONTAINER_SERVER_DIR.to_string());
+    };
+    let invalid =
+        |reason: &str| AgentError::InvalidRequest(format!("Invalid containerDataDir: {}", reason));
+    let raw = value
+        .as_str()
+        .ok_or_else(|| invalid("expected a string"))?
+        .trim();
+    if !raw.starts_with('/') || raw.len() > 255 {
+        return Err(invalid(
+            "must be an absolute path of at most 255 characters",
+        ));
+    }
+    if !raw
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '_' | '-'))
+    {
+        return Err(invalid(
+            "only letters, digits, '/', '.', '_' and '-' are allowed",
+        ));
+    }
+    let path = Path::new(raw);
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::RootDir | Component::Normal(_)))
+    {
+        return Err(invalid("must not contain '.' or '..' segments"));
+    }
+    let normalized: PathBuf = path.components().collect();
+    if normalized == Path::new("/")
+        || ["/proc", "/sys", "/dev"]
+            .iter()
+            .any(|reserved| normalized.starts_with(reserved))
+    {
+        return Err(invalid("cannot be / or under /proc, /sys or /dev"));
+    }
+    Ok(normalized.to_string_lossy().into_owned())
+}
+
+/// The template's `chownMode` for installers: `recursive` (default), `top-level` or `skip`.
+fn installer_chown_mode(template: &serde_json::Map<String, Value>) -> AgentResult<ChownMode> {
+    match template.get("chownMode").filter(|v| !v.is_null()) {
+        None => Ok(ChownMode::default()),
+        Some(value) => value.as_str().and_then(ChownMode::parse).ok_or_else(|| {
+            AgentError::InvalidRequest(format!(
+                "Invalid chownMode {}: expected \"recursive\", \"top-level\" or \"skip\"",
+                value
+            ))
+        }),
+    }
+}
+
 fn validate_safe_path_segment(value: &str, label: &str) -> AgentResult<()> {
     let trimmed = value.trim();
     if trimmed.is_empty() || trimmed.len() > 128 {
@@ -81,10 +292,23 @@ fn validate_safe_path_segment(value: &str, label: &str) -> AgentResult<()> {
     }
 }
 
+/// How the graceful stop command is delivered before falling back to a signal.
+#[derive(Clone, Debug, Default)]
+enum StopMethod {
+    /// Write the stop command to the server's stdin.
+    #[default]
+    Stdin,
+    /// Skip the stop command and go straight to the stop signal.
+    Signal,
+    /// Issue the stop command over RCON, falling back to stdin if that fails.
+    Rcon { port: u16, password: String },
+}
+
 #[derive(Clone, Debug)]
 struct StopPolicy {
     stop_command: Option<String>,
     stop_signal: String,
+    stop_method: StopMethod,
 }
 
 impl Default for StopPolicy {
@@ -92,14 +316,66 @@ impl Default for StopPolicy {
         Self {
             stop_command: None,
             stop_signal: "SIGTERM".to_string(),
+            stop_method: StopMethod::Stdin,
         }
     }
 }
 
-fn parse_stop_policy(msg: &Value) -> StopPolicy {
+/// Resolve RCON connection info: `rconPort` from the template (or `RCON_PORT` in the
+/// environment) and `RCON_PASSWORD` from the environment (or `rconPassword` in the template).
+fn parse_rcon_target(
+    template: &serde_json::Map<String, Value>,
+    environment: Option<&serde_json::Map<String, Value>>,
+) -> AgentResult<StopMethod> {
+    let env_str = |key: &str| {
+        environment
+            .and_then(|env| env.get(key))
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    let port = match template.get("rconPort") {
+        Some(value) => value.as_u64(),
+        None => env_str("RCON_PORT").and_then(|value| value.parse::<u64>().ok()),
+    }
+    .and_then(|port| u16::try_from(port).ok())
+    .filter(|port| *port > 0)
+    .ok_or_else(|| {
+        AgentError::InvalidRequest(
+            "stopMethod 'rcon' requires a valid rconPort or RCON_PORT".to_string(),
+        )
+    })?;
+
+    let password = env_str("RCON_PASSWORD")
+        .or_else(|| {
+            template
+                .get("rconPassword")
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        })
+        .ok_or_else(|| {
+            AgentError::InvalidRequest(
+                "stopMethod 'rcon' requires RCON_PASSWORD or rconPassword".to_string(),
+            )
+        })?;
+    if password.len() > crate::rcon::MAX_RCON_PASSWORD_LEN || password.contains('\0') {
+        return Err(AgentError::InvalidRequest(
+            "Invalid RCON password".to_string(),
+        ));
+    }
+
+    Ok(StopMethod::Rcon {
+        port,
+        password: password.to_string(),
+    })
+}
+
+fn parse_stop_policy(msg: &Value) -> AgentResult<StopPolicy> {
     let mut policy = StopPolicy::default();
     let Some(template) = msg.get("template").and_then(Value::as_object) else {
-        return policy;
+        return Ok(policy);
     };
 
     if let Some(command) = template
@@ -115,14 +391,676 @@ fn parse_stop_policy(msg: &Value) -> StopPolicy {
         .get("sendSignalTo")
         .and_then(Value::as_str)
         .map(str::trim)
+        .filter(|value| !value.is_empty())
     {
-        let normalized = raw_signal.to_ascii_uppercase();
-        if matches!(normalized.as_str(), "SIGTERM" | "SIGINT") {
-            policy.stop_signal = normalized;
+        let mut normalized = raw_signal.to_ascii_uppercase();
+        if !normalized.starts_with("SIG") {
+            normalized.insert_str(0, "SIG");
+        }
+        if !STOP_SIGNALS.contains(&normalized.as_str()) {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid sendSignalTo '{}': expected one of {}",
+                raw_signal,
+                STOP_SIGNALS.join(", ")
+            )));
+        }
+        policy.stop_signal = normalized;
+    }
+
+    policy.stop_method = match template.get("stopMethod").and_then(Value::as_str) {
+        None | Some("stdin") => StopMethod::Stdin,
+        Some("signal") => StopMethod::Signal,
+        Some("rcon") => {
+            parse_rcon_target(template, msg.get("environment").and_then(Value::as_object))?
+        }
+        Some(other) => {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid stopMethod '{}': expected 'stdin', 'signal' or 'rcon'",
+                other
+            )));
+        }
+    };
+
+    Ok(policy)
+}
+
+/// The stop policy for stopping a server now. A bad policy would leave the server
+/// unstoppable, so it falls back to SIGTERM; starts reject it up front instead.
+fn stop_policy_or_sigterm(msg: &Value, server_id: &str) -> StopPolicy {
+    parse_stop_policy(msg).unwrap_or_else(|e| {
+        warn!(
+            "Invalid stop policy for {}, stopping with SIGTERM: {}",
+            server_id, e
+        );
+        StopPolicy {
+            stop_method: StopMethod::Signal,
+            ..StopPolicy::default()
+        }
+    })
+}
+
+/// Build a rustls connector enforcing the configured TLS policy. Returns `None` when no
+/// policy is set so the default connector (and its negotiation) is used unchanged.
+fn build_tls_connector(tls: &TlsConfig) -> AgentResult<Option<Connector>> {
+    if tls.min_version.is_none() && tls.cipher_suites.is_empty() {
+        return Ok(None);
+    }
+
+    let versions: &[&'static rustls::SupportedProtocolVersion] =
+        match tls.min_version.as_deref().map(str::trim) {
+            None | Some("1.2") => &[&rustls::version::TLS13, &rustls::version::TLS12],
+            Some("1.3") => &[&rustls::version::TLS13],
+            Some(other) => {
+                return Err(AgentError::ConfigError(format!(
+                    "Invalid tls.min_version '{}': expected \"1.2\" or \"1.3\"",
+                    other
+                )));
+            }
+        };
+
+    let mut provider = rustls::crypto::ring::default_provider();
+    if !tls.cipher_suites.is_empty() {
+        for name in &tls.cipher_suites {
+            if !provider.cipher_suites.iter().any(|suite| {
+                suite
+                    .suite()
+                    .as_str()
+                    .is_some_and(|s| s.eq_ignore_ascii_case(name.trim()))
+            }) {
+                return Err(AgentError::ConfigError(format!(
+                    "Unsupported tls.cipher_suites entry '{}'",
+                    name
+                )));
+            }
+        }
+        provider.cipher_suites.retain(|suite| {
+            suite.suite().as_str().is_some_and(|s| {
+                tls.cipher_suites
+                    .iter()
+                    .any(|name| s.eq_ignore_ascii_case(name.trim()))
+            })
+        });
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for err in &native.errors {
+        warn!("Failed to load native root certificate: {}", err);
+    }
+    let (added, _) = roots.add_parsable_certificates(native.certs);
+    if added == 0 {
+        return Err(AgentError::ConfigError(
+            "No usable native root certificates found".to_string(),
+        ));
+    }
+
+    let config = rustls::ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_protocol_versions(versions)
+        .map_err(|e| AgentError::ConfigError(format!("Invalid TLS policy: {}", e)))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Some(Connector::Rustls(Arc::new(config))))
+}
+
+/// What a readiness probe checks before a started server is reported as `running`.
+#[derive(Clone, Debug)]
+enum ReadinessCheck {
+    Tcp(u16),
+    Udp(u16),
+    Exec(Vec<String>),
+}
+
+#[derive(Clone, Debug)]
+struct ReadinessProbe {
+    check: ReadinessCheck,
+    timeout: Duration,
+    interval: Duration,
+}
+
+/// What happens once a server fails its health check `failureThreshold` times in a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HealthCheckAction {
+    /// Only tell the backend; the server keeps running.
+    Report,
+    /// Stop the server and report it as `quarantined`.
+    Stop,
+    /// Restart the server the first time; quarantine it if it fails again.
+    RestartThenStop,
+}
+
+/// Template `healthCheck`: a probe re-run for as long as the server is up.
+#[derive(Clone, Debug)]
+struct HealthCheck {
+    check: ReadinessCheck,
+    interval: Duration,
+    initial_delay: Duration,
+    failure_threshold: u32,
+    action: HealthCheckAction,
+    /// Archive the data directory before reporting the server as quarantined.
+    snapshot: bool,
+}
+
+/// Template `scratchSizeMb` / `scratchPath`: a tmpfs scratch directory for the server. It is
+/// charged to the memory limit, so it must leave room for the server itself.
+fn parse_scratch_mount(
+    template: &serde_json::Map<String, Value>,
+    memory_mb: u64,
+) -> AgentResult<Option<(String, u64)>> {
+    let size_mb = match template.get("scratchSizeMb") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(value) => value.as_u64().filter(|mb| *mb > 0).ok_or_else(|| {
+            AgentError::InvalidRequest(
+                "Invalid scratchSizeMb: must be a positive integer".to_string(),
+            )
+        })?,
+    };
+    if size_mb >= memory_mb {
+        return Err(AgentError::InvalidRequest(format!(
+            "scratchSizeMb ({}) must be smaller than the memory allocation ({} MB)",
+            size_mb, memory_mb
+        )));
+    }
+    let path = template
+        .get("scratchPath")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .unwrap_or(DEFAULT_SCRATCH_PATH);
+    let mut components = Path::new(path).components();
+    let valid = path.len() <= 128
+        && components.next() == Some(Component::RootDir)
+        && components.clone().next().is_some()
+        && components
+            .clone()
+            .all(|c| matches!(c, Component::Normal(_)))
+        && !matches!(
+            components.next().and_then(|c| c.as_os_str().to_str()),
+            Some("data" | "dev" | "etc" | "proc" | "sys")
+        );
+    if !valid {
+        return Err(AgentError::InvalidRequest(format!(
+            "Invalid scratchPath '{}': must be an absolute path outside /data, /dev, /etc, /proc and /sys",
+            path
+        )));
+    }
+    Ok(Some((path.to_string(), size_mb)))
+}
+
+/// Template `platform` (`os/architecture[/variant]`): the platform the server's images, its
+/// installer included, are pulled and run for instead of the node's default.
+fn parse_template_platform(
+    template: &serde_json::Map<String, Value>,
+) -> AgentResult<Option<ImagePlatform>> {
+    template
+        .get("platform")
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(ImagePlatform::parse)
+        .transpose()
+}
+
+/// Start message `cgroupParent`, e.g. `tenants/acme`: groups the server's cgroup under a
+/// tenant cgroup so usage can be viewed and limited per tenant.
+fn parse_cgroup_parent(msg: &Value) -> AgentResult<Option<String>> {
+    let parent = match msg.get("cgroupParent") {
+        None | Some(Value::Null) => return Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(|v| v.trim().trim_matches('/'))
+            .ok_or_else(|| {
+                AgentError::InvalidRequest("Invalid cgroupParent: must be a string".to_string())
+            })?,
+    };
+    if parent.is_empty() {
+        return Ok(None);
+    }
+    let segments: Vec<&str> = parent.split('/').collect();
+    let valid = parent.len() <= 128
+        && segments.len() <= 4
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && *segment != "."
+                && *segment != ".."
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        });
+    if !valid {
+        return Err(AgentError::InvalidRequest(format!(
+            "Invalid cgroupParent '{}': use up to 4 '/'-separated segments of letters, digits, '-', '_' and '.'",
+            parent
+        )));
+    }
+    if matches!(segments[0], "system.slice" | "user.slice" | "init.scope") {
+        return Err(AgentError::InvalidRequest(format!(
+            "Invalid cgroupParent '{}': must not be inside a host-managed cgroup",
+            parent
+        )));
+    }
+    Ok(Some(parent.to_string()))
+}
+
+/// Variable name to new value; `None` unsets it.
+type EnvironmentChanges = BTreeMap<String, Option<String>>;
+
+/// `update_environment` changes: string values set a variable, `null` unsets it. Variables
+/// the agent derives itself at start can't be overridden.
+fn parse_environment_update(value: Option<&Value>) -> AgentResult<EnvironmentChanges> {
+    let entries = value
+        .and_then(|v| v.as_object())
+        .ok_or_else(|| AgentError::InvalidRequest("Missing or invalid environment".to_string()))?;
+    if entries.is_empty() || entries.len() > MAX_ENVIRONMENT_UPDATE_KEYS {
+        return Err(AgentError::InvalidRequest(format!(
+            "environment must have between 1 and {} entries",
+            MAX_ENVIRONMENT_UPDATE_KEYS
+        )));
+    }
+    let mut changes = BTreeMap::new();
+    for (key, value) in entries {
+        let valid_key = key
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_key {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid environment variable name '{}'",
+                key
+            )));
         }
+        if matches!(
+            key.as_str(),
+            "SERVER_DIR" | "HOST_SERVER_DIR" | "MEMORY" | "PORT"
+        ) {
+            return Err(AgentError::InvalidRequest(format!(
+                "{} is set by the agent and can't be overridden",
+                key
+            )));
+        }
+        let value = match value {
+            Value::Null => None,
+            Value::String(s) if s.len() <= MAX_ENVIRONMENT_VALUE_LEN => Some(s.clone()),
+            _ => {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Invalid value for {}: expected a string of at most {} bytes or null",
+                    key, MAX_ENVIRONMENT_VALUE_LEN
+                )));
+            }
+        };
+        changes.insert(key.clone(), value);
+    }
+    Ok(changes)
+}
+
+/// Start message (or template) `annotations`: string values under keys that don't claim the
+/// OCI or containerd namespaces, which runtimes interpret themselves.
+fn parse_annotations(value: Option<&Value>) -> AgentResult<HashMap<String, String>> {
+    let Some(value) = value.filter(|v| !v.is_null()) else {
+        return Ok(HashMap::new());
+    };
+    let entries = value.as_object().ok_or_else(|| {
+        AgentError::InvalidRequest("Invalid annotations: must be an object".to_string())
+    })?;
+    if entries.len() > 64 {
+        return Err(AgentError::InvalidRequest(
+            "Invalid annotations: at most 64 entries".to_string(),
+        ));
+    }
+    let mut annotations = HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        let valid_key = !key.is_empty()
+            && key.len() <= 253
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/'));
+        if !valid_key {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid annotation key '{}'",
+                key
+            )));
+        }
+        if key.starts_with("org.opencontainers.") || key.starts_with("io.containerd.") {
+            return Err(AgentError::InvalidRequest(format!(
+                "Annotation key '{}' is reserved for the runtime",
+                key
+            )));
+        }
+        let value = value.as_str().filter(|v| v.len() <= 4096).ok_or_else(|| {
+            AgentError::InvalidRequest(format!(
+                "Invalid annotation '{}': value must be a string of at most 4096 bytes",
+                key
+            ))
+        })?;
+        annotations.insert(key.clone(), value.to_string());
+    }
+    Ok(annotations)
+}
+
+/// Validate a `set_priority` request. Only deprioritizing is allowed: nice 0..=19, and the
+/// best-effort (level 0..=7) or idle I/O classes. `reset: true` restores the kernel defaults.
+fn parse_process_priority(msg: &Value) -> AgentResult<ProcessPriority> {
+    if msg["reset"].as_bool() == Some(true) {
+        return Ok(ProcessPriority::default());
+    }
+    let nice = match &msg["nice"] {
+        Value::Null => 0,
+        value => value
+            .as_i64()
+            .filter(|n| (0..=19).contains(n))
+            .ok_or_else(|| {
+                AgentError::InvalidRequest(
+                    "Invalid nice: must be an integer between 0 and 19".to_string(),
+                )
+            })? as i32,
+    };
+    let io_class = match msg["ioClass"].as_str() {
+        None | Some("none") => IoClass::None,
+        Some("best-effort") => IoClass::BestEffort,
+        Some("idle") => IoClass::Idle,
+        Some(other) => {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid ioClass '{}': expected 'none', 'best-effort' or 'idle'",
+                other
+            )));
+        }
+    };
+    let io_level = match (&msg["ioLevel"], io_class) {
+        (Value::Null, IoClass::BestEffort) => 4,
+        (Value::Null, _) => 0,
+        (value, IoClass::BestEffort) => {
+            value.as_u64().filter(|level| *level <= 7).ok_or_else(|| {
+                AgentError::InvalidRequest(
+                    "Invalid ioLevel: must be an integer between 0 and 7".to_string(),
+                )
+            })? as u8
+        }
+        (_, _) => {
+            return Err(AgentError::InvalidRequest(
+                "ioLevel only applies to the best-effort ioClass".to_string(),
+            ));
+        }
+    };
+    Ok(ProcessPriority {
+        nice,
+        io_class,
+        io_level,
+    })
+}
+
+/// The `type`/`port`/`command` part shared by `readinessProbe` and `healthCheck`.
+fn parse_probe_check(probe: &Value, primary_port: u16, field: &str) -> AgentResult<ReadinessCheck> {
+    let port = match probe.get("port").and_then(Value::as_u64) {
+        Some(port) => u16::try_from(port)
+            .ok()
+            .filter(|p| *p > 0)
+            .ok_or_else(|| AgentError::InvalidRequest(format!("Invalid {} port", field)))?,
+        None => primary_port,
+    };
+    match probe.get("type").and_then(Value::as_str).unwrap_or("tcp") {
+        "tcp" => Ok(ReadinessCheck::Tcp(port)),
+        "udp" => Ok(ReadinessCheck::Udp(port)),
+        "exec" => {
+            let command: Vec<String> = match probe.get("command") {
+                Some(Value::String(cmd)) => {
+                    vec!["/bin/sh".to_string(), "-c".to_string(), cmd.clone()]
+                }
+                Some(Value::Array(args)) => args
+                    .iter()
+                    .filter_map(|a| a.as_str().map(str::to_string))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            if command.is_empty() {
+                return Err(AgentError::InvalidRequest(format!(
+                    "{} of type exec requires a command",
+                    field
+                )));
+            }
+            Ok(ReadinessCheck::Exec(command))
+        }
+        other => Err(AgentError::InvalidRequest(format!(
+            "Unsupported {} type '{}'",
+            field, other
+        ))),
+    }
+}
+
+fn parse_health_check(
+    template: &serde_json::Map<String, Value>,
+    primary_port: u16,
+) -> AgentResult<Option<HealthCheck>> {
+    let Some(spec) = template.get("healthCheck").filter(|v| !v.is_null()) else {
+        return Ok(None);
+    };
+    let check = parse_probe_check(spec, primary_port, "healthCheck")?;
+    let action = match spec.get("action").and_then(Value::as_str) {
+        None | Some("report") => HealthCheckAction::Report,
+        Some("stop") => HealthCheckAction::Stop,
+        Some("restart-then-stop") => HealthCheckAction::RestartThenStop,
+        Some(other) => {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid healthCheck action '{}': expected 'report', 'stop' or 'restart-then-stop'",
+                other
+            )));
+        }
+    };
+    let interval_secs = spec
+        .get("intervalSeconds")
+        .and_then(Value::as_u64)
+        .unwrap_or(30)
+        .clamp(5, 3600);
+    let initial_delay_secs = spec
+        .get("initialDelaySeconds")
+        .and_then(Value::as_u64)
+        .unwrap_or(60)
+        .min(3600);
+    let failure_threshold = spec
+        .get("failureThreshold")
+        .and_then(Value::as_u64)
+        .unwrap_or(3)
+        .clamp(1, 100) as u32;
+    Ok(Some(HealthCheck {
+        check,
+        interval: Duration::from_secs(interval_secs),
+        initial_delay: Duration::from_secs(initial_delay_secs),
+        failure_threshold,
+        action,
+        snapshot: spec
+            .get("snapshot")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    }))
+}
+
+fn parse_readiness_probe(
+    template: &serde_json::Map<String, Value>,
+    primary_port: u16,
+) -> AgentResult<Option<ReadinessProbe>> {
+    let Some(probe) = template.get("readinessProbe").filter(|v| !v.is_null()) else {
+        return Ok(None);
+    };
+    let check = parse_probe_check(probe, primary_port, "readinessProbe")?;
+    let timeout_secs = probe
+        .get("timeoutSeconds")
+        .and_then(Value::as_u64)
+        .unwrap_or(120)
+        .clamp(1, 3600);
+    let interval_secs = probe
+        .get("intervalSeconds")
+        .and_then(Value::as_u64)
+        .unwrap_or(2)
+        .clamp(1, 60);
+    Ok(Some(ReadinessProbe {
+        check,
+        timeout: Duration::from_secs(timeout_secs),
+        interval: Duration::from_secs(interval_secs),
+    }))
+}
+
+/// Compression applied to backup download chunks and buffered metrics batches before
+/// base64 encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum TransferCompression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl TransferCompression {
+    const SUPPORTED: [&'static str; 3] = ["none", "gzip", "zstd"];
+
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" | "raw" => Some(Self::None),
+            "gzip" => Some(Self::Gzip),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Compress a single chunk. Every chunk is a self-contained frame so the backend can
+    /// decode chunks independently as they arrive.
+    fn compress(self, data: &[u8]) -> AgentResult<Vec<u8>> {
+        match self {
+            Self::None => Ok(data.to_vec()),
+            Self::Gzip => {
+                use std::io::Write;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Zstd => Ok(zstd::bulk::compress(data, 3)?),
+        }
+    }
+}
+
+/// Which node capacity limit a server request is checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CapacityScope {
+    Servers,
+    Running,
+}
+
+impl CapacityScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            CapacityScope::Servers => "servers",
+            CapacityScope::Running => "running",
+        }
+    }
+}
+
+/// Bounded tail of a server's console, fed as output is emitted.
+#[derive(Default)]
+struct ConsoleBuffer {
+    lines: VecDeque<String>,
+    /// Whether the last stored line is still waiting for its newline.
+    partial: bool,
+}
+
+impl ConsoleBuffer {
+    fn push(&mut self, data: &str, capacity: usize) {
+        let mut segments = data.split('\n');
+        if self.partial {
+            if let (Some(first), Some(last)) = (segments.next(), self.lines.back_mut()) {
+                last.push_str(first);
+            }
+        }
+        for segment in segments {
+            self.lines.push_back(segment.to_string());
+        }
+        // A trailing newline leaves an empty segment that stands for the next line.
+        self.partial = !data.ends_with('\n');
+        if !self.partial {
+            self.lines.pop_back();
+        }
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+        }
+    }
+
+    fn tail(&self, count: usize) -> Vec<String> {
+        let start = self.lines.len().saturating_sub(count);
+        self.lines.range(start..).cloned().collect()
+    }
+}
+
+/// Persistent copy of installer output in the server directory, so the transcript survives
+/// a dropped connection. The previous install's log is kept as `install.log.1`.
+struct InstallLog {
+    file: Option<tokio::fs::File>,
+}
+
+impl InstallLog {
+    async fn create(server_dir: &Path) -> Self {
+        let path = server_dir.join(INSTALL_LOG_FILE);
+        let _ = tokio::fs::rename(&path, server_dir.join(format!("{}.1", INSTALL_LOG_FILE))).await;
+        // create_new refuses to follow a symlink planted in the (user-writable) server dir.
+        let file = match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Failed to create install log {}: {}", path.display(), e);
+                return Self { file: None };
+            }
+        };
+        if let Err(e) = std::os::unix::fs::fchown(&file, Some(1000), Some(1000)) {
+            warn!("Failed to chown install log {}: {}", path.display(), e);
+        }
+        Self { file: Some(file) }
     }
 
-    policy
+    async fn write(&mut self, data: &str) {
+        if let Some(file) = self.file.as_mut() {
+            if let Err(e) = file.write_all(data.as_bytes()).await {
+                warn!("Failed to write install log: {}", e);
+                self.file = None;
+            }
+        }
+    }
+}
+
+/// Crash bookkeeping for a server, surfaced in stats so crash loops show up in the panel.
+#[derive(Clone, Copy, Debug, Default)]
+struct ServerRunMeta {
+    /// Starts that followed a crash since the last clean stop.
+    restart_count: u32,
+    last_exit_code: Option<i32>,
+    crashed: bool,
+    /// Last reported state was `running` (reported only once any readiness probe passed).
+    ready: bool,
+    /// Already restarted by a `restart-then-stop` health check since the last clean stop.
+    health_restarted: bool,
+}
+
+/// Latest state update per server still awaiting an `ack_state` from the backend.
+struct PendingStateUpdate {
+    seq: u64,
+    message: Value,
+    sent_at: tokio::time::Instant,
+    resends: u32,
+}
+
+/// Acknowledgement tracking is dormant until the backend sends its first `ack_state`, so
+/// backends that don't participate never see resends.
+#[derive(Default)]
+struct StateAckTracker {
+    enabled: bool,
+    pending: HashMap<String, PendingStateUpdate>,
 }
 
 struct BackupUploadSession {
@@ -130,6 +1068,35 @@ struct BackupUploadSession {
     path: PathBuf,
     bytes_written: u64,
     last_activity: tokio::time::Instant,
+    /// Backup slot held for the whole session; released when the session is dropped.
+    _permit: OwnedSemaphorePermit,
+}
+
+/// Backup operations that run in the background under the node-wide backup limit.
+#[derive(Clone, Copy, Debug)]
+enum BackupJob {
+    Create,
+    Restore,
+    Download,
+}
+
+impl BackupJob {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackupJob::Create => "create_backup",
+            BackupJob::Restore => "restore_backup",
+            BackupJob::Download => "download_backup",
+        }
+    }
+
+    /// Event the backend waits for to learn how the job ended.
+    fn result_event(self) -> &'static str {
+        match self {
+            BackupJob::Create => "backup_complete",
+            BackupJob::Restore => "backup_restore_complete",
+            BackupJob::Download => "backup_download_response",
+        }
+    }
 }
 
 pub struct WebSocketHandler {
@@ -139,9 +1106,67 @@ pub struct WebSocketHandler {
     storage_manager: Arc<StorageManager>,
     backend_connected: Arc<RwLock<bool>>,
     write: Arc<RwLock<Option<Arc<tokio::sync::Mutex<WsWrite>>>>>,
-    active_log_streams: Arc<RwLock<HashSet<String>>>,
+    active_log_streams: Arc<RwLock<HashMap<String, tokio::task::AbortHandle>>>,
     monitor_tasks: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
+    health_check_tasks: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
     active_uploads: Arc<RwLock<HashMap<String, BackupUploadSession>>>,
+    transfer_compression: Arc<RwLock<TransferCompression>>,
+    /// Compression for `resource_stats_batch` flushes, negotiated in the handshake.
+    metrics_batch_compression: Arc<RwLock<TransferCompression>>,
+    console_redactions: Arc<RwLock<HashMap<String, Arc<Vec<Regex>>>>>,
+    console_buffers: Arc<RwLock<HashMap<String, ConsoleBuffer>>>,
+    server_meta: Arc<RwLock<HashMap<String, ServerRunMeta>>>,
+    /// Servers whose template opted in to periodic resolv.conf drift repair.
+    dns_repair_servers: Arc<RwLock<HashSet<String>>>,
+    reclaimed_snapshots: Arc<AtomicU64>,
+    backend_latency_ms: Arc<RwLock<Option<i64>>>,
+    state_seq: Arc<AtomicU64>,
+    state_acks: Arc<RwLock<StateAckTracker>>,
+    /// State updates emitted while no backend connection was up, flushed on connect.
+    unsent_state_updates: Arc<RwLock<VecDeque<Value>>>,
+    /// Data volumes currently above `INODE_WARNING_PERCENT`, so the warning is logged once.
+    inode_warnings: Arc<RwLock<HashSet<PathBuf>>>,
+    backup_permits: Arc<Semaphore>,
+    /// Slots for `test_install` runs (`limits.max_concurrent_test_installs`).
+    test_install_permits: Arc<Semaphore>,
+    /// `startAfter` dependency declared by each server's most recent start, for cycle checks.
+    start_dependencies: Arc<RwLock<HashMap<String, String>>>,
+    /// Starts waiting for their `startAfter` dependency, by server id. A new start or a stop
+    /// of the server aborts its waiter.
+    deferred_starts: Arc<RwLock<HashMap<String, tokio::task::AbortHandle>>>,
+    /// Server uuids whose files may be read but not modified (shared with the file tunnel).
+    read_only_servers: Arc<RwLock<HashSet<String>>>,
+    /// Set while the data or backup filesystem is below `limits.low_disk_floor_mb` (shared with
+    /// the file tunnel).
+    low_disk: Arc<AtomicBool>,
+    /// Startup self-check failures reported to the backend in every handshake.
+    capability_warnings: Arc<RwLock<Vec<serde_json::Value>>>,
+    /// Previous (time, CPU ticks) reading of the agent process, for its CPU percentage.
+    agent_cpu_sample: Arc<RwLock<Option<(std::time::Instant, u64)>>>,
+    /// `serverId:serverUuid` pairs already reported as having duplicate containers.
+    reported_duplicates: Arc<RwLock<HashSet<String>>>,
+    /// `tail_file` subscriptions by tail id; aborted on `stop_tail_file` or disconnect.
+    file_tails: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
+    /// Last successful start message per server, so `update_environment` can restart it.
+    /// Persisted with `environment_overrides` and loaded by `restore_start_state`.
+    start_messages: Arc<RwLock<HashMap<String, Value>>>,
+    /// Environment changes from `update_environment` (`None` unsets), applied to every start
+    /// until a start message already carries them.
+    environment_overrides: Arc<RwLock<HashMap<String, EnvironmentChanges>>>,
+    /// Servers being stopped on purpose: `None` while the stop runs, then the end of the
+    /// grace window. Exits in that time are not reported as crashes.
+    intentional_stops: Arc<RwLock<HashMap<String, Option<std::time::Instant>>>>,
+    last_console_broadcast: Arc<RwLock<Option<tokio::time::Instant>>>,
+    /// The persisted autostart state as this agent process found it, and whether the node
+    /// rebooted since it was written.
+    previous_autostart: Arc<RwLock<(AutostartState, bool)>>,
+    /// Set by `stop_all_servers`; stops made while shutting down don't count as the user's.
+    shutting_down: Arc<AtomicBool>,
+    /// Per-server locks serializing start, stop and storage compaction, which mount and
+    /// unmount the server's storage image.
+    server_locks: Arc<std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    /// Long-running request handlers spawned off the read loop; aborted on disconnect.
+    connection_jobs: Arc<std::sync::Mutex<Vec<tokio::task::AbortHandle>>>,
 }
 
 impl Clone for WebSocketHandler {
@@ -155,7 +1180,38 @@ impl Clone for WebSocketHandler {
             write: self.write.clone(),
             active_log_streams: self.active_log_streams.clone(),
             monitor_tasks: self.monitor_tasks.clone(),
+            health_check_tasks: self.health_check_tasks.clone(),
             active_uploads: self.active_uploads.clone(),
+            transfer_compression: self.transfer_compression.clone(),
+            metrics_batch_compression: self.metrics_batch_compression.clone(),
+            console_redactions: self.console_redactions.clone(),
+            console_buffers: self.console_buffers.clone(),
+            server_meta: self.server_meta.clone(),
+            dns_repair_servers: self.dns_repair_servers.clone(),
+            reclaimed_snapshots: self.reclaimed_snapshots.clone(),
+            backend_latency_ms: self.backend_latency_ms.clone(),
+            state_seq: self.state_seq.clone(),
+            state_acks: self.state_acks.clone(),
+            unsent_state_updates: self.unsent_state_updates.clone(),
+            inode_warnings: self.inode_warnings.clone(),
+            backup_permits: self.backup_permits.clone(),
+            test_install_permits: self.test_install_permits.clone(),
+            start_dependencies: self.start_dependencies.clone(),
+            deferred_starts: self.deferred_starts.clone(),
+            read_only_servers: self.read_only_servers.clone(),
+            low_disk: self.low_disk.clone(),
+            capability_warnings: self.capability_warnings.clone(),
+            agent_cpu_sample: self.agent_cpu_sample.clone(),
+            reported_duplicates: self.reported_duplicates.clone(),
+            file_tails: self.file_tails.clone(),
+            start_messages: self.start_messages.clone(),
+            environment_overrides: self.environment_overrides.clone(),
+            intentional_stops: self.intentional_stops.clone(),
+            last_console_broadcast: self.last_console_broadcast.clone(),
+            previous_autostart: self.previous_autostart.clone(),
+            shutting_down: self.shutting_down.clone(),
+            server_locks: self.server_locks.clone(),
+            connection_jobs: self.connection_jobs.clone(),
         }
     }
 }
@@ -177,7 +1233,13 @@ impl WebSocketHandler {
         file_manager: Arc<FileManager>,
         storage_manager: Arc<StorageManager>,
         backend_connected: Arc<RwLock<bool>>,
+        read_only_servers: Arc<RwLock<HashSet<String>>>,
+        low_disk: Arc<AtomicBool>,
     ) -> Self {
+        let backup_permits = Arc::new(Semaphore::new(config.limits.max_concurrent_backups.max(1)));
+        let test_install_permits = Arc::new(Semaphore::new(
+            config.limits.max_concurrent_test_installs.max(1),
+        ));
         Self {
             config,
             runtime,
@@ -185,12 +1247,78 @@ impl WebSocketHandler {
             storage_manager,
             backend_connected,
             write: Arc::new(RwLock::new(None)),
-            active_log_streams: Arc::new(RwLock::new(HashSet::new())),
+            active_log_streams: Arc::new(RwLock::new(HashMap::new())),
             monitor_tasks: Arc::new(RwLock::new(HashMap::new())),
+            health_check_tasks: Arc::new(RwLock::new(HashMap::new())),
             active_uploads: Arc::new(RwLock::new(HashMap::new())),
+            transfer_compression: Arc::new(RwLock::new(TransferCompression::default())),
+            metrics_batch_compression: Arc::new(RwLock::new(TransferCompression::default())),
+            console_redactions: Arc::new(RwLock::new(HashMap::new())),
+            console_buffers: Arc::new(RwLock::new(HashMap::new())),
+            server_meta: Arc::new(RwLock::new(HashMap::new())),
+            dns_repair_servers: Arc::new(RwLock::new(HashSet::new())),
+            reclaimed_snapshots: Arc::new(AtomicU64::new(0)),
+            backend_latency_ms: Arc::new(RwLock::new(None)),
+            state_seq: Arc::new(AtomicU64::new(0)),
+            state_acks: Arc::new(RwLock::new(StateAckTracker::default())),
+            unsent_state_updates: Arc::new(RwLock::new(VecDeque::new())),
+            inode_warnings: Arc::new(RwLock::new(HashSet::new())),
+            backup_permits,
+            test_install_permits,
+            start_dependencies: Arc::new(RwLock::new(HashMap::new())),
+            deferred_starts: Arc::new(RwLock::new(HashMap::new())),
+            read_only_servers,
+            low_disk,
+            capability_warnings: Arc::new(RwLock::new(Vec::new())),
+            agent_cpu_sample: Arc::new(RwLock::new(None)),
+            reported_duplicates: Arc::new(RwLock::new(HashSet::new())),
+            file_tails: Arc::new(RwLock::new(HashMap::new())),
+            start_messages: Arc::new(RwLock::new(HashMap::new())),
+            environment_overrides: Arc::new(RwLock::new(HashMap::new())),
+            intentional_stops: Arc::new(RwLock::new(HashMap::new())),
+            last_console_broadcast: Arc::new(RwLock::new(None)),
+            previous_autostart: Arc::new(RwLock::new((AutostartState::default(), false))),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            server_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            connection_jobs: Arc::new(std::sync::Mutex::new(Vec::new())),
         }
     }
 
+    /// Exclusive access to a server's storage-affecting operations (start, stop, compaction,
+    /// backup and restore).
+    async fn lock_server(&self, server_id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = self
+            .server_locks
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(server_id.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    /// Run a long request handler off the read loop, tied to the current connection.
+    fn spawn_connection_job<F>(&self, job: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(job).abort_handle();
+        let mut jobs = self
+            .connection_jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        jobs.retain(|job| !job.is_finished());
+        jobs.push(handle);
+    }
+
+    /// Record a degraded host capability (e.g. no iptables access) for the backend to surface.
+    pub async fn add_capability_warning(&self, code: &str, message: String) {
+        self.capability_warnings
+            .write()
+            .await
+            .push(json!({ "code": code, "message": message }));
+    }
+
     async fn set_backend_connected(&self, connected: bool) {
         let mut status = self.backend_connected.write().await;
         *status = connected;
@@ -200,74 +1328,101 @@ impl WebSocketHandler {
         &self,
         write: Arc<tokio::sync::Mutex<WsWrite>>,
     ) -> AgentResult<()> {
-        let buffered = match self.storage_manager.read_buffered_metrics().await {
-            Ok(v) => v,
-            Err(e) => {
-                warn!("Failed to read buffered metrics: {}", e);
-                return Ok(());
-            }
-        };
-
-        if buffered.is_empty() {
-            return Ok(());
-        }
-
-        info!("Flushing {} buffered metrics", buffered.len());
+        let compression = *self.metrics_batch_compression.read().await;
+        loop {
+            let buffered = match self.storage_manager.read_buffered_metrics().await {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("Failed to read buffered metrics: {}", e);
+                    return Ok(());
+                }
+            };
 
-        let batch_size = 500usize;
-        for chunk in buffered.chunks(batch_size) {
-            let metrics_value = serde_json::Value::Array(chunk.to_vec());
-            let payload = json!({ "type": "resource_stats_batch", "metrics": metrics_value });
-            let mut w = write.lock().await;
-            if let Err(e) = w.send(Message::Text(payload.to_string().into())).await {
-                warn!("Failed to send buffered metrics batch: {}", e);
-                // leave buffer intact - will retry on next connect
+            if buffered.is_empty() {
                 return Ok(());
             }
-        }
 
-        // All batches sent successfully - clear buffer
-        if let Err(e) = self.storage_manager.clear_buffered_metrics().await {
-            warn!("Failed to clear buffered metrics: {}", e);
-        }
+            info!(
+                "Flushing {} buffered metrics ({} compression)",
+                buffered.len(),
+                compression.as_str()
+            );
 
-        Ok(())
+            let batch_size = 500usize;
+            for chunk in buffered.chunks(batch_size) {
+                let metrics_value = serde_json::Value::Array(chunk.to_vec());
+                let payload = match compression {
+                    TransferCompression::None => {
+                        json!({ "type": "resource_stats_batch", "metrics": metrics_value })
+                    }
+                    _ => {
+                        let compressed =
+                            compression.compress(metrics_value.to_string().as_bytes())?;
+                        json!({
+                            "type": "resource_stats_batch",
+                            "count": chunk.len(),
+                            "compression": compression.as_str(),
+                            "data": base64::engine::general_purpose::STANDARD.encode(compressed),
+                        })
+                    }
+                };
+                let mut w = write.lock().await;
+                if let Err(e) = w.send(Message::Text(payload.to_string().into())).await {
+                    warn!("Failed to send buffered metrics batch: {}", e);
+                    // leave buffer intact - will retry on next connect
+                    return Ok(());
+                }
+            }
+
+            // All batches sent successfully - clear them, then pick up what was buffered meanwhile
+            if let Err(e) = self.storage_manager.clear_buffered_metrics().await {
+                warn!("Failed to clear buffered metrics: {}", e);
+                return Ok(());
+            }
+        }
     }
 
     pub async fn connect_and_listen(&self) -> AgentResult<()> {
+        let mut rejected_backoff = HANDSHAKE_REJECTED_BACKOFF;
         loop {
-            match self.establish_connection().await {
+            let delay = match self.establish_connection().await {
                 Ok(()) => {
                     info!("WebSocket connection closed");
+                    rejected_backoff = HANDSHAKE_REJECTED_BACKOFF;
+                    RECONNECT_DELAY
+                }
+                Err(AgentError::HandshakeRejected(reason)) => {
+                    error!(
+                        "Backend rejected node handshake: {}. Check server.node_id and server.api_key; retrying in {}s",
+                        reason,
+                        rejected_backoff.as_secs()
+                    );
+                    let delay = rejected_backoff;
+                    rejected_backoff = (rejected_backoff * 2).min(MAX_HANDSHAKE_REJECTED_BACKOFF);
+                    delay
                 }
                 Err(e) => {
                     error!("Connection error: {}", e);
+                    RECONNECT_DELAY
                 }
-            }
+            };
 
             self.set_backend_connected(false).await;
-            tokio::time::sleep(Duration::from_secs(5)).await;
+            tokio::time::sleep(delay).await;
         }
     }
 
     async fn establish_connection(&self) -> AgentResult<()> {
         self.set_backend_connected(false).await;
+        *self.transfer_compression.write().await = TransferCompression::default();
+        *self.metrics_batch_compression.write().await = TransferCompression::default();
+        *self.backend_latency_ms.write().await = None;
 
         let (auth_token, token_type) = self.select_agent_auth_token()?;
 
         // Enforce secure transport for non-local backends.
-        let mut parsed_url = Url::parse(&self.config.server.backend_url)
-            .map_err(|e| AgentError::ConfigError(format!("Invalid server.backend_url: {}", e)))?;
-        match parsed_url.scheme() {
-            "wss" => {}
-            "ws" => {}
-            other => {
-                return Err(AgentError::ConfigError(format!(
-                    "Invalid backend_url scheme '{}': expected ws:// or wss://",
-                    other
-                )));
-            }
-        }
+        let mut parsed_url =
+            parse_backend_url(&self.config.server.backend_url).map_err(AgentError::ConfigError)?;
 
         // Put non-sensitive identity data in the URL; send secrets in the handshake message.
         parsed_url
@@ -281,26 +1436,38 @@ impl WebSocketHandler {
         );
         info!("Using {} auth token for agent connection", token_type);
 
-        let (ws_stream, _) = connect_async(ws_url.as_str())
+        let connector = build_tls_connector(&self.config.tls)?;
+        if connector.is_some() && ws_url.scheme() != "wss" {
+            warn!("TLS policy is configured but backend_url is not wss://; it will not apply");
+        }
+        let (ws_stream, _) = connect_async_tls_with_config(ws_url.as_str(), None, false, connector)
             .await
             .map_err(|e| AgentError::NetworkError(format!("Failed to connect: {}", e)))?;
 
         info!("WebSocket connected to backend");
 
         let (write, mut read) = ws_stream.split();
-        let write = Arc::new(tokio::sync::Mutex::new(write));
+        let write = Arc::new(tokio::sync::Mutex::new(SigningSink::new(write)));
         {
             let mut guard = self.write.write().await;
             *guard = Some(write.clone());
         }
 
         // Send handshake
-        let handshake = json!({
+        let capability_warnings = self.capability_warnings.read().await.clone();
+        let mut handshake = json!({
             "type": "node_handshake",
             "token": auth_token,
             "nodeId": self.config.server.node_id,
             "tokenType": token_type,
+            "transferCompression": TransferCompression::SUPPORTED,
+            "metricsBatchCompression": ["none", "gzip"],
+            "capabilityWarnings": capability_warnings,
+            "architecture": host_oci_arch(),
         });
+        if self.config.server.hmac_key().is_some() {
+            handshake["messageSigning"] = json!([message_signing::ALGORITHM]);
+        }
 
         {
             let mut w = write.lock().await;
@@ -311,6 +1478,23 @@ impl WebSocketHandler {
 
         info!("Handshake sent");
 
+        // State changes seen while disconnected (e.g. an adopted server crashing before the
+        // first connect) go out before reconciliation reports current state.
+        let unsent: Vec<Value> = self.unsent_state_updates.write().await.drain(..).collect();
+        if !unsent.is_empty() {
+            info!(
+                "Flushing {} state updates from while disconnected",
+                unsent.len()
+            );
+            let mut w = write.lock().await;
+            for update in unsent {
+                if let Err(err) = w.send(Message::Text(update.to_string().into())).await {
+                    warn!("Failed to flush state update: {}", err);
+                    break;
+                }
+            }
+        }
+
         // Restore console writers for any running containers
         // This is critical after reconnection to prevent console soft-lock
         if let Err(e) = self.runtime.restore_console_writers().await {
@@ -322,11 +1506,6 @@ impl WebSocketHandler {
             warn!("Failed to reconcile server states: {}", e);
         }
 
-        // Flush any buffered metrics now that we're connected
-        if let Err(e) = self.flush_buffered_metrics(write.clone()).await {
-            warn!("Failed to flush buffered metrics: {}", e);
-        }
-
         // Connection-scoped background tasks. Abort on disconnect to avoid accumulation.
         let mut connection_tasks: Vec<tokio::task::JoinHandle<()>> = Vec::new();
 
@@ -345,6 +1524,21 @@ impl WebSocketHandler {
             }
         }));
 
+        // Probe control-channel round-trip time; the backend echoes our timestamp in a pong.
+        let write_clone = write.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(LATENCY_PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let ping = json!({
+                    "type": "ping",
+                    "timestamp": chrono::Utc::now().timestamp_millis(),
+                });
+                let mut w = write_clone.lock().await;
+                let _ = w.send(Message::Text(ping.to_string().into())).await;
+            }
+        }));
+
         // Start periodic state reconciliation task (every 5 minutes)
         // This catches any status drift that may occur
         let handler_clone = self.clone();
@@ -378,12 +1572,140 @@ impl WebSocketHandler {
             }
         }));
 
-        // Listen for messages
-        while let Some(msg) = read.next().await {
+        // Reclaim rootfs snapshots leaked by failed creates or crashes.
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SNAPSHOT_GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                match handler_clone
+                    .runtime
+                    .cleanup_orphaned_snapshots(ORPHANED_SNAPSHOT_GRACE)
+                    .await
+                {
+                    Ok(removed) if !removed.is_empty() => {
+                        handler_clone
+                            .reclaimed_snapshots
+                            .fetch_add(removed.len() as u64, Ordering::Relaxed);
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Orphaned snapshot cleanup failed: {}", e),
+                }
+            }
+        }));
+
+        // Resend state updates the backend hasn't acknowledged (only once it sends acks).
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STATE_ACK_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                handler_clone.resend_unacked_states().await;
+            }
+        }));
+
+        // Remove installer containers left running by crashed installs or a previous agent run.
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(INSTALLER_GC_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = handler_clone
+                    .runtime
+                    .cleanup_orphaned_installers(ORPHANED_INSTALLER_GRACE)
+                    .await
+                {
+                    warn!("Orphaned installer cleanup failed: {}", e);
+                }
+            }
+        }));
+
+        // Retry CNI teardowns that failed, so their veths and iptables rules don't leak.
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CNI_TEARDOWN_RETRY_INTERVAL);
+            loop {
+                interval.tick().await;
+                let cleaned = handler_clone.runtime.retry_failed_cni_teardowns().await;
+                if cleaned > 0 {
+                    info!("Completed {} previously failed CNI teardown(s)", cleaned);
+                }
+            }
+        }));
+
+        // Refuse new disk-consuming work while the node is nearly out of space.
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DISK_GUARD_INTERVAL);
+            loop {
+                interval.tick().await;
+                handler_clone.check_disk_floor().await;
+            }
+        }));
+
+        // Re-apply configured DNS for servers that opted in, since CNI plugins and some server
+        // software rewrite resolv.conf mid-run.
+        let handler_clone = self.clone();
+        connection_tasks.push(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(DNS_REPAIR_INTERVAL);
+            loop {
+                interval.tick().await;
+                let servers: Vec<String> = handler_clone
+                    .dns_repair_servers
+                    .read()
+                    .await
+                    .iter()
+                    .cloned()
+                    .collect();
+                for server_id in servers {
+                    if !handler_clone
+                        .runtime
+                        .is_container_running(&server_id)
+                        .await
+                        .unwrap_or(false)
+                    {
+                        continue;
+                    }
+                    if let Err(e) = handler_clone.runtime.repair_resolv_conf(&server_id).await {
+                        warn!("DNS repair failed for {}: {}", server_id, e);
+                    }
+                }
+            }
+        }));
+
+        // Listen for messages. Until the backend accepts the handshake, silence is treated as
+        // a rejection so auth misconfiguration surfaces instead of idling half-connected.
+        let handshake_deadline = tokio::time::Instant::now() + HANDSHAKE_RESPONSE_TIMEOUT;
+        let mut outcome = Ok(());
+        loop {
+            let msg = if *self.backend_connected.read().await {
+                read.next().await
+            } else {
+                match tokio::time::timeout_at(handshake_deadline, read.next()).await {
+                    Ok(msg) => msg,
+                    Err(_) => {
+                        outcome = Err(AgentError::HandshakeRejected(format!(
+                            "no handshake response within {}s",
+                            HANDSHAKE_RESPONSE_TIMEOUT.as_secs()
+                        )));
+                        break;
+                    }
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
             match msg {
                 Ok(Message::Text(text)) => {
-                    if let Err(e) = self.handle_message(&text, &write).await {
-                        error!("Error handling message: {}", e);
+                    match self.handle_message_with_timeout(&text, &write).await {
+                        Err(e @ AgentError::HandshakeRejected(_)) => {
+                            outcome = Err(e);
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Error handling message: {}", e);
+                        }
+                        Ok(()) => {}
                     }
                 }
                 Ok(Message::Close(_)) => {
@@ -401,17 +1723,28 @@ impl WebSocketHandler {
         for task in connection_tasks {
             task.abort();
         }
+        for job in self
+            .connection_jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain(..)
+        {
+            job.abort();
+        }
 
         // Drop any in-progress uploads on disconnect to avoid stale sessions accumulating across
         // reconnects and to release file descriptors.
         self.cleanup_all_uploads().await;
+        for (_, task) in self.file_tails.write().await.drain() {
+            task.abort();
+        }
 
         {
             let mut guard = self.write.write().await;
             *guard = None;
         }
 
-        Ok(())
+        outcome
     }
 
     async fn cleanup_all_uploads(&self) {
@@ -427,6 +1760,15 @@ impl WebSocketHandler {
         }
     }
 
+    async fn discard_upload(&self, request_id: &str) {
+        let session = { self.active_uploads.write().await.remove(request_id) };
+        if let Some(session) = session {
+            let path = session.path.clone();
+            drop(session.file);
+            let _ = tokio::fs::remove_file(&path).await;
+        }
+    }
+
     async fn cleanup_stale_uploads(&self) {
         let now = tokio::time::Instant::now();
         let sessions: Vec<BackupUploadSession> = {
@@ -452,6 +1794,70 @@ impl WebSocketHandler {
         }
     }
 
+    /// Seconds `msg` may be handled for: a template's `installTimeoutSecs` for installs, which
+    /// can legitimately run for hours, else `protocol.handler_timeout_secs`. 0 means no limit.
+    fn handler_timeout_secs(&self, msg: &Value) -> u64 {
+        match (
+            msg["type"].as_str(),
+            msg["template"]["installTimeoutSecs"].as_u64(),
+        ) {
+            (Some("install_server"), Some(secs)) => secs,
+            _ => self.config.protocol.handler_timeout_secs,
+        }
+    }
+
+    /// Run a handler under the configured timeout so one stuck operation can't freeze the
+    /// read loop. Installer containers remove themselves when their handler is dropped; an
+    /// interrupted upload session is discarded here.
+    async fn handle_message_with_timeout(
+        &self,
+        text: &str,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let msg: Value = serde_json::from_str(text).unwrap_or(Value::Null);
+        let timeout_secs = self.handler_timeout_secs(&msg);
+        if timeout_secs == 0 {
+            return self.handle_message(text, write).await;
+        }
+        let Ok(result) = tokio::time::timeout(
+            Duration::from_secs(timeout_secs),
+            self.handle_message(text, write),
+        )
+        .await
+        else {
+            let msg_type = msg["type"].as_str().unwrap_or("unknown");
+            let reason = format!("{} timed out after {}s", msg_type, timeout_secs);
+            error!("Handler {}", reason);
+
+            if msg_type.starts_with("upload_backup") {
+                if let Some(request_id) = msg["requestId"].as_str() {
+                    self.discard_upload(request_id).await;
+                }
+            }
+            if let (Some(server_id), "install_server" | "start_server") =
+                (msg["serverId"].as_str(), msg_type)
+            {
+                let _ = self
+                    .emit_server_state_update(server_id, "error", Some(reason.clone()), None, None)
+                    .await;
+            }
+
+            let event = json!({
+                "type": "command_error",
+                "command": msg_type,
+                "requestId": msg["requestId"],
+                "serverId": msg["serverId"],
+                "reason": reason,
+            });
+            let mut w = write.lock().await;
+            w.send(Message::Text(event.to_string().into()))
+                .await
+                .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+            return Ok(());
+        };
+        result
+    }
+
     async fn handle_message(
         &self,
         text: &str,
@@ -459,11 +1865,19 @@ impl WebSocketHandler {
     ) -> AgentResult<()> {
         let msg: Value = serde_json::from_str(text)?;
 
+        if let Some(command @ ("install_server" | "create_backup" | "upload_backup_start")) =
+            msg["type"].as_str()
+        {
+            if self.low_disk.load(Ordering::Relaxed) {
+                return self.reject_low_disk(command, &msg, write).await;
+            }
+        }
+
         match msg["type"].as_str() {
             Some("server_control") => self.handle_server_control(&msg).await?,
             Some("install_server") => self.install_server(&msg).await?,
             Some("start_server") => {
-                self.start_server_with_details(&msg).await?;
+                self.start_server_after_dependency(&msg).await?;
             }
             Some("stop_server") => {
                 let server_uuid = msg["serverUuid"]
@@ -471,7 +1885,7 @@ impl WebSocketHandler {
                     .ok_or_else(|| AgentError::InvalidRequest("Missing serverUuid".to_string()))?;
                 let server_id = msg["serverId"].as_str().unwrap_or(server_uuid);
                 let container_id = self.resolve_container_id(server_id, server_uuid).await;
-                let stop_policy = parse_stop_policy(&msg);
+                let stop_policy = stop_policy_or_sigterm(&msg, server_id);
                 self.stop_server(server_id, container_id, &stop_policy)
                     .await?;
             }
@@ -489,26 +1903,61 @@ impl WebSocketHandler {
                     .ok_or_else(|| AgentError::InvalidRequest("Missing serverUuid".to_string()))?;
                 let server_id = msg["serverId"].as_str().unwrap_or(server_uuid);
                 let container_id = self.resolve_container_id(server_id, server_uuid).await;
-                let stop_policy = parse_stop_policy(&msg);
+                let stop_policy = stop_policy_or_sigterm(&msg, server_id);
                 self.stop_server(server_id, container_id, &stop_policy)
                     .await?;
                 tokio::time::sleep(Duration::from_secs(2)).await;
-                self.start_server_with_details(&msg).await?;
+                self.start_server_after_dependency(&msg).await?;
             }
             Some("console_input") => self.handle_console_input(&msg).await?,
+            Some("broadcast_console") => self.handle_broadcast_console(&msg, write).await?,
             Some("file_operation") => self.handle_file_operation(&msg).await?,
-            Some("create_backup") => self.handle_create_backup(&msg, write).await?,
-            Some("restore_backup") => self.handle_restore_backup(&msg, write).await?,
+            Some("set_files_read_only") => self.handle_set_files_read_only(&msg, write).await?,
+            Some("test_install") => self.spawn_test_install(msg, write),
+            Some("preview_install_script") => {
+                self.handle_preview_install_script(&msg, write).await?
+            }
+            Some("create_backup") => self.spawn_backup_job(BackupJob::Create, msg, write),
+            Some("restore_backup") => self.spawn_backup_job(BackupJob::Restore, msg, write),
             Some("delete_backup") => self.handle_delete_backup(&msg, write).await?,
             Some("download_backup_start") => self.handle_download_backup_start(&msg, write).await?,
-            Some("download_backup") => self.handle_download_backup(&msg, write).await?,
+            Some("download_backup") => self.spawn_backup_job(BackupJob::Download, msg, write),
+            Some("collect_support_bundle") => {
+                self.handle_collect_support_bundle(&msg, write).await?
+            }
             Some("upload_backup_start") => self.handle_upload_backup_start(&msg, write).await?,
             Some("upload_backup_chunk") => self.handle_upload_backup_chunk(&msg, write).await?,
             Some("upload_backup_complete") => {
                 self.handle_upload_backup_complete(&msg, write).await?
             }
             Some("resize_storage") => self.handle_resize_storage(&msg, write).await?,
+            Some("compact_storage") => {
+                let handler = self.clone();
+                let write = write.clone();
+                self.spawn_connection_job(async move {
+                    if let Err(e) = handler.handle_compact_storage(&msg, &write).await {
+                        warn!("Storage compaction failed: {}", e);
+                    }
+                });
+            }
             Some("resume_console") => self.resume_console(&msg).await?,
+            Some("console_history") => self.handle_console_history(&msg, write).await?,
+            Some("find_free_ports") => self.handle_find_free_ports(&msg, write).await?,
+            Some("repair_dns") => self.handle_repair_dns(&msg, write).await?,
+            Some("update_ports") => self.handle_update_ports(&msg, write).await?,
+            Some("get_disk_usage") => self.handle_get_disk_usage(&msg, write).await?,
+            Some("cleanup_installers") => self.handle_cleanup_installers(&msg, write).await?,
+            Some("container_diff") => self.handle_container_diff(&msg, write).await?,
+            Some("list_processes") => self.handle_list_processes(&msg, write).await?,
+            Some("repair_console") => self.handle_repair_console(&msg, write).await?,
+            Some("update_environment") => self.handle_update_environment(&msg, write).await?,
+            Some("tail_file") => self.handle_tail_file(&msg, write).await?,
+            Some("stop_tail_file") => self.handle_stop_tail_file(&msg, write).await?,
+            Some("set_priority") => self.handle_set_priority(&msg, write).await?,
+            Some("set_autostart") => self.handle_set_autostart(&msg, write).await?,
+            Some("get_autostart_set") => self.handle_get_autostart_set(&msg, write).await?,
+            Some("prepull_image") => self.handle_prepull_image(&msg, write)?,
+            Some("get_agent_events") => self.handle_get_agent_events(&msg, write).await?,
             Some("request_immediate_stats") => {
                 info!("Received immediate stats request from backend");
                 if let Err(e) = self.send_resource_stats().await {
@@ -518,12 +1967,105 @@ impl WebSocketHandler {
             Some("create_network") => self.handle_create_network(&msg, write).await?,
             Some("update_network") => self.handle_update_network(&msg, write).await?,
             Some("delete_network") => self.handle_delete_network(&msg, write).await?,
+            Some("ping") => {
+                let pong = json!({
+                    "type": "pong",
+                    "timestamp": msg["timestamp"],
+                    "agentTimestamp": chrono::Utc::now().timestamp_millis(),
+                });
+                let mut w = write.lock().await;
+                w.send(Message::Text(pong.to_string().into()))
+                    .await
+                    .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+            }
+            Some("ack_state") => self.handle_ack_state(&msg).await,
+            Some("pong") => {
+                if let Some(sent) = msg["timestamp"].as_i64() {
+                    let rtt = (chrono::Utc::now().timestamp_millis() - sent).max(0);
+                    debug!("Backend round-trip latency: {}ms", rtt);
+                    *self.backend_latency_ms.write().await = Some(rtt);
+                }
+            }
             Some("node_handshake_response") => {
                 info!("Handshake accepted by backend");
+                if let Some(value) = msg["transferCompression"].as_str() {
+                    match TransferCompression::parse(value) {
+                        Some(compression) => {
+                            info!("Using {} transfer compression", compression.as_str());
+                            *self.transfer_compression.write().await = compression;
+                        }
+                        None => warn!("Unsupported transferCompression '{}', using raw", value),
+                    }
+                }
+                match msg["metricsBatchCompression"].as_str() {
+                    None | Some("none") => {}
+                    Some("gzip") => {
+                        *self.metrics_batch_compression.write().await = TransferCompression::Gzip
+                    }
+                    Some(other) => warn!(
+                        "Unsupported metricsBatchCompression '{}', sending plain batches",
+                        other
+                    ),
+                }
+                match (
+                    self.config.server.hmac_key(),
+                    msg["messageSigning"].as_str(),
+                ) {
+                    (Some(key), Some(message_signing::ALGORITHM)) => {
+                        match msg["signingNonce"].as_str().filter(|nonce| !nonce.is_empty()) {
+                            Some(nonce) => {
+                                info!("Signing messages with {}", message_signing::ALGORITHM);
+                                write.lock().await.enable_signing(key, nonce);
+                            }
+                            None => warn!(
+                                "Backend accepted message signing without a signingNonce; messages are unsigned"
+                            ),
+                        }
+                    }
+                    (Some(_), None) => {
+                        warn!("Backend did not accept message signing; messages are unsigned")
+                    }
+                    (_, Some(other)) => {
+                        warn!(
+                            "Unsupported messageSigning '{}', messages are unsigned",
+                            other
+                        )
+                    }
+                    (None, None) => {}
+                }
                 self.set_backend_connected(true).await;
+
+                // Flush metrics buffered during the outage; a long outage can take a while.
+                let handler = self.clone();
+                let write = write.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handler.flush_buffered_metrics(write).await {
+                        warn!("Failed to flush buffered metrics: {}", e);
+                    }
+                });
+            }
+            Some("node_handshake_rejected") => {
+                let reason = msg["reason"].as_str().unwrap_or("no reason given");
+                self.set_backend_connected(false).await;
+                return Err(AgentError::HandshakeRejected(reason.to_string()));
             }
             _ => {
-                warn!("Unknown message type: {}", msg["type"]);
+                if self.config.protocol.warn_unknown_messages {
+                    warn!("Unknown message type: {}", msg["type"]);
+                } else {
+                    debug!("Unknown message type: {}", msg["type"]);
+                }
+                if self.config.protocol.reply_unknown_messages {
+                    let reply = json!({
+                        "type": "unknown_message",
+                        "messageType": msg["type"],
+                        "requestId": msg["requestId"],
+                    });
+                    let mut w = write.lock().await;
+                    w.send(Message::Text(reply.to_string().into()))
+                        .await
+                        .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+                }
             }
         }
 
@@ -550,7 +2092,6 @@ impl WebSocketHandler {
             .and_then(|value| value.as_str())
             .unwrap_or(server_id);
         let container_id = self.resolve_container_id(server_id, server_uuid).await;
-        let stop_policy = parse_stop_policy(msg);
 
         match action {
             "install" => self.install_server(msg).await?,
@@ -564,11 +2105,13 @@ impl WebSocketHandler {
                 self.start_server(server_id, container_id).await?
             }
             "stop" => {
+                let stop_policy = stop_policy_or_sigterm(msg, server_id);
                 self.stop_server(server_id, container_id, &stop_policy)
                     .await?
             }
             "kill" => self.kill_server(server_id, container_id).await?,
             "restart" => {
+                let stop_policy = stop_policy_or_sigterm(msg, server_id);
                 self.stop_server(server_id, container_id, &stop_policy)
                     .await?;
                 tokio::time::sleep(Duration::from_secs(2)).await;
@@ -621,51 +2164,1171 @@ impl WebSocketHandler {
         Ok(())
     }
 
-    async fn resolve_console_container_id(
+    async fn handle_console_history(
         &self,
-        server_id: &str,
-        server_uuid: &str,
-    ) -> Option<String> {
-        let server_id_exists = self.runtime.container_exists(server_id).await;
-        let server_uuid_exists = if server_uuid != server_id {
-            self.runtime.container_exists(server_uuid).await
-        } else {
-            false
-        };
-
-        if !server_id_exists && !server_uuid_exists {
-            return None;
-        }
-
-        let server_id_running = if server_id_exists {
-            self.runtime
-                .is_container_running(server_id)
-                .await
-                .unwrap_or(false)
-        } else {
-            false
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let requested = msg["lines"].as_u64().unwrap_or(100).clamp(1, 10_000) as usize;
+
+        let buffered = {
+            let buffers = self.console_buffers.read().await;
+            buffers
+                .get(server_id)
+                .filter(|buffer| buffer.lines.len() >= requested)
+                .map(|buffer| buffer.tail(requested))
         };
-        let server_uuid_running = if server_uuid_exists {
-            self.runtime
-                .is_container_running(server_uuid)
-                .await
-                .unwrap_or(false)
-        } else {
-            false
+        let (lines, source) = match buffered {
+            Some(lines) => (lines, "buffer"),
+            None => {
+                // The buffer only holds output seen since the agent started; older history
+                // lives in the console log files.
+                let container_id = self.resolve_container_id(server_id, server_uuid).await;
+                let logs = if container_id.is_empty() {
+                    String::new()
+                } else {
+                    self.runtime
+                        .get_logs(&container_id, Some(requested as u32))
+                        .await?
+                };
+                let redactions = { self.console_redactions.read().await.get(server_id).cloned() };
+                let logs = match redactions {
+                    Some(patterns) => redact_console_data(&logs, &patterns),
+                    None => logs,
+                };
+                (logs.lines().map(str::to_string).collect(), "file")
+            }
         };
 
-        if server_id_running && !server_uuid_running {
-            debug!(
-                "Console container resolved to serverId {} (uuid {})",
-                server_id, server_uuid
-            );
-            return Some(server_id.to_string());
-        }
-
-        if server_uuid_running && !server_id_running {
-            warn!(
-                "Console container resolved to uuid {} because serverId {} is not running",
-                server_uuid, server_id
+        let response = json!({
+            "type": "console_history",
+            "serverId": server_id,
+            "requestId": msg["requestId"],
+            "source": source,
+            "lines": lines,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_repair_dns(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let response = match self.runtime.repair_resolv_conf(&container_id).await {
+            Ok(repaired) => json!({
+                "type": "dns_repair_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "repaired": repaired,
+            }),
+            Err(e) => json!({
+                "type": "dns_repair_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_update_ports(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let port_bindings = parse_port_bindings(msg.get("portBindings"))?;
+        if port_bindings.is_empty() {
+            return Err(AgentError::InvalidRequest(
+                "portBindings must not be empty".to_string(),
+            ));
+        }
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        if container_id.is_empty() {
+            return Err(AgentError::NotFound(format!(
+                "Container not found for server {}",
+                server_id
+            )));
+        }
+
+        let result = self
+            .runtime
+            .update_port_forwards(&container_id, &port_bindings)
+            .await;
+        let response = match &result {
+            Ok(()) => json!({
+                "type": "update_ports_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "portBindings": port_bindings,
+            }),
+            Err(e) => json!({
+                "type": "update_ports_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        {
+            let mut w = write.lock().await;
+            w.send(Message::Text(response.to_string().into()))
+                .await
+                .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        }
+        if result.is_ok() {
+            self.emit_server_state_update(
+                server_id,
+                "running",
+                Some("Port bindings updated".to_string()),
+                Some(port_bindings),
+                None,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_get_agent_events(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let limit = msg["limit"].as_u64().unwrap_or(100).clamp(1, 500) as usize;
+        let errors_only = msg["severity"].as_str() == Some("error");
+        let (events, dropped) = crate::agent_events::agent_events().recent(
+            limit,
+            errors_only,
+            msg["serverId"].as_str(),
+            msg["since"].as_i64(),
+        );
+        let response = json!({
+            "type": "agent_events_response",
+            "requestId": msg["requestId"],
+            "success": true,
+            "events": events,
+            "dropped": dropped,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Pull an image ahead of a start so the first `start_server` doesn't wait on the registry.
+    /// The pull runs in the background and finishes with a `prepull_complete` event.
+    fn handle_prepull_image(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let image = msg["image"]
+            .as_str()
+            .map(str::trim)
+            .filter(|image| !image.is_empty())
+            .ok_or_else(|| AgentError::InvalidRequest("Missing image".to_string()))?;
+        if image.len() > 512 || image.starts_with('-') || image.contains(char::is_whitespace) {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid image reference: {}",
+                image
+            )));
+        }
+        let platform = msg["platform"]
+            .as_str()
+            .map(ImagePlatform::parse)
+            .transpose()?;
+
+        let handler = self.clone();
+        let write = write.clone();
+        let image = image.to_string();
+        let request_id = msg["requestId"].clone();
+        tokio::spawn(async move {
+            let started = std::time::Instant::now();
+            info!("Pre-pulling image {}", image);
+            let event = match handler
+                .runtime
+                .prepull_image(&image, platform.as_ref())
+                .await
+            {
+                Ok((qualified, pulled, size_bytes)) => json!({
+                    "type": "prepull_complete",
+                    "requestId": request_id,
+                    "image": qualified,
+                    "success": true,
+                    "pulled": pulled,
+                    "sizeBytes": size_bytes,
+                    "durationMs": started.elapsed().as_millis() as u64,
+                }),
+                Err(e) => {
+                    warn!("Pre-pull of {} failed: {}", image, e);
+                    json!({
+                        "type": "prepull_complete",
+                        "requestId": request_id,
+                        "image": image,
+                        "success": false,
+                        "error": e.to_string(),
+                    })
+                }
+            };
+            let _ = write
+                .lock()
+                .await
+                .send(Message::Text(event.to_string().into()))
+                .await;
+        });
+        Ok(())
+    }
+
+    async fn handle_container_diff(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let limit = msg["limit"]
+            .as_u64()
+            .unwrap_or(1000)
+            .clamp(1, MAX_CONTAINER_DIFF_ENTRIES) as usize;
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let response = match self.runtime.container_diff(&container_id, limit).await {
+            Ok((changes, truncated)) => json!({
+                "type": "container_diff_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "changes": changes,
+                "truncated": truncated,
+            }),
+            Err(e) => json!({
+                "type": "container_diff_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_list_processes(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let limit = msg["limit"]
+            .as_u64()
+            .unwrap_or(100)
+            .clamp(1, MAX_PROCESS_LIST_ENTRIES) as usize;
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let response = match self.runtime.list_processes(&container_id, limit).await {
+            Ok((processes, truncated)) => json!({
+                "type": "process_list_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "processes": processes,
+                "truncated": truncated,
+            }),
+            Err(e) => json!({
+                "type": "process_list_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Reopen a server's stdin FIFO and restart its log stream, for a console that stopped
+    /// taking input or showing output without the server itself failing.
+    async fn handle_repair_console(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let response = match self.runtime.repair_container_io(&container_id).await {
+            Ok((fifo_present, stdin_writable)) => {
+                // A wedged tailer keeps its stream key; drop it so a fresh one takes over.
+                self.stop_log_streams_for_server(server_id).await;
+                self.spawn_log_stream(server_id, &container_id);
+                info!(
+                    "Repaired console for {}: fifo={}, writable={}",
+                    server_id, fifo_present, stdin_writable
+                );
+                json!({
+                    "type": "console_repair_response",
+                    "serverId": server_id,
+                    "requestId": msg["requestId"],
+                    "success": true,
+                    "stdinFifoPresent": fifo_present,
+                    "stdinWritable": stdin_writable,
+                })
+            }
+            Err(e) => json!({
+                "type": "console_repair_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Stream lines appended to a file in the server's data directory as `file_tail`
+    /// messages, starting from its current end, until `stop_tail_file` or disconnect.
+    async fn handle_tail_file(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let path = msg["path"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing path".to_string()))?
+            .to_string();
+        let tail_id = msg["tailId"]
+            .as_str()
+            .or_else(|| msg["requestId"].as_str())
+            .ok_or_else(|| AgentError::InvalidRequest("Missing tailId".to_string()))?
+            .to_string();
+
+        let started = async {
+            let full_path = self
+                .file_manager
+                .resolve_existing_file(server_uuid, &path)?;
+            let mut tails = self.file_tails.write().await;
+            tails.retain(|_, task| !task.is_finished());
+            if !tails.contains_key(&tail_id) && tails.len() >= MAX_FILE_TAILS {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Too many active file tails (limit {})",
+                    MAX_FILE_TAILS
+                )));
+            }
+            let metadata = tokio::fs::metadata(&full_path).await?;
+            let mut tail = ConsoleTail::at(metadata.len(), &metadata)
+                .with_line_limit(self.config.console.max_input_bytes);
+
+            let handler = self.clone();
+            let write = write.clone();
+            let (server_id, server_uuid, tail_key) = (
+                server_id.to_string(),
+                server_uuid.to_string(),
+                tail_id.clone(),
+            );
+            let task = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(FILE_TAIL_POLL_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let full_path = match handler
+                        .file_manager
+                        .resolve_existing_file(&server_uuid, &path)
+                    {
+                        Ok(full_path) => full_path,
+                        // Rotated away or not recreated yet; keep waiting for it.
+                        Err(AgentError::NotFound(_)) => continue,
+                        Err(e) => {
+                            warn!("Stopping file tail {}: {}", tail_key, e);
+                            break;
+                        }
+                    };
+                    let Some(data) = tail.read_new(&full_path, true).await else {
+                        continue;
+                    };
+                    let mut start = data.len().saturating_sub(MAX_FILE_TAIL_CHUNK);
+                    while !data.is_char_boundary(start) {
+                        start += 1;
+                    }
+                    let event = json!({
+                        "type": "file_tail",
+                        "tailId": tail_key,
+                        "serverId": server_id,
+                        "path": path,
+                        "data": &data[start..],
+                        "truncated": start > 0,
+                    });
+                    let sent = write
+                        .lock()
+                        .await
+                        .send(Message::Text(event.to_string().into()))
+                        .await;
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+                handler.file_tails.write().await.remove(&tail_key);
+            });
+            if let Some(previous) = tails.insert(tail_id.clone(), task) {
+                previous.abort();
+            }
+            Ok(())
+        }
+        .await;
+
+        let response = match &started {
+            Ok(()) => json!({
+                "type": "tail_file_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "tailId": tail_id,
+                "success": true,
+            }),
+            Err(e) => json!({
+                "type": "tail_file_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "tailId": tail_id,
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_stop_tail_file(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let tail_id = msg["tailId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing tailId".to_string()))?;
+        let stopped = match self.file_tails.write().await.remove(tail_id) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        };
+        let response = json!({
+            "type": "stop_tail_file_response",
+            "requestId": msg["requestId"],
+            "tailId": tail_id,
+            "success": true,
+            "stopped": stopped,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_set_priority(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let result: AgentResult<(ProcessPriority, usize)> = async {
+            let priority = parse_process_priority(msg)?;
+            // Remember it first so a server that is stopped right now still gets it on start.
+            let persisted = (priority != ProcessPriority::default()).then_some(priority);
+            self.storage_manager
+                .set_server_priority(server_id, persisted)
+                .await?;
+            let container_id = self.resolve_container_id(server_id, server_uuid).await;
+            let adjusted = if self
+                .runtime
+                .is_container_running(&container_id)
+                .await
+                .unwrap_or(false)
+            {
+                self.runtime
+                    .apply_process_priority(&container_id, priority)
+                    .await?
+            } else {
+                0
+            };
+            Ok((priority, adjusted))
+        }
+        .await;
+        let response = match result {
+            Ok((priority, adjusted)) => {
+                info!(
+                    "Set priority of {} to nice {} io {:?}/{} ({} threads)",
+                    server_id, priority.nice, priority.io_class, priority.io_level, adjusted
+                );
+                json!({
+                    "type": "set_priority_result",
+                    "serverId": server_id,
+                    "requestId": msg["requestId"],
+                    "success": true,
+                    "priority": priority,
+                    "threadsAdjusted": adjusted,
+                })
+            }
+            Err(e) => json!({
+                "type": "set_priority_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_set_autostart(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let result: AgentResult<bool> = async {
+            let autostart = msg["autostart"]
+                .as_bool()
+                .ok_or_else(|| AgentError::InvalidRequest("Missing autostart".to_string()))?;
+            let start = if autostart {
+                self.start_messages.read().await.get(server_id).cloned()
+            } else {
+                None
+            };
+            let mut can_resume = false;
+            self.storage_manager
+                .update_autostart_state(|state| {
+                    let entry = state.servers.entry(server_id.to_string()).or_default();
+                    entry.autostart = autostart;
+                    entry.start = if autostart {
+                        start.or_else(|| entry.start.take())
+                    } else {
+                        None
+                    };
+                    can_resume = entry.start.is_some();
+                    if !entry.running && !entry.autostart {
+                        state.servers.remove(server_id);
+                    }
+                })
+                .await?;
+            Ok(can_resume)
+        }
+        .await;
+        let response = match result {
+            Ok(can_resume) => json!({
+                "type": "set_autostart_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "autostart": msg["autostart"],
+                "canResume": can_resume,
+            }),
+            Err(e) => json!({
+                "type": "set_autostart_result",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Which servers were running before this agent started, whether the node rebooted in
+    /// between, and the current running/autostart flags.
+    async fn handle_get_autostart_set(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let current = self.storage_manager.read_autostart_state().await?;
+        let (previous, rebooted) = self.previous_autostart.read().await.clone();
+        let server_ids: std::collections::BTreeSet<&String> = previous
+            .servers
+            .keys()
+            .chain(current.servers.keys())
+            .collect();
+        let servers: Vec<Value> = server_ids
+            .into_iter()
+            .map(|server_id| {
+                let now = current.servers.get(server_id).cloned().unwrap_or_default();
+                json!({
+                    "serverId": server_id,
+                    "wasRunning": previous.servers.get(server_id).is_some_and(|s| s.running),
+                    "running": now.running,
+                    "autostart": now.autostart,
+                    "canResume": now.start.is_some(),
+                })
+            })
+            .collect();
+        let response = json!({
+            "type": "autostart_set",
+            "requestId": msg["requestId"],
+            "rebooted": rebooted,
+            "autostartAfterReboot": self.config.containerd.autostart_after_reboot,
+            "servers": servers,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Snapshot the persisted autostart state for `get_autostart_set` and, after a node
+    /// reboot with `containerd.autostart_after_reboot`, start the flagged servers that were
+    /// running before it.
+    pub async fn restore_autostart(&self) {
+        let previous = match self.storage_manager.read_autostart_state().await {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Failed to read persisted autostart state: {}", e);
+                AutostartState::default()
+            }
+        };
+        let boot_id = tokio::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .await
+            .ok()
+            .map(|id| id.trim().to_string());
+        let rebooted =
+            matches!((&previous.boot_id, &boot_id), (Some(before), Some(now)) if before != now);
+        if let Err(e) = self
+            .storage_manager
+            .update_autostart_state(|state| {
+                // Nothing survives a reboot; servers are marked running again as they start.
+                if rebooted {
+                    for server in state.servers.values_mut() {
+                        server.running = false;
+                    }
+                }
+                state.boot_id = boot_id;
+            })
+            .await
+        {
+            warn!("Failed to update persisted autostart state: {}", e);
+        }
+        let resume: Vec<Value> = if rebooted && self.config.containerd.autostart_after_reboot {
+            previous
+                .servers
+                .values()
+                .filter(|server| server.running && server.autostart)
+                .filter_map(|server| server.start.clone())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        *self.previous_autostart.write().await = (previous, rebooted);
+        if resume.is_empty() {
+            return;
+        }
+        info!(
+            "Starting {} servers that were running before the reboot",
+            resume.len()
+        );
+        let handler = self.clone();
+        tokio::spawn(async move {
+            // Concurrently, so servers with `startAfter` can wait on each other.
+            let starts = resume.iter().map(|msg| {
+                let handler = &handler;
+                async move {
+                    if let Err(e) = handler.start_server_after_dependency(msg).await {
+                        warn!(
+                            "Failed to start {} after reboot: {}",
+                            msg["serverId"].as_str().unwrap_or_default(),
+                            e
+                        );
+                    }
+                }
+            });
+            futures::future::join_all(starts).await;
+        });
+    }
+
+    /// Track which servers are running for `get_autostart_set`. Stops while the agent shuts
+    /// down are left out, so `on_shutdown = "stop"` still resumes servers after a reboot.
+    async fn record_autostart_run_state(&self, server_id: &str, state: &str) {
+        let running = match state {
+            "running" => true,
+            "stopped" | "crashed" | "error" => false,
+            _ => return,
+        };
+        if !running && self.shutting_down.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self
+            .storage_manager
+            .update_autostart_state(|autostart| {
+                let entry = autostart.servers.entry(server_id.to_string()).or_default();
+                entry.running = running;
+                if !entry.running && !entry.autostart {
+                    autostart.servers.remove(server_id);
+                }
+            })
+            .await
+        {
+            warn!("Failed to record run state of {}: {}", server_id, e);
+        }
+    }
+
+    /// Re-apply a persisted `set_priority` after the server's container (re)started.
+    async fn reapply_server_priority(&self, server_id: &str, container_id: &str) {
+        let priority = match self.storage_manager.read_server_priorities().await {
+            Ok(priorities) => priorities.get(server_id).copied(),
+            Err(e) => {
+                warn!("Failed to read persisted server priorities: {}", e);
+                None
+            }
+        };
+        if let Some(priority) = priority {
+            if let Err(e) = self
+                .runtime
+                .apply_process_priority(container_id, priority)
+                .await
+            {
+                warn!("Failed to re-apply priority for {}: {}", server_id, e);
+            }
+        }
+    }
+
+    /// Toggle read-only file access for a server, e.g. while it is suspended. Reads and
+    /// listings keep working so the customer can still retrieve their data.
+    async fn handle_set_files_read_only(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"].as_str().unwrap_or(server_id);
+        let read_only = msg["readOnly"]
+            .as_bool()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing readOnly".to_string()))?;
+        self.storage_manager
+            .set_server_read_only(server_uuid, read_only)
+            .await?;
+        {
+            let mut servers = self.read_only_servers.write().await;
+            if read_only {
+                servers.insert(server_uuid.to_string());
+            } else {
+                servers.remove(server_uuid);
+            }
+        }
+        info!(
+            "Files for server {} are now {}",
+            server_id,
+            if read_only { "read-only" } else { "writable" }
+        );
+        let response = json!({
+            "type": "files_read_only_updated",
+            "serverId": server_id,
+            "requestId": msg["requestId"],
+            "success": true,
+            "readOnly": read_only,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_cleanup_installers(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let grace = msg["graceSecs"]
+            .as_u64()
+            .map(Duration::from_secs)
+            .unwrap_or(ORPHANED_INSTALLER_GRACE);
+        let response = match self.runtime.cleanup_orphaned_installers(grace).await {
+            Ok(removed) => json!({
+                "type": "installers_cleaned",
+                "requestId": msg["requestId"],
+                "success": true,
+                "count": removed.len(),
+                "removed": removed,
+            }),
+            Err(e) => json!({
+                "type": "installers_cleaned",
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    async fn handle_get_disk_usage(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_uuid = msg["serverUuid"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverUuid".to_string()))?;
+        validate_safe_path_segment(server_uuid, "serverUuid")?;
+        let response = match self
+            .storage_manager
+            .server_disk_usage(server_uuid, DISK_USAGE_WALK_TIMEOUT)
+            .await
+        {
+            Ok((used_mb, total_mb)) => {
+                let (inodes_used, inodes_total) = self
+                    .storage_manager
+                    .server_inode_usage(server_uuid)
+                    .await
+                    .unzip();
+                json!({
+                    "type": "disk_usage_response",
+                    "serverUuid": server_uuid,
+                    "requestId": msg["requestId"],
+                    "success": true,
+                    "diskUsageMb": used_mb,
+                    "diskTotalMb": total_mb,
+                    "inodesUsed": inodes_used,
+                    "inodesTotal": inodes_total,
+                })
+            }
+            Err(e) => json!({
+                "type": "disk_usage_response",
+                "serverUuid": server_uuid,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// The backend confirms it applied a state update; anything up to `seq` for that server
+    /// no longer needs resending.
+    async fn handle_ack_state(&self, msg: &Value) {
+        let Some(seq) = msg["seq"].as_u64() else {
+            return;
+        };
+        let mut acks = self.state_acks.write().await;
+        if !acks.enabled {
+            info!("Backend acknowledges state updates; enabling resend of unacknowledged ones");
+            acks.enabled = true;
+        }
+        match msg["serverId"].as_str() {
+            Some(server_id) => {
+                if acks
+                    .pending
+                    .get(server_id)
+                    .is_some_and(|pending| pending.seq <= seq)
+                {
+                    acks.pending.remove(server_id);
+                }
+            }
+            None => acks.pending.retain(|_, pending| pending.seq != seq),
+        }
+    }
+
+    /// Resend the latest state of each server whose update went unacknowledged, giving up
+    /// after a few attempts and leaving the rest to reconciliation.
+    async fn resend_unacked_states(&self) {
+        let now = tokio::time::Instant::now();
+        let due: Vec<Value> = {
+            let mut acks = self.state_acks.write().await;
+            acks.pending.retain(|server_id, pending| {
+                if pending.resends < MAX_STATE_RESENDS {
+                    return true;
+                }
+                warn!(
+                    "State update {} for {} was never acknowledged; leaving it to reconciliation",
+                    pending.seq, server_id
+                );
+                false
+            });
+            acks.pending
+                .values_mut()
+                .filter(|pending| now.duration_since(pending.sent_at) >= STATE_ACK_TIMEOUT)
+                .map(|pending| {
+                    pending.sent_at = now;
+                    pending.resends += 1;
+                    pending.message.clone()
+                })
+                .collect()
+        };
+        if due.is_empty() {
+            return;
+        }
+        let Some(ws) = ({ self.write.read().await.clone() }) else {
+            return;
+        };
+        let mut w = ws.lock().await;
+        for message in due {
+            debug!("Resending unacknowledged state update: {}", message);
+            if let Err(err) = w.send(Message::Text(message.to_string().into())).await {
+                warn!("Failed to resend state update: {}", err);
+                break;
+            }
+        }
+    }
+
+    async fn handle_find_free_ports(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let count = msg["count"].as_u64().unwrap_or(1);
+        if count == 0 || count > MAX_FREE_PORTS_REQUEST {
+            return Err(AgentError::InvalidRequest(format!(
+                "count must be between 1 and {}",
+                MAX_FREE_PORTS_REQUEST
+            )));
+        }
+        let range_start = msg["rangeStart"].as_u64().unwrap_or(1024);
+        let range_end = msg["rangeEnd"].as_u64().unwrap_or(65535);
+        if range_start == 0 || range_start > range_end || range_end > u16::MAX as u64 {
+            return Err(AgentError::InvalidRequest("Invalid port range".to_string()));
+        }
+        let protocol = msg["protocol"].as_str().unwrap_or("tcp");
+        let (check_tcp, check_udp) = match protocol {
+            "tcp" => (true, false),
+            "udp" => (false, true),
+            "both" => (true, true),
+            other => {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Unsupported protocol: {}",
+                    other
+                )))
+            }
+        };
+
+        let forwarded = self.runtime.forwarded_host_ports();
+        let ports = tokio::task::spawn_blocking(move || {
+            // Forwarded ports are DNAT'd rather than bound, so a bind test alone would miss them.
+            (range_start as u16..=range_end as u16)
+                .filter(|port| !forwarded.contains(port))
+                .take(MAX_FREE_PORT_SCAN)
+                .filter(|port| {
+                    (!check_tcp || std::net::TcpListener::bind(("0.0.0.0", *port)).is_ok())
+                        && (!check_udp || std::net::UdpSocket::bind(("0.0.0.0", *port)).is_ok())
+                })
+                .take(count as usize)
+                .collect::<Vec<u16>>()
+        })
+        .await
+        .map_err(|e| AgentError::InternalError(e.to_string()))?;
+
+        let response = json!({
+            "type": "free_ports_response",
+            "requestId": msg["requestId"],
+            "protocol": protocol,
+            "ports": ports,
+            "complete": ports.len() as u64 == count,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// A server has containers under both its id and its uuid. Report it (once per pair) and,
+    /// if configured, remove the stopped one. Returns the name of a removed container.
+    async fn handle_duplicate_containers(
+        &self,
+        server_id: &str,
+        server_uuid: &str,
+        server_id_running: bool,
+        server_uuid_running: bool,
+    ) -> Option<String> {
+        let action = self.config.containerd.duplicate_containers;
+        if action == DuplicateContainerAction::Ignore {
+            return None;
+        }
+        let stopped = match (server_id_running, server_uuid_running) {
+            (true, false) => Some(server_uuid),
+            (false, true) => Some(server_id),
+            _ => None,
+        };
+        let removed = match stopped {
+            Some(name) if action == DuplicateContainerAction::RemoveStopped => {
+                match self.runtime.remove_container(name).await {
+                    Ok(()) => {
+                        info!(
+                            "Removed stopped duplicate container {} for server {}",
+                            name, server_id
+                        );
+                        Some(name.to_string())
+                    }
+                    Err(e) => {
+                        warn!("Failed to remove duplicate container {}: {}", name, e);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        let key = format!("{}:{}", server_id, server_uuid);
+        let first_report = self.reported_duplicates.write().await.insert(key.clone());
+        if removed.is_some() {
+            self.reported_duplicates.write().await.remove(&key);
+        } else if !first_report {
+            return None;
+        }
+        warn!(
+            "Server {} has containers under both its id and uuid {} (running: {}/{})",
+            server_id, server_uuid, server_id_running, server_uuid_running
+        );
+        let writer = { self.write.read().await.clone() };
+        if let Some(ws) = writer {
+            let event = json!({
+                "type": "duplicate_container_detected",
+                "serverId": server_id,
+                "serverUuid": server_uuid,
+                "serverIdRunning": server_id_running,
+                "serverUuidRunning": server_uuid_running,
+                "removed": removed,
+            });
+            let _ = ws
+                .lock()
+                .await
+                .send(Message::Text(event.to_string().into()))
+                .await;
+        }
+        removed
+    }
+
+    async fn resolve_console_container_id(
+        &self,
+        server_id: &str,
+        server_uuid: &str,
+    ) -> Option<String> {
+        let mut server_id_exists = self.runtime.container_exists(server_id).await;
+        let mut server_uuid_exists = if server_uuid != server_id {
+            self.runtime.container_exists(server_uuid).await
+        } else {
+            false
+        };
+
+        if !server_id_exists && !server_uuid_exists {
+            return None;
+        }
+
+        let mut server_id_running = if server_id_exists {
+            self.runtime
+                .is_container_running(server_id)
+                .await
+                .unwrap_or(false)
+        } else {
+            false
+        };
+        let mut server_uuid_running = if server_uuid_exists {
+            self.runtime
+                .is_container_running(server_uuid)
+                .await
+                .unwrap_or(false)
+        } else {
+            false
+        };
+        if server_id_exists && server_uuid_exists {
+            match self
+                .handle_duplicate_containers(
+                    server_id,
+                    server_uuid,
+                    server_id_running,
+                    server_uuid_running,
+                )
+                .await
+            {
+                Some(removed) if removed == server_id => {
+                    server_id_exists = false;
+                    server_id_running = false;
+                }
+                Some(_) => {
+                    server_uuid_exists = false;
+                    server_uuid_running = false;
+                }
+                None => {}
+            }
+        }
+
+        if server_id_running && !server_uuid_running {
+            debug!(
+                "Console container resolved to serverId {} (uuid {})",
+                server_id, server_uuid
+            );
+            return Some(server_id.to_string());
+        }
+
+        if server_uuid_running && !server_id_running {
+            warn!(
+                "Console container resolved to uuid {} because serverId {} is not running",
+                server_uuid, server_id
             );
             return Some(server_uuid.to_string());
         }
@@ -717,6 +3380,8 @@ impl WebSocketHandler {
                     container_name, server_id
                 );
                 self.stop_monitor_task(server_id).await;
+                self.release_adopted_monitor(server_id, container_name)
+                    .await;
                 if self
                     .runtime
                     .is_container_running(container_name)
@@ -741,20 +3406,213 @@ impl WebSocketHandler {
             }
         }
 
-        if cleaned > 0 {
-            info!("Cleaned up {} containers for server {}", cleaned, server_id);
-            self.emit_console_output(
-                server_id,
-                "system",
-                &format!(
-                    "[Catalyst] Cleaned up {} container(s) during error state cleanup.\n",
-                    cleaned
-                ),
-            )
-            .await?;
+        if cleaned > 0 {
+            info!("Cleaned up {} containers for server {}", cleaned, server_id);
+            self.emit_console_output(
+                server_id,
+                "system",
+                &format!(
+                    "[Catalyst] Cleaned up {} container(s) during error state cleanup.\n",
+                    cleaned
+                ),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Lowest free space (MB) across the filesystems holding server data and backups.
+    fn min_free_disk_mb(&self) -> Option<(u64, PathBuf)> {
+        [
+            self.config.server.data_dir.as_path(),
+            Path::new(BACKUP_ROOT),
+        ]
+        .into_iter()
+        .filter_map(|path| {
+            // The backup root may not exist yet; its nearest existing ancestor is what fills.
+            let existing = path.ancestors().find(|p| p.exists())?;
+            let stat = nix::sys::statvfs::statvfs(existing).ok()?;
+            let free_mb =
+                stat.blocks_available() as u64 * stat.fragment_size() as u64 / (1024 * 1024);
+            Some((free_mb, path.to_path_buf()))
+        })
+        .min_by_key(|(free_mb, _)| *free_mb)
+    }
+
+    /// Raise or clear the low-disk flag. It is raised below the floor and only cleared once
+    /// free space exceeds the floor by the recovery margin.
+    async fn check_disk_floor(&self) {
+        let floor_mb = self.config.limits.low_disk_floor_mb;
+        if floor_mb == 0 {
+            return;
+        }
+        let Some((free_mb, path)) = self.min_free_disk_mb() else {
+            return;
+        };
+        let was_low = self.low_disk.load(Ordering::Relaxed);
+        let event = if !was_low && free_mb < floor_mb {
+            self.low_disk.store(true, Ordering::Relaxed);
+            warn!(
+                "{} has {} MB free (floor {} MB); refusing installs, backups and uploads",
+                path.display(),
+                free_mb,
+                floor_mb
+            );
+            "node_disk_critical"
+        } else if was_low && free_mb >= floor_mb + self.config.limits.low_disk_recovery_mb {
+            self.low_disk.store(false, Ordering::Relaxed);
+            info!(
+                "{} has {} MB free again; accepting installs, backups and uploads",
+                path.display(),
+                free_mb
+            );
+            "node_disk_recovered"
+        } else {
+            return;
+        };
+
+        let payload = json!({
+            "type": event,
+            "nodeId": self.config.server.node_id,
+            "path": path.to_string_lossy(),
+            "freeMb": free_mb,
+            "floorMb": floor_mb,
+            "timestamp": chrono::Utc::now().timestamp_millis(),
+        });
+        let writer = { self.write.read().await.clone() };
+        if let Some(ws) = writer {
+            let mut w = ws.lock().await;
+            if let Err(err) = w.send(Message::Text(payload.to_string().into())).await {
+                error!("Failed to send {}: {}", event, err);
+            }
+        }
+    }
+
+    /// Answer a disk-consuming command with a `low_disk` error instead of running it.
+    async fn reject_low_disk(
+        &self,
+        command: &str,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let reason = format!(
+            "Node is below its free disk space floor ({} MB); {} refused",
+            self.config.limits.low_disk_floor_mb, command
+        );
+        warn!("{}", reason);
+        if let (Some(server_id), "install_server") = (msg["serverId"].as_str(), command) {
+            let _ = self
+                .emit_server_state_update(server_id, "error", Some(reason.clone()), None, None)
+                .await;
+        }
+        let event = json!({
+            "type": "command_error",
+            "command": command,
+            "requestId": msg["requestId"],
+            "serverId": msg["serverId"],
+            "error": "low_disk",
+            "reason": reason,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(event.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))
+    }
+
+    /// Reject the request if this node is at its configured server capacity.
+    /// Containers belonging to the server itself are not counted, so restarts and
+    /// reinstalls are never blocked by their own previous container.
+    async fn enforce_capacity(
+        &self,
+        server_id: &str,
+        server_uuid: &str,
+        scope: CapacityScope,
+    ) -> AgentResult<()> {
+        let limit = match scope {
+            CapacityScope::Servers => self.config.limits.max_servers,
+            CapacityScope::Running => self.config.limits.max_running_servers,
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let containers = self.runtime.list_containers().await?;
+        let current = containers
+            .iter()
+            .filter(|c| c.managed && c.id != server_id && c.id != server_uuid)
+            .filter(|c| scope == CapacityScope::Servers || c.status == "Up")
+            .count();
+        if current < limit {
+            return Ok(());
+        }
+
+        warn!(
+            "Rejecting server {}: node at {} capacity ({}/{})",
+            server_id,
+            scope.as_str(),
+            current,
+            limit
+        );
+        let payload = json!({
+            "type": "capacity_exceeded",
+            "serverId": server_id,
+            "serverUuid": server_uuid,
+            "scope": scope.as_str(),
+            "current": current,
+            "limit": limit,
+        });
+        let writer = { self.write.read().await.clone() };
+        if let Some(ws) = writer {
+            let mut w = ws.lock().await;
+            if let Err(err) = w.send(Message::Text(payload.to_string().into())).await {
+                error!("Failed to send capacity_exceeded: {}", err);
+            }
         }
 
-        Ok(())
+        Err(AgentError::InvalidRequest(format!(
+            "capacity_exceeded: node {} limit reached ({}/{})",
+            scope.as_str(),
+            current,
+            limit
+        )))
+    }
+
+    /// Compile and cache the template's console redaction patterns for this server.
+    /// Templates without patterns clear any previously cached set.
+    async fn update_console_redactions(
+        &self,
+        server_id: &str,
+        template: &serde_json::Map<String, Value>,
+    ) {
+        let patterns: Vec<Regex> = template
+            .get("consoleRedactPatterns")
+            .and_then(Value::as_array)
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .filter(|pattern| !pattern.is_empty())
+                    .filter_map(|pattern| match Regex::new(pattern) {
+                        Ok(regex) => Some(regex),
+                        Err(err) => {
+                            warn!(
+                                "Ignoring invalid console redaction pattern for {}: {}",
+                                server_id, err
+                            );
+                            None
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut redactions = self.console_redactions.write().await;
+        if patterns.is_empty() {
+            redactions.remove(server_id);
+        } else {
+            redactions.insert(server_id.to_string(), Arc::new(patterns));
+        }
     }
 
     async fn stop_monitor_task(&self, server_id: &str) {
@@ -762,14 +3620,40 @@ impl WebSocketHandler {
         if let Some(handle) = tasks.remove(server_id) {
             handle.abort();
         }
+        if let Some(handle) = self.health_check_tasks.write().await.remove(server_id) {
+            handle.abort();
+        }
     }
 
     /// Stop all log streams for a server
     /// This is important when switching from installer container to game server container
     async fn stop_log_streams_for_server(&self, server_id: &str) {
         let mut streams = self.active_log_streams.write().await;
-        // Remove all stream keys that start with server_id:
-        streams.retain(|key| !key.starts_with(&format!("{}:", server_id)));
+        // Remove all stream keys that start with server_id: and stop their tailers
+        streams.retain(|key, task| {
+            let keep = !key.starts_with(&format!("{}:", server_id));
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+    }
+
+    /// Drop log streams whose container no longer exists. A tailer normally ends on its own
+    /// once its container stops; one that outlives the container is a leak.
+    async fn prune_log_streams(&self, containers: &[ContainerInfo]) {
+        let mut streams = self.active_log_streams.write().await;
+        streams.retain(|key, task| {
+            let container_id = key.split_once(':').map_or(key.as_str(), |(_, id)| id);
+            let exists = containers
+                .iter()
+                .any(|c| c.id == container_id || c.names == container_id);
+            if !exists {
+                warn!("Dropping leaked log stream {} for a removed container", key);
+                task.abort();
+            }
+            exists
+        });
     }
 
     fn spawn_exit_monitor(&self, server_id: &str, container_id: &str) {
@@ -814,18 +3698,8 @@ impl WebSocketHandler {
                                     .get_container_exit_code(&monitor_container_id)
                                     .await
                                     .unwrap_or(None);
-                                let reason = match exit_code {
-                                    Some(code) => format!("Container exited with code {}", code),
-                                    None => "Container exited".to_string(),
-                                };
-                                let _ = monitor_handler
-                                    .emit_server_state_update(
-                                        &monitor_server_id,
-                                        "crashed",
-                                        Some(reason),
-                                        None,
-                                        exit_code,
-                                    )
+                                monitor_handler
+                                    .report_crash(&monitor_server_id, exit_code)
                                     .await;
                                 break;
                             }
@@ -851,18 +3725,8 @@ impl WebSocketHandler {
                             .get_container_exit_code(&monitor_container_id)
                             .await
                             .unwrap_or(None);
-                        let reason = match exit_code {
-                            Some(code) => format!("Container exited with code {}", code),
-                            None => "Container exited".to_string(),
-                        };
-                        let _ = monitor_handler
-                            .emit_server_state_update(
-                                &monitor_server_id,
-                                "crashed",
-                                Some(reason),
-                                None,
-                                exit_code,
-                            )
+                        monitor_handler
+                            .report_crash(&monitor_server_id, exit_code)
                             .await;
                         break;
                     }
@@ -876,6 +3740,94 @@ impl WebSocketHandler {
         });
     }
 
+    /// Record an unexpected exit and report the server as crashed.
+    async fn report_crash(&self, server_id: &str, exit_code: Option<i32>) {
+        if self.is_intentional_stop(server_id).await {
+            debug!(
+                "Not reporting exit of {} as a crash: it is being stopped",
+                server_id
+            );
+            return;
+        }
+        let meta = {
+            let mut all = self.server_meta.write().await;
+            let meta = all.entry(server_id.to_string()).or_default();
+            meta.last_exit_code = exit_code;
+            meta.crashed = true;
+            *meta
+        };
+        let reason = match exit_code {
+            Some(code) => format!("Container exited with code {}", code),
+            None => "Container exited".to_string(),
+        };
+        let _ = self
+            .emit_server_state_update_with_fields(
+                server_id,
+                "crashed",
+                Some(reason),
+                None,
+                exit_code,
+                json!({
+                    "restartCount": meta.restart_count,
+                    "lastExitCode": meta.last_exit_code,
+                }),
+            )
+            .await;
+    }
+
+    /// Whether `server_id` is being stopped, or was within the grace window.
+    async fn is_intentional_stop(&self, server_id: &str) -> bool {
+        let mut stops = self.intentional_stops.write().await;
+        match stops.get(server_id) {
+            Some(None) => true,
+            Some(Some(until)) if std::time::Instant::now() < *until => true,
+            Some(Some(_)) => {
+                stops.remove(server_id);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Run a deliberate stop or kill with crash reporting suppressed for the server until the
+    /// grace window after it finishes.
+    async fn with_intentional_stop<F>(&self, server_id: &str, stop: F) -> AgentResult<()>
+    where
+        F: std::future::Future<Output = AgentResult<()>>,
+    {
+        self.intentional_stops
+            .write()
+            .await
+            .insert(server_id.to_string(), None);
+        let result = stop.await;
+        let grace = Duration::from_secs(self.config.containerd.stop_crash_grace_secs);
+        self.intentional_stops.write().await.insert(
+            server_id.to_string(),
+            Some(std::time::Instant::now() + grace),
+        );
+        result
+    }
+
+    /// Count a start as a restart when the previous run ended in a crash.
+    async fn record_server_start(&self, server_id: &str) {
+        self.intentional_stops.write().await.remove(server_id);
+        let mut all = self.server_meta.write().await;
+        let meta = all.entry(server_id.to_string()).or_default();
+        if meta.crashed {
+            meta.restart_count = meta.restart_count.saturating_add(1);
+            meta.crashed = false;
+        }
+    }
+
+    /// A deliberate stop ends any crash loop.
+    async fn record_clean_stop(&self, server_id: &str) {
+        if let Some(meta) = self.server_meta.write().await.get_mut(server_id) {
+            meta.restart_count = 0;
+            meta.crashed = false;
+            meta.health_restarted = false;
+        }
+    }
+
     async fn install_server(&self, msg: &Value) -> AgentResult<()> {
         let server_uuid = msg["serverUuid"]
             .as_str()
@@ -903,14 +3855,39 @@ impl WebSocketHandler {
                 AgentError::InvalidRequest("Missing or invalid environment".to_string())
             })?;
 
+        let install_mode = msg["installMode"].as_str().unwrap_or("overlay");
+        if !matches!(install_mode, "clean" | "overlay") {
+            return Err(AgentError::InvalidRequest(format!(
+                "Invalid installMode: {}",
+                install_mode
+            )));
+        }
+
         info!("Installing server: {} (UUID: {})", server_id, server_uuid);
+        self.update_console_redactions(server_id, template).await;
+
+        if !self.storage_manager.has_image(server_uuid) {
+            if let Err(err) = self
+                .enforce_capacity(server_id, server_uuid, CapacityScope::Servers)
+                .await
+            {
+                self.emit_server_state_update(
+                    server_id,
+                    "error",
+                    Some(err.to_string()),
+                    None,
+                    None,
+                )
+                .await?;
+                return Err(err);
+            }
+        }
 
         self.cleanup_all_server_containers(server_id, server_uuid)
             .await?;
 
         // Derive host mount path on-agent (defense in depth). Do not trust control-plane host paths.
-        validate_safe_path_segment(server_uuid, "serverUuid")?;
-        let derived_server_dir = self.config.server.data_dir.join(server_uuid);
+        let derived_server_dir = self.resolve_server_dir(server_uuid)?;
         let host_server_dir = derived_server_dir.to_string_lossy().to_string();
         if let Some(provided) = environment.get("SERVER_DIR").and_then(|v| v.as_str()) {
             if provided != host_server_dir {
@@ -937,22 +3914,48 @@ impl WebSocketHandler {
 
         info!("Created server directory: {}", server_dir_path.display());
 
-        // Replace variables in install script
-        let mut final_script = install_script.to_string();
-        // Strip carriage returns to avoid $'\r': command not found errors
-        final_script = final_script.replace("\r\n", "\n").replace('\r', "\n");
-        for (key, value) in environment {
-            let placeholder = format!("{{{{{}}}}}", key);
-            let replacement = if key == "SERVER_DIR" {
-                CONTAINER_SERVER_DIR
-            } else {
-                value.as_str().unwrap_or("")
-            };
-            // Shell-escape the value to prevent command injection via user-controlled env vars
-            let escaped = shell_escape_value(replacement);
-            final_script = final_script.replace(&placeholder, &escaped);
+        if install_mode == "clean" {
+            if msg["backupBeforeClean"].as_bool().unwrap_or(false) {
+                let backup_path = self
+                    .backup_before_reinstall(server_uuid, &server_dir_path)
+                    .await?;
+                self.emit_console_output(
+                    server_id,
+                    "system",
+                    &format!(
+                        "[Catalyst] Backed up existing files to {}\n",
+                        backup_path.display()
+                    ),
+                )
+                .await?;
+            }
+            let removed = self.file_manager.clear_server_dir(server_uuid).await?;
+            self.emit_console_output(
+                server_id,
+                "system",
+                &format!(
+                    "[Catalyst] Install mode: clean (removed {} existing entries)\n",
+                    removed
+                ),
+            )
+            .await?;
+        } else {
+            self.emit_console_output(
+                server_id,
+                "system",
+                "[Catalyst] Install mode: overlay (keeping existing files)\n",
+            )
+            .await?;
         }
 
+        // Replace variables in install script
+        let container_dir = container_data_dir(template)?;
+        let chown_mode = installer_chown_mode(template)?;
+        let (final_script, unresolved) =
+            render_install_script(install_script, environment, &container_dir);
+        self.check_unresolved_variables(server_id, template, "install script", &unresolved)
+            .await?;
+
         // Get the install image from template (fallback to Alpine if not specified)
         let install_image = template
             .get("installImage")
@@ -967,7 +3970,7 @@ impl WebSocketHandler {
             }
         }
         env_map.insert("HOST_SERVER_DIR".to_string(), host_server_dir.clone());
-        env_map.insert("SERVER_DIR".to_string(), CONTAINER_SERVER_DIR.to_string());
+        env_map.insert("SERVER_DIR".to_string(), container_dir.clone());
 
         info!(
             "Executing installation script in containerized environment using image: {}",
@@ -976,11 +3979,28 @@ impl WebSocketHandler {
         self.emit_console_output(server_id, "system", "[Catalyst] Starting installation...\n")
             .await?;
 
+        let installer_limits = self.installer_limits(msg);
+        if let Some(limits) = installer_limits {
+            info!(
+                "Installer limits for {}: {} MB memory, {} CPU cores",
+                server_id, limits.memory_mb, limits.cpu_cores
+            );
+        }
+
         // Execute the install script in an ephemeral container for complete isolation
-        // The container mounts the server directory at /data and runs the script there
+        // The container mounts the server directory at SERVER_DIR and runs the script there
         let installer = self
             .runtime
-            .spawn_installer_container(install_image, &final_script, &env_map, &host_server_dir)
+            .spawn_installer_container(
+                install_image,
+                parse_template_platform(template)?.as_ref(),
+                &final_script,
+                &env_map,
+                &host_server_dir,
+                &container_dir,
+                installer_limits,
+                chown_mode,
+            )
             .await
             .map_err(|e| {
                 AgentError::IoError(format!("Failed to spawn installer container: {}", e))
@@ -991,6 +4011,13 @@ impl WebSocketHandler {
         let mut stderr_pos = 0u64;
         let mut stdout_buffer = String::new();
         let mut stderr_buffer = String::new();
+        let mut install_log = InstallLog::create(&server_dir_path).await;
+        install_log
+            .write(&format!(
+                "[Catalyst] Installing with image {}\n",
+                install_image
+            ))
+            .await;
 
         loop {
             // Read new stdout content
@@ -999,6 +4026,7 @@ impl WebSocketHandler {
                     for line in content[stdout_pos as usize..].lines() {
                         let payload = format!("{}\n", line);
                         stdout_buffer.push_str(&payload);
+                        install_log.write(&payload).await;
                         self.emit_console_output(server_id, "stdout", &payload)
                             .await?;
                     }
@@ -1011,6 +4039,7 @@ impl WebSocketHandler {
                     for line in content[stderr_pos as usize..].lines() {
                         let payload = format!("{}\n", line);
                         stderr_buffer.push_str(&payload);
+                        install_log.write(&payload).await;
                         self.emit_console_output(server_id, "stderr", &payload)
                             .await?;
                     }
@@ -1026,6 +4055,7 @@ impl WebSocketHandler {
                             for line in content[stdout_pos as usize..].lines() {
                                 let payload = format!("{}\n", line);
                                 stdout_buffer.push_str(&payload);
+                                install_log.write(&payload).await;
                                 self.emit_console_output(server_id, "stdout", &payload)
                                     .await?;
                             }
@@ -1036,12 +4066,33 @@ impl WebSocketHandler {
                             for line in content[stderr_pos as usize..].lines() {
                                 let payload = format!("{}\n", line);
                                 stderr_buffer.push_str(&payload);
+                                install_log.write(&payload).await;
                                 self.emit_console_output(server_id, "stderr", &payload)
                                     .await?;
                             }
                         }
                     }
+                    let oom_killed = exit_code != 0 && installer.was_oom_killed().await;
                     let _ = installer.cleanup().await;
+                    if oom_killed {
+                        let reason = format!(
+                            "Installer was killed for exceeding its memory limit ({} MB)",
+                            installer_limits.map(|l| l.memory_mb).unwrap_or(0)
+                        );
+                        let line = format!("[Catalyst] {}\n", reason);
+                        install_log.write(&line).await;
+                        self.emit_console_output(server_id, "stderr", &line).await?;
+                        self.emit_server_state_update_with_fields(
+                            server_id,
+                            "error",
+                            Some(reason.clone()),
+                            None,
+                            Some(exit_code),
+                            json!({ "installerOomKilled": true }),
+                        )
+                        .await?;
+                        return Err(AgentError::InstallationError(reason));
+                    }
                     if exit_code != 0 {
                         let stderr_trimmed = stderr_buffer.trim();
                         let stdout_trimmed = stdout_buffer.trim();
@@ -1052,6 +4103,12 @@ impl WebSocketHandler {
                         } else {
                             "Install script failed".to_string()
                         };
+                        install_log
+                            .write(&format!(
+                                "[Catalyst] Install script exited with code {}\n",
+                                exit_code
+                            ))
+                            .await;
                         self.emit_console_output(server_id, "stderr", &format!("{}\n", reason))
                             .await?;
                         self.emit_server_state_update(
@@ -1067,6 +4124,9 @@ impl WebSocketHandler {
                             reason
                         )));
                     }
+                    install_log
+                        .write("[Catalyst] Installation complete.\n")
+                        .await;
                     break;
                 }
                 Ok(Err(e)) => {
@@ -1080,65 +4140,538 @@ impl WebSocketHandler {
             }
         }
 
-        if stdout_buffer.trim().is_empty() && stderr_buffer.trim().is_empty() {
-            self.emit_console_output(server_id, "system", "[Catalyst] Installation complete.\n")
-                .await?;
+        if stdout_buffer.trim().is_empty() && stderr_buffer.trim().is_empty() {
+            self.emit_console_output(server_id, "system", "[Catalyst] Installation complete.\n")
+                .await?;
+        }
+
+        // Stop any existing log streams for this server before marking as stopped
+        // This ensures clean state when transitioning to game server container
+        self.stop_log_streams_for_server(server_id).await;
+
+        // Emit state update
+        self.emit_server_state_update(server_id, "stopped", None, None, None)
+            .await?;
+
+        info!("Server installed successfully: {}", server_uuid);
+        Ok(())
+    }
+
+    /// Run a template's install script into a throwaway tmpfs so template authors can iterate
+    /// without a real server. Output streams as `test_install_output`; the outcome is sent as
+    /// `test_install_result`.
+    fn spawn_test_install(&self, msg: Value, write: &Arc<tokio::sync::Mutex<WsWrite>>) {
+        let handler = self.clone();
+        let write = write.clone();
+        tokio::spawn(async move {
+            let started = std::time::Instant::now();
+            let mut response = match handler.run_test_install(&msg, &write).await {
+                Ok(summary) => summary,
+                Err(e) => {
+                    warn!("Test install failed: {}", e);
+                    json!({ "success": false, "error": e.to_string() })
+                }
+            };
+            response["type"] = json!("test_install_result");
+            response["requestId"] = msg["requestId"].clone();
+            response["durationMs"] = json!(started.elapsed().as_millis() as u64);
+            let _ = write
+                .lock()
+                .await
+                .send(Message::Text(response.to_string().into()))
+                .await;
+        });
+    }
+
+    async fn run_test_install(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<Value> {
+        let template = msg["template"]
+            .as_object()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing template".to_string()))?;
+        let install_script = template
+            .get("installScript")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                AgentError::InvalidRequest("Missing installScript in template".to_string())
+            })?;
+        let no_environment = serde_json::Map::new();
+        let environment = msg
+            .get("environment")
+            .and_then(|v| v.as_object())
+            .unwrap_or(&no_environment);
+        let scratch_mb = msg["scratchSizeMb"]
+            .as_u64()
+            .unwrap_or(DEFAULT_TEST_INSTALL_SCRATCH_MB)
+            .clamp(16, MAX_TEST_INSTALL_SCRATCH_MB);
+        let timeout = Duration::from_secs(
+            msg["timeoutSeconds"]
+                .as_u64()
+                .unwrap_or(DEFAULT_TEST_INSTALL_TIMEOUT_SECS)
+                .clamp(10, MAX_TEST_INSTALL_TIMEOUT_SECS),
+        );
+
+        let container_dir = container_data_dir(template)?;
+        let chown_mode = installer_chown_mode(template)?;
+        let (script, unresolved) =
+            render_install_script(install_script, environment, &container_dir);
+        if !unresolved.is_empty() {
+            let names = unresolved.join(", ");
+            if template
+                .get("strictVariables")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false)
+            {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Unresolved variables in install script: {}",
+                    names
+                )));
+            }
+            self.send_test_install_output(
+                msg,
+                write,
+                "system",
+                &format!(
+                    "[Catalyst] Warning: unresolved variables in install script: {}\n",
+                    names
+                ),
+            )
+            .await;
+        }
+        let install_image = template
+            .get("installImage")
+            .and_then(|v| v.as_str())
+            .unwrap_or("alpine:3.19");
+
+        let Ok(_permit) = self.test_install_permits.clone().try_acquire_owned() else {
+            return Err(AgentError::InvalidRequest(format!(
+                "Too many test installs running (limit {})",
+                self.config.limits.max_concurrent_test_installs.max(1)
+            )));
+        };
+        let scratch_dir =
+            PathBuf::from(TEST_INSTALL_SCRATCH_DIR).join(uuid::Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&scratch_dir).await?;
+        let size_option = format!("size={}m,mode=0755", scratch_mb);
+        let mounted = tokio::process::Command::new("mount")
+            .args(["-t", "tmpfs", "-o", &size_option, "tmpfs"])
+            .arg(&scratch_dir)
+            .output()
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to run mount: {}", e)))?;
+        if !mounted.status.success() {
+            let _ = tokio::fs::remove_dir(&scratch_dir).await;
+            return Err(AgentError::IoError(format!(
+                "Failed to mount test install scratch: {}",
+                String::from_utf8_lossy(&mounted.stderr).trim()
+            )));
+        }
+
+        let result = self
+            .run_test_installer(
+                msg,
+                write,
+                install_image,
+                &script,
+                environment,
+                &scratch_dir,
+                &container_dir,
+                chown_mode,
+                timeout,
+            )
+            .await;
+
+        // Lazy unmount: a killed installer may still hold the mount for a moment.
+        let _ = tokio::process::Command::new("umount")
+            .arg("-l")
+            .arg(&scratch_dir)
+            .output()
+            .await;
+        let _ = tokio::fs::remove_dir(&scratch_dir).await;
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run_test_installer(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+        install_image: &str,
+        script: &str,
+        environment: &serde_json::Map<String, Value>,
+        scratch_dir: &Path,
+        container_dir: &str,
+        chown_mode: ChownMode,
+        timeout: Duration,
+    ) -> AgentResult<Value> {
+        let mut env_map: HashMap<String, String> = environment
+            .iter()
+            .filter_map(|(key, value)| value.as_str().map(|s| (key.clone(), s.to_string())))
+            .collect();
+        env_map.insert("SERVER_DIR".to_string(), container_dir.to_string());
+        let installer_limits = self.installer_limits(msg);
+        let platform = match msg["template"].as_object() {
+            Some(template) => parse_template_platform(template)?,
+            None => None,
+        };
+        self.send_test_install_output(
+            msg,
+            write,
+            "system",
+            &format!("[Catalyst] Test install with image {}\n", install_image),
+        )
+        .await;
+        let installer = self
+            .runtime
+            .spawn_installer_container(
+                install_image,
+                platform.as_ref(),
+                script,
+                &env_map,
+                &scratch_dir.to_string_lossy(),
+                container_dir,
+                installer_limits,
+                chown_mode,
+            )
+            .await?;
+
+        let deadline = tokio::time::Instant::now() + timeout;
+        let max_line_bytes = self.config.console.max_input_bytes;
+        let mut stdout_tail = ConsoleTail::default().with_line_limit(max_line_bytes);
+        let mut stderr_tail = ConsoleTail::default().with_line_limit(max_line_bytes);
+        let exit_code = loop {
+            self.forward_test_install_output(
+                msg,
+                write,
+                &mut stdout_tail,
+                &mut stderr_tail,
+                &installer,
+            )
+            .await;
+            match tokio::time::timeout(Duration::from_millis(200), installer.wait()).await {
+                Ok(Ok(code)) => break Some(code),
+                Ok(Err(e)) => {
+                    installer.force_remove().await;
+                    return Err(AgentError::IoError(format!("Installer wait failed: {}", e)));
+                }
+                Err(_) if tokio::time::Instant::now() >= deadline => break None,
+                Err(_) => continue,
+            }
+        };
+        self.forward_test_install_output(
+            msg,
+            write,
+            &mut stdout_tail,
+            &mut stderr_tail,
+            &installer,
+        )
+        .await;
+        for (stream, tail) in [("stdout", &mut stdout_tail), ("stderr", &mut stderr_tail)] {
+            if let Some(rest) = tail.take_partial() {
+                self.send_test_install_output(msg, write, stream, &rest)
+                    .await;
+            }
+        }
+        let timed_out = exit_code.is_none();
+        let oom_killed =
+            exit_code.is_some_and(|code| code != 0) && installer.was_oom_killed().await;
+        if timed_out {
+            self.send_test_install_output(
+                msg,
+                write,
+                "system",
+                &format!(
+                    "[Catalyst] Test install killed after {}s\n",
+                    timeout.as_secs()
+                ),
+            )
+            .await;
+            installer.force_remove().await;
+        } else {
+            let _ = installer.cleanup().await;
+        }
+
+        let root = scratch_dir.to_path_buf();
+        let (files_created, total_bytes) =
+            tokio::task::spawn_blocking(move || summarize_tree(&root))
+                .await
+                .unwrap_or((0, 0));
+        Ok(json!({
+            "success": true,
+            "passed": exit_code == Some(0),
+            "exitCode": exit_code,
+            "timedOut": timed_out,
+            "oomKilled": oom_killed,
+            "filesCreated": files_created,
+            "totalBytes": total_bytes,
+        }))
+    }
+
+    async fn forward_test_install_output(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+        stdout_tail: &mut ConsoleTail,
+        stderr_tail: &mut ConsoleTail,
+        installer: &InstallerHandle,
+    ) {
+        if let Some(data) = stdout_tail.read_new(&installer.stdout_path, false).await {
+            self.send_test_install_output(msg, write, "stdout", &data)
+                .await;
+        }
+        if let Some(data) = stderr_tail.read_new(&installer.stderr_path, false).await {
+            self.send_test_install_output(msg, write, "stderr", &data)
+                .await;
         }
+    }
 
-        // Stop any existing log streams for this server before marking as stopped
-        // This ensures clean state when transitioning to game server container
-        self.stop_log_streams_for_server(server_id).await;
+    async fn send_test_install_output(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+        stream: &str,
+        data: &str,
+    ) {
+        let event = json!({
+            "type": "test_install_output",
+            "requestId": msg["requestId"],
+            "stream": stream,
+            "data": data,
+        });
+        let _ = write
+            .lock()
+            .await
+            .send(Message::Text(event.to_string().into()))
+            .await;
+    }
 
-        // Emit state update
-        self.emit_server_state_update(server_id, "stopped", None, None, None)
+    /// Archive a server's current files into its backup directory before a clean reinstall.
+    async fn backup_before_reinstall(
+        &self,
+        server_uuid: &str,
+        server_dir: &Path,
+    ) -> AgentResult<PathBuf> {
+        let filename = format!(
+            "pre-reinstall-{}.tar.gz",
+            chrono::Utc::now().format("%Y%m%d%H%M%S")
+        );
+        let backup_path = self
+            .resolve_backup_path(server_uuid, &filename, true)
             .await?;
+        if let Some(parent) = backup_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let output = tokio::process::Command::new("tar")
+            .arg("-czf")
+            .arg(&backup_path)
+            .arg("-C")
+            .arg(server_dir)
+            .arg(".")
+            .output()
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+        if !output.status.success() {
+            let _ = tokio::fs::remove_file(&backup_path).await;
+            return Err(AgentError::IoError(format!(
+                "Pre-reinstall backup failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(backup_path)
+    }
 
-        info!("Server installed successfully: {}", server_uuid);
+    /// Placeholders left after substitution would reach the shell literally. Templates with
+    /// `strictVariables` reject them; otherwise they are reported as a console warning.
+    async fn check_unresolved_variables(
+        &self,
+        server_id: &str,
+        template: &serde_json::Map<String, Value>,
+        context: &str,
+        unresolved: &[String],
+    ) -> AgentResult<()> {
+        if unresolved.is_empty() {
+            return Ok(());
+        }
+        let names = unresolved.join(", ");
+        if template
+            .get("strictVariables")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            return Err(AgentError::InvalidRequest(format!(
+                "Unresolved variables in {}: {}",
+                context, names
+            )));
+        }
+        warn!(
+            "Unresolved variables in {} for {}: {}",
+            context, server_id, names
+        );
+        self.emit_console_output(
+            server_id,
+            "system",
+            &format!(
+                "[Catalyst] Warning: unresolved variables in {}: {}\n",
+                context, names
+            ),
+        )
+        .await
+    }
+
+    /// Return the install script after the substitution `install_server` would apply, without
+    /// running it.
+    async fn handle_preview_install_script(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let result: AgentResult<Value> = async {
+            let template = msg["template"]
+                .as_object()
+                .ok_or_else(|| AgentError::InvalidRequest("Missing template".to_string()))?;
+            let install_script = template
+                .get("installScript")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| {
+                    AgentError::InvalidRequest("Missing installScript in template".to_string())
+                })?;
+            let no_environment = serde_json::Map::new();
+            let environment = msg
+                .get("environment")
+                .and_then(|v| v.as_object())
+                .unwrap_or(&no_environment);
+            let container_dir = container_data_dir(template)?;
+            let (script, unresolved) =
+                render_install_script(install_script, environment, &container_dir);
+            let strict = template
+                .get("strictVariables")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            Ok(json!({
+                "script": script,
+                "unresolved": unresolved,
+                "wouldFail": strict && !unresolved.is_empty(),
+                "installImage": template
+                    .get("installImage")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("alpine:3.19"),
+            }))
+        }
+        .await;
+
+        let response = match result {
+            Ok(mut preview) => {
+                preview["type"] = json!("preview_install_script_response");
+                preview["serverId"] = msg["serverId"].clone();
+                preview["requestId"] = msg["requestId"].clone();
+                preview["success"] = json!(true);
+                preview
+            }
+            Err(e) => json!({
+                "type": "preview_install_script_response",
+                "serverId": msg["serverId"],
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
         Ok(())
     }
 
+    /// Installer limits: the server's allocation scaled by the configured multiplier, capped
+    /// by the node-wide installer maximums. `None` when neither source sets a limit.
+    fn installer_limits(&self, msg: &Value) -> Option<InstallerLimits> {
+        let cfg = &self.config.installer;
+        let multiplier = if cfg.resource_multiplier > 0.0 {
+            cfg.resource_multiplier
+        } else {
+            1.0
+        };
+        let scale = |value: u64| (value as f64 * multiplier).ceil() as u64;
+        let cap = |value: Option<u64>, max: Option<u64>| match (value, max) {
+            (Some(v), Some(m)) => Some(v.min(m)),
+            (v, m) => v.or(m),
+        };
+        let memory_mb = cap(
+            msg["allocatedMemoryMb"].as_u64().map(scale),
+            cfg.max_memory_mb,
+        );
+        let cpu_cores = cap(
+            msg["allocatedCpuCores"].as_u64().map(scale),
+            cfg.max_cpu_cores,
+        );
+        if memory_mb.is_none() && cpu_cores.is_none() {
+            return None;
+        }
+        Some(InstallerLimits {
+            memory_mb: memory_mb.unwrap_or(0),
+            cpu_cores: cpu_cores.unwrap_or(0),
+        })
+    }
+
     fn spawn_log_stream(&self, server_id: &str, container_id: &str) {
         let handler = self.clone();
         let server_id = server_id.to_string();
         let container_id = container_id.to_string();
         tokio::spawn(async move {
-            // First, clean up any stale streams for this server
-            // This prevents issues when switching from installer to game server container
-            {
-                let mut streams = handler.active_log_streams.write().await;
-                streams.retain(|key| {
-                    // Keep only streams that don't belong to this server
-                    // or keep the exact stream we're about to create (prevents duplicates)
-                    !key.starts_with(&format!("{}:", server_id))
-                        || *key == format!("{}:{}", server_id, container_id)
-                });
-            }
-
             let stream_key = format!("{}:{}", server_id, container_id);
-            {
-                let mut guard = handler.active_log_streams.write().await;
-                if guard.contains(&stream_key) {
-                    return;
+            let mut streams = handler.active_log_streams.write().await;
+            // First, stop any stale streams for this server
+            // This prevents issues when switching from installer to game server container
+            streams.retain(|key, task| {
+                // Keep only streams that don't belong to this server
+                // or keep the exact stream we're about to create (prevents duplicates)
+                let keep = !key.starts_with(&format!("{}:", server_id)) || *key == stream_key;
+                if !keep {
+                    task.abort();
                 }
-                guard.insert(stream_key.clone());
+                keep
+            });
+            if streams.contains_key(&stream_key) {
+                return;
             }
-            if let Err(err) = handler
-                .stream_container_logs(&server_id, &container_id)
-                .await
-            {
-                error!(
-                    "Failed to stream logs for server {} (container {}): {}",
-                    server_id, container_id, err
+            let max_streams = handler.config.console.max_log_streams;
+            if streams.len() >= max_streams {
+                warn!(
+                    "Refusing log stream for server {} (container {}): {} streams already open (console.max_log_streams)",
+                    server_id, container_id, max_streams
                 );
-                let _ = handler
-                    .emit_console_output(
-                        &server_id,
-                        "system",
-                        &format!("[Catalyst] Log stream error: {}\n", err),
-                    )
-                    .await;
+                return;
             }
-            handler.active_log_streams.write().await.remove(&stream_key);
+
+            let stream_handler = handler.clone();
+            let task_key = stream_key.clone();
+            // Registered before the lock is released, so the task's own removal cannot run first
+            let task = tokio::spawn(async move {
+                if let Err(err) = stream_handler
+                    .stream_container_logs(&server_id, &container_id)
+                    .await
+                {
+                    error!(
+                        "Failed to stream logs for server {} (container {}): {}",
+                        server_id, container_id, err
+                    );
+                    let _ = stream_handler
+                        .emit_console_output(
+                            &server_id,
+                            "system",
+                            &format!("[Catalyst] Log stream error: {}\n", err),
+                        )
+                        .await;
+                }
+                stream_handler
+                    .active_log_streams
+                    .write()
+                    .await
+                    .remove(&task_key);
+            });
+            streams.insert(stream_key, task.abort_handle());
         });
     }
 
@@ -1148,8 +4681,26 @@ impl WebSocketHandler {
         let stdout_path = base.join("stdout");
         let stderr_path = base.join("stderr");
 
-        let mut stdout_pos = 0u64;
-        let mut stderr_pos = 0u64;
+        let merged = self.config.console.stream_order == ConsoleStreamOrder::Merged;
+        let mut watch = ConsoleWatch::new(&base);
+        if watch.is_none() {
+            debug!(
+                "Polling console files of {}; inotify is unavailable",
+                container_id
+            );
+        }
+        let mut tails = [
+            (
+                "stdout",
+                stdout_path,
+                ConsoleTail::default().with_line_limit(self.config.console.max_input_bytes),
+            ),
+            (
+                "stderr",
+                stderr_path,
+                ConsoleTail::default().with_line_limit(self.config.console.max_input_bytes),
+            ),
+        ];
 
         // Tail the stdout/stderr files
         loop {
@@ -1158,77 +4709,264 @@ impl WebSocketHandler {
                 .is_container_running(container_id)
                 .await
                 .unwrap_or(false);
-            let mut had_data = false;
+            let had_data = self
+                .emit_console_tails(server_id, &mut tails, merged)
+                .await?;
 
-            if let Ok(content) = tokio::fs::read_to_string(&stdout_path).await {
-                if (stdout_pos as usize) < content.len() {
-                    for line in content[stdout_pos as usize..].lines() {
-                        let payload = format!("{}\n", line);
-                        self.emit_console_output(server_id, "stdout", &payload)
+            if !running {
+                // Read any final data, including a last line without its newline
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                self.emit_console_tails(server_id, &mut tails, merged)
+                    .await?;
+                for (stream, _, tail) in tails.iter_mut() {
+                    if let Some(rest) = tail.take_partial() {
+                        self.emit_console_output(server_id, stream, &format!("{}\n", rest))
                             .await?;
                     }
-                    stdout_pos = content.len() as u64;
-                    had_data = true;
                 }
+                break;
             }
-            if let Ok(content) = tokio::fs::read_to_string(&stderr_path).await {
-                if (stderr_pos as usize) < content.len() {
-                    for line in content[stderr_pos as usize..].lines() {
-                        let payload = format!("{}\n", line);
-                        self.emit_console_output(server_id, "stderr", &payload)
-                            .await?;
-                    }
-                    stderr_pos = content.len() as u64;
-                    had_data = true;
+
+            if let Some(active) = &watch {
+                // Still wake up now and then to notice the container stopping.
+                if active.wait(CONSOLE_WATCH_TIMEOUT).await {
+                    continue;
                 }
+                watch = None;
             }
+            // Merged ordering is only as fine as the polling, so poll faster while output flows.
+            let delay = match (had_data, merged) {
+                (true, true) => 20,
+                (true, false) => 50,
+                (false, _) => 200,
+            };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
 
-            if !running {
-                // Read any final data
-                tokio::time::sleep(Duration::from_millis(100)).await;
-                if let Ok(content) = tokio::fs::read_to_string(&stdout_path).await {
-                    if (stdout_pos as usize) < content.len() {
-                        for line in content[stdout_pos as usize..].lines() {
-                            self.emit_console_output(server_id, "stdout", &format!("{}\n", line))
-                                .await?;
-                        }
-                    }
-                }
-                if let Ok(content) = tokio::fs::read_to_string(&stderr_path).await {
-                    if (stderr_pos as usize) < content.len() {
-                        for line in content[stderr_pos as usize..].lines() {
-                            self.emit_console_output(server_id, "stderr", &format!("{}\n", line))
-                                .await?;
-                        }
-                    }
+        Ok(())
+    }
+
+    /// Emit what was appended to each console file since the last poll. In merged order,
+    /// when both grew, the file whose last write (mtime) is older goes first, as a whole.
+    /// Returns whether anything was read.
+    async fn emit_console_tails(
+        &self,
+        server_id: &str,
+        tails: &mut [(&'static str, PathBuf, ConsoleTail)],
+        merged: bool,
+    ) -> AgentResult<bool> {
+        let follow_rotation = self.config.console.follow_rotation;
+        let mut chunks = Vec::new();
+        for (stream, path, tail) in tails.iter_mut() {
+            if let Some(content) = tail.read_new(path, follow_rotation).await {
+                chunks.push((tail.modified, *stream, content));
+            }
+        }
+        if merged {
+            chunks.sort_by_key(|(modified, _, _)| *modified);
+        }
+        let had_data = !chunks.is_empty();
+        for (_, stream, content) in chunks {
+            for line in content.lines() {
+                self.emit_console_output(server_id, stream, &format!("{}\n", line))
+                    .await?;
+            }
+        }
+        Ok(had_data)
+    }
+
+    /// Start a server, first waiting in the background for its `startAfter` dependency to be
+    /// running (and ready, if it has a readiness probe). Dependency cycles are rejected.
+    async fn start_server_after_dependency(&self, msg: &Value) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        self.cancel_deferred_start(server_id).await;
+        let Some(dependency) = msg["startAfter"]
+            .as_str()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+        else {
+            self.start_dependencies.write().await.remove(server_id);
+            return self.start_server_with_details(msg).await;
+        };
+        validate_safe_path_segment(dependency, "startAfter")?;
+        let timeout = match msg["startAfterTimeoutSecs"].as_u64() {
+            Some(secs) => Duration::from_secs(secs.clamp(1, MAX_START_DEPENDENCY_TIMEOUT_SECS)),
+            None => START_DEPENDENCY_TIMEOUT,
+        };
+
+        {
+            let mut dependencies = self.start_dependencies.write().await;
+            let mut chain = vec![server_id.to_string(), dependency.to_string()];
+            let mut current = dependency.to_string();
+            while current != server_id {
+                let Some(next) = dependencies.get(&current) else {
+                    break;
+                };
+                if next != server_id && chain.contains(next) {
+                    break;
                 }
-                break;
+                chain.push(next.clone());
+                current = next.clone();
+            }
+            if current == server_id {
+                return Err(AgentError::InvalidRequest(format!(
+                    "startAfter creates a dependency cycle: {}",
+                    chain.join(" -> ")
+                )));
             }
+            dependencies.insert(server_id.to_string(), dependency.to_string());
+        }
+
+        if self.dependency_ready(dependency).await {
+            return self.start_server_with_details(msg).await;
+        }
 
-            tokio::time::sleep(Duration::from_millis(if had_data { 50 } else { 200 })).await;
+        info!(
+            "Server {} waiting up to {}s for {} before starting",
+            server_id,
+            timeout.as_secs(),
+            dependency
+        );
+        let _ = self
+            .emit_console_output(
+                server_id,
+                "system",
+                &format!(
+                    "[Catalyst] Waiting for {} to be running before starting...\n",
+                    dependency
+                ),
+            )
+            .await;
+        let writer = { self.write.read().await.clone() };
+        if let Some(ws) = writer {
+            let event = json!({
+                "type": "server_start_waiting",
+                "serverId": server_id,
+                "startAfter": dependency,
+                "timeoutSecs": timeout.as_secs(),
+            });
+            let _ = ws
+                .lock()
+                .await
+                .send(Message::Text(event.to_string().into()))
+                .await;
         }
 
+        // Wait off the read loop: the dependency's own start may still be queued behind us.
+        let handler = self.clone();
+        let msg = msg.clone();
+        let server_id = server_id.to_string();
+        let msg_server_id = server_id.clone();
+        let dependency = dependency.to_string();
+        let mut deferred = self.deferred_starts.write().await;
+        let waiter = tokio::spawn(async move {
+            let deadline = tokio::time::Instant::now() + timeout;
+            while !handler.dependency_ready(&dependency).await {
+                if tokio::time::Instant::now() >= deadline {
+                    let reason = format!(
+                        "Dependency {} was not running within {}s",
+                        dependency,
+                        timeout.as_secs()
+                    );
+                    warn!("Not starting {}: {}", server_id, reason);
+                    let _ = handler
+                        .emit_server_state_update(&server_id, "error", Some(reason), None, None)
+                        .await;
+                    return;
+                }
+                tokio::time::sleep(START_DEPENDENCY_POLL_INTERVAL).await;
+            }
+            // Past this point the start runs to completion; nothing can abort it half-way.
+            handler.deferred_starts.write().await.remove(&server_id);
+            if let Err(e) = handler.start_server_with_details(&msg).await {
+                error!("Deferred start of {} failed: {}", server_id, e);
+            }
+        });
+        deferred.retain(|_, waiter| !waiter.is_finished());
+        deferred.insert(msg_server_id, waiter.abort_handle());
         Ok(())
     }
 
+    /// Abort a start still waiting for its dependency, if there is one.
+    async fn cancel_deferred_start(&self, server_id: &str) {
+        let waiter = self.deferred_starts.write().await.remove(server_id);
+        if let Some(waiter) = waiter.filter(|waiter| !waiter.is_finished()) {
+            waiter.abort();
+            info!("Cancelled the deferred start of {}", server_id);
+        }
+    }
+
+    /// A dependency counts as up once its container runs and, if this agent started it, its
+    /// `running` state (sent after any readiness probe) has been reported.
+    async fn dependency_ready(&self, dependency: &str) -> bool {
+        let dependency_uuid = self
+            .start_messages
+            .read()
+            .await
+            .get(dependency)
+            .and_then(|msg| msg["serverUuid"].as_str().map(str::to_string))
+            .unwrap_or_else(|| dependency.to_string());
+        let container_id = self
+            .resolve_container_id(dependency, &dependency_uuid)
+            .await;
+        if container_id.is_empty()
+            || !self
+                .runtime
+                .is_container_running(&container_id)
+                .await
+                .unwrap_or(false)
+        {
+            return false;
+        }
+        self.server_meta
+            .read()
+            .await
+            .get(dependency)
+            .is_none_or(|meta| meta.ready)
+    }
+
     async fn start_server_with_details(&self, msg: &Value) -> AgentResult<()> {
         let server_id = msg["serverId"]
             .as_str()
             .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let msg = &self.apply_environment_overrides(server_id, msg).await;
+        let _server_lock = self.lock_server(server_id).await;
 
         let result: AgentResult<()> = async {
             let server_uuid = msg["serverUuid"]
                 .as_str()
                 .ok_or_else(|| AgentError::InvalidRequest("Missing serverUuid".to_string()))?;
 
+            // Already-installed servers only count against the running limit; anything else
+            // is a new server and counts against the node's total server capacity.
+            let scope = if self.storage_manager.has_image(server_uuid) {
+                CapacityScope::Running
+            } else {
+                CapacityScope::Servers
+            };
+            self.enforce_capacity(server_id, server_uuid, scope).await?;
+
             let template = msg["template"]
                 .as_object()
                 .ok_or_else(|| AgentError::InvalidRequest("Missing template".to_string()))?;
+            // Catch a bad stop policy now rather than when the server needs stopping.
+            parse_stop_policy(msg)?;
+            self.update_console_redactions(server_id, template).await;
 
+            // A server's own image choice wins; otherwise the template's image for this node's
+            // architecture, then its default image.
             let docker_image = msg
                 .get("environment")
                 .and_then(|v| v.get("TEMPLATE_IMAGE"))
                 .and_then(|v| v.as_str())
+                .or_else(|| {
+                    template
+                        .get("imageByArch")
+                        .and_then(|v| v.get(host_oci_arch()))
+                        .and_then(|v| v.as_str())
+                })
                 .or_else(|| template.get("image").and_then(|v| v.as_str()))
                 .ok_or_else(|| {
                     AgentError::InvalidRequest("Missing image in template".to_string())
@@ -1266,7 +5004,85 @@ impl WebSocketHandler {
                 ));
             }
 
+            let tty = template
+                .get("tty")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let seccomp_audit = match template.get("seccompMode").and_then(|v| v.as_str()) {
+                None | Some("enforce") => false,
+                Some("audit") => true,
+                Some(other) => {
+                    return Err(AgentError::InvalidRequest(format!(
+                        "Invalid seccompMode '{}': expected 'enforce' or 'audit'",
+                        other
+                    )));
+                }
+            };
+            let oom_score_adj = match template.get("oomScoreAdj") {
+                None | Some(Value::Null) => None,
+                Some(value) => Some(
+                    value
+                        .as_i64()
+                        .filter(|adj| (-1000..=1000).contains(adj))
+                        .ok_or_else(|| {
+                            AgentError::InvalidRequest(
+                                "Invalid oomScoreAdj: must be an integer between -1000 and 1000"
+                                    .to_string(),
+                            )
+                        })? as i32,
+                ),
+            };
+            let scratch = parse_scratch_mount(template, memory_mb)?;
+            let path_profile = match template.get("pathProfile").and_then(|v| v.as_str()) {
+                None => PathProfile::Default,
+                Some(value) => PathProfile::parse(value).ok_or_else(|| {
+                    AgentError::InvalidRequest(format!(
+                        "Invalid pathProfile '{}': expected 'default', 'sysctl-friendly' or 'strict'",
+                        value
+                    ))
+                })?,
+            };
+            let readiness_probe = parse_readiness_probe(template, primary_port)?;
+            let cgroup_parent = parse_cgroup_parent(msg)?;
+            let annotations =
+                parse_annotations(msg.get("annotations").or_else(|| template.get("annotations")))?;
+            let platform = parse_template_platform(template)?;
+            let runtime_class = match msg
+                .get("runtimeClass")
+                .or_else(|| template.get("runtimeClass"))
+                .and_then(|v| v.as_str())
+                .filter(|v| !v.is_empty())
+            {
+                None => None,
+                Some(class) => Some(
+                    self.config
+                        .containerd
+                        .runtime_classes
+                        .get(class)
+                        .ok_or_else(|| {
+                            AgentError::InvalidRequest(format!(
+                                "Unknown runtimeClass '{}' on this node",
+                                class
+                            ))
+                        })?
+                        .as_str(),
+                ),
+            };
+            let health_check = parse_health_check(template, primary_port)?;
+            let repair_dns = template
+                .get("repairDns")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+
             let network_mode = msg.get("networkMode").and_then(|v| v.as_str());
+            let mtu = match msg.get("mtu").and_then(|v| v.as_u64()) {
+                Some(value) => {
+                    let value = u32::try_from(value).unwrap_or(u32::MAX);
+                    NetworkManager::validate_mtu(value)?;
+                    Some(value)
+                }
+                None => None,
+            };
             let port_bindings_value = msg.get("portBindings");
 
             let environment = msg
@@ -1285,8 +5101,7 @@ impl WebSocketHandler {
             }
 
             // Derive host mount path on-agent (defense in depth). Do not trust control-plane host paths.
-            validate_safe_path_segment(server_uuid, "serverUuid")?;
-            let derived_server_dir = self.config.server.data_dir.join(server_uuid);
+            let derived_server_dir = self.resolve_server_dir(server_uuid)?;
             let host_server_dir = derived_server_dir.to_string_lossy().to_string();
             if let Some(provided) = environment.get("SERVER_DIR").and_then(|v| v.as_str()) {
                 if provided != host_server_dir {
@@ -1301,8 +5116,17 @@ impl WebSocketHandler {
             self.storage_manager
                 .ensure_mounted(server_uuid, &server_dir_path, disk_mb)
                 .await?;
+            // Never start against a directory that isn't the server's own storage, otherwise
+            // the server would come up with an empty data dir.
+            self.storage_manager
+                .verify_mounted(server_uuid, &server_dir_path)
+                .await?;
+            let container_dir = container_data_dir(template)?;
             env_map.insert("HOST_SERVER_DIR".to_string(), host_server_dir.clone());
-            env_map.insert("SERVER_DIR".to_string(), CONTAINER_SERVER_DIR.to_string());
+            env_map.insert("SERVER_DIR".to_string(), container_dir.clone());
+            if let Some((path, _)) = &scratch {
+                env_map.insert("SCRATCH_DIR".to_string(), path.clone());
+            }
 
             info!("Starting server: {} (UUID: {})", server_id, server_uuid);
             info!(
@@ -1341,11 +5165,15 @@ impl WebSocketHandler {
                 env_map.insert("MEMORY_XMS".to_string(), memory_xms.to_string());
             }
 
-            // Replace all {{VARIABLE}} placeholders
-            for (key, value) in &env_map {
-                let placeholder = format!("{{{{{}}}}}", key);
-                final_startup_command = final_startup_command.replace(&placeholder, value);
-            }
+            // Replace all {{VARIABLE}} / {{VARIABLE:-default}} placeholders
+            let (substituted, unresolved) = substitute_template_vars(
+                &final_startup_command,
+                |key| env_map.get(key).cloned(),
+                str::to_string,
+            );
+            final_startup_command = substituted;
+            self.check_unresolved_variables(server_id, template, "startup command", &unresolved)
+                .await?;
 
             // Some templates use bash-style arithmetic tests like ((1)); convert for /bin/sh.
             final_startup_command = normalize_startup_for_sh(&final_startup_command);
@@ -1354,32 +5182,25 @@ impl WebSocketHandler {
 
             let network_ip = env_map
                 .get("CATALYST_NETWORK_IP")
-                .or_else(|| env_map.get("AERO_NETWORK_IP"))
-                .map(|value| value.as_str());
-
-            let mut port_bindings = HashMap::new();
-            if let Some(map) = port_bindings_value.and_then(|value| value.as_object()) {
-                for (container_port, host_port) in map {
-                    let container_port = container_port.parse::<u16>().map_err(|_| {
-                        AgentError::InvalidRequest(
-                            "Invalid portBindings container port".to_string(),
-                        )
-                    })?;
-                    let host_port = host_port.as_u64().ok_or_else(|| {
-                        AgentError::InvalidRequest("Invalid portBindings host port".to_string())
-                    })?;
-                    if host_port == 0 || host_port > u16::MAX as u64 {
-                        return Err(AgentError::InvalidRequest(
-                            "Invalid portBindings host port".to_string(),
-                        ));
-                    }
-                    port_bindings.insert(container_port, host_port as u16);
-                }
-            }
+                .or_else(|| env_map.get("AERO_NETWORK_IP"))
+                .map(|value| value.as_str());
+
+            let port_bindings = parse_port_bindings(port_bindings_value)?;
 
             self.cleanup_all_server_containers(server_id, server_uuid)
                 .await?;
 
+            // Runs after the old container is gone, so lock files it held can be cleared.
+            self.run_pre_start_hook(
+                msg,
+                server_id,
+                template,
+                &env_map,
+                &host_server_dir,
+                &container_dir,
+            )
+                .await?;
+
             // Create and start container
             self.runtime
                 .create_container(crate::runtime_manager::ContainerConfig {
@@ -1390,12 +5211,31 @@ impl WebSocketHandler {
                     memory_mb,
                     cpu_cores,
                     data_dir: &host_server_dir,
+                    container_data_dir: &container_dir,
                     port: primary_port,
                     port_bindings: &port_bindings,
                     network_mode,
                     network_ip,
+                    mtu,
+                    tty,
+                    seccomp_audit,
+                    oom_score_adj,
+                    scratch: scratch.as_ref().map(|(path, mb)| (path.as_str(), *mb)),
+                    path_profile,
+                    cgroup_parent: cgroup_parent.as_deref(),
+                    runtime: runtime_class,
+                    annotations: &annotations,
+                    platform: platform.as_ref(),
                 })
                 .await?;
+            if seccomp_audit {
+                self.emit_console_output(
+                    server_id,
+                    "system",
+                    "[Catalyst] Seccomp audit mode: blocked syscalls are logged, not denied\n",
+                )
+                .await?;
+            }
 
             let is_running = match self.runtime.is_container_running(server_id).await {
                 Ok(value) => value,
@@ -1422,41 +5262,650 @@ impl WebSocketHandler {
                 return Err(AgentError::ContainerError(reason));
             }
 
-            let container_id = self.resolve_container_id(server_id, server_uuid).await;
-            if !container_id.is_empty() {
-                // Stop any existing log streams for this server before starting new one
-                // This is critical when transitioning from installer to game server container
-                self.stop_log_streams_for_server(server_id).await;
-                self.spawn_log_stream(server_id, &container_id);
-                self.spawn_exit_monitor(server_id, &container_id);
+            self.record_server_start(server_id).await;
+            {
+                let mut dns_repair = self.dns_repair_servers.write().await;
+                // Only generated resolv.conf files can be repaired.
+                if repair_dns && self.config.networking.dns_mode == DnsMode::Override {
+                    dns_repair.insert(server_id.to_string());
+                } else {
+                    dns_repair.remove(server_id);
+                }
+            }
+
+            let container_id = self.resolve_container_id(server_id, server_uuid).await;
+            if !container_id.is_empty() {
+                // Stop any existing log streams for this server before starting new one
+                // This is critical when transitioning from installer to game server container
+                self.stop_log_streams_for_server(server_id).await;
+                self.spawn_log_stream(server_id, &container_id);
+                self.spawn_exit_monitor(server_id, &container_id);
+                self.reapply_server_priority(server_id, &container_id).await;
+                if let Some(health_check) = health_check {
+                    self.spawn_health_check(server_id, msg, health_check).await;
+                }
+            }
+
+            // Host-network containers share the node's address; there is nothing to report.
+            let container_ip = if network_mode == Some("host") {
+                None
+            } else {
+                self.container_ip(server_id).await
+            };
+
+            if let Some(probe) = readiness_probe {
+                // Probing can take minutes; don't hold up the message loop while it runs.
+                let handler = self.clone();
+                let probe_server_id = server_id.to_string();
+                tokio::spawn(async move {
+                    handler
+                        .report_running_when_ready(
+                            &probe_server_id,
+                            probe,
+                            port_bindings,
+                            container_ip,
+                        )
+                        .await;
+                });
+                info!("Server started, waiting for readiness: {}", server_id);
+                return Ok(());
+            }
+
+            // Emit state update
+            self.emit_server_state_update_with_fields(
+                server_id,
+                "running",
+                None,
+                Some(port_bindings.clone()),
+                None,
+                self.running_fields(&container_id, container_ip).await,
+            )
+            .await?;
+
+            info!("Server started successfully: {}", server_id);
+            Ok(())
+        }
+        .await;
+
+        if let Err(err) = &result {
+            let reason = format!("Start failed: {}", err);
+            let _ = self
+                .emit_console_output(server_id, "stderr", &format!("[Catalyst] {}\n", reason))
+                .await;
+            let _ = self
+                .emit_server_state_update(server_id, "error", Some(reason), None, None)
+                .await;
+        } else {
+            self.start_messages
+                .write()
+                .await
+                .insert(server_id.to_string(), msg.clone());
+            if let Err(e) = self
+                .storage_manager
+                .update_start_state(server_id, |state| state.start = Some(msg.clone()))
+                .await
+            {
+                warn!("Failed to persist start of {}: {}", server_id, e);
+            }
+            if let Err(e) = self
+                .storage_manager
+                .update_autostart_state(|state| {
+                    if let Some(entry) = state.servers.get_mut(server_id) {
+                        if entry.autostart {
+                            entry.start = Some(msg.clone());
+                        }
+                    }
+                })
+                .await
+            {
+                warn!(
+                    "Failed to persist start of autostart server {}: {}",
+                    server_id, e
+                );
+            }
+        }
+
+        result
+    }
+
+    /// `msg` with the server's pending `update_environment` changes applied. Once the backend
+    /// sends a start whose environment already matches them, they are dropped.
+    async fn apply_environment_overrides(&self, server_id: &str, msg: &Value) -> Value {
+        let mut overrides = self.environment_overrides.write().await;
+        let Some(changes) = overrides.get(server_id) else {
+            return msg.clone();
+        };
+        let environment = msg.get("environment").and_then(|v| v.as_object());
+        let caught_up = changes.iter().all(|(key, value)| {
+            environment
+                .and_then(|env| env.get(key))
+                .and_then(|v| v.as_str())
+                == value.as_deref()
+        });
+        if caught_up {
+            overrides.remove(server_id);
+            drop(overrides);
+            if let Err(e) = self
+                .storage_manager
+                .update_start_state(server_id, |state| state.environment.clear())
+                .await
+            {
+                warn!(
+                    "Failed to forget environment changes of {}: {}",
+                    server_id, e
+                );
+            }
+            return msg.clone();
+        }
+        let mut msg = msg.clone();
+        if !msg["environment"].is_object() {
+            msg["environment"] = json!({});
+        }
+        if let Some(environment) = msg["environment"].as_object_mut() {
+            for (key, value) in changes {
+                match value {
+                    Some(value) => {
+                        environment.insert(key.clone(), Value::String(value.clone()));
+                    }
+                    None => {
+                        environment.remove(key);
+                    }
+                }
+            }
+        }
+        msg
+    }
+
+    /// Record environment changes for a server's next starts and, with `restart`, restart a
+    /// running server with them using the parameters of its last start.
+    async fn handle_update_environment(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let restart = msg["restart"].as_bool().unwrap_or(false);
+
+        let result: AgentResult<bool> = async {
+            let changes = parse_environment_update(msg.get("environment"))?;
+            let start_msg = self.start_messages.read().await.get(server_id).cloned();
+            if restart && start_msg.is_none() {
+                return Err(AgentError::InvalidRequest(format!(
+                    "Server {} has not been started by this agent; send a full start instead",
+                    server_id
+                )));
+            }
+            let pending = {
+                let mut overrides = self.environment_overrides.write().await;
+                let pending = overrides.entry(server_id.to_string()).or_default();
+                pending.extend(changes);
+                pending.clone()
+            };
+            self.storage_manager
+                .update_start_state(server_id, |state| state.environment = pending)
+                .await?;
+            info!("Updated environment for {}", server_id);
+
+            let Some(start_msg) = start_msg.filter(|_| restart) else {
+                return Ok(false);
+            };
+            let server_uuid = start_msg["serverUuid"].as_str().unwrap_or(server_id);
+            let container_id = self.resolve_container_id(server_id, server_uuid).await;
+            if container_id.is_empty()
+                || !self
+                    .runtime
+                    .is_container_running(&container_id)
+                    .await
+                    .unwrap_or(false)
+            {
+                return Ok(false);
+            }
+            let stop_policy = stop_policy_or_sigterm(&start_msg, server_id);
+            self.stop_server(server_id, container_id, &stop_policy)
+                .await?;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            self.start_server_after_dependency(&start_msg).await?;
+            Ok(true)
+        }
+        .await;
+
+        let response = match &result {
+            Ok(restarted) => json!({
+                "type": "update_environment_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": true,
+                "restarted": restarted,
+            }),
+            Err(e) => json!({
+                "type": "update_environment_response",
+                "serverId": server_id,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": e.to_string(),
+            }),
+        };
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Run the template's optional `preStart` script in a throwaway installer-image container
+    /// with the server directory mounted. A non-zero exit, OOM kill or timeout fails the start
+    /// with the tail of the hook's output as the reason.
+    async fn run_pre_start_hook(
+        &self,
+        msg: &Value,
+        server_id: &str,
+        template: &serde_json::Map<String, Value>,
+        env_map: &HashMap<String, String>,
+        host_server_dir: &str,
+        container_dir: &str,
+    ) -> AgentResult<()> {
+        let Some(hook) = template
+            .get("preStart")
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.trim().is_empty())
+        else {
+            return Ok(());
+        };
+        let timeout = Duration::from_secs(
+            template
+                .get("preStartTimeoutSeconds")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(DEFAULT_PRE_START_TIMEOUT_SECS)
+                .clamp(1, MAX_PRE_START_TIMEOUT_SECS),
+        );
+        let hook = hook.replace("\r\n", "\n").replace('\r', "\n");
+        let (script, unresolved) =
+            substitute_template_vars(&hook, |key| env_map.get(key).cloned(), shell_escape_value);
+        self.check_unresolved_variables(server_id, template, "preStart hook", &unresolved)
+            .await?;
+        let image = template
+            .get("installImage")
+            .and_then(|v| v.as_str())
+            .unwrap_or("alpine:3.19");
+
+        info!("Running pre-start hook for {} with {}", server_id, image);
+        self.emit_console_output(
+            server_id,
+            "system",
+            "[Catalyst] Running pre-start hook...\n",
+        )
+        .await?;
+        let installer = self
+            .runtime
+            .spawn_installer_container(
+                image,
+                parse_template_platform(template)?.as_ref(),
+                &script,
+                env_map,
+                host_server_dir,
+                container_dir,
+                self.installer_limits(msg),
+                installer_chown_mode(template)?,
+            )
+            .await?;
+        let exit_code = match tokio::time::timeout(timeout, installer.wait()).await {
+            Ok(Ok(code)) => Some(code),
+            Ok(Err(e)) => {
+                installer.force_remove().await;
+                return Err(AgentError::ContainerError(format!(
+                    "preStart hook wait failed: {}",
+                    e
+                )));
+            }
+            Err(_) => None,
+        };
+
+        let mut output = String::new();
+        for (stream, path) in [
+            ("stdout", &installer.stdout_path),
+            ("stderr", &installer.stderr_path),
+        ] {
+            let content = tokio::fs::read_to_string(path).await.unwrap_or_default();
+            for line in content.lines() {
+                let payload = format!("{}\n", line);
+                let _ = self.emit_console_output(server_id, stream, &payload).await;
+                output.push_str(&payload);
+            }
+        }
+        let oom_killed =
+            exit_code.is_some_and(|code| code != 0) && installer.was_oom_killed().await;
+        if exit_code.is_none() {
+            installer.force_remove().await;
+        } else {
+            let _ = installer.cleanup().await;
+        }
+
+        let failure = match exit_code {
+            Some(0) => return Ok(()),
+            None => format!("preStart hook timed out after {}s", timeout.as_secs()),
+            Some(_) if oom_killed => {
+                "preStart hook was killed for exceeding its memory limit".to_string()
+            }
+            Some(code) => format!("preStart hook exited with code {}", code),
+        };
+        let output = output.trim_end();
+        let mut start = output.len().saturating_sub(MAX_PRE_START_REASON_BYTES);
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+        Err(AgentError::ContainerError(if output.is_empty() {
+            failure
+        } else {
+            format!("{}: {}", failure, &output[start..])
+        }))
+    }
+
+    /// Poll the readiness probe and report `running` once it passes. If the timeout elapses
+    /// first, `running` is still reported but flagged with `readinessTimedOut`.
+    async fn report_running_when_ready(
+        &self,
+        server_id: &str,
+        probe: ReadinessProbe,
+        port_bindings: HashMap<u16, u16>,
+        container_ip: Option<String>,
+    ) {
+        let deadline = tokio::time::Instant::now() + probe.timeout;
+        let timed_out = loop {
+            if !self
+                .runtime
+                .is_container_running(server_id)
+                .await
+                .unwrap_or(false)
+            {
+                // The exit monitor reports the stop; there is nothing to mark running.
+                debug!("Readiness probe abandoned, {} is not running", server_id);
+                return;
+            }
+            if self.probe_passes(server_id, &probe.check).await {
+                break false;
+            }
+            if tokio::time::Instant::now() + probe.interval > deadline {
+                break true;
+            }
+            tokio::time::sleep(probe.interval).await;
+        };
+
+        if timed_out {
+            warn!(
+                "Readiness probe for {} did not pass within {}s",
+                server_id,
+                probe.timeout.as_secs()
+            );
+        }
+        let mut fields = self.running_fields(server_id, container_ip).await;
+        fields["readinessTimedOut"] = json!(timed_out);
+        if let Err(e) = self
+            .emit_server_state_update_with_fields(
+                server_id,
+                "running",
+                None,
+                Some(port_bindings),
+                None,
+                fields,
+            )
+            .await
+        {
+            warn!("Failed to report {} as running: {}", server_id, e);
+        }
+    }
+
+    /// Run `health_check` for as long as the server is up. `start_msg` is kept so a
+    /// `restart-then-stop` check can start the server again with the same details.
+    async fn spawn_health_check(
+        &self,
+        server_id: &str,
+        start_msg: &Value,
+        health_check: HealthCheck,
+    ) {
+        let handler = self.clone();
+        let task_server_id = server_id.to_string();
+        let start_msg = start_msg.clone();
+        let task = tokio::spawn(async move {
+            handler
+                .run_health_check(&task_server_id, start_msg, health_check)
+                .await;
+        });
+        if let Some(existing) = self
+            .health_check_tasks
+            .write()
+            .await
+            .insert(server_id.to_string(), task)
+        {
+            existing.abort();
+        }
+    }
+
+    async fn run_health_check(&self, server_id: &str, start_msg: Value, health_check: HealthCheck) {
+        tokio::time::sleep(health_check.initial_delay).await;
+        let mut failures = 0u32;
+        loop {
+            if !self
+                .runtime
+                .is_container_running(server_id)
+                .await
+                .unwrap_or(false)
+            {
+                debug!("Health check stopped, {} is not running", server_id);
+                return;
+            }
+            // A hung exec probe counts as a failure rather than stalling the check.
+            let passed = tokio::time::timeout(
+                health_check.interval,
+                self.probe_passes(server_id, &health_check.check),
+            )
+            .await
+            .unwrap_or(false);
+            if passed {
+                failures = 0;
+            } else {
+                failures = failures.saturating_add(1);
+                warn!(
+                    "Health check for {} failed ({}/{})",
+                    server_id, failures, health_check.failure_threshold
+                );
+                if failures == health_check.failure_threshold {
+                    if health_check.action == HealthCheckAction::Report {
+                        self.report_failed_health_check(server_id, failures).await;
+                    } else {
+                        // Quarantining stops the server, which aborts this task; run it detached.
+                        let handler = self.clone();
+                        let server_id = server_id.to_string();
+                        tokio::spawn(async move {
+                            handler
+                                .quarantine_server(&server_id, start_msg, health_check, failures)
+                                .await;
+                        });
+                        return;
+                    }
+                }
+            }
+            tokio::time::sleep(health_check.interval).await;
+        }
+    }
+
+    async fn probe_passes(&self, container_id: &str, check: &ReadinessCheck) -> bool {
+        match check {
+            ReadinessCheck::Tcp(port) => self
+                .runtime
+                .is_port_listening(container_id, *port, false)
+                .await
+                .unwrap_or(false),
+            ReadinessCheck::Udp(port) => self
+                .runtime
+                .is_port_listening(container_id, *port, true)
+                .await
+                .unwrap_or(false),
+            ReadinessCheck::Exec(command) => matches!(
+                self.runtime
+                    .exec_with_status(container_id, command.iter().map(String::as_str).collect())
+                    .await,
+                Ok((Some(0), _, _))
+            ),
+        }
+    }
+
+    async fn report_failed_health_check(&self, server_id: &str, failures: u32) {
+        let writer = { self.write.read().await.clone() };
+        if let Some(ws) = writer {
+            let event = json!({
+                "type": "server_health_check_failed",
+                "serverId": server_id,
+                "consecutiveFailures": failures,
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+            });
+            let _ = ws
+                .lock()
+                .await
+                .send(Message::Text(event.to_string().into()))
+                .await;
+        }
+    }
+
+    /// Act on a server that keeps failing its health check: restart it once if the template
+    /// allows, otherwise stop it and report it as `quarantined`.
+    async fn quarantine_server(
+        &self,
+        server_id: &str,
+        start_msg: Value,
+        health_check: HealthCheck,
+        failures: u32,
+    ) {
+        let restart = health_check.action == HealthCheckAction::RestartThenStop
+            && !self
+                .server_meta
+                .read()
+                .await
+                .get(server_id)
+                .is_some_and(|meta| meta.health_restarted);
+        let server_uuid = start_msg["serverUuid"].as_str().unwrap_or(server_id);
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let stop_policy = stop_policy_or_sigterm(&start_msg, server_id);
+        let _ = self
+            .emit_console_output(
+                server_id,
+                "system",
+                &format!(
+                    "[Catalyst] Health check failed {} times in a row, {} the server...\n",
+                    failures,
+                    if restart { "restarting" } else { "stopping" }
+                ),
+            )
+            .await;
+        if let Err(e) = self
+            .stop_server(server_id, container_id, &stop_policy)
+            .await
+        {
+            error!("Failed to stop unhealthy server {}: {}", server_id, e);
+            return;
+        }
+
+        if restart {
+            self.server_meta
+                .write()
+                .await
+                .entry(server_id.to_string())
+                .or_default()
+                .health_restarted = true;
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            if let Err(e) = self.restart_after_health_check(&start_msg).await {
+                error!("Failed to restart unhealthy server {}: {}", server_id, e);
             }
+            return;
+        }
 
-            // Emit state update
-            self.emit_server_state_update(
+        let snapshot = if health_check.snapshot {
+            match self.snapshot_for_forensics(server_uuid).await {
+                Ok(path) => Some(path.to_string_lossy().to_string()),
+                Err(e) => {
+                    warn!("Forensic snapshot of {} failed: {}", server_id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        warn!(
+            "Server {} quarantined after failed health checks",
+            server_id
+        );
+        if let Err(e) = self
+            .emit_server_state_update_with_fields(
                 server_id,
-                "running",
+                "quarantined",
+                Some(format!("Health check failed {} times in a row", failures)),
                 None,
-                Some(port_bindings.clone()),
                 None,
+                json!({
+                    "healthCheckFailures": failures,
+                    "forensicSnapshot": snapshot,
+                }),
             )
-            .await?;
-
-            info!("Server started successfully: {}", server_id);
-            Ok(())
+            .await
+        {
+            warn!("Failed to report {} as quarantined: {}", server_id, e);
         }
-        .await;
+    }
 
-        if let Err(err) = &result {
-            let reason = format!("Start failed: {}", err);
-            let _ = self
-                .emit_console_output(server_id, "stderr", &format!("[Catalyst] {}\n", reason))
-                .await;
-            let _ = self
-                .emit_server_state_update(server_id, "error", Some(reason), None, None)
-                .await;
+    /// Boxed as a `dyn` future: starting spawns the health check again, and the recursive
+    /// future type would otherwise not be provably `Send`.
+    fn restart_after_health_check<'a>(
+        &'a self,
+        start_msg: &'a Value,
+    ) -> Pin<Box<dyn Future<Output = AgentResult<()>> + Send + 'a>> {
+        Box::pin(self.start_server_after_dependency(start_msg))
+    }
+
+    /// Archive a quarantined server's data directory next to its backups for inspection.
+    async fn snapshot_for_forensics(&self, server_uuid: &str) -> AgentResult<PathBuf> {
+        let server_dir = self.resolve_server_dir(server_uuid)?;
+        let snapshot_dir = self.backup_base_dir(server_uuid);
+        tokio::fs::create_dir_all(&snapshot_dir).await?;
+        let snapshot_path = snapshot_dir.join(format!(
+            "quarantine-{}.tar.gz",
+            chrono::Utc::now().format("%Y%m%dT%H%M%SZ")
+        ));
+        let _permit = self
+            .backup_permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| AgentError::InternalError(e.to_string()))?;
+        let output = tokio::process::Command::new("tar")
+            .arg("-czf")
+            .arg(&snapshot_path)
+            .arg("-C")
+            .arg(&server_dir)
+            .arg(".")
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+        if !output.status.success() {
+            return Err(AgentError::IoError(format!(
+                "Snapshot archive failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
         }
+        Ok(snapshot_path)
+    }
 
-        result
+    /// CNI-assigned address of a container, if it has one. Host-network containers have none.
+    async fn container_ip(&self, container_id: &str) -> Option<String> {
+        match self.runtime.get_container_ip(container_id).await {
+            Ok(ip) if !ip.is_empty() => Some(ip),
+            Ok(_) => None,
+            Err(e) => {
+                debug!("Could not resolve IP for {}: {}", container_id, e);
+                None
+            }
+        }
     }
 
     async fn start_server(&self, server_id: &str, container_id: String) -> AgentResult<()> {
@@ -1474,10 +5923,19 @@ impl WebSocketHandler {
         // In production, fetch server config from database or local cache
         match self.runtime.start_container(&container_id).await {
             Ok(()) => {
+                self.record_server_start(server_id).await;
                 self.spawn_log_stream(server_id, &container_id);
                 self.spawn_exit_monitor(server_id, &container_id);
-                self.emit_server_state_update(server_id, "running", None, None, None)
-                    .await?;
+                let container_ip = self.container_ip(&container_id).await;
+                self.emit_server_state_update_with_fields(
+                    server_id,
+                    "running",
+                    None,
+                    None,
+                    None,
+                    self.running_fields(&container_id, container_ip).await,
+                )
+                .await?;
                 Ok(())
             }
             Err(err) => {
@@ -1511,11 +5969,138 @@ impl WebSocketHandler {
         }
     }
 
+    /// Load the start messages and pending environment changes persisted before the agent
+    /// restarted, so `update_environment` and `stop_all_servers` still know them.
+    pub async fn restore_start_state(&self) {
+        let states = match self.storage_manager.read_start_state().await {
+            Ok(states) => states,
+            Err(e) => {
+                warn!("Failed to read persisted start state: {}", e);
+                return;
+            }
+        };
+        let mut start_messages = self.start_messages.write().await;
+        let mut overrides = self.environment_overrides.write().await;
+        for (server_id, state) in states {
+            if let Some(start) = state.start {
+                start_messages.insert(server_id.clone(), start);
+            }
+            if !state.environment.is_empty() {
+                overrides.insert(server_id, state.environment);
+            }
+        }
+    }
+
+    /// Re-attach exit monitors and log streams to managed containers left running by a previous
+    /// agent process, without waiting for the backend connection, so exits in between are not
+    /// missed. Adopted servers are keyed by container name until the backend starts them again.
+    pub async fn adopt_running_containers(&self) {
+        if let Err(e) = self.runtime.restore_console_writers().await {
+            warn!("Failed to restore console writers: {}", e);
+        }
+        let containers = match self.runtime.list_containers().await {
+            Ok(containers) => containers,
+            Err(err) => {
+                warn!("Failed to list containers for adoption: {}", err);
+                return;
+            }
+        };
+        let mut adopted = 0;
+        for container in containers
+            .iter()
+            .filter(|container| container.managed && container.status.contains("Up"))
+        {
+            let server_id = normalize_container_name(&container.names);
+            if server_id.is_empty() {
+                continue;
+            }
+            debug!("Adopting running container {}", container.id);
+            self.spawn_log_stream(&server_id, &container.id);
+            self.spawn_exit_monitor(&server_id, &container.id);
+            adopted += 1;
+        }
+        if adopted > 0 {
+            info!("Adopted {} running server containers", adopted);
+        }
+    }
+
+    /// Drop the monitor an adoption pass keyed by container name, now that the backend addresses
+    /// the server by its id; otherwise a deliberate stop would be reported as a crash.
+    async fn release_adopted_monitor(&self, server_id: &str, container_id: &str) {
+        if !container_id.is_empty() && container_id != server_id {
+            self.stop_monitor_task(container_id).await;
+        }
+    }
+
+    /// Gracefully stop every running server, each with the stop policy of its last start, which
+    /// survives agent restarts (see `restore_start_state`). A server with no recorded start is
+    /// sent SIGTERM. Used when the agent shuts down with `containerd.on_shutdown = "stop"`.
+    pub async fn stop_all_servers(&self) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        let containers = match self.runtime.list_containers().await {
+            Ok(containers) => containers,
+            Err(err) => {
+                error!("Failed to list containers for shutdown: {}", err);
+                return;
+            }
+        };
+        let start_messages = self.start_messages.read().await.clone();
+        let stops = containers
+            .into_iter()
+            .filter(|container| container.managed && container.status.contains("Up"))
+            .map(|container| {
+                let name = normalize_container_name(&container.names);
+                let start_msg = start_messages.iter().find(|(server_id, msg)| {
+                    **server_id == name || msg["serverUuid"].as_str() == Some(name.as_str())
+                });
+                let (server_id, stop_policy) = match start_msg {
+                    Some((server_id, msg)) => {
+                        (server_id.clone(), stop_policy_or_sigterm(msg, server_id))
+                    }
+                    None => {
+                        warn!("No recorded start for {}, stopping it with SIGTERM", name);
+                        let stop_policy = StopPolicy {
+                            stop_method: StopMethod::Signal,
+                            ..StopPolicy::default()
+                        };
+                        (name, stop_policy)
+                    }
+                };
+                async move {
+                    info!("Stopping server {} for agent shutdown", server_id);
+                    if let Err(err) = self
+                        .stop_server(&server_id, container.id, &stop_policy)
+                        .await
+                    {
+                        warn!("Failed to stop server {} on shutdown: {}", server_id, err);
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        futures::future::join_all(stops).await;
+    }
+
     async fn stop_server(
         &self,
         server_id: &str,
         container_id: String,
         stop_policy: &StopPolicy,
+    ) -> AgentResult<()> {
+        self.cancel_deferred_start(server_id).await;
+        let _server_lock = self.lock_server(server_id).await;
+        self.release_adopted_monitor(server_id, &container_id).await;
+        self.with_intentional_stop(
+            server_id,
+            self.stop_server_inner(server_id, container_id, stop_policy),
+        )
+        .await
+    }
+
+    async fn stop_server_inner(
+        &self,
+        server_id: &str,
+        container_id: String,
+        stop_policy: &StopPolicy,
     ) -> AgentResult<()> {
         if container_id.is_empty() {
             info!(
@@ -1523,8 +6108,16 @@ impl WebSocketHandler {
                 server_id
             );
             self.stop_monitor_task(server_id).await;
-            self.emit_server_state_update(server_id, "stopped", None, None, None)
-                .await?;
+            self.record_clean_stop(server_id).await;
+            self.emit_server_state_update_with_fields(
+                server_id,
+                "stopped",
+                None,
+                None,
+                None,
+                json!({ "stopMethod": "not_running" }),
+            )
+            .await?;
             return Ok(());
         }
         info!(
@@ -1534,6 +6127,8 @@ impl WebSocketHandler {
 
         self.stop_monitor_task(server_id).await;
 
+        // How the server actually went down, reported with the final `stopped` state.
+        let mut stop_fields = json!({ "stopMethod": "not_running" });
         if self
             .runtime
             .is_container_running(&container_id)
@@ -1541,27 +6136,22 @@ impl WebSocketHandler {
             .unwrap_or(false)
         {
             let mut stopped_gracefully = false;
-            if let Some(command) = stop_policy.stop_command.as_deref() {
-                let payload = if command.ends_with('\n') {
-                    command.to_string()
-                } else {
-                    format!("{}\n", command)
-                };
-                let _ = self
-                    .emit_console_output(
-                        server_id,
-                        "system",
-                        "[Catalyst] Sending graceful stop command to server process...\n",
-                    )
-                    .await;
-
-                match self.runtime.send_input(&container_id, &payload).await {
+            let stop_command = match stop_policy.stop_method {
+                StopMethod::Signal => None,
+                _ => stop_policy.stop_command.as_deref(),
+            };
+            if let Some(command) = stop_command {
+                match self
+                    .deliver_stop_command(server_id, &container_id, stop_policy, command)
+                    .await
+                {
                     Ok(()) => {
                         if self
                             .wait_for_container_shutdown(&container_id, Duration::from_secs(20))
                             .await
                         {
                             stopped_gracefully = true;
+                            stop_fields = json!({ "stopMethod": "command" });
                         } else {
                             let _ = self
                                 .emit_console_output(
@@ -1605,9 +6195,19 @@ impl WebSocketHandler {
                         ),
                     )
                     .await;
-                self.runtime
+                let killed = self
+                    .runtime
                     .stop_container_with_signal(&container_id, &stop_policy.stop_signal, 30)
                     .await?;
+                stop_fields = if killed {
+                    warn!(
+                        "Server {} ignored {} and was killed after the timeout",
+                        server_id, stop_policy.stop_signal
+                    );
+                    json!({ "stopMethod": "kill_after_timeout", "stopSignal": "SIGKILL" })
+                } else {
+                    json!({ "stopMethod": "signal", "stopSignal": stop_policy.stop_signal })
+                };
             }
         }
 
@@ -1615,13 +6215,93 @@ impl WebSocketHandler {
             self.runtime.remove_container(&container_id).await?;
         }
 
-        self.emit_server_state_update(server_id, "stopped", None, None, None)
-            .await?;
+        self.record_clean_stop(server_id).await;
+        self.emit_server_state_update_with_fields(
+            server_id,
+            "stopped",
+            None,
+            None,
+            None,
+            stop_fields,
+        )
+        .await?;
 
         Ok(())
     }
 
+    /// Send the graceful stop command using the policy's method. RCON failures fall back to
+    /// writing the command to stdin.
+    async fn deliver_stop_command(
+        &self,
+        server_id: &str,
+        container_id: &str,
+        stop_policy: &StopPolicy,
+        command: &str,
+    ) -> AgentResult<()> {
+        if let StopMethod::Rcon { port, password } = &stop_policy.stop_method {
+            let _ = self
+                .emit_console_output(
+                    server_id,
+                    "system",
+                    "[Catalyst] Sending graceful stop command over RCON...\n",
+                )
+                .await;
+            let host = self
+                .container_ip(container_id)
+                .await
+                .unwrap_or_else(|| "127.0.0.1".to_string());
+            let result = async {
+                let mut client =
+                    crate::rcon::RconClient::connect(&host, *port, password, RCON_IO_TIMEOUT)
+                        .await?;
+                client.execute(command.trim_end()).await
+            }
+            .await;
+            match result {
+                Ok(_) => return Ok(()),
+                Err(err) => {
+                    warn!(
+                        "RCON stop failed for server {} ({}:{}): {}",
+                        server_id, host, port, err
+                    );
+                    let _ = self
+                        .emit_console_output(
+                            server_id,
+                            "system",
+                            &format!(
+                                "[Catalyst] RCON stop failed ({}), falling back to stdin...\n",
+                                err
+                            ),
+                        )
+                        .await;
+                }
+            }
+        } else {
+            let _ = self
+                .emit_console_output(
+                    server_id,
+                    "system",
+                    "[Catalyst] Sending graceful stop command to server process...\n",
+                )
+                .await;
+        }
+
+        let payload = if command.ends_with('\n') {
+            command.to_string()
+        } else {
+            format!("{}\n", command)
+        };
+        self.runtime.send_input(container_id, &payload).await
+    }
+
     async fn kill_server(&self, server_id: &str, container_id: String) -> AgentResult<()> {
+        self.cancel_deferred_start(server_id).await;
+        self.release_adopted_monitor(server_id, &container_id).await;
+        self.with_intentional_stop(server_id, self.kill_server_inner(server_id, container_id))
+            .await
+    }
+
+    async fn kill_server_inner(&self, server_id: &str, container_id: String) -> AgentResult<()> {
         if container_id.is_empty() {
             info!(
                 "No container found for server {}, marking as killed",
@@ -1695,6 +6375,32 @@ impl WebSocketHandler {
             .as_str()
             .ok_or_else(|| AgentError::InvalidRequest("Missing data".to_string()))?;
 
+        let max_bytes = self.config.console.max_input_bytes;
+        if data.len() > max_bytes {
+            warn!(
+                "Rejected console input for server {}: {} bytes exceeds limit of {}",
+                server_id,
+                data.len(),
+                max_bytes
+            );
+            let writer = { self.write.read().await.clone() };
+            if let Some(ws) = writer {
+                let event = json!({
+                    "type": "console_input_rejected",
+                    "serverId": server_id,
+                    "reason": "too_large",
+                    "bytes": data.len(),
+                    "maxBytes": max_bytes,
+                });
+                let _ = ws
+                    .lock()
+                    .await
+                    .send(Message::Text(event.to_string().into()))
+                    .await;
+            }
+            return Ok(());
+        }
+
         let server_uuid = msg
             .get("serverUuid")
             .and_then(|value| value.as_str())
@@ -1746,6 +6452,234 @@ impl WebSocketHandler {
         Ok(())
     }
 
+    /// Trim every mounted storage image every `limits.fstrim_interval_hours`, so sparse images
+    /// give freed space back to the host without a `compact_storage` request per server.
+    pub async fn run_fstrim_schedule(&self) {
+        let hours = self.config.limits.fstrim_interval_hours;
+        if hours == 0 {
+            return;
+        }
+        let period = Duration::from_secs(hours * 3600);
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            self.fstrim_sweep().await;
+        }
+    }
+
+    async fn fstrim_sweep(&self) {
+        let servers = match self.storage_manager.mounted_image_servers().await {
+            Ok(servers) => servers,
+            Err(e) => {
+                warn!("Scheduled fstrim could not list storage images: {}", e);
+                return;
+            }
+        };
+        let start_messages = self.start_messages.read().await.clone();
+        let mut total_reclaimed = 0;
+        for server_uuid in servers {
+            // Images are named by uuid but containers by server id.
+            let server_id = start_messages
+                .iter()
+                .find(|(_, msg)| msg["serverUuid"].as_str() == Some(server_uuid.as_str()))
+                .map(|(server_id, _)| server_id.as_str())
+                .unwrap_or(&server_uuid);
+            let _server_lock = self.lock_server(server_id).await;
+            let container_id = self.resolve_container_id(server_id, &server_uuid).await;
+            let running = !container_id.is_empty()
+                && self
+                    .runtime
+                    .is_container_running(&container_id)
+                    .await
+                    .unwrap_or(false);
+            if running {
+                if let Some(pressure) = self.runtime.io_pressure_percent(&container_id).await {
+                    if pressure > FSTRIM_MAX_IO_PRESSURE_PERCENT {
+                        info!(
+                            "Skipping scheduled fstrim for {}: I/O pressure {:.1}%",
+                            server_uuid, pressure
+                        );
+                        continue;
+                    }
+                }
+            }
+            let Ok(server_dir) = self.resolve_server_dir(&server_uuid) else {
+                continue;
+            };
+            match self
+                .storage_manager
+                .compact(&server_uuid, &server_dir, running)
+                .await
+            {
+                Ok((reclaimed, _)) => total_reclaimed += reclaimed,
+                // Filesystems without online discard are trimmed by compact_storage once stopped.
+                Err(AgentError::InvalidRequest(reason)) => {
+                    debug!("Skipping scheduled fstrim for {}: {}", server_uuid, reason)
+                }
+                Err(e) => warn!("Scheduled fstrim failed for {}: {}", server_uuid, e),
+            }
+        }
+        info!(
+            "Scheduled fstrim reclaimed {} bytes across server storage images",
+            total_reclaimed
+        );
+    }
+
+    /// Write an announcement to the stdin of the listed servers, or every running server,
+    /// using each template's `broadcastCommand` (default `say {message}`).
+    async fn handle_broadcast_console(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let message = msg["message"]
+            .as_str()
+            .map(str::trim)
+            .filter(|message| !message.is_empty())
+            .ok_or_else(|| AgentError::InvalidRequest("Missing message".to_string()))?;
+        if message.len() > MAX_BROADCAST_MESSAGE_LEN {
+            return Err(AgentError::InvalidRequest(format!(
+                "Broadcast message exceeds {} bytes",
+                MAX_BROADCAST_MESSAGE_LEN
+            )));
+        }
+        // A newline would let the message run as a second console command.
+        if message.chars().any(char::is_control) {
+            return Err(AgentError::InvalidRequest(
+                "Broadcast message must be a single line without control characters".to_string(),
+            ));
+        }
+        let fallback_command = msg["command"].as_str().unwrap_or(DEFAULT_BROADCAST_COMMAND);
+        if !fallback_command.contains("{message}") {
+            return Err(AgentError::InvalidRequest(
+                "Broadcast command must contain {message}".to_string(),
+            ));
+        }
+        let requested: Option<Vec<String>> = match msg.get("serverIds") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(ids)) => Some(
+                ids.iter()
+                    .map(|id| {
+                        id.as_str().map(str::to_string).ok_or_else(|| {
+                            AgentError::InvalidRequest(
+                                "serverIds must be an array of strings".to_string(),
+                            )
+                        })
+                    })
+                    .collect::<AgentResult<_>>()?,
+            ),
+            Some(_) => {
+                return Err(AgentError::InvalidRequest(
+                    "serverIds must be an array of strings".to_string(),
+                ))
+            }
+        };
+
+        {
+            let mut last = self.last_console_broadcast.write().await;
+            let now = tokio::time::Instant::now();
+            if let Some(previous) = *last {
+                let elapsed = now.duration_since(previous);
+                if elapsed < CONSOLE_BROADCAST_INTERVAL {
+                    let response = json!({
+                        "type": "broadcast_console_result",
+                        "requestId": msg["requestId"],
+                        "success": false,
+                        "error": "rate_limited",
+                        "retryAfterMs": (CONSOLE_BROADCAST_INTERVAL - elapsed).as_millis() as u64,
+                    });
+                    let mut w = write.lock().await;
+                    w.send(Message::Text(response.to_string().into()))
+                        .await
+                        .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+                    return Ok(());
+                }
+            }
+            *last = Some(now);
+        }
+
+        // (server id, server uuid) pairs; without a list, every running managed server.
+        let start_messages = self.start_messages.read().await.clone();
+        let targets: Vec<(String, String)> = match requested {
+            Some(ids) => ids
+                .into_iter()
+                .map(|server_id| {
+                    let server_uuid = start_messages
+                        .get(&server_id)
+                        .and_then(|start| start["serverUuid"].as_str())
+                        .unwrap_or(&server_id)
+                        .to_string();
+                    (server_id, server_uuid)
+                })
+                .collect(),
+            None => self
+                .runtime
+                .list_containers()
+                .await?
+                .into_iter()
+                .filter(|container| container.managed && container.status.contains("Up"))
+                .map(|container| {
+                    let name = normalize_container_name(&container.names);
+                    let server_id = start_messages
+                        .iter()
+                        .find(|(server_id, start)| {
+                            **server_id == name
+                                || start["serverUuid"].as_str() == Some(name.as_str())
+                        })
+                        .map(|(server_id, _)| server_id.clone())
+                        .unwrap_or_else(|| name.clone());
+                    (server_id, name)
+                })
+                .collect(),
+        };
+
+        info!("Broadcasting console message to {} servers", targets.len());
+        let deliveries = targets.into_iter().map(|(server_id, server_uuid)| {
+            let command = start_messages
+                .get(&server_id)
+                .and_then(|start| start["template"]["broadcastCommand"].as_str())
+                .filter(|command| command.contains("{message}"))
+                .unwrap_or(fallback_command)
+                .replace("{message}", message);
+            async move {
+                let container_id = self.resolve_container_id(&server_id, &server_uuid).await;
+                let result = if container_id.is_empty() {
+                    Err(AgentError::ContainerError(format!(
+                        "Container not found for server {}",
+                        server_id
+                    )))
+                } else {
+                    self.runtime
+                        .send_input(&container_id, &format!("{}\n", command))
+                        .await
+                };
+                match result {
+                    Ok(()) => json!({ "serverId": server_id, "success": true }),
+                    Err(err) => {
+                        warn!("Console broadcast to server {} failed: {}", server_id, err);
+                        json!({ "serverId": server_id, "success": false, "error": err.to_string() })
+                    }
+                }
+            }
+        });
+        let results = futures::future::join_all(deliveries).await;
+        let delivered = results.iter().filter(|r| r["success"] == true).count();
+
+        let response = json!({
+            "type": "broadcast_console_result",
+            "requestId": msg["requestId"],
+            "success": true,
+            "delivered": delivered,
+            "failed": results.len() - delivered,
+            "results": results,
+        });
+        let mut w = write.lock().await;
+        w.send(Message::Text(response.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+        Ok(())
+    }
+
     async fn handle_file_operation(&self, msg: &Value) -> AgentResult<()> {
         let op_type = msg
             .get("operation")
@@ -1766,7 +6700,16 @@ impl WebSocketHandler {
             .ok_or_else(|| AgentError::InvalidRequest("Missing path".to_string()))?;
 
         let request_id = msg["requestId"].as_str().map(|value| value.to_string());
+        // Suspended servers (flagged by the backend per request) get read-only files too.
+        let read_only = msg["suspended"].as_bool().unwrap_or(false)
+            || self.read_only_servers.read().await.contains(server_uuid);
         let result = match op_type {
+            "write" | "delete" | "rename" | "append" | "mkdir" if read_only => {
+                Err(AgentError::PermissionDenied(format!(
+                    "Server files are read-only; {} is not allowed",
+                    op_type
+                )))
+            }
             "read" => self
                 .file_manager
                 .read_file(server_uuid, path)
@@ -1802,6 +6745,22 @@ impl WebSocketHandler {
                 .list_dir(server_uuid, path)
                 .await
                 .map(|entries| Some(json!({ "entries": entries }))),
+            "append" => {
+                let data = msg["data"]
+                    .as_str()
+                    .ok_or_else(|| AgentError::InvalidRequest("Missing data".to_string()))?;
+                self.file_manager
+                    .append_file(server_uuid, path, data.as_bytes())
+                    .await
+                    .map(|_| None)
+            }
+            "mkdir" => {
+                let recursive = msg["recursive"].as_bool().unwrap_or(false);
+                self.file_manager
+                    .create_dir(server_uuid, path, recursive)
+                    .await
+                    .map(|_| None)
+            }
             _ => {
                 return Err(AgentError::InvalidRequest(format!(
                     "Unknown file operation: {}",
@@ -1841,6 +6800,94 @@ impl WebSocketHandler {
         result.map(|_| ())
     }
 
+    /// Run a backup operation in the background once a node-wide backup slot is free, so a
+    /// burst of scheduled backups queues instead of saturating disk and CPU. The backend is
+    /// told when a request has to wait, and gets the job's result event with `success: false`
+    /// when it fails. The slot is released however the job ends. Creating and restoring hold
+    /// the server's lock, so they never overlap a start, stop or compaction of it.
+    fn spawn_backup_job(
+        &self,
+        job: BackupJob,
+        msg: Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) {
+        let handler = self.clone();
+        let write = write.clone();
+        self.spawn_connection_job(async move {
+            let _permit = match handler.backup_permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    info!(
+                        "Backup limit reached, queueing {} for {}",
+                        job.as_str(),
+                        msg["serverId"].as_str().unwrap_or("unknown")
+                    );
+                    let event = json!({
+                        "type": "backup_waiting",
+                        "operation": job.as_str(),
+                        "requestId": msg["requestId"],
+                        "serverId": msg["serverId"],
+                        "backupId": msg["backupId"],
+                        "limit": handler.config.limits.max_concurrent_backups.max(1),
+                    });
+                    let _ = write
+                        .lock()
+                        .await
+                        .send(Message::Text(event.to_string().into()))
+                        .await;
+                    match handler.backup_permits.clone().acquire_owned().await {
+                        Ok(permit) => permit,
+                        Err(_) => return,
+                    }
+                }
+            };
+
+            let _server_lock = match (job, msg["serverId"].as_str()) {
+                (BackupJob::Create | BackupJob::Restore, Some(server_id)) => {
+                    Some(handler.lock_server(server_id).await)
+                }
+                _ => None,
+            };
+            let run = async {
+                match job {
+                    BackupJob::Create => handler.handle_create_backup(&msg, &write).await,
+                    BackupJob::Restore => handler.handle_restore_backup(&msg, &write).await,
+                    BackupJob::Download => handler.handle_download_backup(&msg, &write).await,
+                }
+            };
+            let timeout_secs = handler.config.protocol.handler_timeout_secs;
+            let result = if timeout_secs == 0 {
+                run.await
+            } else {
+                tokio::time::timeout(Duration::from_secs(timeout_secs), run)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(AgentError::InternalError(format!(
+                            "{} timed out after {}s",
+                            job.as_str(),
+                            timeout_secs
+                        )))
+                    })
+            };
+            if let Err(err) = result {
+                error!("Backup job {} failed: {}", job.as_str(), err);
+                let event = json!({
+                    "type": job.result_event(),
+                    "requestId": msg["requestId"],
+                    "serverId": msg["serverId"],
+                    "backupId": msg["backupId"],
+                    "success": false,
+                    "error": err.to_string(),
+                });
+                let _ = write
+                    .lock()
+                    .await
+                    .send(Message::Text(event.to_string().into()))
+                    .await;
+            }
+        });
+    }
+
     async fn handle_create_backup(
         &self,
         msg: &Value,
@@ -1857,9 +6904,9 @@ impl WebSocketHandler {
             .ok_or_else(|| AgentError::InvalidRequest("Missing backupName".to_string()))?;
         let backup_path_override = msg["backupPath"].as_str();
         let backup_id = msg["backupId"].as_str();
+        let encryption = self.backup_encryption(msg).await?;
 
-        validate_safe_path_segment(server_uuid, "serverUuid")?;
-        let server_dir = self.config.server.data_dir.join(server_uuid);
+        let server_dir = self.resolve_server_dir(server_uuid)?;
         if let Some(provided) = msg["serverDir"].as_str() {
             let derived = server_dir.to_string_lossy();
             if provided != derived {
@@ -1872,7 +6919,12 @@ impl WebSocketHandler {
         let backup_path = match backup_path_override {
             Some(path) => self.resolve_backup_path(server_uuid, path, true).await?,
             None => {
-                let filename = format!("{}.tar.gz", backup_name);
+                let extension = if encryption.is_some() {
+                    "tar.gz.enc"
+                } else {
+                    "tar.gz"
+                };
+                let filename = format!("{}.{}", backup_name, extension);
                 self.resolve_backup_path(server_uuid, &filename, true)
                     .await?
             }
@@ -1898,18 +6950,64 @@ impl WebSocketHandler {
             backup_path.display()
         );
 
-        let archive_result = tokio::process::Command::new("tar")
-            .arg("-czf")
-            .arg(&backup_path)
-            .arg("-C")
-            .arg(&server_dir)
-            .arg(".")
-            .output()
-            .await
-            .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+        let mut sealed_with = None;
+        let archive_result = match &encryption {
+            None => tokio::process::Command::new("tar")
+                .arg("-czf")
+                .arg(&backup_path)
+                .arg("-C")
+                .arg(&server_dir)
+                .arg(".")
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?,
+            Some((key_source, secret)) => {
+                // The archive is encrypted as tar produces it; plaintext never reaches disk.
+                let mut child = tokio::process::Command::new("tar")
+                    .arg("-czf")
+                    .arg("-")
+                    .arg("-C")
+                    .arg(&server_dir)
+                    .arg(".")
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+                let mut archive = child
+                    .stdout
+                    .take()
+                    .ok_or_else(|| AgentError::IoError("tar stdout unavailable".to_string()))?;
+                let mut output_file = tokio::fs::File::create(&backup_path).await?;
+                let (sealed, output) = tokio::join!(
+                    async move {
+                        backup_crypto::encrypt_stream(
+                            &mut archive,
+                            &mut output_file,
+                            *key_source,
+                            secret,
+                        )
+                        .await
+                    },
+                    child.wait_with_output()
+                );
+                match sealed {
+                    Ok(header) => sealed_with = Some(header),
+                    Err(err) => {
+                        let _ = tokio::fs::remove_file(&backup_path).await;
+                        return Err(err);
+                    }
+                }
+                output.map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?
+            }
+        };
 
         if !archive_result.status.success() {
             let stderr = String::from_utf8_lossy(&archive_result.stderr);
+            if sealed_with.is_some() {
+                let _ = tokio::fs::remove_file(&backup_path).await;
+            }
             return Err(AgentError::IoError(format!(
                 "Backup archive failed: {}",
                 stderr
@@ -1932,14 +7030,26 @@ impl WebSocketHandler {
             hasher.update(&buffer[..read]);
         }
         let checksum = format!("{:x}", hasher.finalize());
+        let encryption_info = sealed_with.map(|header| {
+            json!({
+                "algorithm": backup_crypto::ALGORITHM,
+                "kdf": backup_crypto::KDF,
+                "iterations": header.iterations,
+                "salt": base64::engine::general_purpose::STANDARD.encode(header.salt),
+                "iv": base64::engine::general_purpose::STANDARD.encode(header.iv),
+                "keySource": header.key_source.as_str(),
+            })
+        });
 
         let event = json!({
             "type": "backup_complete",
             "serverId": server_id,
+            "success": true,
             "backupName": backup_name,
             "backupPath": backup_path.to_string_lossy(),
             "sizeMb": size_mb,
             "checksum": checksum,
+            "encryption": encryption_info,
             "backupId": backup_id,
             "timestamp": chrono::Utc::now().timestamp_millis(),
         });
@@ -1968,8 +7078,7 @@ impl WebSocketHandler {
             .and_then(|value| value.as_str())
             .unwrap_or(server_id);
 
-        validate_safe_path_segment(server_uuid, "serverUuid")?;
-        let server_dir = self.config.server.data_dir.join(server_uuid);
+        let server_dir = self.resolve_server_dir(server_uuid)?;
         if let Some(provided) = msg["serverDir"].as_str() {
             let derived = server_dir.to_string_lossy();
             if provided != derived {
@@ -1999,14 +7108,51 @@ impl WebSocketHandler {
             server_dir.display()
         );
 
-        let restore_result = tokio::process::Command::new("tar")
-            .arg("-xzf")
-            .arg(&backup_file)
-            .arg("-C")
-            .arg(&server_dir)
-            .output()
-            .await
-            .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+        let mut file = tokio::fs::File::open(&backup_file).await?;
+        let restore_result = match backup_crypto::read_header(&mut file).await? {
+            None => tokio::process::Command::new("tar")
+                .arg("-xzf")
+                .arg(&backup_file)
+                .arg("-C")
+                .arg(&server_dir)
+                .kill_on_drop(true)
+                .output()
+                .await
+                .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?,
+            Some(header) => {
+                let secret = self.backup_decryption_secret(msg, &header).await?;
+                let mut plaintext = DecryptingReader::new(file, &header, &secret).await?;
+                let mut child = tokio::process::Command::new("tar")
+                    .arg("-xzf")
+                    .arg("-")
+                    .arg("-C")
+                    .arg(&server_dir)
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::null())
+                    .stderr(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+                let mut stdin = child
+                    .stdin
+                    .take()
+                    .ok_or_else(|| AgentError::IoError("tar stdin unavailable".to_string()))?;
+                let (copied, output) = tokio::join!(
+                    async move { tokio::io::copy(&mut plaintext, &mut stdin).await },
+                    child.wait_with_output()
+                );
+                let output =
+                    output.map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+                if let Err(e) = copied {
+                    return Err(AgentError::IoError(format!(
+                        "Backup decryption failed: {} (tar: {})",
+                        e,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+                output
+            }
+        };
 
         if !restore_result.status.success() {
             let stderr = String::from_utf8_lossy(&restore_result.stderr);
@@ -2016,9 +7162,31 @@ impl WebSocketHandler {
             )));
         }
 
+        // Archives keep the uid/gid of the node they were taken on. Optionally hand everything
+        // back to the runtime user, as the installer does, so migrated servers can read their files.
+        if msg["fixOwnership"].as_bool().unwrap_or(false) {
+            let uid = msg["runAsUser"].as_u64().unwrap_or(1000);
+            let gid = msg["runAsGroup"].as_u64().unwrap_or(uid);
+            let chown_result = tokio::process::Command::new("chown")
+                .arg("-R")
+                .arg(format!("{}:{}", uid, gid))
+                .arg(&server_dir)
+                .output()
+                .await
+                .map_err(|e| AgentError::IoError(format!("Failed to run chown: {}", e)))?;
+            if !chown_result.status.success() {
+                let stderr = String::from_utf8_lossy(&chown_result.stderr);
+                return Err(AgentError::IoError(format!(
+                    "Restored files could not be chowned to {}:{}: {}",
+                    uid, gid, stderr
+                )));
+            }
+        }
+
         let event = json!({
             "type": "backup_restore_complete",
             "serverId": server_id,
+            "success": true,
             "backupPath": backup_path,
         });
 
@@ -2171,19 +7339,256 @@ impl WebSocketHandler {
                 return Ok(());
             }
         };
-        let mut buffer = vec![0u8; 256 * 1024];
-        loop {
-            let read = match file.read(&mut buffer).await {
-                Ok(read) => read,
+        let encryption = match backup_crypto::read_header(&mut file).await? {
+            None => None,
+            Some(header) => match self.backup_decryption_secret(msg, &header).await {
+                Ok(secret) => Some((header, secret)),
                 Err(err) => {
                     let event = json!({
                         "type": "backup_download_chunk",
                         "requestId": request_id,
                         "serverId": server_id,
-                        "error": format!("Failed to read backup file: {}", err),
+                        "error": err.to_string(),
                         "done": true,
                     });
                     let mut w = write.lock().await;
+                    w.send(Message::Text(event.to_string().into()))
+                        .await
+                        .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+                    return Ok(());
+                }
+            },
+        };
+        let compression = self.request_compression(msg).await?;
+        let header = json!({
+            "type": "backup_download_chunk",
+            "requestId": request_id,
+            "serverId": server_id,
+        });
+        match encryption {
+            None => {
+                self.send_file_chunks(&mut file, header, compression, "backup", write)
+                    .await
+            }
+            Some((backup_header, secret)) => {
+                let mut plaintext = DecryptingReader::new(file, &backup_header, &secret).await?;
+                self.send_file_chunks(&mut plaintext, header, compression, "backup", write)
+                    .await
+            }
+        }
+    }
+
+    async fn handle_collect_support_bundle(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let request_id = msg["requestId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing requestId".to_string()))?;
+        let bundle_id = uuid::Uuid::new_v4();
+        let staging = std::env::temp_dir().join(format!("catalyst-support-{}", bundle_id));
+        let archive = std::env::temp_dir().join(format!("catalyst-support-{}.tar.gz", bundle_id));
+        info!("Collecting support bundle for request {}", request_id);
+
+        let result: AgentResult<()> = async {
+            self.build_support_bundle(&staging, &archive).await?;
+            let mut file = tokio::fs::File::open(&archive).await?;
+            let compression = self.request_compression(msg).await?;
+            let header = json!({
+                "type": "support_bundle_chunk",
+                "requestId": request_id,
+            });
+            self.send_file_chunks(&mut file, header, compression, "support bundle", write)
+                .await
+        }
+        .await;
+
+        let _ = tokio::fs::remove_dir_all(&staging).await;
+        let _ = tokio::fs::remove_file(&archive).await;
+
+        if let Err(err) = &result {
+            let event = json!({
+                "type": "support_bundle_chunk",
+                "requestId": request_id,
+                "error": format!("Failed to collect support bundle: {}", err),
+                "done": true,
+            });
+            let mut w = write.lock().await;
+            let _ = w.send(Message::Text(event.to_string().into())).await;
+        }
+        result
+    }
+
+    /// Gather diagnostics into `staging` and pack them into `archive`. The API and HMAC keys
+    /// and secret-looking container environment values are redacted from every file.
+    async fn build_support_bundle(&self, staging: &Path, archive: &Path) -> AgentResult<()> {
+        tokio::fs::create_dir_all(staging.join("cni")).await?;
+        let secrets: Vec<&str> = [
+            Some(self.config.server.api_key.trim()),
+            self.config.server.hmac_key(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|secret| !secret.is_empty())
+        .collect();
+        let redact = |text: &str| {
+            secrets.iter().fold(text.to_string(), |text, secret| {
+                text.replace(secret, "[REDACTED]")
+            })
+        };
+
+        let mut config = (*self.config).clone();
+        config.server.api_key = "[REDACTED]".to_string();
+        if config.server.hmac_key.is_some() {
+            config.server.hmac_key = Some("[REDACTED]".to_string());
+        }
+        let config_toml = toml::to_string_pretty(&config)
+            .map_err(|e| AgentError::InternalError(format!("Failed to serialize config: {}", e)))?;
+        tokio::fs::write(staging.join("config.toml"), config_toml).await?;
+
+        let socket = self
+            .config
+            .containerd
+            .socket_path
+            .to_string_lossy()
+            .to_string();
+        let commands: [(&str, &str, Vec<&str>); 4] = [
+            (
+                "agent.log",
+                "journalctl",
+                vec!["-u", "catalyst-agent", "-n", "5000", "--no-pager"],
+            ),
+            (
+                "ctr-version.txt",
+                "ctr",
+                vec!["--address", &socket, "version"],
+            ),
+            (
+                "ctr-plugins.txt",
+                "ctr",
+                vec!["--address", &socket, "plugins", "ls"],
+            ),
+            ("iptables.txt", "iptables-save", Vec::new()),
+        ];
+        for (name, program, args) in commands {
+            let text = match tokio::process::Command::new(program)
+                .args(&args)
+                .output()
+                .await
+            {
+                Ok(out) => format!(
+                    "{}{}",
+                    String::from_utf8_lossy(&out.stdout),
+                    String::from_utf8_lossy(&out.stderr)
+                ),
+                Err(e) => format!("failed to run {}: {}\n", program, e),
+            };
+            tokio::fs::write(staging.join(name), redact(&text)).await?;
+        }
+
+        let mut containers = Vec::new();
+        for container in self.runtime.list_containers().await? {
+            if !container.managed {
+                continue;
+            }
+            let mut spec = self
+                .runtime
+                .get_container_spec(&container.id)
+                .await
+                .unwrap_or(Value::Null);
+            if let Some(env) = spec
+                .pointer_mut("/process/env")
+                .and_then(Value::as_array_mut)
+            {
+                for entry in env.iter_mut() {
+                    if let Some(redacted) = entry.as_str().and_then(redact_env_entry) {
+                        *entry = Value::String(redacted);
+                    }
+                }
+            }
+            containers.push(json!({
+                "id": container.id,
+                "status": container.status,
+                "hasTask": container.has_task,
+                "image": container.image,
+                "imageDigest": container.image_digest,
+                "spec": spec,
+            }));
+        }
+        let containers = serde_json::to_string_pretty(&containers)?;
+        tokio::fs::write(staging.join("containers.json"), redact(&containers)).await?;
+
+        if let Ok(mut entries) = tokio::fs::read_dir(self.runtime.cni_results_dir()).await {
+            while let Some(entry) = entries.next_entry().await? {
+                if !entry.file_type().await?.is_file() {
+                    continue;
+                }
+                if let Ok(content) = tokio::fs::read_to_string(entry.path()).await {
+                    tokio::fs::write(
+                        staging.join("cni").join(entry.file_name()),
+                        redact(&content),
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        let output = tokio::process::Command::new("tar")
+            .arg("-czf")
+            .arg(archive)
+            .arg("-C")
+            .arg(staging)
+            .arg(".")
+            .output()
+            .await
+            .map_err(|e| AgentError::IoError(format!("Failed to run tar: {}", e)))?;
+        if !output.status.success() {
+            return Err(AgentError::IoError(format!(
+                "Support bundle archive failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Per-request override, otherwise whatever was negotiated during the handshake.
+    async fn request_compression(&self, msg: &Value) -> AgentResult<TransferCompression> {
+        match msg["transferCompression"].as_str() {
+            Some(value) => TransferCompression::parse(value).ok_or_else(|| {
+                AgentError::InvalidRequest(format!("Unsupported transferCompression: {}", value))
+            }),
+            None => Ok(*self.transfer_compression.read().await),
+        }
+    }
+
+    /// Stream a file as base64 chunks. Every event carries the fields of `header`, and the
+    /// last one is marked `done` (with an `error` if reading failed part-way).
+    async fn send_file_chunks<R: tokio::io::AsyncRead + Unpin>(
+        &self,
+        file: &mut R,
+        header: Value,
+        compression: TransferCompression,
+        label: &str,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let event_with = |fields: Value| {
+            let mut event = header.clone();
+            if let (Some(target), Value::Object(extra)) = (event.as_object_mut(), fields) {
+                target.extend(extra);
+            }
+            event
+        };
+        let mut buffer = vec![0u8; 256 * 1024];
+        loop {
+            let read = match file.read(&mut buffer).await {
+                Ok(read) => read,
+                Err(err) => {
+                    let event = event_with(json!({
+                        "error": format!("Failed to read {} file: {}", label, err),
+                        "done": true,
+                    }));
+                    let mut w = write.lock().await;
                     w.send(Message::Text(event.to_string().into()))
                         .await
                         .map_err(|e| AgentError::NetworkError(e.to_string()))?;
@@ -2191,12 +7596,7 @@ impl WebSocketHandler {
                 }
             };
             if read == 0 {
-                let done_event = json!({
-                    "type": "backup_download_chunk",
-                    "requestId": request_id,
-                    "serverId": server_id,
-                    "done": true,
-                });
+                let done_event = event_with(json!({ "done": true }));
                 let mut w = write.lock().await;
                 w.send(Message::Text(done_event.to_string().into()))
                     .await
@@ -2204,14 +7604,13 @@ impl WebSocketHandler {
                 break;
             }
 
-            let chunk = base64::engine::general_purpose::STANDARD.encode(&buffer[..read]);
-            let event = json!({
-                "type": "backup_download_chunk",
-                "requestId": request_id,
-                "serverId": server_id,
+            let payload = compression.compress(&buffer[..read])?;
+            let chunk = base64::engine::general_purpose::STANDARD.encode(&payload);
+            let event = event_with(json!({
                 "data": chunk,
+                "compression": compression.as_str(),
                 "done": false,
-            });
+            }));
             let mut w = write.lock().await;
             w.send(Message::Text(event.to_string().into()))
                 .await
@@ -2239,6 +7638,23 @@ impl WebSocketHandler {
         let backup_file = self
             .resolve_backup_path(server_uuid, backup_path, true)
             .await?;
+        // A restarted upload replaces its previous session (and frees that session's slot).
+        self.discard_upload(request_id).await;
+        // Upload chunks arrive on the read loop, so the session can't wait for a slot here.
+        let Ok(permit) = self.backup_permits.clone().try_acquire_owned() else {
+            let event = json!({
+                "type": "backup_upload_response",
+                "requestId": request_id,
+                "success": false,
+                "retryable": true,
+                "error": "Backup concurrency limit reached, retry later",
+            });
+            let mut w = write.lock().await;
+            w.send(Message::Text(event.to_string().into()))
+                .await
+                .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+            return Ok(());
+        };
         let file = match tokio::fs::File::create(&backup_file).await {
             Ok(f) => f,
             Err(e) => {
@@ -2261,19 +7677,13 @@ impl WebSocketHandler {
             path: backup_file.clone(),
             bytes_written: 0,
             last_activity: tokio::time::Instant::now(),
+            _permit: permit,
         };
 
-        let old_session = {
-            let mut uploads = self.active_uploads.write().await;
-            let old = uploads.remove(request_id);
-            uploads.insert(request_id.to_string(), session);
-            old
-        };
-        if let Some(old) = old_session {
-            let path = old.path.clone();
-            drop(old.file);
-            let _ = tokio::fs::remove_file(&path).await;
-        }
+        self.active_uploads
+            .write()
+            .await
+            .insert(request_id.to_string(), session);
 
         let event = json!({
             "type": "backup_upload_response",
@@ -2434,8 +7844,89 @@ impl WebSocketHandler {
         Ok(())
     }
 
-    fn backup_base_dir(&self, server_uuid: &str) -> PathBuf {
-        PathBuf::from("/var/lib/catalyst/backups").join(server_uuid)
+    /// Host directory for a server's data, derived on-agent from the configured layout.
+    fn resolve_server_dir(&self, server_uuid: &str) -> AgentResult<PathBuf> {
+        validate_safe_path_segment(server_uuid, "serverUuid")?;
+        Ok(self
+            .config
+            .server
+            .data_layout
+            .server_dir(&self.config.server.data_dir, server_uuid))
+    }
+
+    fn backup_base_dir(&self, server_uuid: &str) -> PathBuf {
+        self.config
+            .server
+            .data_layout
+            .server_dir(Path::new(BACKUP_ROOT), server_uuid)
+    }
+
+    /// Key for a new backup from its `encryption` field: `true` or `{}` uses the node key,
+    /// `{"passphrase": ...}` a backend-held passphrase. `None` leaves the archive plain.
+    async fn backup_encryption(&self, msg: &Value) -> AgentResult<Option<(KeySource, String)>> {
+        match &msg["encryption"] {
+            Value::Null | Value::Bool(false) => Ok(None),
+            Value::Bool(true) => Ok(Some((KeySource::Node, self.node_backup_secret()?))),
+            Value::Object(options) => match options.get("passphrase") {
+                None | Some(Value::Null) => Ok(Some((KeySource::Node, self.node_backup_secret()?))),
+                Some(Value::String(passphrase)) if !passphrase.is_empty() => {
+                    Ok(Some((KeySource::Passphrase, passphrase.clone())))
+                }
+                Some(_) => Err(AgentError::InvalidRequest(
+                    "encryption.passphrase must be a non-empty string".to_string(),
+                )),
+            },
+            _ => Err(AgentError::InvalidRequest(
+                "encryption must be a boolean or an object".to_string(),
+            )),
+        }
+    }
+
+    /// Secret needed to open an encrypted backup, as recorded in its header.
+    async fn backup_decryption_secret(
+        &self,
+        msg: &Value,
+        header: &BackupHeader,
+    ) -> AgentResult<String> {
+        match header.key_source {
+            KeySource::Node => self.node_backup_secret(),
+            KeySource::LegacyNode => self.legacy_node_backup_secret(),
+            KeySource::Passphrase => msg["encryption"]["passphrase"]
+                .as_str()
+                .filter(|passphrase| !passphrase.is_empty())
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    AgentError::InvalidRequest(
+                        "Backup is encrypted with a passphrase; encryption.passphrase is required"
+                            .to_string(),
+                    )
+                }),
+        }
+    }
+
+    /// The node secret from `server.backup_secret`, independent of the API key so rotating
+    /// that keeps backups readable.
+    fn node_backup_secret(&self) -> AgentResult<String> {
+        self.config
+            .server
+            .backup_secret()
+            .map(|secret| format!("catalyst-backup-node:{}", secret))
+            .ok_or_else(|| {
+                AgentError::ConfigError(
+                    "server.backup_secret is required for node-keyed backup encryption".to_string(),
+                )
+            })
+    }
+
+    /// Key of archives written before `backup_secret`, derived from the API key.
+    fn legacy_node_backup_secret(&self) -> AgentResult<String> {
+        let api_key = self.config.server.api_key.trim();
+        if api_key.is_empty() {
+            return Err(AgentError::ConfigError(
+                "server.api_key is required for node-keyed backup encryption".to_string(),
+            ));
+        }
+        Ok(format!("catalyst-backup:{}", api_key))
     }
 
     async fn resolve_backup_path(
@@ -2523,7 +8014,7 @@ impl WebSocketHandler {
             .as_u64()
             .ok_or_else(|| AgentError::InvalidRequest("Missing allocatedDiskMb".to_string()))?;
 
-        let server_dir = PathBuf::from(self.config.server.data_dir.as_path()).join(server_uuid);
+        let server_dir = self.resolve_server_dir(server_uuid)?;
         let allow_online_grow = true;
 
         let result = self
@@ -2564,6 +8055,64 @@ impl WebSocketHandler {
         Ok(())
     }
 
+    async fn handle_compact_storage(
+        &self,
+        msg: &Value,
+        write: &Arc<tokio::sync::Mutex<WsWrite>>,
+    ) -> AgentResult<()> {
+        let server_id = msg["serverId"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverId".to_string()))?;
+        let server_uuid = msg["serverUuid"]
+            .as_str()
+            .ok_or_else(|| AgentError::InvalidRequest("Missing serverUuid".to_string()))?;
+
+        let server_dir = self.resolve_server_dir(server_uuid)?;
+        let _server_lock = self.lock_server(server_id).await;
+        let container_id = self.resolve_container_id(server_id, server_uuid).await;
+        let running = !container_id.is_empty()
+            && self
+                .runtime
+                .is_container_running(&container_id)
+                .await
+                .unwrap_or(false);
+
+        let result = self
+            .storage_manager
+            .compact(server_uuid, &server_dir, running)
+            .await;
+
+        let event = match &result {
+            Ok((reclaimed, allocated)) => json!({
+                "type": "storage_compact_complete",
+                "serverId": server_id,
+                "serverUuid": server_uuid,
+                "requestId": msg["requestId"],
+                "success": true,
+                "online": running,
+                "reclaimedBytes": reclaimed,
+                "allocatedBytes": allocated,
+            }),
+            Err(err) => json!({
+                "type": "storage_compact_complete",
+                "serverId": server_id,
+                "serverUuid": server_uuid,
+                "requestId": msg["requestId"],
+                "success": false,
+                "error": err.to_string(),
+            }),
+        };
+
+        let mut w = write.lock().await;
+        w.send(Message::Text(event.to_string().into()))
+            .await
+            .map_err(|e| AgentError::NetworkError(e.to_string()))?;
+
+        result?;
+
+        Ok(())
+    }
+
     /// Handle create_network message
     async fn handle_create_network(
         &self,
@@ -2585,6 +8134,7 @@ impl WebSocketHandler {
                 "networkName": network.name,
                 "success": false,
                 "error": err.to_string(),
+                "errorCode": err.code(),
             }),
         };
 
@@ -2625,6 +8175,7 @@ impl WebSocketHandler {
                 "networkName": network.name,
                 "success": false,
                 "error": err.to_string(),
+                "errorCode": err.code(),
             }),
         };
 
@@ -2661,6 +8212,7 @@ impl WebSocketHandler {
                 "networkName": network_name,
                 "success": false,
                 "error": err.to_string(),
+                "errorCode": err.code(),
             }),
         };
 
@@ -2686,6 +8238,13 @@ impl WebSocketHandler {
             gateway: msg["gateway"].as_str().map(|s| s.to_string()),
             range_start: msg["rangeStart"].as_str().map(|s| s.to_string()),
             range_end: msg["rangeEnd"].as_str().map(|s| s.to_string()),
+            mtu: msg["mtu"]
+                .as_u64()
+                .map(|v| {
+                    u32::try_from(v)
+                        .map_err(|_| AgentError::InvalidRequest(format!("Invalid MTU {}", v)))
+                })
+                .transpose()?,
         })
     }
 
@@ -2697,15 +8256,91 @@ impl WebSocketHandler {
         port_bindings: Option<HashMap<u16, u16>>,
         exit_code: Option<i32>,
     ) -> AgentResult<()> {
-        let msg = json!({
+        self.emit_server_state_update_with_fields(
+            server_id,
+            state,
+            reason,
+            port_bindings,
+            exit_code,
+            Value::Null,
+        )
+        .await
+    }
+
+    /// Like `emit_server_state_update`, merging the keys of `fields` into the message.
+    async fn emit_server_state_update_with_fields(
+        &self,
+        server_id: &str,
+        state: &str,
+        reason: Option<String>,
+        port_bindings: Option<HashMap<u16, u16>>,
+        exit_code: Option<i32>,
+        fields: Value,
+    ) -> AgentResult<()> {
+        // Only claim ports are forwarded if the firewall actually has the rules.
+        let ports_verified = match &port_bindings {
+            Some(bindings) => match self
+                .runtime
+                .missing_port_forwards(server_id, bindings)
+                .await
+            {
+                Ok(Some(missing)) => {
+                    for (container_port, host_port) in &missing {
+                        warn!(
+                            "Port forward {} -> {} for {} is missing its DNAT rule",
+                            host_port, container_port, server_id
+                        );
+                    }
+                    Some(missing.is_empty())
+                }
+                Ok(None) => None,
+                Err(e) => {
+                    warn!("Failed to verify port forwards for {}: {}", server_id, e);
+                    Some(false)
+                }
+            },
+            None => None,
+        };
+
+        self.record_autostart_run_state(server_id, state).await;
+        {
+            let mut meta = self.server_meta.write().await;
+            if state == "running" {
+                meta.entry(server_id.to_string()).or_default().ready = true;
+            } else if let Some(entry) = meta.get_mut(server_id) {
+                entry.ready = false;
+            }
+        }
+
+        let seq = self.state_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut msg = json!({
             "type": "server_state_update",
             "serverId": server_id,
             "state": state,
             "timestamp": chrono::Utc::now().timestamp_millis(),
             "reason": reason,
             "portBindings": port_bindings,
+            "portsVerified": ports_verified,
             "exitCode": exit_code,
+            "seq": seq,
         });
+        if let (Some(target), Value::Object(extra)) = (msg.as_object_mut(), fields) {
+            target.extend(extra);
+        }
+        {
+            let mut acks = self.state_acks.write().await;
+            if acks.enabled {
+                acks.pending.insert(
+                    server_id.to_string(),
+                    PendingStateUpdate {
+                        seq,
+                        message: msg.clone(),
+                        sent_at: tokio::time::Instant::now(),
+                        resends: 0,
+                    },
+                );
+            }
+        }
 
         debug!("Emitting state update: {}", msg);
 
@@ -2715,6 +8350,12 @@ impl WebSocketHandler {
             if let Err(err) = w.send(Message::Text(msg.to_string().into())).await {
                 error!("Failed to send state update: {}", err);
             }
+        } else {
+            let mut unsent = self.unsent_state_updates.write().await;
+            if unsent.len() >= MAX_UNSENT_STATE_UPDATES {
+                unsent.pop_front();
+            }
+            unsent.push_back(msg);
         }
 
         Ok(())
@@ -2730,6 +8371,24 @@ impl WebSocketHandler {
             return Ok(());
         }
 
+        let redactions = { self.console_redactions.read().await.get(server_id).cloned() };
+        let redacted;
+        let data = match redactions {
+            Some(patterns) => {
+                redacted = redact_console_data(data, &patterns);
+                redacted.as_str()
+            }
+            None => data,
+        };
+
+        if self.config.console.history_lines > 0 {
+            let mut buffers = self.console_buffers.write().await;
+            buffers
+                .entry(server_id.to_string())
+                .or_default()
+                .push(data, self.config.console.history_lines);
+        }
+
         let msg = json!({
             "type": "console_output",
             "serverId": server_id,
@@ -2749,9 +8408,89 @@ impl WebSocketHandler {
         Ok(())
     }
 
+    /// Extra fields for a `running` state update: the container's address and the image
+    /// digest it was created from, so the backend knows exactly which image bits run.
+    async fn running_fields(&self, container_id: &str, container_ip: Option<String>) -> Value {
+        let mut fields = container_ip_fields(container_ip);
+        if let Some(digest) = self.runtime.container_image_digest(container_id).await {
+            fields["imageDigest"] = json!(digest);
+        }
+        fields
+    }
+
+    /// The agent's own footprint and internal gauges, so leaks show up before they hurt the node.
+    async fn agent_self_usage(&self) -> Value {
+        let pid = std::process::id();
+        let cpu_percent = match read_proc_stat(pid) {
+            Some((_, ticks)) => {
+                let now = std::time::Instant::now();
+                let previous = self.agent_cpu_sample.write().await.replace((now, ticks));
+                previous.and_then(|(then, previous_ticks)| {
+                    let secs = now.duration_since(then).as_secs_f64();
+                    (secs > 0.0).then(|| {
+                        let percent = ticks.saturating_sub(previous_ticks) as f64
+                            / clock_ticks_per_sec()
+                            / secs
+                            * 100.0;
+                        (percent * 100.0).round() / 100.0
+                    })
+                })
+            }
+            None => None,
+        };
+        // Listing a /proc/self directory opens one descriptor of its own.
+        let open_fds = std::fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|entries| entries.count().saturating_sub(1));
+        let threads = std::fs::read_dir("/proc/self/task")
+            .ok()
+            .map(|entries| entries.count());
+        json!({
+            "cpuPercent": cpu_percent,
+            "rssBytes": read_proc_rss(pid).await,
+            "openFds": open_fds,
+            "threads": threads,
+            "tokioTasks": tokio::runtime::Handle::current().metrics().num_alive_tasks(),
+            "activeLogStreams": self.active_log_streams.read().await.len(),
+            "maxLogStreams": self.config.console.max_log_streams,
+            "monitorTasks": self.monitor_tasks.read().await.len(),
+            "uploadSessions": self.active_uploads.read().await.len(),
+        })
+    }
+
+    /// Warn when a data volume runs low on inodes, which fails writes with ENOSPC while
+    /// plenty of bytes are still free. Logged once per crossing of `INODE_WARNING_PERCENT`.
+    async fn check_inode_usage(&self, volumes: &[DataVolume]) {
+        let mut warned = self.inode_warnings.write().await;
+        for volume in volumes {
+            if volume.inodes_total == 0 {
+                continue;
+            }
+            let percent = volume.inodes_used * 100 / volume.inodes_total;
+            if percent >= INODE_WARNING_PERCENT {
+                if warned.insert(volume.mount_point.clone()) {
+                    warn!(
+                        "{} has used {}% of its inodes ({} of {}); new files will fail with ENOSPC once they run out",
+                        volume.mount_point.display(),
+                        percent,
+                        volume.inodes_used,
+                        volume.inodes_total
+                    );
+                }
+            } else if warned.remove(&volume.mount_point) {
+                info!(
+                    "{} inode usage is back to {}%",
+                    volume.mount_point.display(),
+                    percent
+                );
+            }
+        }
+    }
+
     pub async fn send_health_report(&self) -> AgentResult<()> {
         debug!("Sending health report");
         let containers = self.runtime.list_containers().await?;
+        self.prune_log_streams(&containers).await;
         let mut system = System::new();
         system.refresh_cpu_all();
         system.refresh_memory();
@@ -2767,6 +8506,39 @@ impl WebSocketHandler {
             disk_usage_mb +=
                 disk.total_space().saturating_sub(disk.available_space()) / (1024 * 1024);
         }
+        let volumes = match self.storage_manager.data_volumes().await {
+            Ok(volumes) => volumes,
+            Err(e) => {
+                warn!("Failed to resolve data volumes: {}", e);
+                Vec::new()
+            }
+        };
+        self.check_inode_usage(&volumes).await;
+        // The first volume is the one holding the data directory itself.
+        let (inodes_used, inodes_total) = volumes
+            .first()
+            .map(|v| (v.inodes_used, v.inodes_total))
+            .unzip();
+        let data_volumes: Vec<Value> = volumes
+            .into_iter()
+            .map(|v| {
+                json!({
+                    "mountPoint": v.mount_point.to_string_lossy(),
+                    "freeMb": v.free_mb,
+                    "totalMb": v.total_mb,
+                    "inodesUsed": v.inodes_used,
+                    "inodesTotal": v.inodes_total,
+                })
+            })
+            .collect();
+
+        let egress = self.runtime.egress_limiter().map(|egress| {
+            json!({
+                "interface": egress.interface(),
+                "limitMbps": egress.limit_mbps(),
+                "currentMbps": egress.sample_throughput_mbps(),
+            })
+        });
 
         let health = json!({
             "type": "health_report",
@@ -2777,8 +8549,24 @@ impl WebSocketHandler {
             "memoryTotalMb": memory_total_mb,
             "diskUsageMb": disk_usage_mb,
             "diskTotalMb": disk_total_mb,
+            "dataVolumes": data_volumes,
+            "inodesUsed": inodes_used,
+            "inodesTotal": inodes_total,
             "containerCount": containers.iter().filter(|c| c.managed).count(),
+            "runningCount": containers
+                .iter()
+                .filter(|c| c.managed && c.status == "Up")
+                .count(),
+            "maxServers": self.config.limits.max_servers,
+            "maxRunningServers": self.config.limits.max_running_servers,
+            "activeBackups": self.config.limits.max_concurrent_backups.max(1)
+                - self.backup_permits.available_permits(),
+            "reclaimedSnapshots": self.reclaimed_snapshots.load(Ordering::Relaxed),
+            "backendLatencyMs": *self.backend_latency_ms.read().await,
             "uptimeSeconds": get_uptime(),
+            "egress": egress,
+            "lowDisk": self.low_disk.load(Ordering::Relaxed),
+            "agent": self.agent_self_usage().await,
         });
 
         debug!("Health report: {}", health);
@@ -2825,44 +8613,64 @@ impl WebSocketHandler {
             }
         }
 
-        // Report state for all known containers
-        for container in containers {
-            if !container.managed {
-                continue;
-            }
-            let server_uuid = normalize_container_name(&container.names);
-            if server_uuid.is_empty() {
-                continue;
-            }
-
-            let is_running = container.status.contains("Up");
-            let state = if is_running { "running" } else { "stopped" };
+        // Report state for all known containers; exit codes are looked up in parallel but
+        // sent in container order.
+        let syncs: Vec<_> = containers
+            .into_iter()
+            .filter_map(|container| {
+                let server_uuid = normalize_container_name(&container.names);
+                (container.managed && !server_uuid.is_empty()).then_some((container, server_uuid))
+            })
+            .map(|(container, server_uuid)| async move {
+                let is_running = container.status.contains("Up");
+                let orphaned = !container.has_task
+                    && container.created_at.is_some_and(|created| {
+                        created.elapsed().unwrap_or_default() >= ORPHANED_CONTAINER_GRACE
+                    });
+                let state = if is_running {
+                    "running"
+                } else if orphaned {
+                    // No task to start or inspect; the backend decides whether to recreate
+                    // (start_server cleans the stale container up) or delete the server.
+                    warn!(
+                        "Container {} has no task; reporting it as orphaned",
+                        container.id
+                    );
+                    "orphaned"
+                } else {
+                    "stopped"
+                };
 
-            // If container is stopped, try to get exit code
-            let exit_code = if !is_running {
-                self.runtime
-                    .get_container_exit_code(&container.id)
-                    .await
-                    .ok()
-                    .flatten()
-            } else {
-                None
-            };
+                // If container is stopped, try to get exit code
+                let exit_code = if !is_running {
+                    self.runtime
+                        .get_container_exit_code(&container.id)
+                        .await
+                        .ok()
+                        .flatten()
+                } else {
+                    None
+                };
 
-            info!(
-                "Reconciling container: name='{}', uuid='{}', status='{}', state='{}'",
-                container.names, server_uuid, container.status, state
-            );
+                info!(
+                    "Reconciling container: name='{}', uuid='{}', status='{}', state='{}'",
+                    container.names, server_uuid, container.status, state
+                );
 
-            let msg = json!({
-                "type": "server_state_sync",
-                "serverUuid": server_uuid,
-                "containerId": server_uuid,  // Use container name (CUID), not internal container ID
-                "state": state,
-                "exitCode": exit_code,
-                "timestamp": chrono::Utc::now().timestamp_millis(),
-            });
+                json!({
+                    "type": "server_state_sync",
+                    "serverUuid": server_uuid,
+                    "containerId": server_uuid,  // Use container name (CUID), not internal container ID
+                    "state": state,
+                    "exitCode": exit_code,
+                    "imageDigest": container.image_digest,
+                    "timestamp": chrono::Utc::now().timestamp_millis(),
+                })
+            })
+            .collect();
+        let mut syncs = futures::stream::iter(syncs).buffered(self.container_query_concurrency());
 
+        while let Some(msg) = syncs.next().await {
             let mut w = ws.lock().await;
             if let Err(err) = w.send(Message::Text(msg.to_string().into())).await {
                 warn!("Failed to send state sync: {}", err);
@@ -2911,6 +8719,17 @@ impl WebSocketHandler {
 
             let mut receiver = event_stream.receiver;
 
+            // Managed/unmanaged status per container id, so events don't each cost a lookup.
+            // Seeded from the current container list so deletes of existing containers resolve.
+            let mut managed_cache: HashMap<String, bool> =
+                match self.runtime.list_containers().await {
+                    Ok(containers) => containers.into_iter().map(|c| (c.id, c.managed)).collect(),
+                    Err(e) => {
+                        warn!("Failed to seed managed container cache: {}", e);
+                        HashMap::new()
+                    }
+                };
+
             // Read events from containerd gRPC streaming
             while let Ok(Some(envelope)) = receiver.message().await {
                 let topic = &envelope.topic;
@@ -2932,8 +8751,11 @@ impl WebSocketHandler {
                     continue;
                 }
 
-                // Skip non-Catalyst containers (Catalyst uses CUID IDs starting with 'c' or 'catalyst-installer-')
-                if !container_name.starts_with("cm") && !container_name.starts_with("catalyst-") {
+                // Only Catalyst-managed containers (by label) are of interest, whatever their id.
+                if !self
+                    .event_container_managed(&mut managed_cache, &container_name, topic)
+                    .await
+                {
                     continue;
                 }
 
@@ -2970,6 +8792,33 @@ impl WebSocketHandler {
         }
     }
 
+    /// Resolve whether an event's container is labelled `catalyst.managed`, consulting the
+    /// cache first. Deleted containers can no longer be looked up, so their entry is consumed.
+    async fn event_container_managed(
+        &self,
+        cache: &mut HashMap<String, bool>,
+        container_id: &str,
+        topic: &str,
+    ) -> bool {
+        if topic == "/containers/delete" {
+            return cache.remove(container_id).unwrap_or(false);
+        }
+        if let Some(managed) = cache.get(container_id) {
+            return *managed;
+        }
+        match self.runtime.is_managed_container(container_id).await {
+            Ok(Some(managed)) => {
+                cache.insert(container_id.to_string(), managed);
+                managed
+            }
+            Ok(None) => false,
+            Err(e) => {
+                debug!("Could not look up container {}: {}", container_id, e);
+                false
+            }
+        }
+    }
+
     /// Sync a specific container's state to the backend
     async fn sync_container_state(&self, container_name: &str) -> AgentResult<()> {
         let writer = { self.write.read().await.clone() };
@@ -3050,66 +8899,55 @@ impl WebSocketHandler {
         }
 
         let writer_opt = { self.write.read().await.clone() };
-        // writer_opt may be None if we're not connected; we will buffer metrics to disk in that case;
-
-        for container in containers {
-            if !container.status.contains("Up") || !container.managed {
-                continue;
+        let buffer_cap = self.config.limits.metrics_buffer_max_mb * 1024 * 1024;
+
+        // The conntrack table is read once per pass and counted for every container address.
+        let lookups: Vec<_> = containers
+            .iter()
+            .filter(|c| c.managed && c.status.contains("Up"))
+            .map(|container| async move {
+                self.container_ip(&container.id)
+                    .await
+                    .map(|ip| (container.id.clone(), ip))
+            })
+            .collect();
+        let container_ips: HashMap<String, String> = futures::stream::iter(lookups)
+            .buffer_unordered(self.container_query_concurrency())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        let connections = if container_ips.is_empty() {
+            None
+        } else {
+            let ips: HashSet<String> = container_ips.values().cloned().collect();
+            let counts = conntrack_counts(&ips).await;
+            if let Some((_, true)) = &counts {
+                debug!("Conntrack table exceeds the read limit; connection counts are partial");
             }
+            counts
+        };
+        // writer_opt may be None if we're not connected; we will buffer metrics to disk in that case;
 
-            let server_uuid = normalize_container_name(&container.names);
-            if server_uuid.is_empty() {
+        // Containers are sampled in parallel; payloads are still sent in container order.
+        let samples: Vec<_> = containers
+            .iter()
+            .filter(|container| {
+                container.managed
+                    && container.status.contains("Up")
+                    && !normalize_container_name(&container.names).is_empty()
+            })
+            .map(|container| self.resource_stats_payload(container, &container_ips, &connections))
+            .collect();
+        let mut payloads =
+            futures::stream::iter(samples).buffered(self.container_query_concurrency());
+
+        while let Some(payload) = payloads.next().await {
+            let Some(payload) = payload else {
                 continue;
-            }
-
-            let stats = match self.runtime.get_stats(&container.id).await {
-                Ok(stats) => stats,
-                Err(err) => {
-                    warn!(
-                        "Failed to fetch stats for container {}: {}",
-                        container.id, err
-                    );
-                    continue;
-                }
-            };
-
-            let cpu_percent = parse_percent(&stats.cpu_percent).unwrap_or(0.0);
-            let memory_usage_mb = parse_memory_usage_mb(&stats.memory_usage).unwrap_or(0);
-            let (network_rx_bytes, network_tx_bytes) =
-                parse_io_pair_bytes(&stats.net_io).unwrap_or((0, 0));
-            let (disk_read_bytes, disk_write_bytes) =
-                parse_io_pair_bytes(&stats.block_io).unwrap_or((0, 0));
-            let disk_io_mb = (disk_read_bytes + disk_write_bytes) / (1024 * 1024);
-            let (disk_usage_mb, disk_total_mb) = match self
-                .runtime
-                .exec(&container.id, vec!["df", "-m", "/data"])
-                .await
-                .ok()
-                .and_then(|output| parse_df_output_mb(&output))
-            {
-                Some(value) => value,
-                None => {
-                    warn!(
-                        "Failed to read filesystem usage for container {}. Falling back to block IO stats.",
-                        container.id
-                    );
-                    (disk_io_mb, 0)
-                }
             };
 
-            let payload = json!({
-                "type": "resource_stats",
-                "serverUuid": server_uuid,
-                "cpuPercent": cpu_percent,
-                "memoryUsageMb": memory_usage_mb,
-                "networkRxBytes": network_rx_bytes,
-                "networkTxBytes": network_tx_bytes,
-                "diskIoMb": disk_io_mb,
-                "diskUsageMb": disk_usage_mb,
-                "diskTotalMb": disk_total_mb,
-                "timestamp": chrono::Utc::now().timestamp_millis(),
-            });
-
             // If we have a live write handle, send; otherwise buffer to disk immediately
             match &writer_opt {
                 Some(ws) => {
@@ -3118,8 +8956,10 @@ impl WebSocketHandler {
                         Ok(_) => {}
                         Err(err) => {
                             warn!("Failed to send resource stats: {}. Buffering to disk.", err);
-                            if let Err(e) =
-                                self.storage_manager.append_buffered_metric(&payload).await
+                            if let Err(e) = self
+                                .storage_manager
+                                .append_buffered_metric(&payload, buffer_cap)
+                                .await
                             {
                                 warn!("Failed to buffer metric to disk: {}", e);
                             }
@@ -3128,7 +8968,11 @@ impl WebSocketHandler {
                 }
                 None => {
                     // No connection - persist metric locally for later flush
-                    if let Err(e) = self.storage_manager.append_buffered_metric(&payload).await {
+                    if let Err(e) = self
+                        .storage_manager
+                        .append_buffered_metric(&payload, buffer_cap)
+                        .await
+                    {
                         warn!("Failed to buffer metric to disk: {}", e);
                     }
                 }
@@ -3137,6 +8981,158 @@ impl WebSocketHandler {
 
         Ok(())
     }
+
+    /// One container's `resource_stats` message, or `None` when its stats can't be read.
+    async fn resource_stats_payload(
+        &self,
+        container: &ContainerInfo,
+        container_ips: &HashMap<String, String>,
+        connections: &ConntrackSnapshot,
+    ) -> Option<Value> {
+        let server_uuid = normalize_container_name(&container.names);
+        let stats = match self.runtime.get_stats(&container.id).await {
+            Ok(stats) => stats,
+            Err(err) => {
+                warn!(
+                    "Failed to fetch stats for container {}: {}",
+                    container.id, err
+                );
+                return None;
+            }
+        };
+
+        let cpu_percent = parse_percent(&stats.cpu_percent).unwrap_or(0.0);
+        let memory_usage_mb = parse_memory_usage_mb(&stats.memory_usage).unwrap_or(0);
+        let (network_rx_bytes, network_tx_bytes) =
+            parse_io_pair_bytes(&stats.net_io).unwrap_or((0, 0));
+        let (disk_read_bytes, disk_write_bytes) =
+            parse_io_pair_bytes(&stats.block_io).unwrap_or((0, 0));
+        let disk_io_mb = (disk_read_bytes + disk_write_bytes) / (1024 * 1024);
+        let (disk_usage_mb, disk_total_mb) = match self
+            .runtime
+            // Execs run in the server's data directory, wherever it is mounted.
+            .exec(&container.id, vec!["df", "-m", "."])
+            .await
+            .ok()
+            .and_then(|output| parse_df_output_mb(&output))
+        {
+            Some(value) => value,
+            None => {
+                warn!(
+                    "Failed to read filesystem usage for container {}. Falling back to block IO stats.",
+                    container.id
+                );
+                (disk_io_mb, 0)
+            }
+        };
+
+        let meta = self
+            .server_meta
+            .read()
+            .await
+            .get(&server_uuid)
+            .copied()
+            .unwrap_or_default();
+        let data_volume = self
+            .storage_manager
+            .volume_for_server(&server_uuid)
+            .await
+            .ok()
+            .map(|path| path.to_string_lossy().into_owned());
+        let (inodes_used, inodes_total) = self
+            .storage_manager
+            .server_inode_usage(&server_uuid)
+            .await
+            .unzip();
+
+        let connection_counts = container_ips.get(&container.id).and_then(|ip| {
+            let (counts, truncated) = connections.as_ref()?;
+            let c = counts.get(ip).copied().unwrap_or_default();
+            Some(json!({
+                "tcp": c.tcp,
+                "udp": c.udp,
+                "other": c.other,
+                "halfOpen": c.half_open,
+                "partial": truncated,
+            }))
+        });
+
+        Some(json!({
+            "type": "resource_stats",
+            "serverUuid": server_uuid,
+            "cpuPercent": cpu_percent,
+            "memoryUsageMb": memory_usage_mb,
+            "networkRxBytes": network_rx_bytes,
+            "networkTxBytes": network_tx_bytes,
+            "diskIoMb": disk_io_mb,
+            "ioPressurePercent": stats.io_pressure_percent,
+            "connections": connection_counts,
+            "diskUsageMb": disk_usage_mb,
+            "diskTotalMb": disk_total_mb,
+            "dataVolume": data_volume,
+            "inodesUsed": inodes_used,
+            "inodesTotal": inodes_total,
+            "restartCount": meta.restart_count,
+            "lastExitCode": meta.last_exit_code,
+            "timestamp": chrono::Utc::now().timestamp_millis(),
+        }))
+    }
+
+    /// Parallelism for per-container queries in reconciliation and stats passes.
+    fn container_query_concurrency(&self) -> usize {
+        self.config.limits.container_query_concurrency.max(1)
+    }
+}
+
+/// Replace every match of the redaction patterns with `***`.
+fn redact_console_data(data: &str, patterns: &[Regex]) -> String {
+    let mut output = data.to_string();
+    for pattern in patterns {
+        if let std::borrow::Cow::Owned(replaced) = pattern.replace_all(&output, "***") {
+            output = replaced;
+        }
+    }
+    output
+}
+
+/// Parse a `{ "containerPort": hostPort }` map from the backend.
+fn parse_port_bindings(value: Option<&Value>) -> AgentResult<HashMap<u16, u16>> {
+    let mut port_bindings = HashMap::new();
+    if let Some(map) = value.and_then(|value| value.as_object()) {
+        for (container_port, host_port) in map {
+            let container_port = container_port.parse::<u16>().map_err(|_| {
+                AgentError::InvalidRequest("Invalid portBindings container port".to_string())
+            })?;
+            let host_port = host_port.as_u64().ok_or_else(|| {
+                AgentError::InvalidRequest("Invalid portBindings host port".to_string())
+            })?;
+            if host_port == 0 || host_port > u16::MAX as u64 {
+                return Err(AgentError::InvalidRequest(
+                    "Invalid portBindings host port".to_string(),
+                ));
+            }
+            port_bindings.insert(container_port, host_port as u16);
+        }
+    }
+    Ok(port_bindings)
+}
+
+/// Extra state-update fields carrying a container's IP, or none when it has no own address.
+fn container_ip_fields(container_ip: Option<String>) -> Value {
+    match container_ip {
+        Some(ip) => json!({ "containerIp": ip }),
+        None => json!({}),
+    }
+}
+
+/// Mask the value of a `KEY=value` environment entry whose name looks secret.
+fn redact_env_entry(entry: &str) -> Option<String> {
+    let (key, _) = entry.split_once('=')?;
+    let upper = key.to_ascii_uppercase();
+    ["KEY", "SECRET", "TOKEN", "PASSWORD", "PASS"]
+        .iter()
+        .any(|marker| upper.contains(marker))
+        .then(|| format!("{}=[REDACTED]", key))
 }
 
 fn get_uptime() -> u64 {
@@ -3264,3 +9260,72 @@ fn parse_df_output_mb(output: &str) -> Option<(u64, u64)> {
     let used_mb = parts[2].parse::<u64>().ok()?;
     Some((used_mb, total_mb))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn console_tail_restarts_after_truncation() {
+        let dir = std::env::temp_dir().join(format!("catalyst-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stdout");
+        let mut tail = ConsoleTail::default();
+
+        std::fs::write(&path, "first line\nsecond line\n").unwrap();
+        assert_eq!(
+            tail.read_new(&path, true).await.as_deref(),
+            Some("first line\nsecond line\n")
+        );
+        assert_eq!(tail.read_new(&path, true).await, None);
+
+        // Truncated in place, then regrown past the old read position.
+        std::fs::write(&path, "after\n").unwrap();
+        assert_eq!(tail.read_new(&path, true).await.as_deref(), Some("after\n"));
+        std::fs::write(&path, "after\nrotation keeps going\n").unwrap();
+        assert_eq!(
+            tail.read_new(&path, true).await.as_deref(),
+            Some("rotation keeps going\n")
+        );
+
+        // Replaced by a new, larger file: the inode changes so everything is new.
+        let replacement = dir.join("stdout.new");
+        std::fs::write(&replacement, "a brand new console file that is longer\n").unwrap();
+        std::fs::rename(&replacement, &path).unwrap();
+        assert_eq!(
+            tail.read_new(&path, true).await.as_deref(),
+            Some("a brand new console file that is longer\n")
+        );
+
+        // A line is held back until its newline arrives.
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, b"half a ").unwrap();
+        assert_eq!(tail.read_new(&path, true).await, None);
+        std::io::Write::write_all(&mut file, b"line\nnext").unwrap();
+        assert_eq!(
+            tail.read_new(&path, true).await.as_deref(),
+            Some("half a line\n")
+        );
+        assert_eq!(tail.take_partial().as_deref(), Some("next"));
+
+        // Cut back by the size cap: only output after the kept history is new, even without
+        // follow_rotation.
+        std::io::Write::write_all(&mut file, " line\n".repeat(20).as_bytes()).unwrap();
+        std::io::Write::write_all(&mut file, b"kept line\n").unwrap();
+        assert!(tail.read_new(&path, false).await.is_some());
+        assert!(crate::runtime_manager::cap_console_file(&path, 32).unwrap());
+        std::io::Write::write_all(&mut file, b"after the cap\n").unwrap();
+        assert_eq!(
+            tail.read_new(&path, false).await.as_deref(),
+            Some("after the cap\n")
+        );
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("kept line\nafter the cap\n"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}